@@ -0,0 +1,147 @@
+//! Chunk search latency: brute force (load every embedding, score each) vs
+//! the persistent HNSW index, over one owner's knowledge base.
+//!
+//!     cargo bench --bench vector_index
+//!
+//! `BENCH_CHUNKS` (default 100000) and `BENCH_DIMS` (default 1024, the
+//! default embedding model's size) change the data set. Vectors are
+//! clustered around random centres, like embeddings of related text are.
+//! `BENCH_QUANTIZATION` (none, int8 or binary) sets the index's mode.
+
+use std::time::{Duration, Instant};
+
+use memory_assistant::db::quantization::Quantization;
+use memory_assistant::db::{Database, Repository};
+use memory_assistant::tools::embedding::{bytes_to_embedding, cosine_similarity, embedding_to_bytes};
+
+const OWNER: u64 = 1;
+const CHUNKS_PER_DOC: usize = 1000;
+const CLUSTERS: usize = 1000;
+const K: usize = 10;
+const BRUTE_FORCE_QUERIES: usize = 20;
+const INDEX_QUERIES: usize = 500;
+
+fn env_usize(name: &str, default: usize) -> usize {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+struct Rng(u64);
+
+impl Rng {
+    /// Uniform in [-1, 1).
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+    }
+
+    fn vector(&mut self, dims: usize) -> Vec<f32> {
+        (0..dims).map(|_| self.next()).collect()
+    }
+
+    fn near(&mut self, centre: &[f32], spread: f32) -> Vec<f32> {
+        centre.iter().map(|c| c + spread * self.next()).collect()
+    }
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    sorted[((sorted.len() - 1) as f64 * p).round() as usize]
+}
+
+fn report(name: &str, mut times: Vec<Duration>) {
+    times.sort();
+    println!(
+        "{name:<24} p50 {:>9.2} ms   p99 {:>9.2} ms   ({} queries)",
+        percentile(&times, 0.5).as_secs_f64() * 1000.0,
+        percentile(&times, 0.99).as_secs_f64() * 1000.0,
+        times.len()
+    );
+}
+
+/// The old search path: every embedding of the owner, scored one by one.
+async fn brute_force(db: &Database, query: &[f32]) -> Vec<i64> {
+    let mut scored: Vec<(i64, f32)> = db
+        .load_all_embeddings(OWNER)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|(chunk, blob)| (chunk.id, cosine_similarity(query, &bytes_to_embedding(&blob))))
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.into_iter().take(K).map(|(id, _)| id).collect()
+}
+
+#[tokio::main]
+async fn main() {
+    let chunks = env_usize("BENCH_CHUNKS", 100_000);
+    let dims = env_usize("BENCH_DIMS", 1024);
+    let mode: Quantization = std::env::var("BENCH_QUANTIZATION").unwrap_or_default().parse().unwrap();
+    let path = std::env::temp_dir().join(format!("ma-bench-vectors-{}.db", std::process::id()));
+    let path_str = path.to_str().unwrap().to_string();
+
+    println!("{chunks} chunks x {dims} dimensions, k = {K}, quantization {}", mode.as_str());
+    let mut rng = Rng(0x5EED);
+    let centres: Vec<Vec<f32>> = (0..CLUSTERS).map(|_| rng.vector(dims)).collect();
+
+    let db = Database::open(&path_str).unwrap();
+    db.set_quantization(mode).await.unwrap();
+    let started = Instant::now();
+    let mut written = 0;
+    while written < chunks {
+        let count = CHUNKS_PER_DOC.min(chunks - written);
+        let doc = db.save_document(OWNER, &format!("doc {written}"), "bench", None, None).await.unwrap();
+        let rows: Vec<(usize, usize, usize, String)> =
+            (0..count).map(|i| (i, i + 1, i + 1, format!("chunk {}", written + i))).collect();
+        let refs: Vec<(usize, usize, usize, &str)> = rows.iter().map(|(a, b, c, s)| (*a, *b, *c, s.as_str())).collect();
+        let ids = db.save_chunks(doc, &refs).await.unwrap();
+        let blobs: Vec<Vec<u8>> = (0..count)
+            .map(|i| embedding_to_bytes(&rng.near(&centres[(written + i) % CLUSTERS], 0.5)))
+            .collect();
+        db.update_chunk_embeddings(&ids, &blobs).await.unwrap();
+        written += count;
+    }
+    println!("{:<24} {:>9.1} s", "load data", started.elapsed().as_secs_f64());
+
+    let queries: Vec<Vec<f32>> =
+        (0..INDEX_QUERIES).map(|i| rng.near(&centres[(i * 7919) % CLUSTERS], 0.5)).collect();
+
+    let mut times = Vec::new();
+    let mut exact = Vec::new();
+    for query in &queries[..BRUTE_FORCE_QUERIES] {
+        let started = Instant::now();
+        exact.push(brute_force(&db, query).await);
+        times.push(started.elapsed());
+    }
+    report("brute force", times);
+
+    let started = Instant::now();
+    let built = db.ensure_vector_indexes().await.unwrap();
+    assert_eq!(built, 1);
+    println!("{:<24} {:>9.1} s", "build index", started.elapsed().as_secs_f64());
+    drop(db);
+
+    // A fresh handle, as after a restart: the first search loads the stored graph
+    let db = Database::open(&path_str).unwrap();
+    let started = Instant::now();
+    db.nearest_chunks(OWNER, &queries[0], K).await.unwrap();
+    println!("{:<24} {:>9.1} s", "load index", started.elapsed().as_secs_f64());
+
+    let mut times = Vec::new();
+    let mut found = 0;
+    for (i, query) in queries.iter().enumerate() {
+        let started = Instant::now();
+        let hits = db.nearest_chunks(OWNER, query, K).await.unwrap();
+        times.push(started.elapsed());
+        if let Some(exact) = exact.get(i) {
+            found += hits.iter().filter(|(chunk, _)| exact.contains(&chunk.id)).count();
+        }
+    }
+    report("hnsw index", times);
+    println!("{:<24} {:>9.3}", "recall@10", found as f64 / (BRUTE_FORCE_QUERIES * K) as f64);
+
+    drop(db);
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{path_str}{suffix}"));
+    }
+}
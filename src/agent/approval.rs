@@ -0,0 +1,76 @@
+//! Settling pending write requests. A decision removes the request and is
+//! written to `approval_log` in one transaction, together with who decided;
+//! approving then runs the queued tool call with the approver as the actor
+//! and records what the tool returned.
+
+use crate::config::Config;
+use crate::db::{ApprovalDecision, Database, DbResult, PendingItem, Repository};
+use crate::provider::ProviderPool;
+use crate::tools::Embedder;
+
+use super::tool_registry::{ToolOutput, ToolRegistry};
+
+/// Who may settle pending requests, and how long they can be settled.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ApprovalPolicy<'a> {
+    /// Whitelisted users; empty lets everyone write directly.
+    pub allowed_users: &'a [u64],
+    /// Requests older than this can no longer be decided (0 = no limit).
+    pub pending_ttl_hours: u32,
+}
+
+impl<'a> ApprovalPolicy<'a> {
+    pub fn from_config(config: &'a Config) -> Self {
+        Self { allowed_users: &config.allowed_users, pending_ttl_hours: config.pending_ttl_hours }
+    }
+}
+
+/// Approve request `id` of `scope_id` and run its tool. Returns the request
+/// and the tool's result, or `None` if there is no such request (already
+/// decided or expired).
+pub async fn approve_pending(
+    db: &Database,
+    pool: &ProviderPool,
+    embedder: &dyn Embedder,
+    policy: ApprovalPolicy<'_>,
+    scope_id: u64,
+    id: i64,
+    approver: u64,
+) -> DbResult<Option<(PendingItem, String)>> {
+    let taken = db.take_pending(scope_id, id, policy.pending_ttl_hours, ApprovalDecision::Approved, approver).await?;
+    let Some((item, log_id)) = taken else {
+        return Ok(None);
+    };
+    // The approver is whitelisted, so this runs instead of queueing again
+    let output = Box::pin(ToolRegistry::execute(
+        &item.tool_name,
+        &item.args_json,
+        approver,
+        item.scope_id,
+        db,
+        pool,
+        embedder,
+        // Queued calls are write tools, which never read uploads
+        None,
+        policy,
+    ))
+    .await;
+    let result = match output {
+        ToolOutput::Text(t) => t,
+        ToolOutput::Image { text, .. } => text,
+    };
+    db.set_approval_result(log_id, &result).await?;
+    Ok(Some((item, result)))
+}
+
+/// Reject request `id` of `scope_id`. `None` if there is no such request.
+pub async fn reject_pending(
+    db: &Database,
+    policy: ApprovalPolicy<'_>,
+    scope_id: u64,
+    id: i64,
+    rejecter: u64,
+) -> DbResult<Option<PendingItem>> {
+    let taken = db.take_pending(scope_id, id, policy.pending_ttl_hours, ApprovalDecision::Rejected, rejecter).await?;
+    Ok(taken.map(|(item, _)| item))
+}
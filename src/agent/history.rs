@@ -0,0 +1,179 @@
+//! Conversation history as stored `provider::Message` values.
+//!
+//! Each turn is saved as the user's message, the assistant's tool calls with
+//! their results, and the final reply, so the next turn replays what the
+//! model actually did. Stored copies are capped: long text and tool output
+//! are cut, oversized images are replaced by a note, and oversized tool call
+//! arguments are kept as a truncated string.
+
+use tracing::warn;
+
+use crate::db::{NewSessionMessage, SessionMessage};
+use crate::provider::{ImageData, Message, MessageContent, Role, ToolCall};
+
+/// Size caps applied to history before it is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryLimits {
+    /// Characters kept of user and assistant text (including extracted file contents).
+    pub max_text_chars: usize,
+    /// Characters kept of each tool result and each tool call's arguments.
+    pub max_tool_chars: usize,
+    /// Largest image kept, in bytes of base64; 0 drops every image.
+    pub max_image_bytes: usize,
+}
+
+impl Default for HistoryLimits {
+    fn default() -> Self {
+        Self { max_text_chars: 8000, max_tool_chars: 2000, max_image_bytes: 256 * 1024 }
+    }
+}
+
+/// The row to store for `message`. `text` overrides the plain-text rendering,
+/// e.g. the short "[photo] caption" kept for a user's upload.
+pub fn to_stored(message: &Message, text: Option<&str>, limits: &HistoryLimits) -> NewSessionMessage {
+    let capped = Message { role: message.role.clone(), content: cap_content(&message.content, limits) };
+    let content = match text {
+        Some(text) => text.to_string(),
+        None => describe(&capped.content),
+    };
+    NewSessionMessage {
+        role: role_name(&message.role).to_string(),
+        content,
+        message: serde_json::to_string(&capped)
+            .inspect_err(|e| warn!("Failed to serialize history message: {e}"))
+            .ok(),
+    }
+}
+
+/// Messages to replay from stored rows. Rows saved before history kept tool
+/// calls become plain text. Anything before the first user message is
+/// dropped, so a window cut by retention never opens with orphaned tool results.
+pub fn replay(rows: Vec<SessionMessage>) -> Vec<Message> {
+    let messages = rows.into_iter().filter_map(|row| {
+        if let Some(json) = &row.message {
+            match serde_json::from_str::<Message>(json) {
+                Ok(message) => return Some(message),
+                Err(e) => warn!("Unreadable history message {}: {e}", row.id),
+            }
+        }
+        let role = match row.role.as_str() {
+            "user" => Role::User,
+            "assistant" => Role::Assistant,
+            _ => return None,
+        };
+        Some(Message { role, content: MessageContent::Text(row.content) })
+    });
+    messages.skip_while(|m| m.role != Role::User).collect()
+}
+
+fn role_name(role: &Role) -> &'static str {
+    match role {
+        Role::System => "system",
+        Role::User => "user",
+        Role::Assistant => "assistant",
+        Role::Tool => "tool",
+    }
+}
+
+/// Plain-text rendering for summaries and titles.
+fn describe(content: &MessageContent) -> String {
+    match content {
+        MessageContent::ToolResult { name, content, .. } => format!("[{name}] {content}"),
+        MessageContent::ToolResultWithImage { name, text, .. } => format!("[{name}] {text}"),
+        MessageContent::AssistantWithToolCalls { text, tool_calls, .. } => {
+            let names: Vec<&str> = tool_calls.iter().map(|tc| tc.function.name.as_str()).collect();
+            let calls = format!("[calls {}]", names.join(", "));
+            match text.as_deref().filter(|t| !t.is_empty()) {
+                Some(text) => format!("{text}\n{calls}"),
+                None => calls,
+            }
+        }
+        other => other.as_text().to_string(),
+    }
+}
+
+fn cap_content(content: &MessageContent, limits: &HistoryLimits) -> MessageContent {
+    let text = |s: &str| truncate(s, limits.max_text_chars);
+    let tool = |s: &str| truncate(s, limits.max_tool_chars);
+    let fits = |image: &str| !image.is_empty() && image.len() <= limits.max_image_bytes;
+
+    match content {
+        MessageContent::Text(s) => MessageContent::Text(text(s)),
+        MessageContent::ImageWithText { text: t, image_base64, media_type } => {
+            if fits(image_base64) {
+                MessageContent::ImageWithText {
+                    text: text(t),
+                    image_base64: image_base64.clone(),
+                    media_type: media_type.clone(),
+                }
+            } else {
+                MessageContent::Text(format!("{}\n{}", text(t), omitted(1)))
+            }
+        }
+        MessageContent::MultiImageWithText { text: t, images } => {
+            let kept: Vec<ImageData> = images.iter().filter(|i| fits(&i.image_base64)).cloned().collect();
+            let dropped = images.len() - kept.len();
+            let t = if dropped > 0 { format!("{}\n{}", text(t), omitted(dropped)) } else { text(t) };
+            if kept.is_empty() {
+                MessageContent::Text(t)
+            } else {
+                MessageContent::MultiImageWithText { text: t, images: kept }
+            }
+        }
+        MessageContent::ToolResult { tool_call_id, name, content } => MessageContent::ToolResult {
+            tool_call_id: tool_call_id.clone(),
+            name: name.clone(),
+            content: tool(content),
+        },
+        MessageContent::ToolResultWithImage { tool_call_id, name, text: t, image_base64, media_type } => {
+            if fits(image_base64) {
+                MessageContent::ToolResultWithImage {
+                    tool_call_id: tool_call_id.clone(),
+                    name: name.clone(),
+                    text: tool(t),
+                    image_base64: image_base64.clone(),
+                    media_type: media_type.clone(),
+                }
+            } else {
+                MessageContent::ToolResult {
+                    tool_call_id: tool_call_id.clone(),
+                    name: name.clone(),
+                    content: format!("{}\n{}", tool(t), omitted(1)),
+                }
+            }
+        }
+        MessageContent::AssistantWithToolCalls { text: t, tool_calls, reasoning_content } => {
+            MessageContent::AssistantWithToolCalls {
+                text: t.as_deref().map(text),
+                tool_calls: tool_calls.iter().map(|tc| cap_arguments(tc, limits.max_tool_chars)).collect(),
+                reasoning_content: reasoning_content.as_deref().map(text),
+            }
+        }
+    }
+}
+
+/// Arguments must stay valid JSON for providers that parse them, so long
+/// ones are replaced by an object holding their start.
+fn cap_arguments(call: &ToolCall, max_chars: usize) -> ToolCall {
+    let mut call = call.clone();
+    if call.function.arguments.chars().count() > max_chars {
+        let start = truncate(&call.function.arguments, max_chars);
+        call.function.arguments = serde_json::json!({ "truncated_arguments": start }).to_string();
+    }
+    call
+}
+
+fn truncate(s: &str, max_chars: usize) -> String {
+    match s.char_indices().nth(max_chars) {
+        Some((cut, _)) => format!("{}… [{} more characters not kept]", &s[..cut], s[cut..].chars().count()),
+        None => s.to_string(),
+    }
+}
+
+fn omitted(images: usize) -> String {
+    if images == 1 {
+        "[image not kept in history]".to_string()
+    } else {
+        format!("[{images} images not kept in history]")
+    }
+}
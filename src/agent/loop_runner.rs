@@ -24,6 +24,9 @@ pub struct AgentResult {
     pub turns: usize,
     /// Accumulated usage across all turns.
     pub usage: Usage,
+    /// Assistant tool calls and their results, in order, for the session
+    /// history. The final reply is `response`.
+    pub tool_messages: Vec<Message>,
 }
 
 pub struct AgentLoop;
@@ -40,10 +43,11 @@ impl AgentLoop {
         db: &Database,
         max_turns: usize,
         history: Vec<Message>,
-        embedding_client: Option<&crate::tools::EmbeddingClient>,
+        embedder: &dyn crate::tools::Embedder,
+        file_cipher: Option<&crate::tools::file_crypto::FileCipher>,
         model: &str,
         on_progress: F,
-        allowed_users: &[u64],
+        approvals: super::approval::ApprovalPolicy<'_>,
     ) -> Result<AgentResult, String>
     where
         F: Fn(AgentProgress),
@@ -63,6 +67,7 @@ impl AgentLoop {
             role: Role::User,
             content: user_content,
         });
+        let first_new = messages.len();
 
         for turn in 0..max_turns {
             debug!("Agent turn {}/{}", turn + 1, max_turns);
@@ -97,6 +102,7 @@ impl AgentLoop {
                     provider: last_provider,
                     turns: turn + 1,
                     usage: total_usage,
+                    tool_messages: messages.split_off(first_new),
                 });
             }
 
@@ -125,8 +131,9 @@ impl AgentLoop {
                     kb_owner_id,
                     db,
                     pool,
-                    embedding_client,
-                    allowed_users,
+                    embedder,
+                    file_cipher,
+                    approvals,
                 )
                 .await;
 
@@ -170,6 +177,7 @@ impl AgentLoop {
             provider: last_provider,
             turns: max_turns,
             usage: total_usage,
+            tool_messages: messages.split_off(first_new),
         })
     }
 }
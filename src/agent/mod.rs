@@ -1,5 +1,8 @@
+pub mod approval;
+pub mod history;
 mod loop_runner;
 mod tool_registry;
 
+pub use history::HistoryLimits;
 pub use loop_runner::{AgentLoop, AgentProgress};
 pub use tool_registry::{ToolRegistry, ToolOutput};
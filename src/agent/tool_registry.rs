@@ -2,6 +2,10 @@ use serde_json::json;
 
 use crate::provider::{ToolDef, FunctionDef, ProviderPool};
 use crate::tools;
+use crate::tools::file_crypto::FileCipher;
+use crate::db::Repository;
+use crate::db::audit::{self, NewAuditEntry};
+use super::approval::ApprovalPolicy;
 
 /// Output from a tool execution — either plain text or text + image.
 pub enum ToolOutput {
@@ -55,7 +59,7 @@ impl ToolRegistry {
                 }),
             ),
             tool_def("memory_delete",
-                "Delete a fact from long-term memory by its ID.",
+                "Move a fact from long-term memory to the trash by its ID. It can be restored with trash_restore.",
                 json!({
                     "type": "object",
                     "properties": {
@@ -75,6 +79,26 @@ impl ToolRegistry {
                     "required": ["id", "new_fact"]
                 }),
             ),
+            tool_def("memory_history",
+                "Show the revision history of a fact: edits, and facts it replaced or was replaced by (with who changed it and which tool). Returns revision IDs for memory_revert.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "id": { "type": "integer", "description": "The fact ID" }
+                    },
+                    "required": ["id"]
+                }),
+            ),
+            tool_def("memory_revert",
+                "Undo a fact revision from memory_history: restores the old text, or brings back a replaced fact (moving its replacement to the trash).",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "revision_id": { "type": "integer", "description": "Revision ID from memory_history" }
+                    },
+                    "required": ["revision_id"]
+                }),
+            ),
             // --- Categories ---
             tool_def("category_list",
                 "List all available memory categories for this user.",
@@ -139,7 +163,7 @@ impl ToolRegistry {
                 }),
             ),
             tool_def("knowledge_patch",
-                "Patch a knowledge document by replacing specific text. Only affected chunks are re-indexed. Use this instead of delete+save when updating existing docs.",
+                "Patch a knowledge document by replacing specific text. Only affected chunks are re-indexed. Each patch is stored as a new version. Use this instead of delete+save when updating existing docs.",
                 json!({
                     "type": "object",
                     "properties": {
@@ -150,8 +174,41 @@ impl ToolRegistry {
                     "required": ["doc_id", "old_text", "new_text"]
                 }),
             ),
+            tool_def("knowledge_versions",
+                "List stored versions of a knowledge document (one per save, patch, or rollback) with who made them and lines changed.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "doc_id": { "type": "integer", "description": "Document ID" }
+                    },
+                    "required": ["doc_id"]
+                }),
+            ),
+            tool_def("knowledge_diff",
+                "Show a unified diff between two versions of a knowledge document. Use knowledge_versions to find version numbers.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "doc_id": { "type": "integer", "description": "Document ID" },
+                        "from_version": { "type": "integer", "description": "Older version number" },
+                        "to_version": { "type": "integer", "description": "Newer version number (default: latest)" }
+                    },
+                    "required": ["doc_id", "from_version"]
+                }),
+            ),
+            tool_def("knowledge_rollback",
+                "Restore a knowledge document to an earlier version. The restored content is saved as a new version and re-chunked and re-embedded.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "doc_id": { "type": "integer", "description": "Document ID" },
+                        "version": { "type": "integer", "description": "Version number to restore" }
+                    },
+                    "required": ["doc_id", "version"]
+                }),
+            ),
             tool_def("knowledge_delete",
-                "Delete a knowledge document and all its chunks by ID. Use knowledge_list to find the ID first.",
+                "Move a knowledge document (with its chunks) to the trash by ID. It can be restored with trash_restore. Use knowledge_list to find the ID first.",
                 json!({
                     "type": "object",
                     "properties": {
@@ -170,6 +227,45 @@ impl ToolRegistry {
                     "required": ["query"]
                 }),
             ),
+            // --- Trash ---
+            tool_def("trash_list",
+                "List deleted facts and documents still in the trash. Items are purged automatically after the retention period.",
+                json!({ "type": "object", "properties": {} }),
+            ),
+            tool_def("trash_restore",
+                "Restore a deleted fact or document from the trash. Use trash_list to find the type and ID.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "type": { "type": "string", "enum": ["fact", "document"], "description": "Kind of item to restore" },
+                        "id": { "type": "integer", "description": "Fact or document ID" }
+                    },
+                    "required": ["type", "id"]
+                }),
+            ),
+            tool_def("trash_purge",
+                "Permanently delete one item from the trash, or empty the whole trash if no type/id is given. This cannot be undone.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "type": { "type": "string", "enum": ["fact", "document"], "description": "Kind of item to purge (optional)" },
+                        "id": { "type": "integer", "description": "Fact or document ID (optional)" }
+                    }
+                }),
+            ),
+            // --- Audit ---
+            tool_def("audit_search",
+                "Search the audit log of knowledge base writes: who ran which write tool, with what arguments, and which facts, documents and categories it changed. Newest first.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "query": { "type": "string", "description": "Text to find in the arguments, results or changed rows (optional)" },
+                        "tool": { "type": "string", "description": "Only entries of this tool, e.g. memory_edit or undo (optional)" },
+                        "user_id": { "type": "integer", "description": "Only writes by this Telegram user ID (optional)" },
+                        "limit": { "type": "integer", "description": "Max entries (default 10, max 50)" }
+                    }
+                }),
+            ),
             // --- Pending Approval ---
             tool_def("pending_list",
                 "List all pending write requests waiting for approval. Shows request ID, requester, tool, and summary.",
@@ -284,14 +380,15 @@ impl ToolRegistry {
 
     /// Tools that modify memory/knowledge — restricted to whitelisted users in group chats.
     const WRITE_TOOLS: &'static [&'static str] = &[
-        "memory_save", "memory_edit", "memory_delete",
+        "memory_save", "memory_edit", "memory_delete", "memory_revert",
         "category_add", "category_delete",
-        "knowledge_save", "knowledge_patch", "knowledge_delete",
+        "knowledge_save", "knowledge_patch", "knowledge_rollback", "knowledge_delete",
+        "trash_restore", "trash_purge",
     ];
 
     /// Execute a tool by name with given arguments.
     /// `kb_owner_id` is the knowledge base owner (user_id in private, chat_id in groups).
-    /// `allowed_users` is used to restrict write tools in group chats.
+    /// `approvals.allowed_users` restricts write tools in group chats.
     pub async fn execute(
         tool_name: &str,
         args_json: &str,
@@ -299,9 +396,11 @@ impl ToolRegistry {
         kb_owner_id: u64,
         db: &crate::db::Database,
         pool: &ProviderPool,
-        embedding_client: Option<&crate::tools::EmbeddingClient>,
-        allowed_users: &[u64],
+        embedder: &dyn crate::tools::Embedder,
+        file_cipher: Option<&FileCipher>,
+        approvals: ApprovalPolicy<'_>,
     ) -> ToolOutput {
+        let allowed_users = approvals.allowed_users;
         // Non-whitelisted users: save write requests to pending queue for approval
         if Self::WRITE_TOOLS.contains(&tool_name)
             && !allowed_users.is_empty()
@@ -309,7 +408,7 @@ impl ToolRegistry {
         {
             let args_parsed: serde_json::Value = serde_json::from_str(args_json).unwrap_or_default();
             let summary = Self::build_pending_summary(tool_name, &args_parsed);
-            match db.save_pending(kb_owner_id, user_id, tool_name, args_json, &summary) {
+            match db.save_pending(kb_owner_id, user_id, tool_name, args_json, &summary).await {
                 Ok(id) => return ToolOutput::Text(
                     format!("BLOCKED: Bạn không có quyền thực hiện thao tác này. Yêu cầu #{id} đã được gửi vào hàng chờ duyệt. Admin cần dùng /approve {id} để phê duyệt hoặc /reject {id} để từ chối. KHÔNG được nói rằng đã lưu thành công — hãy thông báo cho user biết yêu cầu đang CHỜ DUYỆT.")
                 ),
@@ -318,268 +417,278 @@ impl ToolRegistry {
         }
 
         let args: serde_json::Value = serde_json::from_str(args_json).unwrap_or_default();
+        if tool_name == "image_read" {
+            let path = args["path"].as_str().unwrap_or("");
+            return Self::read_image(path, file_cipher).await;
+        }
 
-        let text_result = match tool_name {
-            "memory_save" => {
-                let fact = args["fact"].as_str().unwrap_or("");
-                let category = args["category"].as_str().unwrap_or("general");
-                tools::memory_save(db, kb_owner_id, fact, category, embedding_client).await
-            }
-            "memory_search" => {
-                let keyword = args["keyword"].as_str().unwrap_or("");
-                tools::memory_search(db, kb_owner_id, keyword, embedding_client).await
-            }
-            "memory_list" => {
-                let category = args["category"].as_str();
-                tools::memory_list(db, kb_owner_id, category).await
-            }
-            "memory_edit" => {
-                let id = args["id"].as_i64().unwrap_or(0);
-                let new_fact = args["new_fact"].as_str().unwrap_or("");
-                if new_fact.is_empty() {
-                    "Error: new_fact cannot be empty".into()
-                } else {
-                    match db.update_fact(kb_owner_id, id, new_fact) {
-                        Ok(true) => {
-                            // Re-embed and recompute relations
-                            if let Some(client) = embedding_client {
-                                if let Ok(embeddings) =
-                                    client.embed_batch(&[new_fact], "document").await
-                                {
-                                    if let Some(emb) = embeddings.first() {
-                                        let blob =
-                                            crate::tools::embedding::embedding_to_bytes(emb);
-                                        let _ = db.update_fact_embedding(id, &blob);
-                                        // Delete old relations and recompute
-                                        let _ = db.delete_fact_relations(id);
-                                        if let Ok(all_facts) = db.load_all_fact_embeddings(kb_owner_id) {
-                                            let mut similarities: Vec<(i64, f32)> = all_facts
-                                                .iter()
-                                                .filter(|(fid, _, _, _)| *fid != id)
-                                                .map(|(fid, _, _, emb_blob)| {
-                                                    let other = crate::tools::embedding::bytes_to_embedding(emb_blob);
-                                                    let sim = crate::tools::embedding::cosine_similarity(emb, &other);
-                                                    (*fid, sim)
-                                                })
-                                                .filter(|(_, sim)| *sim > 0.75)
-                                                .collect();
-                                            similarities.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-                                            for (related_id, sim) in similarities.iter().take(3) {
-                                                let _ = db.link_facts(id, *related_id, *sim);
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            format!("Updated memory #{id}: \"{new_fact}\"")
-                        }
-                        Ok(false) => format!("Memory #{id} not found."),
-                        Err(e) => format!("Error: {e}"),
-                    }
+        let run = async {
+            match tool_name {
+                "memory_save" => {
+                    let fact = args["fact"].as_str().unwrap_or("");
+                    let category = args["category"].as_str().unwrap_or("general");
+                    tools::memory_save(db, kb_owner_id, user_id, fact, category, embedder).await
                 }
-            }
-            "memory_delete" => {
-                let id = args["id"].as_i64().unwrap_or(0);
-                match db.delete_fact(kb_owner_id, id) {
-                    Ok(true) => format!("Deleted memory #{id}."),
-                    Ok(false) => format!("Memory #{id} not found."),
-                    Err(e) => format!("Error: {e}"),
+                "memory_search" => {
+                    let keyword = args["keyword"].as_str().unwrap_or("");
+                    tools::memory_search(db, kb_owner_id, keyword, embedder).await
                 }
-            }
-            "category_list" => {
-                let _ = db.ensure_default_categories(kb_owner_id);
-                match db.list_categories(kb_owner_id) {
-                    Ok(cats) if cats.is_empty() => "No categories found.".into(),
-                    Ok(cats) => cats.join(", "),
-                    Err(e) => format!("Error: {e}"),
+                "memory_list" => {
+                    let category = args["category"].as_str();
+                    tools::memory_list(db, kb_owner_id, category).await
                 }
-            }
-            "category_add" => {
-                let name = args["name"].as_str().unwrap_or("");
-                if name.is_empty() {
-                    "Error: name cannot be empty".into()
-                } else {
-                    let _ = db.ensure_default_categories(kb_owner_id);
-                    match db.add_category(kb_owner_id, name) {
-                        Ok(()) => format!("Category '{name}' created."),
+                "memory_edit" => {
+                    let id = args["id"].as_i64().unwrap_or(0);
+                    let new_fact = args["new_fact"].as_str().unwrap_or("");
+                    tools::memory_edit(db, kb_owner_id, user_id, id, new_fact, embedder).await
+                }
+                "memory_history" => {
+                    let id = args["id"].as_i64().unwrap_or(0);
+                    tools::memory_history(db, kb_owner_id, id).await
+                }
+                "memory_revert" => {
+                    let revision_id = args["revision_id"].as_i64().unwrap_or(0);
+                    tools::memory_revert(db, kb_owner_id, user_id, revision_id, embedder).await
+                }
+                "memory_delete" => {
+                    let id = args["id"].as_i64().unwrap_or(0);
+                    match db.delete_fact(kb_owner_id, id).await {
+                        Ok(true) => format!("Moved memory #{id} to trash."),
+                        Ok(false) => format!("Memory #{id} not found."),
                         Err(e) => format!("Error: {e}"),
                     }
                 }
-            }
-            "category_delete" => {
-                let name = args["name"].as_str().unwrap_or("");
-                if name.is_empty() {
-                    "Error: name cannot be empty".into()
-                } else if name == "preference" {
-                    "Error: category 'preference' is protected and cannot be deleted.".into()
-                } else {
-                    match db.delete_category(kb_owner_id, name) {
-                        Ok(true) => format!("Category '{name}' deleted."),
-                        Ok(false) => format!("Category '{name}' not found."),
+                "category_list" => {
+                    let _ = db.ensure_default_categories(kb_owner_id).await;
+                    match db.list_categories(kb_owner_id).await {
+                        Ok(cats) if cats.is_empty() => "No categories found.".into(),
+                        Ok(cats) => cats.join(", "),
                         Err(e) => format!("Error: {e}"),
                     }
                 }
-            }
-            "knowledge_save" => {
-                let title = args["title"].as_str().unwrap_or("");
-                let content = args["content"].as_str().unwrap_or("");
-                let source = args["source"].as_str();
-                let tags = args["tags"].as_str();
+                "category_add" => {
+                    let name = args["name"].as_str().unwrap_or("");
+                    if name.is_empty() {
+                        "Error: name cannot be empty".into()
+                    } else {
+                        let _ = db.ensure_default_categories(kb_owner_id).await;
+                        match db.add_category(kb_owner_id, name).await {
+                            Ok(()) => format!("Category '{name}' created."),
+                            Err(e) => format!("Error: {e}"),
+                        }
+                    }
+                }
+                "category_delete" => {
+                    let name = args["name"].as_str().unwrap_or("");
+                    if name.is_empty() {
+                        "Error: name cannot be empty".into()
+                    } else if name == "preference" {
+                        "Error: category 'preference' is protected and cannot be deleted.".into()
+                    } else {
+                        match db.delete_category(kb_owner_id, name).await {
+                            Ok(true) => format!("Category '{name}' deleted."),
+                            Ok(false) => format!("Category '{name}' not found."),
+                            Err(e) => format!("Error: {e}"),
+                        }
+                    }
+                }
+                "knowledge_save" => {
+                    let title = args["title"].as_str().unwrap_or("");
+                    let content = args["content"].as_str().unwrap_or("");
+                    let source = args["source"].as_str();
+                    let tags = args["tags"].as_str();
 
-                match tools::knowledge_save(db, kb_owner_id, title, content, source, tags, embedding_client).await {
-                    Ok((doc_id, msg)) => {
-                        // Auto-extract entities in background
-                        let text = format!("{title}\n\n{content}");
-                        let entity_count = tools::extract_and_link_entities(
-                            pool, db, kb_owner_id, "document", doc_id, &text,
-                        ).await;
-                        if entity_count > 0 {
-                            format!("{msg}\nExtracted {entity_count} entities.")
-                        } else {
-                            msg
+                    match tools::knowledge_save(db, kb_owner_id, title, content, source, tags, embedder).await {
+                        Ok((doc_id, msg)) => {
+                            // Auto-extract entities in background
+                            let text = format!("{title}\n\n{content}");
+                            let entity_count = tools::extract_and_link_entities(
+                                pool, db, kb_owner_id, "document", doc_id, &text,
+                            ).await;
+                            if entity_count > 0 {
+                                format!("{msg}\nExtracted {entity_count} entities.")
+                            } else {
+                                msg
+                            }
                         }
+                        Err(e) => format!("Error: {e}"),
                     }
-                    Err(e) => format!("Error: {e}"),
                 }
-            }
-            "knowledge_search" => {
-                let query = args["query"].as_str().unwrap_or("");
-                tools::knowledge_search(db, kb_owner_id, query, embedding_client).await
-            }
-            "knowledge_list" => {
-                tools::knowledge_list(db, kb_owner_id).await
-            }
-            "knowledge_get" => {
-                let doc_id = args["doc_id"].as_i64().unwrap_or(0);
-                match db.get_document(kb_owner_id, doc_id) {
-                    Ok((title, content, source, tags)) => {
-                        let src = source.as_deref().unwrap_or("none");
-                        let tgs = tags.as_deref().unwrap_or("none");
-                        let mut out = format!("# {title}\nSource: {src}\nTags: {tgs}\n\n{content}");
-                        // Append linked memory facts
-                        if let Ok(linked) = db.get_doc_linked_facts(doc_id) {
-                            if !linked.is_empty() {
-                                out.push_str("\n\nLinked memories:");
-                                for (fid, fact, _cat) in &linked {
-                                    out.push_str(&format!("\n- [{fid}] {fact}"));
+                "knowledge_search" => {
+                    let query = args["query"].as_str().unwrap_or("");
+                    tools::knowledge_search(db, kb_owner_id, query, embedder).await
+                }
+                "knowledge_list" => {
+                    tools::knowledge_list(db, kb_owner_id).await
+                }
+                "knowledge_get" => {
+                    let doc_id = args["doc_id"].as_i64().unwrap_or(0);
+                    match db.get_document(kb_owner_id, doc_id).await {
+                        Ok(doc) => {
+                            let src = doc.source.as_deref().unwrap_or("none");
+                            let tgs = doc.tags.as_deref().unwrap_or("none");
+                            let mut out = format!("# {}\nSource: {src}\nTags: {tgs}\n\n{}", doc.title, doc.content);
+                            // Append linked memory facts
+                            if let Ok(linked) = db.get_doc_linked_facts(doc_id).await {
+                                if !linked.is_empty() {
+                                    out.push_str("\n\nLinked memories:");
+                                    for fact in &linked {
+                                        out.push_str(&format!("\n- [{}] {}", fact.id, fact.text));
+                                    }
                                 }
                             }
+                            out
                         }
-                        out
+                        Err(_) => format!("Document #{doc_id} not found."),
                     }
-                    Err(_) => format!("Document #{doc_id} not found."),
                 }
-            }
-            "knowledge_patch" => {
-                let doc_id = args["doc_id"].as_i64().unwrap_or(0);
-                let old_text = args["old_text"].as_str().unwrap_or("");
-                let new_text = args["new_text"].as_str().unwrap_or("");
-                if old_text.is_empty() {
-                    "Error: old_text cannot be empty".into()
-                } else {
-                    tools::knowledge_patch(db, kb_owner_id, doc_id, old_text, new_text, embedding_client).await
+                "knowledge_patch" => {
+                    let doc_id = args["doc_id"].as_i64().unwrap_or(0);
+                    let old_text = args["old_text"].as_str().unwrap_or("");
+                    let new_text = args["new_text"].as_str().unwrap_or("");
+                    if old_text.is_empty() {
+                        "Error: old_text cannot be empty".into()
+                    } else {
+                        tools::knowledge_patch(db, kb_owner_id, user_id, doc_id, old_text, new_text, embedder).await
+                    }
                 }
-            }
-            "knowledge_delete" => {
-                let doc_id = args["doc_id"].as_i64().unwrap_or(0);
-                match db.delete_document(kb_owner_id, doc_id) {
-                    Ok(true) => format!("Deleted document #{doc_id} and all its chunks."),
-                    Ok(false) => format!("Document #{doc_id} not found."),
-                    Err(e) => format!("Error: {e}"),
+                "knowledge_versions" => {
+                    let doc_id = args["doc_id"].as_i64().unwrap_or(0);
+                    tools::knowledge_versions(db, kb_owner_id, doc_id).await
                 }
-            }
-            "entity_search" => {
-                let query = args["query"].as_str().unwrap_or("");
-                tools::entity_search(db, kb_owner_id, query).await
-            }
-            "pending_list" => {
-                match db.list_pending(kb_owner_id) {
-                    Ok(items) if items.is_empty() => "No pending requests.".into(),
-                    Ok(items) => {
-                        let mut out = format!("{} pending request(s):\n", items.len());
-                        for (id, requested_by, _tool, summary, created_at) in &items {
-                            out.push_str(&format!("#{id} | user {requested_by} | {summary} | {created_at}\n"));
-                        }
-                        out
+                "knowledge_diff" => {
+                    let doc_id = args["doc_id"].as_i64().unwrap_or(0);
+                    let from_version = args["from_version"].as_i64().unwrap_or(1);
+                    let to_version = args["to_version"].as_i64();
+                    tools::knowledge_diff(db, kb_owner_id, doc_id, from_version, to_version).await
+                }
+                "knowledge_rollback" => {
+                    let doc_id = args["doc_id"].as_i64().unwrap_or(0);
+                    let version = args["version"].as_i64().unwrap_or(0);
+                    tools::knowledge_rollback(db, kb_owner_id, user_id, doc_id, version, embedder).await
+                }
+                "knowledge_delete" => {
+                    let doc_id = args["doc_id"].as_i64().unwrap_or(0);
+                    match db.delete_document(kb_owner_id, doc_id).await {
+                        Ok(true) => format!("Moved document #{doc_id} and its chunks to trash."),
+                        Ok(false) => format!("Document #{doc_id} not found."),
+                        Err(e) => format!("Error: {e}"),
                     }
-                    Err(e) => format!("Error: {e}"),
                 }
-            }
-            "pending_approve" => {
-                if !allowed_users.is_empty() && !allowed_users.contains(&user_id) {
-                    "Permission denied: only whitelisted users can approve requests.".into()
-                } else {
+                "entity_search" => {
+                    let query = args["query"].as_str().unwrap_or("");
+                    tools::entity_search(db, kb_owner_id, query).await
+                }
+                "trash_list" => tools::trash_list(db, kb_owner_id).await,
+                "trash_restore" => {
+                    let kind = args["type"].as_str().unwrap_or("");
                     let id = args["id"].as_i64().unwrap_or(0);
-                    match db.get_pending(id) {
-                        Ok((scope_id, _requested_by, tool_name, args_json, summary)) => {
-                            // Execute the original tool (recursive call with whitelisted user)
-                            let result = Box::pin(Self::execute(
-                                &tool_name, &args_json, user_id, scope_id, db, pool, embedding_client, allowed_users,
-                            )).await;
-                            let result_text = match result {
-                                ToolOutput::Text(t) => t,
-                                ToolOutput::Image { text, .. } => text,
-                            };
-                            let _ = db.delete_pending(id);
-                            format!("Approved #{id}: {summary}\n{result_text}")
+                    tools::trash_restore(db, kb_owner_id, kind, id).await
+                }
+                "trash_purge" => {
+                    let kind = args["type"].as_str();
+                    let id = args["id"].as_i64();
+                    tools::trash_purge(db, kb_owner_id, kind, id).await
+                }
+                "audit_search" => {
+                    let query = args["query"].as_str();
+                    let tool = args["tool"].as_str();
+                    let author = args["user_id"].as_u64();
+                    let limit = args["limit"].as_u64().map(|v| v as usize);
+                    tools::audit_search(db, kb_owner_id, query, tool, author, limit).await
+                }
+                "pending_list" => {
+                    match db.list_pending(kb_owner_id).await {
+                        Ok(items) if items.is_empty() => "No pending requests.".into(),
+                        Ok(items) => {
+                            let mut out = format!("{} pending request(s):\n", items.len());
+                            for item in &items {
+                                out.push_str(&format!(
+                                    "#{} | user {} | {} | {}\n",
+                                    item.id, item.requested_by, item.summary, item.created_at
+                                ));
+                            }
+                            out
                         }
-                        Err(_) => format!("Pending #{id} not found."),
+                        Err(e) => format!("Error: {e}"),
                     }
                 }
-            }
-            "pending_reject" => {
-                if !allowed_users.is_empty() && !allowed_users.contains(&user_id) {
-                    "Permission denied: only whitelisted users can reject requests.".into()
-                } else {
-                    let id = args["id"].as_i64().unwrap_or(0);
-                    match db.delete_pending(id) {
-                        Ok(true) => format!("Rejected and removed #{id}."),
-                        Ok(false) => format!("Pending #{id} not found."),
-                        Err(e) => format!("Error: {e}"),
+                "pending_approve" => {
+                    if !allowed_users.is_empty() && !allowed_users.contains(&user_id) {
+                        "Permission denied: only whitelisted users can approve requests.".into()
+                    } else {
+                        let id = args["id"].as_i64().unwrap_or(0);
+                        match super::approval::approve_pending(
+                            db, pool, embedder, approvals, kb_owner_id, id, user_id,
+                        )
+                        .await
+                        {
+                            Ok(Some((item, result_text))) => format!("Approved #{id}: {}\n{result_text}", item.summary),
+                            Ok(None) => format!("Pending #{id} not found."),
+                            Err(e) => format!("Error: {e}"),
+                        }
                     }
                 }
+                "pending_reject" => {
+                    if !allowed_users.is_empty() && !allowed_users.contains(&user_id) {
+                        "Permission denied: only whitelisted users can reject requests.".into()
+                    } else {
+                        let id = args["id"].as_i64().unwrap_or(0);
+                        match super::approval::reject_pending(db, approvals, kb_owner_id, id, user_id).await {
+                            Ok(Some(_)) => format!("Rejected and removed #{id}."),
+                            Ok(None) => format!("Pending #{id} not found."),
+                            Err(e) => format!("Error: {e}"),
+                        }
+                    }
+                }
+                "get_datetime" => tools::get_datetime().await,
+                "bash" => {
+                    let command = args["command"].as_str().unwrap_or("");
+                    let timeout = args["timeout"].as_u64().map(|t| t.min(120));
+                    tools::bash_exec(command, timeout).await
+                }
+                "file_read" => {
+                    let path = args["path"].as_str().unwrap_or("");
+                    let offset = args["offset"].as_u64().map(|v| v as usize);
+                    let limit = args["limit"].as_u64().map(|v| v as usize);
+                    tools::file_read(path, offset, limit, file_cipher).await
+                }
+                "file_write" => {
+                    let path = args["path"].as_str().unwrap_or("");
+                    let content = args["content"].as_str().unwrap_or("");
+                    tools::file_write(path, content).await
+                }
+                "file_list" => {
+                    let path = args["path"].as_str().unwrap_or("");
+                    let recursive = args["recursive"].as_bool().unwrap_or(false);
+                    tools::file_list(path, recursive).await
+                }
+                "grep" => {
+                    let pattern = args["pattern"].as_str().unwrap_or("");
+                    let path = args["path"].as_str();
+                    let include = args["include"].as_str();
+                    let context = args["context"].as_u64().map(|v| v as usize);
+                    tools::grep_search(pattern, path, include, context).await
+                }
+                "glob" => {
+                    let pattern = args["pattern"].as_str().unwrap_or("");
+                    let path = args["path"].as_str();
+                    tools::glob_search(pattern, path).await
+                }
+                _ => format!("Unknown tool: {tool_name}"),
             }
-            "get_datetime" => tools::get_datetime().await,
-            "bash" => {
-                let command = args["command"].as_str().unwrap_or("");
-                let timeout = args["timeout"].as_u64().map(|t| t.min(120));
-                tools::bash_exec(command, timeout).await
-            }
-            "file_read" => {
-                let path = args["path"].as_str().unwrap_or("");
-                let offset = args["offset"].as_u64().map(|v| v as usize);
-                let limit = args["limit"].as_u64().map(|v| v as usize);
-                tools::file_read(path, offset, limit).await
-            }
-            "file_write" => {
-                let path = args["path"].as_str().unwrap_or("");
-                let content = args["content"].as_str().unwrap_or("");
-                tools::file_write(path, content).await
-            }
-            "file_list" => {
-                let path = args["path"].as_str().unwrap_or("");
-                let recursive = args["recursive"].as_bool().unwrap_or(false);
-                tools::file_list(path, recursive).await
-            }
-            "grep" => {
-                let pattern = args["pattern"].as_str().unwrap_or("");
-                let path = args["path"].as_str();
-                let include = args["include"].as_str();
-                let context = args["context"].as_u64().map(|v| v as usize);
-                tools::grep_search(pattern, path, include, context).await
-            }
-            "glob" => {
-                let pattern = args["pattern"].as_str().unwrap_or("");
-                let path = args["path"].as_str();
-                tools::glob_search(pattern, path).await
-            }
-            "image_read" => {
-                let path = args["path"].as_str().unwrap_or("");
-                return Self::read_image(path).await;
-            }
-            _ => format!("Unknown tool: {tool_name}"),
         };
 
+        if !Self::WRITE_TOOLS.contains(&tool_name) {
+            return ToolOutput::Text(run.await);
+        }
+        // Writes are recorded in audit_log with the rows this call changed
+        let (text_result, changes) = audit::track_changes(run).await;
+        let entry = NewAuditEntry { user_id, kb_owner_id, tool: tool_name, args_json, result: &text_result };
+        if let Err(e) = db.log_audit(entry, &changes).await {
+            tracing::warn!("Failed to record {tool_name} in the audit log: {e}");
+        }
+
         ToolOutput::Text(text_result)
     }
 
@@ -602,6 +711,10 @@ impl ToolRegistry {
                 let id = args["id"].as_i64().unwrap_or(0);
                 format!("[memory_delete] #{id}")
             }
+            "memory_revert" => {
+                let revision_id = args["revision_id"].as_i64().unwrap_or(0);
+                format!("[memory_revert] r{revision_id}")
+            }
             "knowledge_save" => {
                 let title = args["title"].as_str().unwrap_or("");
                 format!("[knowledge_save] \"{title}\"")
@@ -610,10 +723,24 @@ impl ToolRegistry {
                 let doc_id = args["doc_id"].as_i64().unwrap_or(0);
                 format!("[knowledge_patch] doc #{doc_id}")
             }
+            "knowledge_rollback" => {
+                let doc_id = args["doc_id"].as_i64().unwrap_or(0);
+                let version = args["version"].as_i64().unwrap_or(0);
+                format!("[knowledge_rollback] doc #{doc_id} → v{version}")
+            }
             "knowledge_delete" => {
                 let doc_id = args["doc_id"].as_i64().unwrap_or(0);
                 format!("[knowledge_delete] doc #{doc_id}")
             }
+            "trash_restore" => {
+                let kind = args["type"].as_str().unwrap_or("");
+                let id = args["id"].as_i64().unwrap_or(0);
+                format!("[trash_restore] {kind} #{id}")
+            }
+            "trash_purge" => match (args["type"].as_str(), args["id"].as_i64()) {
+                (Some(kind), Some(id)) => format!("[trash_purge] {kind} #{id}"),
+                _ => "[trash_purge] empty trash".into(),
+            },
             "category_add" => {
                 let name = args["name"].as_str().unwrap_or("");
                 format!("[category_add] \"{name}\"")
@@ -627,7 +754,7 @@ impl ToolRegistry {
     }
 
     /// Read an image file from disk, return as ToolOutput::Image for vision analysis.
-    async fn read_image(path: &str) -> ToolOutput {
+    async fn read_image(path: &str, cipher: Option<&FileCipher>) -> ToolOutput {
         let p = if path.starts_with('~') {
             let home = std::env::var("HOME").unwrap_or_default();
             std::path::PathBuf::from(home).join(&path[2..])
@@ -651,7 +778,7 @@ impl ToolRegistry {
             _ => {}
         }
 
-        let bytes = match tokio::fs::read(&p).await {
+        let bytes = match crate::tools::uploads::read_file(&p, cipher).await {
             Ok(b) => b,
             Err(e) => return ToolOutput::Text(format!("Error reading file: {e}")),
         };
@@ -0,0 +1,256 @@
+//! Maintenance subcommands that run instead of the bot:
+//!
+//! ```text
+//! memory-assistant export <owner_id> [file.zip] [--embeddings | --obsidian]
+//! memory-assistant import <file.zip> <owner_id>
+//! memory-assistant rotate-key [--decrypt]
+//! memory-assistant doctor [--fix]
+//! memory-assistant split-owners
+//! ```
+//!
+//! With `DB_PER_OWNER` on, each command works on the owner files under
+//! `DB_DATA_DIR` as well as the main database.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::config;
+use crate::db::owners::{self, OwnerDatabases, OwnerFileOptions};
+use crate::db::{self, Database, encryption, export::ExportBundle};
+use crate::tools::file_crypto::{self, FileCipher};
+use crate::tools::uploads::documents_dir;
+
+const USAGE: &str = "Usage:\n  \
+    memory-assistant export <owner_id> [file.zip] [--embeddings | --obsidian]\n  \
+    memory-assistant import <file.zip> <owner_id>\n  \
+    memory-assistant rotate-key [--decrypt]   (new key from NEW_ENCRYPTION_KEY or stdin)\n  \
+    memory-assistant doctor [--fix]\n  \
+    memory-assistant split-owners   (copy each owner's data into its own file, DB_PER_OWNER)";
+
+/// Run a subcommand if `args` (without the program name) names one.
+/// Returns `None` when the bot should start normally.
+pub async fn run(args: &[String]) -> Option<Result<(), String>> {
+    let (command, rest) = args.split_first()?;
+    let result = match command.as_str() {
+        "export" => export(rest).await,
+        "import" => import(rest).await,
+        "rotate-key" => rotate_key(rest),
+        "doctor" => doctor(rest).await,
+        "split-owners" => split_owners().await,
+        "help" | "--help" | "-h" => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn open_db() -> Result<Database, String> {
+    let key = config::encryption_key_from_env();
+    Database::open_encrypted(db::DEFAULT_DB_PATH, db::DEFAULT_READ_CONNECTIONS, key.as_deref())
+        .map_err(|e| format!("Failed to open {}: {e}", db::DEFAULT_DB_PATH))
+}
+
+/// The main database plus, with `DB_PER_OWNER`, the owner files.
+fn open_dbs() -> Result<OwnerDatabases, String> {
+    let main = open_db()?;
+    let Some(dir) = config::per_owner_dir_from_env() else {
+        return Ok(OwnerDatabases::shared(main));
+    };
+    let options = OwnerFileOptions {
+        dir: dir.clone().into(),
+        read_connections: 1,
+        key: config::encryption_key_from_env(),
+        fts_trigram: config::fts_trigram_from_env(),
+        quantization: config::quantization_from_env(),
+        idle: Duration::ZERO,
+    };
+    OwnerDatabases::per_owner(main, options).map_err(|e| format!("{dir}: {e}"))
+}
+
+async fn export(args: &[String]) -> Result<(), String> {
+    let with_embeddings = args.iter().any(|a| a == "--embeddings");
+    let obsidian = args.iter().any(|a| a == "--obsidian");
+    let positional: Vec<&String> = args.iter().filter(|a| !a.starts_with("--")).collect();
+    let owner_id: u64 = positional
+        .first()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| USAGE.to_string())?;
+    let path = positional
+        .get(1)
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| if obsidian { vault_file_name(owner_id) } else { export_file_name(owner_id) });
+
+    let db = open_dbs()?.owner(owner_id).await.map_err(|e| e.to_string())?;
+    let bundle = db.export_owner(owner_id, with_embeddings && !obsidian).await.map_err(|e| e.to_string())?;
+    let file = File::create(&path).map_err(|e| format!("{path}: {e}"))?;
+    if obsidian {
+        bundle.write_obsidian_zip(file).map_err(|e| format!("{path}: {e}"))?;
+    } else {
+        bundle.write_zip(file).map_err(|e| format!("{path}: {e}"))?;
+    }
+
+    println!(
+        "Exported owner {owner_id} to {path}: {} facts, {} documents, {} chunks, {} entities",
+        bundle.facts.len(),
+        bundle.documents.len(),
+        bundle.chunks.len(),
+        bundle.entities.len()
+    );
+    Ok(())
+}
+
+async fn import(args: &[String]) -> Result<(), String> {
+    let [path, owner_id] = args else {
+        return Err(USAGE.to_string());
+    };
+    let owner_id: u64 = owner_id.parse().map_err(|_| USAGE.to_string())?;
+
+    let file = File::open(path).map_err(|e| format!("{path}: {e}"))?;
+    let bundle = ExportBundle::read_zip(BufReader::new(file)).map_err(|e| format!("{path}: {e}"))?;
+    let source_owner = bundle.manifest.owner_id;
+
+    let db = open_dbs()?.owner(owner_id).await.map_err(|e| e.to_string())?;
+    let stats = db.import_owner(owner_id, bundle).await.map_err(|e| e.to_string())?;
+    println!("Imported owner {source_owner} from {path} into {owner_id}: {stats}");
+    Ok(())
+}
+
+/// Re-encrypt the database and saved files from the current `ENCRYPTION_KEY`
+/// (or plaintext) to a new key (or plaintext with `--decrypt`). Run with the bot stopped.
+fn rotate_key(args: &[String]) -> Result<(), String> {
+    let old_key = config::encryption_key_from_env();
+    let new_key = if args.iter().any(|a| a == "--decrypt") {
+        None
+    } else {
+        Some(read_new_key()?)
+    };
+    if old_key == new_key {
+        return Err("The new key is the same as the current ENCRYPTION_KEY.".into());
+    }
+
+    let mut db_paths = vec![PathBuf::from(db::DEFAULT_DB_PATH)];
+    if let Some(dir) = config::per_owner_dir_from_env() {
+        let files = owners::list_owner_files(Path::new(&dir)).map_err(|e| format!("{dir}: {e}"))?;
+        db_paths.extend(files.into_iter().map(|(_, path)| path));
+    }
+    let mut previous = Vec::new();
+    for db_path in &db_paths {
+        let old = encryption::rekey_database(db_path, old_key.as_deref(), new_key.as_deref())
+            .map_err(|e| format!("{}: {e}", db_path.display()))?;
+        println!("Re-keyed {}", db_path.display());
+        previous.push(old);
+    }
+
+    let docs = documents_dir();
+    let cipher = |key: &str| FileCipher::for_dir(key, &docs).map_err(|e| format!("{}: {e}", docs.display()));
+    let old_cipher = old_key.as_deref().map(cipher).transpose()?;
+    let new_cipher = new_key.as_deref().map(cipher).transpose()?;
+    let stats = file_crypto::reencrypt_dir(&docs, old_cipher.as_ref(), new_cipher.as_ref())
+        .map_err(|e| format!("{}: {e}", docs.display()))?;
+    println!("Re-encrypted {} file(s) under {} ({} unchanged)", stats.rewritten, docs.display(), stats.unchanged);
+
+    println!(
+        "\nNext:\n  \
+         1. {} ENCRYPTION_KEY in .env\n  \
+         2. Start the bot and check it works\n  \
+         3. Delete {} (the database{} under the old key)\n\
+         Existing backups stay encrypted with the old key.",
+        if new_key.is_some() { "Set the new key as" } else { "Remove" },
+        previous.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", "),
+        if previous.len() > 1 { "s" } else { "" }
+    );
+    Ok(())
+}
+
+/// Check every database (and the entity graph of every owner), repairing with `--fix`.
+async fn doctor(args: &[String]) -> Result<(), String> {
+    let fix = args.iter().any(|a| a == "--fix");
+    let dbs = open_dbs()?;
+    let mut databases = vec![(db::DEFAULT_DB_PATH.to_string(), dbs.main().clone())];
+    for (id, db) in dbs.owner_files().await.map_err(|e| e.to_string())? {
+        databases.push((format!("owner {id}"), db));
+    }
+
+    let (mut problems, mut rechunk) = (false, false);
+    for (label, db) in &databases {
+        let (integrity, graph) = if fix {
+            (db.repair_integrity().await, db.repair_entity_graph(None).await)
+        } else {
+            (db.check_integrity().await, db.check_entity_graph(None).await)
+        };
+        let integrity = integrity.map_err(|e| format!("{label}: {e}"))?;
+        let graph = graph.map_err(|e| format!("{label}: {e}"))?;
+        let repaired = if fix { " (repaired)" } else { "" };
+        if !integrity.is_clean() {
+            println!("{label}, database{repaired}:\n{integrity}");
+        }
+        if !graph.is_clean() {
+            println!(
+                "{label}, entity graph{repaired}:\n- Mentions of deleted sources: {}\n- Mentions no longer in their source: {}\n- Entities with no mentions: {}",
+                graph.dangling_mentions,
+                graph.stale_mentions,
+                graph.orphan_entities
+            );
+        }
+        problems |= !integrity.is_clean() || !graph.is_clean();
+        rechunk |= !integrity.stale_chunk_docs.is_empty();
+    }
+
+    if !problems {
+        println!("No problems found in {} database(s).", databases.len());
+    } else if !fix {
+        println!("\nRun with --fix to repair.");
+    } else if rechunk {
+        println!("\nStale chunks were removed; their documents are re-chunked when the bot next starts.");
+    }
+    Ok(())
+}
+
+/// Copy each owner's data from the main database into its own file, for
+/// switching an existing install to `DB_PER_OWNER`.
+async fn split_owners() -> Result<(), String> {
+    let dbs = open_dbs()?;
+    if !dbs.is_per_owner() {
+        return Err("Set DB_PER_OWNER=true (and optionally DB_DATA_DIR) in .env first.".into());
+    }
+    let results = dbs.split_from_main().await.map_err(|e| e.to_string())?;
+    for (owner_id, stats) in &results {
+        let path = dbs.owner_path(*owner_id).unwrap_or_default();
+        println!("Owner {owner_id} -> {}: {stats}", path.display());
+    }
+    println!(
+        "\nCopied {} owner(s). {} itself is unchanged; the copied data can be deleted from it once the bot \
+         runs fine in per-owner mode.",
+        results.len(),
+        db::DEFAULT_DB_PATH
+    );
+    Ok(())
+}
+
+fn read_new_key() -> Result<String, String> {
+    if let Some(key) = std::env::var("NEW_ENCRYPTION_KEY").ok().filter(|k| !k.is_empty()) {
+        return Ok(key);
+    }
+    eprintln!("New encryption key (one line):");
+    let mut line = String::new();
+    std::io::stdin().lock().read_line(&mut line).map_err(|e| e.to_string())?;
+    let key = line.trim_end_matches(['\r', '\n']).to_string();
+    if key.is_empty() {
+        return Err("No new key given.".into());
+    }
+    Ok(key)
+}
+
+/// Default bundle name, e.g. `kb-123456789-20250101-120000.zip`.
+pub fn export_file_name(owner_id: u64) -> String {
+    format!("kb-{owner_id}-{}.zip", chrono::Utc::now().format("%Y%m%d-%H%M%S"))
+}
+
+/// Default Obsidian vault name, e.g. `vault-123456789-20250101-120000.zip`.
+pub fn vault_file_name(owner_id: u64) -> String {
+    format!("vault-{owner_id}-{}.zip", chrono::Utc::now().format("%Y%m%d-%H%M%S"))
+}
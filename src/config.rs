@@ -1,5 +1,13 @@
 use std::collections::HashMap;
 
+use tracing::warn;
+
+use crate::agent::HistoryLimits;
+use crate::db::quantization::Quantization;
+use crate::db::{SessionRetention, SpendLimits};
+use crate::tools::embedding::EmbeddingBackend;
+use crate::tools::{BudgetPolicy, OverBudgetAction};
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub telegram_bot_token: String,
@@ -7,12 +15,44 @@ pub struct Config {
     pub allowed_groups: Vec<u64>,
     pub claude_keys: Vec<String>,
     pub max_agent_turns: usize,
-    pub voyage_api_key: Option<String>,
-    pub voyage_model: String,
+    /// Where embeddings come from; `None` turns semantic search off.
+    pub embedding: Option<EmbeddingBackend>,
     pub openai_api_key: Option<String>,
     pub gemini_api_key: Option<String>,
     pub kimi_api_key: Option<String>,
     pub deepseek_api_key: Option<String>,
+    pub db_read_connections: usize,
+    /// Keep each KB owner's data in its own file under `db_data_dir`.
+    pub db_per_owner: bool,
+    pub db_data_dir: String,
+    /// Minutes an owner file stays open without use before it is closed.
+    pub db_idle_minutes: u64,
+    /// Days a deleted fact/document stays in the trash before it is purged (0 = never).
+    pub trash_retention_days: u32,
+    /// Directory that scheduled and `/backup now` snapshots are written to.
+    pub backup_dir: String,
+    /// Hours between scheduled snapshots (0 = only on `/backup now`).
+    pub backup_interval_hours: u64,
+    pub backup_keep_daily: usize,
+    pub backup_keep_weekly: usize,
+    /// Default `session_messages` retention for owners without a `/retention` override.
+    pub session_retention: SessionRetention,
+    /// Hours a pending write request waits for a decision before it expires (0 = never).
+    pub pending_ttl_hours: u32,
+    /// Size caps on the messages, tool calls and images kept in chat history.
+    pub history_limits: HistoryLimits,
+    /// Key for at-rest encryption of the database (SQLCipher) and saved files.
+    pub encryption_key: Option<String>,
+    /// Keep trigram indexes next to the word indexes for substring search.
+    pub fts_trigram: bool,
+    /// How the vector indexes hold embeddings; searches rerank exactly either way.
+    pub embedding_quantization: Quantization,
+    /// Default USD budgets and what happens when one is used up.
+    pub budget: BudgetPolicy,
+    /// Most `embedding_cache` entries kept; the least recently used go first (0 = no cap).
+    pub embedding_cache_max_entries: u64,
+    /// Days an `embedding_cache` entry is kept without being used (0 = forever).
+    pub embedding_cache_max_age_days: u32,
 }
 
 impl Config {
@@ -47,19 +87,211 @@ impl Config {
                 .get("MAX_AGENT_TURNS")
                 .and_then(|v| v.parse().ok())
                 .unwrap_or(5),
-            voyage_api_key: env.get("VOYAGE_API_KEY").cloned().filter(|s| !s.is_empty()),
-            voyage_model: env
-                .get("VOYAGE_MODEL")
-                .cloned()
-                .unwrap_or_else(|| "voyage-4-lite".to_string()),
+            embedding: embedding_backend(&env),
             openai_api_key: env.get("OPENAI_API_KEY").cloned().filter(|s| !s.is_empty()),
             gemini_api_key: env.get("GEMINI_API_KEY").cloned().filter(|s| !s.is_empty()),
             kimi_api_key: env.get("KIMI_API_KEY").cloned().filter(|s| !s.is_empty()),
             deepseek_api_key: env.get("DEEPSEEK_API_KEY").cloned().filter(|s| !s.is_empty()),
+            db_read_connections: env
+                .get("DB_READ_CONNECTIONS")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(crate::db::DEFAULT_READ_CONNECTIONS),
+            db_per_owner: env
+                .get("DB_PER_OWNER")
+                .is_some_and(|v| matches!(v.as_str(), "1" | "true" | "on")),
+            db_data_dir: data_dir(&env),
+            db_idle_minutes: env
+                .get("DB_IDLE_MINUTES")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(10),
+            trash_retention_days: env
+                .get("TRASH_RETENTION_DAYS")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(30),
+            backup_dir: env
+                .get("BACKUP_DIR")
+                .cloned()
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| "backups".to_string()),
+            backup_interval_hours: env
+                .get("BACKUP_INTERVAL_HOURS")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(24),
+            backup_keep_daily: env
+                .get("BACKUP_KEEP_DAILY")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(7),
+            backup_keep_weekly: env
+                .get("BACKUP_KEEP_WEEKLY")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(4),
+            session_retention: SessionRetention {
+                max_age_days: env
+                    .get("SESSION_RETENTION_DAYS")
+                    .and_then(|v| v.parse().ok())
+                    .filter(|&d| d > 0),
+                max_messages: env
+                    .get("SESSION_MAX_MESSAGES")
+                    .and_then(|v| v.parse().ok())
+                    .map_or(Some(200), |n| (n > 0).then_some(n)),
+                summarize: env
+                    .get("SESSION_SUMMARIZE")
+                    .is_some_and(|v| matches!(v.as_str(), "1" | "true" | "on")),
+            },
+            pending_ttl_hours: env
+                .get("PENDING_TTL_HOURS")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(48),
+            history_limits: {
+                let default = HistoryLimits::default();
+                HistoryLimits {
+                    max_text_chars: env
+                        .get("HISTORY_MAX_TEXT_CHARS")
+                        .and_then(|v| v.parse().ok())
+                        .unwrap_or(default.max_text_chars),
+                    max_tool_chars: env
+                        .get("HISTORY_MAX_TOOL_CHARS")
+                        .and_then(|v| v.parse().ok())
+                        .unwrap_or(default.max_tool_chars),
+                    max_image_bytes: env
+                        .get("HISTORY_MAX_IMAGE_KB")
+                        .and_then(|v| v.parse::<usize>().ok())
+                        .map_or(default.max_image_bytes, |kb| kb * 1024),
+                }
+            },
+            encryption_key: env.get("ENCRYPTION_KEY").cloned().filter(|s| !s.is_empty()),
+            fts_trigram: env
+                .get("FTS_TRIGRAM")
+                .is_some_and(|v| matches!(v.as_str(), "1" | "true" | "on")),
+            embedding_quantization: quantization(&env),
+            embedding_cache_max_entries: env
+                .get("EMBEDDING_CACHE_MAX_ENTRIES")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(200_000),
+            embedding_cache_max_age_days: env
+                .get("EMBEDDING_CACHE_MAX_AGE_DAYS")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(90),
+            budget: BudgetPolicy {
+                owner: SpendLimits {
+                    daily_usd: parse_usd(&env, "BUDGET_OWNER_DAILY_USD"),
+                    monthly_usd: parse_usd(&env, "BUDGET_OWNER_MONTHLY_USD"),
+                },
+                user: SpendLimits {
+                    daily_usd: parse_usd(&env, "BUDGET_USER_DAILY_USD"),
+                    monthly_usd: parse_usd(&env, "BUDGET_USER_MONTHLY_USD"),
+                },
+                action: env
+                    .get("BUDGET_ACTION")
+                    .and_then(|v| OverBudgetAction::parse(v))
+                    .unwrap_or_default(),
+                fallback_model: env
+                    .get("BUDGET_FALLBACK_MODEL")
+                    .and_then(|v| crate::provider::model_registry::resolve_model(v))
+                    .map_or(crate::provider::model_registry::DEFAULT_MODEL, |m| m.id)
+                    .to_string(),
+                alert_percent: env
+                    .get("BUDGET_ALERT_PERCENT")
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(80),
+            },
         }
     }
 }
 
+/// `ENCRYPTION_KEY` on its own, for CLI subcommands that run without a bot token.
+pub fn encryption_key_from_env() -> Option<String> {
+    load_dotenv().remove("ENCRYPTION_KEY").filter(|s| !s.is_empty())
+}
+
+/// `DB_DATA_DIR` when `DB_PER_OWNER` is on, for CLI subcommands that run
+/// without a bot token. `None` in shared mode.
+pub fn per_owner_dir_from_env() -> Option<String> {
+    let env = load_dotenv();
+    env.get("DB_PER_OWNER")
+        .is_some_and(|v| matches!(v.as_str(), "1" | "true" | "on"))
+        .then(|| data_dir(&env))
+}
+
+/// `FTS_TRIGRAM` on its own, so owner files opened by the CLI keep their indexes.
+pub fn fts_trigram_from_env() -> bool {
+    load_dotenv().get("FTS_TRIGRAM").is_some_and(|v| matches!(v.as_str(), "1" | "true" | "on"))
+}
+
+/// `EMBEDDING_QUANTIZATION` on its own, so owner files opened by the CLI keep their mode.
+pub fn quantization_from_env() -> Quantization {
+    quantization(&load_dotenv())
+}
+
+fn quantization(env: &HashMap<String, String>) -> Quantization {
+    env.get("EMBEDDING_QUANTIZATION").and_then(|v| v.parse().ok()).unwrap_or_default()
+}
+
+/// `EMBEDDING_BACKEND` with its `EMBEDDING_MODEL` / `EMBEDDING_API_KEY` /
+/// `EMBEDDING_BASE_URL`. Unset, Voyage is used when `VOYAGE_API_KEY` is.
+/// An unknown backend, or a chosen one missing its key or URL, is logged.
+fn embedding_backend(env: &HashMap<String, String>) -> Option<EmbeddingBackend> {
+    let get = |key: &str| env.get(key).cloned().filter(|s| !s.is_empty());
+    let model = |default: &str| get("EMBEDDING_MODEL").unwrap_or_else(|| default.to_string());
+    let base_url = |default: &str| get("EMBEDDING_BASE_URL").unwrap_or_else(|| default.to_string());
+    let api_key = get("EMBEDDING_API_KEY");
+    let chosen = get("EMBEDDING_BACKEND");
+    let backend = chosen.clone().unwrap_or_else(|| "voyage".to_string()).to_ascii_lowercase();
+    // Without EMBEDDING_BACKEND a missing Voyage key just means semantic search is off
+    let require = |value: Option<String>, vars: &str| {
+        if value.is_none() && chosen.is_some() {
+            warn!("EMBEDDING_BACKEND={backend} needs {vars}; semantic search is off");
+        }
+        value
+    };
+    match backend.as_str() {
+        "voyage" => Some(EmbeddingBackend::Voyage {
+            api_key: require(api_key.or_else(|| get("VOYAGE_API_KEY")), "EMBEDDING_API_KEY or VOYAGE_API_KEY")?,
+            model: get("EMBEDDING_MODEL").or_else(|| get("VOYAGE_MODEL")).unwrap_or_else(|| "voyage-4-lite".to_string()),
+        }),
+        "openai" => Some(EmbeddingBackend::OpenAiCompatible {
+            base_url: base_url("https://api.openai.com/v1"),
+            api_key: Some(require(api_key.or_else(|| get("OPENAI_API_KEY")), "EMBEDDING_API_KEY or OPENAI_API_KEY")?),
+            model: model("text-embedding-3-small"),
+        }),
+        "gemini" => Some(EmbeddingBackend::OpenAiCompatible {
+            base_url: base_url("https://generativelanguage.googleapis.com/v1beta/openai"),
+            api_key: Some(require(api_key.or_else(|| get("GEMINI_API_KEY")), "EMBEDDING_API_KEY or GEMINI_API_KEY")?),
+            model: model("gemini-embedding-001"),
+        }),
+        "ollama" => Some(EmbeddingBackend::OpenAiCompatible {
+            base_url: base_url("http://localhost:11434/v1"),
+            api_key,
+            model: model("nomic-embed-text"),
+        }),
+        // llama-server serves whichever model it was started with
+        "llamacpp" | "llama.cpp" => Some(EmbeddingBackend::OpenAiCompatible {
+            base_url: base_url("http://localhost:8080/v1"),
+            api_key,
+            model: model("llama.cpp"),
+        }),
+        "openai-compatible" => Some(EmbeddingBackend::OpenAiCompatible {
+            base_url: require(get("EMBEDDING_BASE_URL"), "EMBEDDING_BASE_URL")?,
+            api_key,
+            model: require(get("EMBEDDING_MODEL"), "EMBEDDING_MODEL")?,
+        }),
+        "hashing" | "hash" => Some(EmbeddingBackend::Hashing {
+            dims: get("EMBEDDING_DIMS").and_then(|v| v.parse().ok()).unwrap_or(256),
+        }),
+        _ => {
+            warn!(
+                "Unknown EMBEDDING_BACKEND={backend} (expected voyage, openai, gemini, ollama, llamacpp, \
+                 openai-compatible or hashing); semantic search is off"
+            );
+            None
+        }
+    }
+}
+
+fn data_dir(env: &HashMap<String, String>) -> String {
+    env.get("DB_DATA_DIR").cloned().filter(|s| !s.is_empty()).unwrap_or_else(|| "data".to_string())
+}
+
 /// Load .env file into a HashMap without polluting process environment.
 fn load_dotenv() -> HashMap<String, String> {
     dotenvy::dotenv_iter()
@@ -67,6 +299,11 @@ fn load_dotenv() -> HashMap<String, String> {
         .unwrap_or_default()
 }
 
+/// A positive USD amount; unset, 0 or unparsable means no limit.
+fn parse_usd(env: &HashMap<String, String>, key: &str) -> Option<f64> {
+    env.get(key).and_then(|v| v.trim().parse().ok()).filter(|&usd: &f64| usd > 0.0)
+}
+
 fn parse_keys(env: &HashMap<String, String>, key: &str) -> Vec<String> {
     env.get(key)
         .map(|s| {
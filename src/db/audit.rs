@@ -0,0 +1,636 @@
+//! Append-only record of knowledge base writes (`audit_log`).
+//!
+//! A write tool call runs inside [`track_changes`]. Its writes go through
+//! the single writer connection, where temp triggers note the first state of
+//! every fact, document and category they touch; before the writer is
+//! released the touched rows are read back as their state after. The entry
+//! keeps the rows that differ, so writes made by other calls meanwhile never
+//! land in it. Facts are compared by text, category and trash state;
+//! documents by title, source, tags, trash state and latest stored version,
+//! so document text is not copied into the log.
+//!
+//! Entries are never changed or removed (triggers reject it).
+//! [`Database::undo_last_write`] puts the changed rows back and appends an
+//! `undo` entry pointing at the one it reversed.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use rusqlite::types::Type;
+use rusqlite::{Connection, OptionalExtension, Row, params};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::{Database, DbError, DbResult, entity_graph, insert_document_version, insert_fact_revision};
+
+/// Tool name of the entries written by [`Database::undo_last_write`].
+pub const UNDO_TOOL: &str = "undo";
+
+/// A fact as the audit log records it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FactState {
+    pub id: i64,
+    pub text: String,
+    pub category: String,
+    pub trashed: bool,
+}
+
+/// A document as the audit log records it. The text is the content of
+/// `version` in `knowledge_document_versions`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocumentState {
+    pub id: i64,
+    pub title: String,
+    pub source: Option<String>,
+    pub tags: Option<String>,
+    pub version: i64,
+    pub trashed: bool,
+}
+
+/// The rows one write changed, as they were on one side of it. A fact or
+/// document missing from `before` was created by the write; one missing from
+/// `after` was deleted for good. `categories` are the names that exist on
+/// this side only.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditState {
+    pub facts: Vec<FactState>,
+    pub documents: Vec<DocumentState>,
+    pub categories: Vec<String>,
+}
+
+/// One `audit_log` entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    pub id: i64,
+    /// Who ran the tool; for an approved request, the approver.
+    pub user_id: u64,
+    pub kb_owner_id: u64,
+    pub tool: String,
+    pub args_json: String,
+    pub result: String,
+    pub before: AuditState,
+    pub after: AuditState,
+    /// For an `undo` entry, the entry it reversed.
+    pub undoes: Option<i64>,
+    /// The `undo` entry that reversed this one.
+    pub undone_by: Option<i64>,
+    pub created_at: String,
+}
+
+/// A tool call to record with [`Database::log_audit`].
+#[derive(Debug, Clone, Copy)]
+pub struct NewAuditEntry<'a> {
+    pub user_id: u64,
+    pub kb_owner_id: u64,
+    pub tool: &'a str,
+    pub args_json: &'a str,
+    pub result: &'a str,
+}
+
+/// Filters for [`Database::search_audit`]; `None` matches everything.
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub user_id: Option<u64>,
+    pub tool: Option<String>,
+    /// Substring of the arguments, the result or the changed rows.
+    pub text: Option<String>,
+}
+
+/// A reversed write: the entry that was undone (with `undone_by` set) and
+/// the `undo` entry recording what the reversal changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Undo {
+    pub reversed: AuditEntry,
+    pub entry: AuditEntry,
+}
+
+/// A fact, document or category as it was before the first tracked write
+/// that touched it and after the last one; `None` where it did not exist.
+#[derive(Debug, Clone)]
+struct Touched<T> {
+    owner_id: u64,
+    before: Option<T>,
+    after: Option<T>,
+}
+
+/// The rows a tool call's writes touched, collected by [`track_changes`].
+#[derive(Debug, Clone, Default)]
+pub struct AuditChanges {
+    facts: BTreeMap<i64, Touched<FactState>>,
+    documents: BTreeMap<i64, Touched<DocumentState>>,
+    categories: BTreeMap<(u64, String), Touched<()>>,
+}
+
+impl AuditChanges {
+    /// `(before, after)`: the rows of `owner_id` that differ between the two sides.
+    pub fn states(&self, owner_id: u64) -> (AuditState, AuditState) {
+        let (mut before, mut after) = (AuditState::default(), AuditState::default());
+        changed_rows(&self.facts, owner_id, &mut before.facts, &mut after.facts);
+        changed_rows(&self.documents, owner_id, &mut before.documents, &mut after.documents);
+        for ((_, name), touched) in self.categories.iter().filter(|(_, t)| t.owner_id == owner_id) {
+            match (touched.before.is_some(), touched.after.is_some()) {
+                (true, false) => before.categories.push(name.clone()),
+                (false, true) => after.categories.push(name.clone()),
+                _ => {}
+            }
+        }
+        (before, after)
+    }
+}
+
+fn changed_rows<T: Clone + PartialEq>(
+    rows: &BTreeMap<i64, Touched<T>>,
+    owner_id: u64,
+    before: &mut Vec<T>,
+    after: &mut Vec<T>,
+) {
+    for touched in rows.values().filter(|t| t.owner_id == owner_id && t.before != t.after) {
+        before.extend(touched.before.clone());
+        after.extend(touched.after.clone());
+    }
+}
+
+tokio::task_local! {
+    static TRACKED: Arc<Mutex<AuditChanges>>;
+}
+
+/// Run `fut` and collect the rows changed by every [`Database`] write it
+/// makes, on any database, for [`Database::log_audit`].
+pub async fn track_changes<F: Future>(fut: F) -> (F::Output, AuditChanges) {
+    let changes = Arc::new(Mutex::new(AuditChanges::default()));
+    let output = TRACKED.scope(changes.clone(), fut).await;
+    let changes = std::mem::take(&mut *changes.lock().unwrap_or_else(|e| e.into_inner()));
+    (output, changes)
+}
+
+/// The changes of the [`track_changes`] call this task is inside, if any.
+pub(super) fn current_tracker() -> Option<Arc<Mutex<AuditChanges>>> {
+    TRACKED.try_with(Arc::clone).ok()
+}
+
+/// JSON of a [`FactState`] from the `memory_facts` row `r`.
+fn fact_json(r: &str) -> String {
+    format!(
+        "json_object('id', {r}.id, 'text', {r}.fact, 'category', {r}.category,
+                     'trashed', json(CASE WHEN {r}.deleted_at IS NULL THEN 'false' ELSE 'true' END))"
+    )
+}
+
+/// JSON of a [`DocumentState`] from the `knowledge_documents` row `r`.
+fn document_json(r: &str) -> String {
+    format!(
+        "json_object('id', {r}.id, 'title', {r}.title, 'source', {r}.source, 'tags', {r}.tags,
+                     'version', (SELECT COALESCE(MAX(v.version), 0) FROM knowledge_document_versions v WHERE v.doc_id = {r}.id),
+                     'trashed', json(CASE WHEN {r}.deleted_at IS NULL THEN 'false' ELSE 'true' END))"
+    )
+}
+
+/// Set up change tracking on the writer connection: `audit_touched` gets
+/// the state of each fact, document and category (`before_json`, NULL if it
+/// did not exist) when a write first touches it, while `audit_active` has a
+/// row. Temp objects, so they live and die with the connection.
+pub(super) fn install_change_tracking(conn: &Connection) -> rusqlite::Result<()> {
+    let (fact_old, doc_old, doc_row) = (fact_json("OLD"), document_json("OLD"), document_json("d"));
+    conn.execute_batch(&format!(
+        "CREATE TEMP TABLE IF NOT EXISTS audit_active (id INTEGER PRIMARY KEY);
+        CREATE TEMP TABLE IF NOT EXISTS audit_touched (
+            kind TEXT NOT NULL,
+            owner_id INTEGER NOT NULL,
+            item_id INTEGER NOT NULL,
+            name TEXT NOT NULL DEFAULT '',
+            before_json TEXT,
+            PRIMARY KEY (kind, item_id, name)
+        );
+
+        CREATE TEMP TRIGGER IF NOT EXISTS audit_fact_insert AFTER INSERT ON main.memory_facts
+        WHEN EXISTS (SELECT 1 FROM audit_active) BEGIN
+            INSERT OR IGNORE INTO audit_touched (kind, owner_id, item_id) VALUES ('fact', NEW.user_id, NEW.id);
+        END;
+        CREATE TEMP TRIGGER IF NOT EXISTS audit_fact_update BEFORE UPDATE OF fact, category, deleted_at ON main.memory_facts
+        WHEN EXISTS (SELECT 1 FROM audit_active) BEGIN
+            INSERT OR IGNORE INTO audit_touched (kind, owner_id, item_id, before_json)
+            VALUES ('fact', OLD.user_id, OLD.id, {fact_old});
+        END;
+        CREATE TEMP TRIGGER IF NOT EXISTS audit_fact_delete BEFORE DELETE ON main.memory_facts
+        WHEN EXISTS (SELECT 1 FROM audit_active) BEGIN
+            INSERT OR IGNORE INTO audit_touched (kind, owner_id, item_id, before_json)
+            VALUES ('fact', OLD.user_id, OLD.id, {fact_old});
+        END;
+
+        CREATE TEMP TRIGGER IF NOT EXISTS audit_document_insert AFTER INSERT ON main.knowledge_documents
+        WHEN EXISTS (SELECT 1 FROM audit_active) BEGIN
+            INSERT OR IGNORE INTO audit_touched (kind, owner_id, item_id) VALUES ('document', NEW.user_id, NEW.id);
+        END;
+        CREATE TEMP TRIGGER IF NOT EXISTS audit_document_update
+        BEFORE UPDATE OF title, content, source, tags, deleted_at ON main.knowledge_documents
+        WHEN EXISTS (SELECT 1 FROM audit_active) BEGIN
+            INSERT OR IGNORE INTO audit_touched (kind, owner_id, item_id, before_json)
+            VALUES ('document', OLD.user_id, OLD.id, {doc_old});
+        END;
+        CREATE TEMP TRIGGER IF NOT EXISTS audit_document_delete BEFORE DELETE ON main.knowledge_documents
+        WHEN EXISTS (SELECT 1 FROM audit_active) BEGIN
+            INSERT OR IGNORE INTO audit_touched (kind, owner_id, item_id, before_json)
+            VALUES ('document', OLD.user_id, OLD.id, {doc_old});
+        END;
+        -- A new version may be stored before the document row is updated
+        CREATE TEMP TRIGGER IF NOT EXISTS audit_document_version BEFORE INSERT ON main.knowledge_document_versions
+        WHEN EXISTS (SELECT 1 FROM audit_active) BEGIN
+            INSERT OR IGNORE INTO audit_touched (kind, owner_id, item_id, before_json)
+            SELECT 'document', d.user_id, d.id, {doc_row} FROM knowledge_documents d WHERE d.id = NEW.doc_id;
+        END;
+
+        CREATE TEMP TRIGGER IF NOT EXISTS audit_category_insert AFTER INSERT ON main.categories
+        WHEN EXISTS (SELECT 1 FROM audit_active) BEGIN
+            INSERT OR IGNORE INTO audit_touched (kind, owner_id, item_id, name)
+            VALUES ('category', NEW.user_id, NEW.user_id, NEW.name);
+        END;
+        CREATE TEMP TRIGGER IF NOT EXISTS audit_category_rename AFTER UPDATE OF name ON main.categories
+        WHEN EXISTS (SELECT 1 FROM audit_active) BEGIN
+            INSERT OR IGNORE INTO audit_touched (kind, owner_id, item_id, name, before_json)
+            VALUES ('category', OLD.user_id, OLD.user_id, OLD.name, 'true');
+            INSERT OR IGNORE INTO audit_touched (kind, owner_id, item_id, name)
+            VALUES ('category', NEW.user_id, NEW.user_id, NEW.name);
+        END;
+        CREATE TEMP TRIGGER IF NOT EXISTS audit_category_delete BEFORE DELETE ON main.categories
+        WHEN EXISTS (SELECT 1 FROM audit_active) BEGIN
+            INSERT OR IGNORE INTO audit_touched (kind, owner_id, item_id, name, before_json)
+            VALUES ('category', OLD.user_id, OLD.user_id, OLD.name, 'true');
+        END;"
+    ))
+}
+
+/// Run `f` on the writer with change tracking on, and fold the rows it
+/// touched into `changes` before the writer is released.
+pub(super) fn tracked<T>(conn: &mut Connection, changes: &Mutex<AuditChanges>, f: impl FnOnce(&mut Connection) -> T) -> T {
+    if let Err(e) = start_tracking(conn) {
+        warn!("Could not track changes for the audit log: {e}");
+        return f(conn);
+    }
+    let output = f(conn);
+    let mut changes = changes.lock().unwrap_or_else(|e| e.into_inner());
+    if let Err(e) = collect_changes(conn, &mut changes) {
+        warn!("Could not record changed rows for the audit log: {e}");
+    }
+    output
+}
+
+fn start_tracking(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute("INSERT OR IGNORE INTO temp.audit_active (id) VALUES (1)", []).map(|_| ())
+}
+
+/// Move the rows noted since [`start_tracking`] into `changes`, with their
+/// current state as `after`, and stop tracking. A row already in `changes`
+/// keeps its first `before`.
+fn collect_changes(conn: &Connection, changes: &mut AuditChanges) -> rusqlite::Result<()> {
+    let touched = {
+        let mut stmt = conn.prepare("SELECT kind, owner_id, item_id, name, before_json FROM temp.audit_touched")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)? as u64,
+                row.get::<_, i64>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, Option<String>>(4)?,
+            ))
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+    };
+    // Stop tracking even if reading failed
+    conn.execute_batch("DELETE FROM temp.audit_touched; DELETE FROM temp.audit_active;")?;
+
+    for (kind, owner_id, id, name, before) in touched? {
+        match kind.as_str() {
+            "fact" => {
+                let before = before.as_deref().map(parse_state).transpose()?;
+                let after = fact_state(conn, id)?;
+                changes.facts.entry(id).or_insert(Touched { owner_id, before, after: None }).after = after;
+            }
+            "document" => {
+                let before = before.as_deref().map(parse_state).transpose()?;
+                let after = document_state(conn, id)?;
+                changes.documents.entry(id).or_insert(Touched { owner_id, before, after: None }).after = after;
+            }
+            _ => {
+                let exists: bool = conn.query_row(
+                    "SELECT EXISTS (SELECT 1 FROM categories WHERE user_id = ?1 AND name = ?2)",
+                    params![owner_id as i64, name],
+                    |row| row.get(0),
+                )?;
+                let before = before.map(|_| ());
+                let touched = changes.categories.entry((owner_id, name)).or_insert(Touched { owner_id, before, after: None });
+                touched.after = exists.then_some(());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parse_state<T: DeserializeOwned>(json: &str) -> rusqlite::Result<T> {
+    serde_json::from_str(json).map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e)))
+}
+
+fn fact_state(conn: &Connection, id: i64) -> rusqlite::Result<Option<FactState>> {
+    conn.query_row(
+        "SELECT id, fact, category, deleted_at IS NOT NULL FROM memory_facts WHERE id = ?1",
+        params![id],
+        |row| Ok(FactState { id: row.get(0)?, text: row.get(1)?, category: row.get(2)?, trashed: row.get(3)? }),
+    )
+    .optional()
+}
+
+fn document_state(conn: &Connection, id: i64) -> rusqlite::Result<Option<DocumentState>> {
+    conn.query_row(
+        "SELECT kd.id, kd.title, kd.source, kd.tags,
+                (SELECT COALESCE(MAX(v.version), 0) FROM knowledge_document_versions v WHERE v.doc_id = kd.id),
+                kd.deleted_at IS NOT NULL
+         FROM knowledge_documents kd WHERE kd.id = ?1",
+        params![id],
+        |row| {
+            Ok(DocumentState {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                source: row.get(2)?,
+                tags: row.get(3)?,
+                version: row.get(4)?,
+                trashed: row.get(5)?,
+            })
+        },
+    )
+    .optional()
+}
+
+impl Database {
+    /// Append an entry for a tool call with the rows of its knowledge base
+    /// that its writes changed (see [`track_changes`]). Returns the entry ID.
+    pub async fn log_audit(&self, entry: NewAuditEntry<'_>, changes: &AuditChanges) -> DbResult<i64> {
+        let (before, after) = changes.states(entry.kb_owner_id);
+        let NewAuditEntry { user_id, kb_owner_id, .. } = entry;
+        let (tool, args_json, result) = (entry.tool.to_string(), entry.args_json.to_string(), entry.result.to_string());
+        self.write(move |conn| {
+            let row = AuditRow { user_id, kb_owner_id, tool: &tool, args_json: &args_json, result: &result };
+            insert_audit(conn, row, &before, &after, None)
+        })
+        .await
+    }
+
+    /// Entries of `kb_owner_id` matching `filter`, newest first.
+    pub async fn search_audit(&self, kb_owner_id: u64, filter: AuditFilter, limit: usize) -> DbResult<Vec<AuditEntry>> {
+        self.read(move |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {AUDIT_COLUMNS} FROM audit_log a
+                 WHERE a.kb_owner_id = ?1
+                   AND (?2 IS NULL OR a.user_id = ?2)
+                   AND (?3 IS NULL OR a.tool = ?3)
+                   AND (?4 IS NULL OR a.args_json LIKE '%' || ?4 || '%' OR a.result LIKE '%' || ?4 || '%'
+                        OR a.before_json LIKE '%' || ?4 || '%' OR a.after_json LIKE '%' || ?4 || '%')
+                 ORDER BY a.id DESC LIMIT ?5"
+            ))?;
+            let rows = stmt.query_map(
+                params![
+                    kb_owner_id as i64,
+                    filter.user_id.map(|id| id as i64),
+                    filter.tool,
+                    filter.text,
+                    limit as i64
+                ],
+                audit_from_row,
+            )?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await
+    }
+
+    /// Reverse `user_id`'s most recent write to `kb_owner_id` that changed
+    /// something and is not undone yet. Facts and documents it created go to
+    /// the trash; changed ones get their old text (as a new revision or
+    /// version), category, title and trash state back; categories are
+    /// re-added or removed. Fails with [`DbError::Conflict`] when a row has
+    /// changed since or was purged. `None` if there is nothing to undo.
+    pub async fn undo_last_write(&self, user_id: u64, kb_owner_id: u64) -> DbResult<Option<Undo>> {
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let entry = tx
+                .query_row(
+                    &format!(
+                        "SELECT {AUDIT_COLUMNS} FROM audit_log a
+                         WHERE a.kb_owner_id = ?1 AND a.user_id = ?2 AND a.tool != ?3
+                           AND a.before_json != a.after_json
+                           AND NOT EXISTS (SELECT 1 FROM audit_log u WHERE u.undoes = a.id)
+                         ORDER BY a.id DESC LIMIT 1"
+                    ),
+                    params![kb_owner_id as i64, user_id as i64, UNDO_TOOL],
+                    audit_from_row,
+                )
+                .optional()?;
+            let Some(mut reversed) = entry else {
+                return Ok(None);
+            };
+
+            check_undoable(&tx, &reversed)?;
+            start_tracking(&tx)?;
+            let mut changes = AuditChanges::default();
+            let restored = restore(&tx, &reversed, user_id);
+            collect_changes(&tx, &mut changes)?;
+            restored?;
+            let (changed_before, changed_after) = changes.states(kb_owner_id);
+
+            let args_json = serde_json::json!({ "audit_id": reversed.id }).to_string();
+            let result = format!("Reversed #{} ({})", reversed.id, reversed.tool);
+            let row = AuditRow { user_id, kb_owner_id, tool: UNDO_TOOL, args_json: &args_json, result: &result };
+            let id = insert_audit(&tx, row, &changed_before, &changed_after, Some(reversed.id))?;
+            let entry = tx.query_row(
+                &format!("SELECT {AUDIT_COLUMNS} FROM audit_log a WHERE a.id = ?1"),
+                params![id],
+                audit_from_row,
+            )?;
+            tx.commit()?;
+            reversed.undone_by = Some(id);
+            Ok::<_, DbError>(Some(Undo { reversed, entry }))
+        })
+        .await
+    }
+}
+
+/// Columns [`audit_from_row`] expects, for `audit_log a`.
+const AUDIT_COLUMNS: &str = "a.id, a.user_id, a.kb_owner_id, a.tool, a.args_json, a.result, a.before_json, \
+    a.after_json, a.undoes, (SELECT u.id FROM audit_log u WHERE u.undoes = a.id), a.created_at";
+
+fn audit_from_row(row: &Row) -> rusqlite::Result<AuditEntry> {
+    let state = |idx: usize| -> rusqlite::Result<AuditState> {
+        let json: String = row.get(idx)?;
+        serde_json::from_str(&json).map_err(|e| rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, Box::new(e)))
+    };
+    Ok(AuditEntry {
+        id: row.get(0)?,
+        user_id: row.get::<_, i64>(1)? as u64,
+        kb_owner_id: row.get::<_, i64>(2)? as u64,
+        tool: row.get(3)?,
+        args_json: row.get(4)?,
+        result: row.get(5)?,
+        before: state(6)?,
+        after: state(7)?,
+        undoes: row.get(8)?,
+        undone_by: row.get(9)?,
+        created_at: row.get(10)?,
+    })
+}
+
+struct AuditRow<'a> {
+    user_id: u64,
+    kb_owner_id: u64,
+    tool: &'a str,
+    args_json: &'a str,
+    result: &'a str,
+}
+
+fn insert_audit(
+    conn: &Connection,
+    row: AuditRow<'_>,
+    before: &AuditState,
+    after: &AuditState,
+    undoes: Option<i64>,
+) -> rusqlite::Result<i64> {
+    let json = |state: &AuditState| {
+        serde_json::to_string(state).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+    };
+    conn.execute(
+        "INSERT INTO audit_log (user_id, kb_owner_id, tool, args_json, result, before_json, after_json, undoes)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            row.user_id as i64,
+            row.kb_owner_id as i64,
+            row.tool,
+            row.args_json,
+            row.result,
+            json(before)?,
+            json(after)?,
+            undoes
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Every row `entry` left behind must still look the way it did, and nothing
+/// it changed may have been purged since.
+fn check_undoable(conn: &Connection, entry: &AuditEntry) -> DbResult<()> {
+    let conflict = |what: String| DbError::Conflict(format!("Cannot undo #{} ({}): {what}.", entry.id, entry.tool));
+    for fact in &entry.after.facts {
+        if fact_state(conn, fact.id)?.as_ref() != Some(fact) {
+            return Err(conflict(format!("memory #{} has changed since", fact.id)));
+        }
+    }
+    for doc in &entry.after.documents {
+        let unchanged = match document_state(conn, doc.id)? {
+            Some(current) => same_document(conn, &current, doc)?,
+            None => false,
+        };
+        if !unchanged {
+            return Err(conflict(format!("document #{} has changed since", doc.id)));
+        }
+    }
+    for fact in &entry.before.facts {
+        if fact_state(conn, fact.id)?.is_none() {
+            return Err(conflict(format!("memory #{} was permanently deleted", fact.id)));
+        }
+    }
+    for doc in &entry.before.documents {
+        if document_state(conn, doc.id)?.is_none() {
+            return Err(conflict(format!("document #{} was permanently deleted", doc.id)));
+        }
+    }
+    Ok(())
+}
+
+/// Equal apart from the version number, as long as both versions hold the
+/// same text (an undo stores the restored text as a new version).
+fn same_document(conn: &Connection, current: &DocumentState, expected: &DocumentState) -> rusqlite::Result<bool> {
+    let meta = |d: &DocumentState| (d.title.clone(), d.source.clone(), d.tags.clone(), d.trashed);
+    if meta(current) != meta(expected) {
+        return Ok(false);
+    }
+    if current.version == expected.version {
+        return Ok(true);
+    }
+    Ok(version_content(conn, current.id, current.version)? == version_content(conn, expected.id, expected.version)?)
+}
+
+fn version_content(conn: &Connection, doc_id: i64, version: i64) -> rusqlite::Result<Option<String>> {
+    conn.query_row(
+        "SELECT content FROM knowledge_document_versions WHERE doc_id = ?1 AND version = ?2",
+        params![doc_id, version],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// Put every row `entry` changed back the way it was. Runs after
+/// [`check_undoable`], inside the caller's transaction.
+fn restore(conn: &Connection, entry: &AuditEntry, actor_id: u64) -> rusqlite::Result<()> {
+    for old in &entry.before.facts {
+        let Some(current) = fact_state(conn, old.id)? else {
+            continue;
+        };
+        if current.text != old.text {
+            // The embedding is stale once the text changes
+            conn.execute(
+                "UPDATE memory_facts SET fact = ?1, embedding = NULL WHERE id = ?2",
+                params![old.text, old.id],
+            )?;
+            insert_fact_revision(conn, old.id, &current.text, &old.text, None, actor_id, UNDO_TOOL)?;
+            entity_graph::refresh_mentions(conn, "fact", old.id)?;
+        }
+        conn.execute(
+            "UPDATE memory_facts SET category = ?1,
+                 deleted_at = CASE WHEN ?2 THEN COALESCE(deleted_at, datetime('now')) END
+             WHERE id = ?3",
+            params![old.category, old.trashed, old.id],
+        )?;
+    }
+    for new in entry.after.facts.iter().filter(|f| !entry.before.facts.iter().any(|old| old.id == f.id)) {
+        conn.execute(
+            "UPDATE memory_facts SET deleted_at = datetime('now') WHERE id = ?1 AND deleted_at IS NULL",
+            params![new.id],
+        )?;
+    }
+
+    for old in &entry.before.documents {
+        let current: String =
+            conn.query_row("SELECT content FROM knowledge_documents WHERE id = ?1", params![old.id], |row| row.get(0))?;
+        if let Some(content) = version_content(conn, old.id, old.version)?
+            && content != current
+        {
+            // Chunks are rebuilt by the caller (or the startup re-chunking pass)
+            conn.execute("UPDATE knowledge_documents SET content = ?1 WHERE id = ?2", params![content, old.id])?;
+            conn.execute("DELETE FROM knowledge_chunks WHERE doc_id = ?1", params![old.id])?;
+            insert_document_version(conn, old.id, &current, &content, actor_id, UNDO_TOOL)?;
+            entity_graph::refresh_mentions(conn, "document", old.id)?;
+        }
+        conn.execute(
+            "UPDATE knowledge_documents SET title = ?1, source = ?2, tags = ?3,
+                 deleted_at = CASE WHEN ?4 THEN COALESCE(deleted_at, datetime('now')) END
+             WHERE id = ?5",
+            params![old.title, old.source, old.tags, old.trashed, old.id],
+        )?;
+    }
+    for new in entry.after.documents.iter().filter(|d| !entry.before.documents.iter().any(|old| old.id == d.id)) {
+        conn.execute(
+            "UPDATE knowledge_documents SET deleted_at = datetime('now') WHERE id = ?1 AND deleted_at IS NULL",
+            params![new.id],
+        )?;
+    }
+
+    for name in &entry.before.categories {
+        conn.execute(
+            "INSERT OR IGNORE INTO categories (user_id, name) VALUES (?1, ?2)",
+            params![entry.kb_owner_id as i64, name],
+        )?;
+    }
+    for name in &entry.after.categories {
+        conn.execute(
+            "DELETE FROM categories WHERE user_id = ?1 AND name = ?2",
+            params![entry.kb_owner_id as i64, name],
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,195 @@
+//! Online snapshots of the live database into a backup directory.
+//!
+//! Snapshots are taken with SQLite's backup API from a reader connection, so
+//! the bot keeps writing while one runs. Each file is checked with
+//! `PRAGMA integrity_check` before it replaces older ones, and rotation keeps
+//! the newest snapshot of each of the last N days and M ISO weeks.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use rusqlite::backup::{Backup, StepResult};
+use rusqlite::{Connection, OpenFlags};
+
+use super::{Database, DbError};
+
+const FILE_PREFIX: &str = "memory-assistant-";
+const FILE_SUFFIX: &str = ".db";
+const TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackupError {
+    #[error("snapshot {path} failed integrity check: {report}")]
+    Corrupt { path: PathBuf, report: String },
+    #[error(transparent)]
+    Db(#[from] DbError),
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// How many snapshots rotation keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// Newest snapshot of each of the last `daily` days that have one.
+    pub daily: usize,
+    /// Newest snapshot of each of the last `weekly` ISO weeks that have one.
+    pub weekly: usize,
+}
+
+/// A snapshot file in the backup directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackupInfo {
+    pub path: PathBuf,
+    pub created_at: NaiveDateTime,
+    pub size_bytes: u64,
+}
+
+/// Result of [`Database::backup_now`].
+#[derive(Debug, Clone)]
+pub struct BackupReport {
+    pub backup: BackupInfo,
+    /// Older snapshots deleted by rotation.
+    pub removed: Vec<PathBuf>,
+}
+
+impl Database {
+    /// Copy the whole database to `dest` with the online backup API.
+    /// `dest` is overwritten if it exists, and encrypted with the same key
+    /// as the live database.
+    pub async fn backup_to(&self, dest: &Path) -> Result<(), DbError> {
+        let dest = dest.to_path_buf();
+        let key = self.pool.key().map(str::to_string);
+        self.read(move |conn| {
+            let mut target = Connection::open(&dest)?;
+            if let Some(key) = &key {
+                target.pragma_update(None, "key", key)?;
+            }
+            copy_database(conn, &mut target)
+        })
+        .await
+    }
+
+    /// Snapshot into `dir`, verify the copy, then rotate old snapshots.
+    ///
+    /// The snapshot is written under a temporary name and only renamed into
+    /// place once it passes `PRAGMA integrity_check`, so a failed run never
+    /// counts towards retention.
+    pub async fn backup_now(&self, dir: &Path, policy: RetentionPolicy) -> Result<BackupReport, BackupError> {
+        std::fs::create_dir_all(dir)?;
+        // Whole seconds, matching what the file name can round-trip
+        let now = chrono::Utc::now().naive_utc();
+        let created_at = now.with_nanosecond(0).unwrap_or(now);
+        let path = dir.join(file_name(created_at));
+        let partial = path.with_extension("db.partial");
+
+        if let Err(e) = self.backup_to(&partial).await {
+            let _ = std::fs::remove_file(&partial);
+            return Err(e.into());
+        }
+
+        let checked = partial.clone();
+        let key = self.pool.key().map(str::to_string);
+        let verified = tokio::task::spawn_blocking(move || verify_backup(&checked, key.as_deref()))
+            .await
+            .map_err(DbError::from)?;
+        if let Err(e) = verified {
+            let _ = std::fs::remove_file(&partial);
+            return Err(e);
+        }
+        std::fs::rename(&partial, &path)?;
+
+        let backup = BackupInfo { size_bytes: std::fs::metadata(&path)?.len(), path, created_at };
+        let removed = rotate_backups(dir, policy)?;
+        Ok(BackupReport { backup, removed })
+    }
+}
+
+/// Copy in a single step: the source holds one read snapshot for the whole
+/// copy (WAL keeps writers unblocked), so concurrent writes never force a restart.
+fn copy_database(source: &Connection, target: &mut Connection) -> rusqlite::Result<()> {
+    let backup = Backup::new(source, target)?;
+    loop {
+        match backup.step(-1)? {
+            StepResult::Done => return Ok(()),
+            _ => std::thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}
+
+/// Run `PRAGMA integrity_check` on a snapshot file, keyed with `key` if the
+/// database is encrypted. Blocking.
+pub fn verify_backup(path: &Path, key: Option<&str>) -> Result<(), BackupError> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    if let Some(key) = key {
+        conn.pragma_update(None, "key", key)?;
+    }
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+    let report = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+    if report.len() == 1 && report[0] == "ok" {
+        Ok(())
+    } else {
+        Err(BackupError::Corrupt { path: path.to_path_buf(), report: report.join("; ") })
+    }
+}
+
+/// Snapshots in `dir`, newest first. A missing directory has none.
+pub fn list_backups(dir: &Path) -> std::io::Result<Vec<BackupInfo>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut backups = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(created_at) = name.to_str().and_then(parse_file_name) else {
+            continue;
+        };
+        backups.push(BackupInfo { path: entry.path(), created_at, size_bytes: entry.metadata()?.len() });
+    }
+    backups.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+    Ok(backups)
+}
+
+/// Snapshots that fall outside `policy`. `backups` must be newest first.
+pub fn expired_backups(backups: &[BackupInfo], policy: RetentionPolicy) -> Vec<&BackupInfo> {
+    let mut days = HashSet::new();
+    let mut weeks = HashSet::new();
+    backups
+        .iter()
+        .filter(|b| {
+            let date = b.created_at.date();
+            let week = date.iso_week();
+            // Newest-first, so the first snapshot seen for a day/week is the one kept
+            let keep_day = days.len() < policy.daily && days.insert(date);
+            let keep_week = weeks.len() < policy.weekly && weeks.insert((week.year(), week.week()));
+            !(keep_day || keep_week)
+        })
+        .collect()
+}
+
+/// Delete snapshots outside `policy`; returns the removed paths.
+pub fn rotate_backups(dir: &Path, policy: RetentionPolicy) -> std::io::Result<Vec<PathBuf>> {
+    let backups = list_backups(dir)?;
+    let mut removed = Vec::new();
+    for backup in expired_backups(&backups, policy) {
+        std::fs::remove_file(&backup.path)?;
+        removed.push(backup.path.clone());
+    }
+    Ok(removed)
+}
+
+fn file_name(created_at: NaiveDateTime) -> String {
+    format!("{FILE_PREFIX}{}{FILE_SUFFIX}", created_at.format(TIMESTAMP_FORMAT))
+}
+
+fn parse_file_name(name: &str) -> Option<NaiveDateTime> {
+    let stamp = name.strip_prefix(FILE_PREFIX)?.strip_suffix(FILE_SUFFIX)?;
+    NaiveDateTime::parse_from_str(stamp, TIMESTAMP_FORMAT).ok()
+}
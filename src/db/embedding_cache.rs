@@ -0,0 +1,227 @@
+//! Embeddings already fetched from the embedding API, keyed by model, input
+//! type and the SHA-256 of the text. Overlapping chunks, re-saved documents
+//! and duplicate facts are looked up here instead of being embedded again.
+//!
+//! The cache lives in the main database and only holds hashes, never text.
+//! Lookups are plain reads: hits, misses and when each entry was last used
+//! are counted in memory and written out by [`Database::flush_embedding_cache_usage`],
+//! after which [`Database::prune_embedding_cache`] drops the least recently
+//! used entries.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use rusqlite::{Connection, OptionalExtension, params};
+use sha2::{Digest, Sha256};
+
+use super::{Database, DbResult};
+
+/// SHA-256 of an embedded text.
+pub type TextHash = [u8; 32];
+
+pub fn text_hash(text: &str) -> TextHash {
+    Sha256::digest(text.as_bytes()).into()
+}
+
+/// Size of the cache and how often it was used, over every model.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EmbeddingCacheStats {
+    pub entries: u64,
+    /// Total size of the stored embeddings.
+    pub bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl EmbeddingCacheStats {
+    /// Share of lookups served from the cache, 0 before the first one.
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 { 0.0 } else { self.hits as f64 / lookups as f64 }
+    }
+}
+
+/// Cache use since the last flush: hits and misses per model, and the keys that hit.
+#[derive(Debug, Default)]
+pub(crate) struct CacheUsage(Mutex<PendingUsage>);
+
+#[derive(Debug, Default)]
+struct PendingUsage {
+    counts: HashMap<String, (u64, u64)>,
+    used: HashSet<(String, String, TextHash)>,
+}
+
+impl CacheUsage {
+    fn record(&self, model: &str, input_type: &str, hits: &[TextHash], misses: usize) {
+        let mut pending = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let counts = pending.counts.entry(model.to_string()).or_default();
+        counts.0 += hits.len() as u64;
+        counts.1 += misses as u64;
+        pending.used.extend(hits.iter().map(|hash| (model.to_string(), input_type.to_string(), *hash)));
+    }
+
+    fn take(&self) -> PendingUsage {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Hits and misses not yet flushed, over every model.
+    fn unflushed(&self) -> (u64, u64) {
+        let pending = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        pending.counts.values().fold((0, 0), |(h, m), (hits, misses)| (h + hits, m + misses))
+    }
+
+    /// Put back usage whose flush failed, so it is written next time.
+    fn restore(&self, usage: PendingUsage) {
+        let mut pending = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        for (model, (hits, misses)) in usage.counts {
+            let counts = pending.counts.entry(model).or_default();
+            counts.0 += hits;
+            counts.1 += misses;
+        }
+        pending.used.extend(usage.used);
+    }
+}
+
+impl Database {
+    /// The cached embedding (little-endian f32s) of each of `hashes`, `None`
+    /// where there is none. Every lookup counts as a hit or a miss of `model`,
+    /// in memory until the next flush.
+    pub async fn cached_embeddings(
+        &self,
+        model: &str,
+        input_type: &str,
+        hashes: &[TextHash],
+    ) -> DbResult<Vec<Option<Vec<u8>>>> {
+        let model = model.to_string();
+        let input_type = input_type.to_string();
+        let hashes = hashes.to_vec();
+        let found = self
+            .read(move |conn| {
+                let mut select = conn.prepare_cached(
+                    "SELECT embedding FROM embedding_cache
+                     WHERE model = ?1 AND input_type = ?2 AND text_hash = ?3",
+                )?;
+                let mut found = Vec::with_capacity(hashes.len());
+                for hash in &hashes {
+                    let embedding: Option<Vec<u8>> =
+                        select.query_row(params![model, input_type, &hash[..]], |row| row.get(0)).optional()?;
+                    found.push((*hash, embedding));
+                }
+                Ok::<_, rusqlite::Error>((model, input_type, found))
+            })
+            .await?;
+        let (model, input_type, found) = found;
+        let hits: Vec<TextHash> = found.iter().filter(|(_, e)| e.is_some()).map(|(hash, _)| *hash).collect();
+        self.cache_usage.record(&model, &input_type, &hits, found.len() - hits.len());
+        Ok(found.into_iter().map(|(_, embedding)| embedding).collect())
+    }
+
+    /// Write the hits, misses and last-use times counted since the last flush.
+    pub async fn flush_embedding_cache_usage(&self) -> DbResult<()> {
+        let usage = self.cache_usage.take();
+        if usage.counts.is_empty() {
+            return Ok(());
+        }
+        let cache_usage = self.cache_usage.clone();
+        self.write(move |conn| {
+            let written = write_usage(conn, &usage);
+            if written.is_err() {
+                cache_usage.restore(usage);
+            }
+            written
+        })
+        .await
+    }
+
+    /// Drop entries unused for `max_age_days`, then the least recently used
+    /// beyond `max_entries` (0 turns either limit off). Returns how many went.
+    pub async fn prune_embedding_cache(&self, max_entries: u64, max_age_days: u32) -> DbResult<usize> {
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let mut removed = 0;
+            if max_age_days > 0 {
+                removed += tx.execute(
+                    "DELETE FROM embedding_cache WHERE last_used_at < datetime('now', '-' || ?1 || ' days')",
+                    params![max_age_days],
+                )?;
+            }
+            if max_entries > 0 {
+                removed += tx.execute(
+                    "DELETE FROM embedding_cache WHERE (model, input_type, text_hash) IN (
+                         SELECT model, input_type, text_hash FROM embedding_cache
+                         ORDER BY last_used_at DESC LIMIT -1 OFFSET ?1
+                     )",
+                    params![max_entries as i64],
+                )?;
+            }
+            tx.commit()?;
+            Ok::<_, rusqlite::Error>(removed)
+        })
+        .await
+    }
+
+    /// Remember freshly fetched embeddings of `model`.
+    pub async fn cache_embeddings(&self, model: &str, input_type: &str, entries: Vec<(TextHash, Vec<u8>)>) -> DbResult<()> {
+        let model = model.to_string();
+        let input_type = input_type.to_string();
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            {
+                let mut insert = tx.prepare(
+                    "INSERT OR REPLACE INTO embedding_cache (model, input_type, text_hash, embedding)
+                     VALUES (?1, ?2, ?3, ?4)",
+                )?;
+                for (hash, embedding) in &entries {
+                    insert.execute(params![model, input_type, &hash[..], embedding])?;
+                }
+            }
+            tx.commit()
+        })
+        .await
+    }
+
+    /// Cache size and lookups so far, including those not flushed yet.
+    pub async fn embedding_cache_stats(&self) -> DbResult<EmbeddingCacheStats> {
+        let (unflushed_hits, unflushed_misses) = self.cache_usage.unflushed();
+        self.read(move |conn| {
+            let (entries, bytes) = conn.query_row(
+                "SELECT COUNT(*), COALESCE(SUM(length(embedding)), 0) FROM embedding_cache",
+                [],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+            )?;
+            let (hits, misses) = conn.query_row(
+                "SELECT COALESCE(SUM(hits), 0), COALESCE(SUM(misses), 0) FROM embedding_cache_stats",
+                [],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+            )?;
+            Ok::<_, rusqlite::Error>(EmbeddingCacheStats {
+                entries: entries as u64,
+                bytes: bytes as u64,
+                hits: hits as u64 + unflushed_hits,
+                misses: misses as u64 + unflushed_misses,
+            })
+        })
+        .await
+    }
+}
+
+fn write_usage(conn: &mut Connection, usage: &PendingUsage) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    {
+        let mut count = tx.prepare(
+            "INSERT INTO embedding_cache_stats (model, hits, misses) VALUES (?1, ?2, ?3)
+             ON CONFLICT(model) DO UPDATE SET hits = hits + excluded.hits, misses = misses + excluded.misses",
+        )?;
+        for (model, (hits, misses)) in &usage.counts {
+            count.execute(params![model, *hits as i64, *misses as i64])?;
+        }
+        let mut touch = tx.prepare(
+            "UPDATE embedding_cache SET last_used_at = datetime('now')
+             WHERE model = ?1 AND input_type = ?2 AND text_hash = ?3",
+        )?;
+        for (model, input_type, hash) in &usage.used {
+            touch.execute(params![model, input_type, &hash[..]])?;
+        }
+    }
+    tx.commit()
+}
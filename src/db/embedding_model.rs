@@ -0,0 +1,118 @@
+//! Which embedding model produced the stored vectors.
+//!
+//! `vector_settings` records the model name and vector size. Vectors from two
+//! models cannot be compared, so when the configured backend differs at
+//! startup every `embedding` / `embedding_q` and the vector indexes built over
+//! them are cleared; the startup migrations then embed facts and chunks again.
+
+use rusqlite::{Connection, OptionalExtension, params};
+
+use super::{Database, DbResult};
+
+/// What [`Database::check_embedding_model`] found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModelCheck {
+    /// The recorded model is the configured one.
+    Unchanged,
+    /// Nothing was recorded and no stored vector disagrees; the model is now recorded.
+    Recorded,
+    /// Another model produced the stored vectors, which were dropped.
+    Cleared { previous: String, facts: usize, chunks: usize },
+}
+
+/// The recorded model and vector size, if any.
+pub(super) fn recorded(conn: &Connection) -> rusqlite::Result<Option<(String, usize)>> {
+    let recorded: Option<(Option<String>, Option<i64>)> = conn
+        .query_row("SELECT embedding_model, embedding_dims FROM vector_settings WHERE id = 1", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .optional()?;
+    Ok(recorded.and_then(|(model, dims)| Some((model?, dims.unwrap_or_default() as usize))))
+}
+
+pub(super) fn record(conn: &Connection, model: &str, dims: usize) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO vector_settings (id, embedding_model, embedding_dims) VALUES (1, ?1, ?2)
+         ON CONFLICT(id) DO UPDATE SET embedding_model = excluded.embedding_model,
+                                       embedding_dims = excluded.embedding_dims",
+        params![model, dims as i64],
+    )?;
+    Ok(())
+}
+
+/// Size of some stored vector, if any row has one.
+fn stored_dims(conn: &Connection) -> rusqlite::Result<Option<usize>> {
+    let dims: Option<i64> = conn
+        .query_row(
+            "SELECT length(embedding) / 4 FROM (
+                 SELECT embedding FROM memory_facts WHERE embedding IS NOT NULL
+                 UNION ALL
+                 SELECT embedding FROM knowledge_chunks WHERE embedding IS NOT NULL
+             ) LIMIT 1",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(dims.map(|d| d as usize))
+}
+
+impl Database {
+    /// Compare `model` (producing `dims`-sized vectors) with the one recorded
+    /// for the stored embeddings, and record it. Databases from before the
+    /// model was recorded are taken to match unless their vectors are of
+    /// another size. On a mismatch the embeddings and vector indexes are cleared.
+    pub async fn check_embedding_model(&self, model: &str, dims: usize) -> DbResult<ModelCheck> {
+        let model = model.to_string();
+        let check = self
+            .write(move |conn| {
+                let tx = conn.transaction()?;
+                let previous = match recorded(&tx)? {
+                    Some((name, recorded_dims)) => {
+                        if name == model && recorded_dims == dims {
+                            return Ok::<_, rusqlite::Error>(ModelCheck::Unchanged);
+                        }
+                        Some(format!("{name} ({recorded_dims} dims)"))
+                    }
+                    None => stored_dims(&tx)?.filter(|d| *d != dims).map(|d| format!("an unrecorded model ({d} dims)")),
+                };
+                record(&tx, &model, dims)?;
+                let check = match previous {
+                    None => ModelCheck::Recorded,
+                    Some(previous) => {
+                        let facts = tx.execute(
+                            "UPDATE memory_facts SET embedding = NULL, embedding_q = NULL WHERE embedding IS NOT NULL",
+                            [],
+                        )?;
+                        let chunks = tx.execute(
+                            "UPDATE knowledge_chunks SET embedding = NULL, embedding_q = NULL WHERE embedding IS NOT NULL",
+                            [],
+                        )?;
+                        tx.execute_batch(
+                            "DELETE FROM vector_index_meta;
+                             DELETE FROM vector_index_nodes;
+                             DELETE FROM vector_index_log;",
+                        )?;
+                        ModelCheck::Cleared { previous, facts, chunks }
+                    }
+                };
+                tx.commit()?;
+                Ok(check)
+            })
+            .await?;
+        if matches!(check, ModelCheck::Cleared { .. }) {
+            self.vectors.clear();
+        }
+        Ok(check)
+    }
+
+    /// `(id, content)` of chunks that have no embedding, oldest first.
+    pub async fn get_unembedded_chunks(&self, limit: usize) -> DbResult<Vec<(i64, String)>> {
+        self.read(move |conn| {
+            let mut stmt =
+                conn.prepare("SELECT id, content FROM knowledge_chunks WHERE embedding IS NULL ORDER BY id LIMIT ?1")?;
+            let rows = stmt.query_map(params![limit as i64], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await
+    }
+}
@@ -0,0 +1,93 @@
+//! SQLCipher support. With the `sqlcipher` feature the whole database file
+//! (facts, documents, chat history, FTS indexes) is encrypted with the key
+//! from `ENCRYPTION_KEY`; without it a configured key is refused rather than
+//! silently ignored.
+
+use std::path::{Path, PathBuf};
+
+use rusqlite::{Connection, DatabaseName, OptionalExtension};
+
+#[derive(Debug, thiserror::Error)]
+pub enum EncryptionError {
+    #[error("ENCRYPTION_KEY is set but this binary was built without the `sqlcipher` feature")]
+    Unavailable,
+    #[error("re-keyed copy failed integrity check: {0}")]
+    Corrupt(String),
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Whether this binary was built against SQLCipher.
+pub fn is_available(conn: &Connection) -> rusqlite::Result<bool> {
+    let version: Option<String> = conn
+        .query_row("PRAGMA cipher_version", [], |row| row.get(0))
+        .optional()?;
+    Ok(version.is_some())
+}
+
+/// Key a freshly opened connection. Must run before any other statement.
+/// A wrong key surfaces here as "file is not a database".
+pub fn apply_key(conn: &Connection, key: &str) -> Result<(), EncryptionError> {
+    if !is_available(conn)? {
+        return Err(EncryptionError::Unavailable);
+    }
+    conn.pragma_update(None, "key", key)?;
+    conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0))?;
+    Ok(())
+}
+
+/// Re-encrypt the database file at `path` from `old_key` to `new_key`
+/// (`None` = plaintext on either side). The bot must not be running.
+///
+/// The data is copied with `sqlcipher_export` into a sibling file, checked,
+/// and then swapped in; the original is kept as `<path>.pre-rekey` until the
+/// caller removes it.
+pub fn rekey_database(path: &Path, old_key: Option<&str>, new_key: Option<&str>) -> Result<PathBuf, EncryptionError> {
+    let conn = Connection::open(path)?;
+    if !is_available(&conn)? {
+        return Err(EncryptionError::Unavailable);
+    }
+    if let Some(key) = old_key {
+        apply_key(&conn, key)?;
+    }
+    // Fold the WAL into the main file so the export sees every commit
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+
+    let tmp = sibling(path, "rekey");
+    let _ = std::fs::remove_file(&tmp);
+    conn.execute(
+        "ATTACH DATABASE ?1 AS rekeyed KEY ?2",
+        rusqlite::params![tmp.to_string_lossy(), new_key.unwrap_or("")],
+    )?;
+    conn.query_row("SELECT sqlcipher_export('rekeyed')", [], |_| Ok(()))?;
+    let user_version: i64 = conn.query_row("PRAGMA main.user_version", [], |row| row.get(0))?;
+    conn.pragma_update(Some(DatabaseName::Attached("rekeyed")), "user_version", user_version)?;
+    conn.execute("DETACH DATABASE rekeyed", [])?;
+    drop(conn);
+
+    // Refuse to swap in a copy that does not open cleanly with the new key
+    let check = Connection::open(&tmp)?;
+    if let Some(key) = new_key {
+        apply_key(&check, key)?;
+    }
+    let report: String = check.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+    drop(check);
+    if report != "ok" {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(EncryptionError::Corrupt(report));
+    }
+
+    let previous = sibling(path, "pre-rekey");
+    std::fs::rename(path, &previous)?;
+    std::fs::rename(&tmp, path)?;
+    for suffix in ["-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+    }
+    Ok(previous)
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    PathBuf::from(format!("{}.{suffix}", path.display()))
+}
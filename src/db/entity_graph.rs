@@ -0,0 +1,203 @@
+//! Consistency of the entity graph: `entity_mentions` rows point at facts and
+//! documents by `(source_type, source_id)` without a foreign key, so they are
+//! kept in line here and by the v9 delete triggers.
+//!
+//! A mention whose context snippet was found in the source text is "anchored":
+//! if a later edit removes the entity's name from the source, the mention is
+//! stale and goes. Mentions without a snippet (the extractor normalized the
+//! name to something not in the text) cannot be checked and are left alone.
+
+use rusqlite::{Connection, OptionalExtension, params};
+
+use super::{Database, DbResult};
+
+/// Entities younger than this are never collected, so one saved just before
+/// its first mention is not lost to a concurrent GC pass.
+const ORPHAN_GRACE: &str = "-1 hour";
+
+/// Characters of source text kept on each side of a mention.
+const CONTEXT_CHARS: usize = 30;
+
+/// Inconsistencies found (or repaired) in the entity graph.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EntityGraphReport {
+    /// Mentions whose source no longer exists, belongs to another owner, or
+    /// whose entity is missing.
+    pub dangling_mentions: usize,
+    /// Anchored mentions whose entity name is no longer in the source text.
+    pub stale_mentions: usize,
+    /// Entities with no mentions left.
+    pub orphan_entities: usize,
+}
+
+impl EntityGraphReport {
+    pub fn is_clean(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Database {
+    /// Count entity graph problems for `owner` (`None` = every owner).
+    pub async fn check_entity_graph(&self, owner: Option<u64>) -> DbResult<EntityGraphReport> {
+        self.read(move |conn| {
+            let (dangling, stale) = classify_mentions(conn, owner)?;
+            Ok::<_, rusqlite::Error>(EntityGraphReport {
+                dangling_mentions: dangling.len(),
+                stale_mentions: stale.len(),
+                orphan_entities: orphan_entities(conn, owner)?.len(),
+            })
+        })
+        .await
+    }
+
+    /// Delete dangling and stale mentions, then orphaned entities. Returns
+    /// what was removed.
+    pub async fn repair_entity_graph(&self, owner: Option<u64>) -> DbResult<EntityGraphReport> {
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let (dangling, stale) = classify_mentions(&tx, owner)?;
+            for id in dangling.iter().chain(&stale) {
+                tx.execute("DELETE FROM entity_mentions WHERE id = ?1", params![id])?;
+            }
+            let orphans = delete_orphan_entities(&tx, owner)?;
+            tx.commit()?;
+            Ok::<_, rusqlite::Error>(EntityGraphReport {
+                dangling_mentions: dangling.len(),
+                stale_mentions: stale.len(),
+                orphan_entities: orphans,
+            })
+        })
+        .await
+    }
+
+    /// Delete entities that are no longer mentioned anywhere. Mentions from
+    /// trashed facts/documents still count, so restoring one keeps its graph.
+    pub async fn gc_orphan_entities(&self, owner: Option<u64>) -> DbResult<usize> {
+        self.write(move |conn| delete_orphan_entities(conn, owner)).await
+    }
+}
+
+/// Snippet of `text` around the first case-insensitive occurrence of `name`,
+/// or `None` if the name does not appear.
+pub fn mention_context(text: &str, name: &str) -> Option<String> {
+    // Char-based indexing so multibyte text (Vietnamese, CJK) cannot panic.
+    // One lowercase char per char keeps both sequences the same length.
+    let fold = |c: char| c.to_lowercase().next().unwrap_or(c);
+    let chars: Vec<char> = text.chars().collect();
+    let lower_chars: Vec<char> = chars.iter().copied().map(fold).collect();
+    let name_chars: Vec<char> = name.chars().map(fold).collect();
+    if name_chars.is_empty() {
+        return None;
+    }
+
+    let pos = lower_chars.windows(name_chars.len()).position(|w| w == name_chars.as_slice())?;
+    let start = pos.saturating_sub(CONTEXT_CHARS);
+    let end = (pos + name_chars.len() + CONTEXT_CHARS).min(chars.len());
+    Some(chars[start..end].iter().collect())
+}
+
+/// Text the extractor sees for a source: `title\n\ncontent` for documents,
+/// the fact itself for facts. `None` if the source does not exist.
+pub(super) fn source_text(conn: &Connection, source_type: &str, source_id: i64) -> rusqlite::Result<Option<String>> {
+    let sql = match source_type {
+        "document" => "SELECT title || char(10) || char(10) || content FROM knowledge_documents WHERE id = ?1",
+        "fact" => "SELECT fact FROM memory_facts WHERE id = ?1",
+        _ => return Ok(None),
+    };
+    conn.query_row(sql, params![source_id], |row| row.get(0)).optional()
+}
+
+/// Re-anchor the mentions of one source after its text changed: refresh the
+/// context snippet, or drop the mention if the entity name is gone.
+/// Returns the number of mentions removed. Runs inside the caller's transaction.
+pub(super) fn refresh_mentions(conn: &Connection, source_type: &str, source_id: i64) -> rusqlite::Result<usize> {
+    let Some(text) = source_text(conn, source_type, source_id)? else {
+        return Ok(0);
+    };
+    let mentions: Vec<(i64, String)> = {
+        let mut stmt = conn.prepare(
+            "SELECT em.id, e.name FROM entity_mentions em JOIN entities e ON e.id = em.entity_id
+             WHERE em.source_type = ?1 AND em.source_id = ?2 AND em.context IS NOT NULL",
+        )?;
+        let rows = stmt.query_map(params![source_type, source_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+
+    let mut removed = 0;
+    for (id, name) in mentions {
+        match mention_context(&text, &name) {
+            Some(context) => {
+                conn.execute("UPDATE entity_mentions SET context = ?1 WHERE id = ?2", params![context, id])?;
+            }
+            None => {
+                conn.execute("DELETE FROM entity_mentions WHERE id = ?1", params![id])?;
+                removed += 1;
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// Split problem mentions into `(dangling, stale)` ids. A mention belongs to
+/// its entity's owner, or to its source's owner once the entity is gone.
+fn classify_mentions(conn: &Connection, owner: Option<u64>) -> rusqlite::Result<(Vec<i64>, Vec<i64>)> {
+    let mut stmt = conn.prepare(
+        "SELECT * FROM (
+         SELECT em.id, e.user_id AS entity_owner, e.name, em.context,
+                CASE em.source_type
+                    WHEN 'document' THEN (SELECT user_id FROM knowledge_documents WHERE id = em.source_id)
+                    WHEN 'fact' THEN (SELECT user_id FROM memory_facts WHERE id = em.source_id)
+                END AS source_owner,
+                CASE em.source_type
+                    WHEN 'document' THEN (SELECT title || char(10) || char(10) || content FROM knowledge_documents WHERE id = em.source_id)
+                    WHEN 'fact' THEN (SELECT fact FROM memory_facts WHERE id = em.source_id)
+                END
+         FROM entity_mentions em
+         LEFT JOIN entities e ON e.id = em.entity_id
+         )
+         WHERE ?1 IS NULL OR entity_owner = ?1 OR (entity_owner IS NULL AND source_owner = ?1)",
+    )?;
+    let rows = stmt.query_map(params![owner.map(|o| o as i64)], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, Option<i64>>(1)?,
+            row.get::<_, Option<String>>(2)?,
+            row.get::<_, Option<String>>(3)?,
+            row.get::<_, Option<i64>>(4)?,
+            row.get::<_, Option<String>>(5)?,
+        ))
+    })?;
+
+    let (mut dangling, mut stale) = (Vec::new(), Vec::new());
+    for row in rows {
+        let (id, entity_owner, name, context, source_owner, text) = row?;
+        match (entity_owner, name, source_owner, text) {
+            (Some(entity_owner), Some(name), Some(source_owner), Some(text)) if entity_owner == source_owner => {
+                if context.is_some() && mention_context(&text, &name).is_none() {
+                    stale.push(id);
+                }
+            }
+            _ => dangling.push(id),
+        }
+    }
+    Ok((dangling, stale))
+}
+
+fn orphan_entities(conn: &Connection, owner: Option<u64>) -> rusqlite::Result<Vec<i64>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id FROM entities e
+         WHERE NOT EXISTS (SELECT 1 FROM entity_mentions em WHERE em.entity_id = e.id)
+           AND created_at < datetime('now', '{ORPHAN_GRACE}')
+           AND (?1 IS NULL OR user_id = ?1)"
+    ))?;
+    let rows = stmt.query_map(params![owner.map(|o| o as i64)], |row| row.get(0))?;
+    rows.collect()
+}
+
+fn delete_orphan_entities(conn: &Connection, owner: Option<u64>) -> rusqlite::Result<usize> {
+    let ids = orphan_entities(conn, owner)?;
+    for id in &ids {
+        conn.execute("DELETE FROM entities WHERE id = ?1", params![id])?;
+    }
+    Ok(ids.len())
+}
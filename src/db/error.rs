@@ -0,0 +1,19 @@
+use super::MigrationError;
+
+pub type DbResult<T> = Result<T, DbError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DbError {
+    #[error("{0} not found.")]
+    NotFound(String),
+    #[error("{0}")]
+    Conflict(String),
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    #[error(transparent)]
+    Migration(#[from] MigrationError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Database task failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
+}
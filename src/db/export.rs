@@ -0,0 +1,646 @@
+//! Per-owner export bundles: a ZIP of JSONL files (one per table) plus a
+//! `manifest.json`. Import remaps every id, so a bundle can be merged into
+//! any owner — including one that already has data.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Seek, Write};
+
+use base64::Engine;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use super::{Database, DbResult, embedding_model, migrations};
+
+/// Identifies a bundle written by this crate.
+pub const BUNDLE_FORMAT: &str = "memory-assistant-kb";
+/// Bump when the layout of the JSONL records changes incompatibly.
+/// v2: the manifest names the embedding model.
+pub const BUNDLE_VERSION: u32 = 2;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BundleError {
+    #[error("not a memory-assistant export (format {0:?})")]
+    UnknownFormat(String),
+    #[error("bundle version {found} is newer than this binary supports ({supported})")]
+    TooNew { found: u32, supported: u32 },
+    #[error("{file} line {line}: {source}")]
+    Record {
+        file: &'static str,
+        line: usize,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Zip(#[from] zip::result::ZipError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub version: u32,
+    pub schema_version: i64,
+    pub owner_id: u64,
+    pub exported_at: String,
+    /// Whether fact and chunk records carry their embeddings.
+    pub embeddings: bool,
+    /// Model and vector size the embeddings came from, when the source had them recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding_model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding_dims: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CategoryRecord {
+    pub name: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FactRecord {
+    pub id: i64,
+    pub fact: String,
+    pub category: String,
+    pub created_at: String,
+    pub access_count: i64,
+    pub last_accessed_at: Option<String>,
+    pub deleted_at: Option<String>,
+    /// Base64 of the little-endian f32 embedding.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentRecord {
+    pub id: i64,
+    pub title: String,
+    pub content: String,
+    pub source: Option<String>,
+    pub tags: Option<String>,
+    pub created_at: String,
+    pub deleted_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkRecord {
+    pub doc_id: i64,
+    pub chunk_index: i64,
+    pub start_line: i64,
+    pub end_line: i64,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityRecord {
+    pub id: i64,
+    pub name: String,
+    pub entity_type: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MentionRecord {
+    pub entity_id: i64,
+    pub source_type: String,
+    pub source_id: i64,
+    pub context: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KbLinkRecord {
+    pub fact_id: i64,
+    pub doc_id: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FactRelationRecord {
+    pub fact_id_1: i64,
+    pub fact_id_2: i64,
+    pub similarity: f64,
+}
+
+/// Everything one KB owner has, with ids as they were in the source database.
+#[derive(Debug, Clone)]
+pub struct ExportBundle {
+    pub manifest: Manifest,
+    pub categories: Vec<CategoryRecord>,
+    pub facts: Vec<FactRecord>,
+    pub documents: Vec<DocumentRecord>,
+    pub chunks: Vec<ChunkRecord>,
+    pub entities: Vec<EntityRecord>,
+    pub mentions: Vec<MentionRecord>,
+    pub kb_links: Vec<KbLinkRecord>,
+    pub fact_relations: Vec<FactRelationRecord>,
+}
+
+/// What an import added, and how many records matched existing rows instead.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportStats {
+    pub categories: usize,
+    pub facts: usize,
+    pub documents: usize,
+    pub chunks: usize,
+    pub entities: usize,
+    pub mentions: usize,
+    pub kb_links: usize,
+    pub fact_relations: usize,
+    /// Facts and documents already present in the target owner.
+    pub merged: usize,
+    /// The bundle's embeddings came from another model (or an unknown one) and were left out.
+    pub embeddings_dropped: bool,
+}
+
+impl std::fmt::Display for ImportStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} facts, {} documents ({} chunks), {} categories, {} entities, {} mentions, {} links, {} relations; {} already present",
+            self.facts,
+            self.documents,
+            self.chunks,
+            self.categories,
+            self.entities,
+            self.mentions,
+            self.kb_links,
+            self.fact_relations,
+            self.merged
+        )?;
+        if self.embeddings_dropped {
+            write!(f, "; embeddings from another model left out, re-embedded on the next start")?;
+        }
+        Ok(())
+    }
+}
+
+impl ExportBundle {
+    /// Write the bundle as a ZIP archive.
+    pub fn write_zip<W: Write + Seek>(&self, writer: W) -> Result<(), BundleError> {
+        let mut zip = zip::ZipWriter::new(writer);
+        let options = zip::write::SimpleFileOptions::default();
+
+        zip.start_file("manifest.json", options)?;
+        serde_json::to_writer_pretty(&mut zip, &self.manifest)?;
+
+        write_jsonl(&mut zip, options, "categories.jsonl", &self.categories)?;
+        write_jsonl(&mut zip, options, "facts.jsonl", &self.facts)?;
+        write_jsonl(&mut zip, options, "documents.jsonl", &self.documents)?;
+        write_jsonl(&mut zip, options, "chunks.jsonl", &self.chunks)?;
+        write_jsonl(&mut zip, options, "entities.jsonl", &self.entities)?;
+        write_jsonl(&mut zip, options, "mentions.jsonl", &self.mentions)?;
+        write_jsonl(&mut zip, options, "kb_links.jsonl", &self.kb_links)?;
+        write_jsonl(&mut zip, options, "fact_relations.jsonl", &self.fact_relations)?;
+
+        zip.finish()?;
+        Ok(())
+    }
+
+    /// Read a bundle written by [`ExportBundle::write_zip`]. Missing JSONL files are treated as empty.
+    pub fn read_zip<R: Read + Seek>(reader: R) -> Result<Self, BundleError> {
+        let mut zip = zip::ZipArchive::new(reader)?;
+
+        let manifest: Manifest = serde_json::from_reader(zip.by_name("manifest.json")?)?;
+        if manifest.format != BUNDLE_FORMAT {
+            return Err(BundleError::UnknownFormat(manifest.format));
+        }
+        if manifest.version > BUNDLE_VERSION {
+            return Err(BundleError::TooNew { found: manifest.version, supported: BUNDLE_VERSION });
+        }
+
+        Ok(Self {
+            manifest,
+            categories: read_jsonl(&mut zip, "categories.jsonl")?,
+            facts: read_jsonl(&mut zip, "facts.jsonl")?,
+            documents: read_jsonl(&mut zip, "documents.jsonl")?,
+            chunks: read_jsonl(&mut zip, "chunks.jsonl")?,
+            entities: read_jsonl(&mut zip, "entities.jsonl")?,
+            mentions: read_jsonl(&mut zip, "mentions.jsonl")?,
+            kb_links: read_jsonl(&mut zip, "kb_links.jsonl")?,
+            fact_relations: read_jsonl(&mut zip, "fact_relations.jsonl")?,
+        })
+    }
+}
+
+fn write_jsonl<W: Write + Seek, T: Serialize>(
+    zip: &mut zip::ZipWriter<W>,
+    options: zip::write::SimpleFileOptions,
+    name: &str,
+    records: &[T],
+) -> Result<(), BundleError> {
+    zip.start_file(name, options)?;
+    for record in records {
+        serde_json::to_writer(&mut *zip, record)?;
+        zip.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+fn read_jsonl<R: Read + Seek, T: DeserializeOwned>(
+    zip: &mut zip::ZipArchive<R>,
+    name: &'static str,
+) -> Result<Vec<T>, BundleError> {
+    let file = match zip.by_name(name) {
+        Ok(f) => f,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut records = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line)
+            .map_err(|source| BundleError::Record { file: name, line: i + 1, source })?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+fn encode_embedding(blob: Option<Vec<u8>>) -> Option<String> {
+    blob.map(|b| base64::engine::general_purpose::STANDARD.encode(b))
+}
+
+fn decode_embedding(encoded: Option<&str>) -> Option<Vec<u8>> {
+    encoded.and_then(|s| base64::engine::general_purpose::STANDARD.decode(s).ok())
+}
+
+impl Database {
+    /// Snapshot everything `owner_id` has, trashed items included.
+    /// Embeddings are only included when `with_embeddings` is set.
+    pub async fn export_owner(&self, owner_id: u64, with_embeddings: bool) -> DbResult<ExportBundle> {
+        self.read(move |conn| {
+            // One read transaction so every table comes from the same snapshot
+            let tx = conn.unchecked_transaction()?;
+            let bundle = export_tables(&tx, owner_id, with_embeddings)?;
+            tx.finish()?;
+            Ok::<_, rusqlite::Error>(bundle)
+        })
+        .await
+    }
+
+    /// Merge a bundle into `owner_id` in a single transaction.
+    ///
+    /// Facts (same text and category) and documents (same title and content)
+    /// that already exist are reused instead of duplicated. Chunks are only
+    /// imported when the bundle carries embeddings from the model this
+    /// database's vectors come from; otherwise the embeddings are dropped and
+    /// the documents left unchunked, to be re-indexed by the startup migration.
+    pub async fn import_owner(&self, owner_id: u64, bundle: ExportBundle) -> DbResult<ImportStats> {
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let stats = import_tables(&tx, owner_id, &bundle)?;
+            tx.commit()?;
+            Ok::<_, rusqlite::Error>(stats)
+        })
+        .await
+    }
+}
+
+fn export_tables(conn: &Connection, owner_id: u64, with_embeddings: bool) -> rusqlite::Result<ExportBundle> {
+    let owner = owner_id as i64;
+
+    let categories = {
+        let mut stmt = conn.prepare("SELECT name, created_at FROM categories WHERE user_id = ?1 ORDER BY id")?;
+        let rows = stmt.query_map(params![owner], |row| {
+            Ok(CategoryRecord { name: row.get(0)?, created_at: row.get(1)? })
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+
+    let facts = {
+        let mut stmt = conn.prepare(
+            "SELECT id, fact, category, created_at, access_count, last_accessed_at, deleted_at, embedding
+             FROM memory_facts WHERE user_id = ?1 ORDER BY id"
+        )?;
+        let rows = stmt.query_map(params![owner], |row| {
+            Ok(FactRecord {
+                id: row.get(0)?,
+                fact: row.get(1)?,
+                category: row.get(2)?,
+                created_at: row.get(3)?,
+                access_count: row.get(4)?,
+                last_accessed_at: row.get(5)?,
+                deleted_at: row.get(6)?,
+                embedding: if with_embeddings { encode_embedding(row.get(7)?) } else { None },
+            })
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+
+    let documents = {
+        let mut stmt = conn.prepare(
+            "SELECT id, title, content, source, tags, created_at, deleted_at
+             FROM knowledge_documents WHERE user_id = ?1 ORDER BY id"
+        )?;
+        let rows = stmt.query_map(params![owner], |row| {
+            Ok(DocumentRecord {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                content: row.get(2)?,
+                source: row.get(3)?,
+                tags: row.get(4)?,
+                created_at: row.get(5)?,
+                deleted_at: row.get(6)?,
+            })
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+
+    let chunks = {
+        let mut stmt = conn.prepare(
+            "SELECT kc.doc_id, kc.chunk_index, kc.start_line, kc.end_line, kc.content, kc.embedding
+             FROM knowledge_chunks kc
+             JOIN knowledge_documents kd ON kd.id = kc.doc_id
+             WHERE kd.user_id = ?1 ORDER BY kc.doc_id, kc.chunk_index"
+        )?;
+        let rows = stmt.query_map(params![owner], |row| {
+            Ok(ChunkRecord {
+                doc_id: row.get(0)?,
+                chunk_index: row.get(1)?,
+                start_line: row.get(2)?,
+                end_line: row.get(3)?,
+                content: row.get(4)?,
+                embedding: if with_embeddings { encode_embedding(row.get(5)?) } else { None },
+            })
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+
+    let entities = {
+        let mut stmt = conn.prepare(
+            "SELECT id, name, entity_type, created_at FROM entities WHERE user_id = ?1 ORDER BY id"
+        )?;
+        let rows = stmt.query_map(params![owner], |row| {
+            Ok(EntityRecord {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                entity_type: row.get(2)?,
+                created_at: row.get(3)?,
+            })
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+
+    let mentions = {
+        let mut stmt = conn.prepare(
+            "SELECT em.entity_id, em.source_type, em.source_id, em.context, em.created_at
+             FROM entity_mentions em
+             JOIN entities e ON e.id = em.entity_id
+             WHERE e.user_id = ?1 ORDER BY em.id"
+        )?;
+        let rows = stmt.query_map(params![owner], |row| {
+            Ok(MentionRecord {
+                entity_id: row.get(0)?,
+                source_type: row.get(1)?,
+                source_id: row.get(2)?,
+                context: row.get(3)?,
+                created_at: row.get(4)?,
+            })
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+
+    let kb_links = {
+        let mut stmt = conn.prepare(
+            "SELECT mkl.fact_id, mkl.doc_id FROM memory_kb_links mkl
+             JOIN memory_facts mf ON mf.id = mkl.fact_id
+             WHERE mf.user_id = ?1 ORDER BY mkl.id"
+        )?;
+        let rows = stmt.query_map(params![owner], |row| {
+            Ok(KbLinkRecord { fact_id: row.get(0)?, doc_id: row.get(1)? })
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+
+    let fact_relations = {
+        let mut stmt = conn.prepare(
+            "SELECT fr.fact_id_1, fr.fact_id_2, fr.similarity FROM fact_relations fr
+             JOIN memory_facts mf ON mf.id = fr.fact_id_1
+             WHERE mf.user_id = ?1 ORDER BY fr.id"
+        )?;
+        let rows = stmt.query_map(params![owner], |row| {
+            Ok(FactRelationRecord {
+                fact_id_1: row.get(0)?,
+                fact_id_2: row.get(1)?,
+                similarity: row.get(2)?,
+            })
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+
+    let model = if with_embeddings { embedding_model::recorded(conn)? } else { None };
+    Ok(ExportBundle {
+        manifest: Manifest {
+            format: BUNDLE_FORMAT.to_string(),
+            version: BUNDLE_VERSION,
+            schema_version: migrations::current_version(conn)?,
+            owner_id,
+            exported_at: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            embeddings: with_embeddings,
+            embedding_dims: model.as_ref().map(|(_, dims)| *dims),
+            embedding_model: model.map(|(name, _)| name),
+        },
+        categories,
+        facts,
+        documents,
+        chunks,
+        entities,
+        mentions,
+        kb_links,
+        fact_relations,
+    })
+}
+
+/// Whether the bundle's embeddings came from the model recorded for this
+/// database. A database with none recorded takes the bundle's, so the startup
+/// check clears them if the configured model differs.
+fn embeddings_fit(conn: &Connection, manifest: &Manifest) -> rusqlite::Result<bool> {
+    let bundle = manifest.embedding_model.clone().zip(manifest.embedding_dims);
+    match (embedding_model::recorded(conn)?, bundle) {
+        (Some(target), Some(bundle)) => Ok(target == bundle),
+        (Some(_), None) => Ok(false),
+        (None, Some((model, dims))) => {
+            embedding_model::record(conn, &model, dims)?;
+            Ok(true)
+        }
+        (None, None) => Ok(true),
+    }
+}
+
+fn import_tables(conn: &Connection, owner_id: u64, bundle: &ExportBundle) -> rusqlite::Result<ImportStats> {
+    let owner = owner_id as i64;
+    let mut stats = ImportStats::default();
+    let embeddings = bundle.manifest.embeddings && embeddings_fit(conn, &bundle.manifest)?;
+    stats.embeddings_dropped = bundle.manifest.embeddings && !embeddings;
+
+    for cat in &bundle.categories {
+        stats.categories += conn.execute(
+            "INSERT OR IGNORE INTO categories (user_id, name, created_at) VALUES (?1, ?2, ?3)",
+            params![owner, cat.name, cat.created_at],
+        )?;
+    }
+
+    // Old fact id → id in this database
+    let mut fact_ids: HashMap<i64, i64> = HashMap::new();
+    for fact in &bundle.facts {
+        let existing: Option<i64> = conn
+            .query_row(
+                "SELECT id FROM memory_facts WHERE user_id = ?1 AND fact = ?2 AND category = ?3",
+                params![owner, fact.fact, fact.category],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let id = match existing {
+            Some(id) => {
+                stats.merged += 1;
+                id
+            }
+            None => {
+                conn.execute(
+                    "INSERT INTO memory_facts (user_id, fact, category, created_at, access_count, last_accessed_at, deleted_at, embedding)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![
+                        owner,
+                        fact.fact,
+                        fact.category,
+                        fact.created_at,
+                        fact.access_count,
+                        fact.last_accessed_at,
+                        fact.deleted_at,
+                        if embeddings { decode_embedding(fact.embedding.as_deref()) } else { None },
+                    ],
+                )?;
+                stats.facts += 1;
+                conn.last_insert_rowid()
+            }
+        };
+        fact_ids.insert(fact.id, id);
+    }
+
+    // Old doc id → (id in this database, newly inserted)
+    let mut doc_ids: HashMap<i64, (i64, bool)> = HashMap::new();
+    for doc in &bundle.documents {
+        let existing: Option<i64> = conn
+            .query_row(
+                "SELECT id FROM knowledge_documents WHERE user_id = ?1 AND title = ?2 AND content = ?3",
+                params![owner, doc.title, doc.content],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let entry = match existing {
+            Some(id) => {
+                stats.merged += 1;
+                (id, false)
+            }
+            None => {
+                conn.execute(
+                    "INSERT INTO knowledge_documents (user_id, title, content, source, tags, created_at, deleted_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![owner, doc.title, doc.content, doc.source, doc.tags, doc.created_at, doc.deleted_at],
+                )?;
+                let id = conn.last_insert_rowid();
+                conn.execute(
+                    "INSERT INTO knowledge_document_versions (doc_id, version, content, tool) VALUES (?1, 1, ?2, 'import')",
+                    params![id, doc.content],
+                )?;
+                stats.documents += 1;
+                (id, true)
+            }
+        };
+        doc_ids.insert(doc.id, entry);
+    }
+
+    // Without usable embeddings, leave the documents unchunked so the startup
+    // migration chunks and embeds them instead.
+    if embeddings {
+        for chunk in &bundle.chunks {
+            let Some(&(doc_id, true)) = doc_ids.get(&chunk.doc_id) else {
+                continue;
+            };
+            stats.chunks += conn.execute(
+                "INSERT INTO knowledge_chunks (doc_id, chunk_index, start_line, end_line, content, embedding)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    doc_id,
+                    chunk.chunk_index,
+                    chunk.start_line,
+                    chunk.end_line,
+                    chunk.content,
+                    decode_embedding(chunk.embedding.as_deref()),
+                ],
+            )?;
+        }
+    }
+
+    let mut entity_ids: HashMap<i64, i64> = HashMap::new();
+    for entity in &bundle.entities {
+        stats.entities += conn.execute(
+            "INSERT OR IGNORE INTO entities (user_id, name, entity_type, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![owner, entity.name, entity.entity_type, entity.created_at],
+        )?;
+        let id: i64 = conn.query_row(
+            "SELECT id FROM entities WHERE user_id = ?1 AND name = ?2 AND entity_type = ?3",
+            params![owner, entity.name, entity.entity_type],
+            |row| row.get(0),
+        )?;
+        entity_ids.insert(entity.id, id);
+    }
+
+    for mention in &bundle.mentions {
+        let Some(&entity_id) = entity_ids.get(&mention.entity_id) else {
+            continue;
+        };
+        let source_id = match mention.source_type.as_str() {
+            "fact" => fact_ids.get(&mention.source_id).copied(),
+            "document" => doc_ids.get(&mention.source_id).map(|&(id, _)| id),
+            _ => None,
+        };
+        let Some(source_id) = source_id else {
+            continue;
+        };
+        let exists: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM entity_mentions
+             WHERE entity_id = ?1 AND source_type = ?2 AND source_id = ?3 AND context IS ?4",
+            params![entity_id, mention.source_type, source_id, mention.context],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            stats.mentions += conn.execute(
+                "INSERT INTO entity_mentions (entity_id, source_type, source_id, context, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![entity_id, mention.source_type, source_id, mention.context, mention.created_at],
+            )?;
+        }
+    }
+
+    for link in &bundle.kb_links {
+        if let (Some(&fact_id), Some(&(doc_id, _))) = (fact_ids.get(&link.fact_id), doc_ids.get(&link.doc_id)) {
+            stats.kb_links += conn.execute(
+                "INSERT OR IGNORE INTO memory_kb_links (fact_id, doc_id) VALUES (?1, ?2)",
+                params![fact_id, doc_id],
+            )?;
+        }
+    }
+
+    for rel in &bundle.fact_relations {
+        if let (Some(&a), Some(&b)) = (fact_ids.get(&rel.fact_id_1), fact_ids.get(&rel.fact_id_2)) {
+            if a == b {
+                continue;
+            }
+            let (lo, hi) = if a < b { (a, b) } else { (b, a) };
+            stats.fact_relations += conn.execute(
+                "INSERT OR IGNORE INTO fact_relations (fact_id_1, fact_id_2, similarity) VALUES (?1, ?2, ?3)",
+                params![lo, hi, rel.similarity],
+            )?;
+        }
+    }
+
+    Ok(stats)
+}
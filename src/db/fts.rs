@@ -0,0 +1,197 @@
+//! Full-text query building and the optional trigram indexes.
+//!
+//! The FTS tables use `unicode61 remove_diacritics 2`, which folds tone marks
+//! ("hợp" = "hop") but keeps "đ" as a letter of its own. Queries are therefore
+//! rewritten so every d/đ in a token matches both spellings: "hop dong" finds
+//! "hợp đồng", and "hợp đồng" finds text typed without diacritics.
+//!
+//! With `FTS_TRIGRAM` on, `*_tri` tables index the same columns with the
+//! trigram tokenizer. Searches fall back to them when the word index finds
+//! nothing, which catches substrings inside long compound tokens.
+
+use rusqlite::Connection;
+
+use super::{Database, DbResult};
+
+/// Weight of a document title relative to its content in BM25 ranking.
+pub const TITLE_WEIGHT: f64 = 10.0;
+
+/// Tokens with more d/đ than this get only their all-"d" and all-"đ"
+/// spellings instead of every combination.
+const MAX_D_LETTERS: usize = 3;
+
+/// Trigram tables are matched on at least this many characters.
+const MIN_TRIGRAM_CHARS: usize = 3;
+
+const TRIGRAM_SCHEMA: &str = "
+    CREATE VIRTUAL TABLE IF NOT EXISTS memory_facts_tri USING fts5(
+        fact, content='memory_facts', content_rowid='id', tokenize='trigram remove_diacritics 1'
+    );
+    CREATE TRIGGER IF NOT EXISTS memory_facts_tri_ai AFTER INSERT ON memory_facts BEGIN
+        INSERT INTO memory_facts_tri(rowid, fact) VALUES (new.id, new.fact);
+    END;
+    CREATE TRIGGER IF NOT EXISTS memory_facts_tri_ad AFTER DELETE ON memory_facts BEGIN
+        INSERT INTO memory_facts_tri(memory_facts_tri, rowid, fact) VALUES('delete', old.id, old.fact);
+    END;
+    CREATE TRIGGER IF NOT EXISTS memory_facts_tri_au AFTER UPDATE OF fact ON memory_facts BEGIN
+        INSERT INTO memory_facts_tri(memory_facts_tri, rowid, fact) VALUES('delete', old.id, old.fact);
+        INSERT INTO memory_facts_tri(rowid, fact) VALUES (new.id, new.fact);
+    END;
+
+    CREATE VIRTUAL TABLE IF NOT EXISTS knowledge_docs_tri USING fts5(
+        title, content, content='knowledge_documents', content_rowid='id', tokenize='trigram remove_diacritics 1'
+    );
+    CREATE TRIGGER IF NOT EXISTS knowledge_docs_tri_ai AFTER INSERT ON knowledge_documents BEGIN
+        INSERT INTO knowledge_docs_tri(rowid, title, content) VALUES (new.id, new.title, new.content);
+    END;
+    CREATE TRIGGER IF NOT EXISTS knowledge_docs_tri_ad AFTER DELETE ON knowledge_documents BEGIN
+        INSERT INTO knowledge_docs_tri(knowledge_docs_tri, rowid, title, content) VALUES('delete', old.id, old.title, old.content);
+    END;
+    CREATE TRIGGER IF NOT EXISTS knowledge_docs_tri_au AFTER UPDATE OF title, content ON knowledge_documents BEGIN
+        INSERT INTO knowledge_docs_tri(knowledge_docs_tri, rowid, title, content) VALUES('delete', old.id, old.title, old.content);
+        INSERT INTO knowledge_docs_tri(rowid, title, content) VALUES (new.id, new.title, new.content);
+    END;
+
+    CREATE VIRTUAL TABLE IF NOT EXISTS knowledge_chunks_tri USING fts5(
+        content, content='knowledge_chunks', content_rowid='id', tokenize='trigram remove_diacritics 1'
+    );
+    CREATE TRIGGER IF NOT EXISTS knowledge_chunks_tri_ai AFTER INSERT ON knowledge_chunks BEGIN
+        INSERT INTO knowledge_chunks_tri(rowid, content) VALUES (new.id, new.content);
+    END;
+    CREATE TRIGGER IF NOT EXISTS knowledge_chunks_tri_ad AFTER DELETE ON knowledge_chunks BEGIN
+        INSERT INTO knowledge_chunks_tri(knowledge_chunks_tri, rowid, content) VALUES('delete', old.id, old.content);
+    END;
+    CREATE TRIGGER IF NOT EXISTS knowledge_chunks_tri_au AFTER UPDATE OF content ON knowledge_chunks BEGIN
+        INSERT INTO knowledge_chunks_tri(knowledge_chunks_tri, rowid, content) VALUES('delete', old.id, old.content);
+        INSERT INTO knowledge_chunks_tri(rowid, content) VALUES (new.id, new.content);
+    END;
+";
+
+const TRIGRAM_TABLES: &[&str] = &["memory_facts_tri", "knowledge_docs_tri", "knowledge_chunks_tri"];
+
+impl Database {
+    /// Create (and fill) or drop the trigram indexes to match `enabled`.
+    /// Returns whether anything changed.
+    pub async fn set_trigram_index(&self, enabled: bool) -> DbResult<bool> {
+        self.write(move |conn| {
+            if has_trigram_index(conn)? == enabled {
+                return Ok(false);
+            }
+            let tx = conn.transaction()?;
+            if enabled {
+                tx.execute_batch(TRIGRAM_SCHEMA)?;
+                for table in TRIGRAM_TABLES {
+                    tx.execute(&format!("INSERT INTO {table}({table}) VALUES('rebuild')"), [])?;
+                }
+            } else {
+                for table in TRIGRAM_TABLES {
+                    for suffix in ["ai", "ad", "au"] {
+                        tx.execute(&format!("DROP TRIGGER IF EXISTS {table}_{suffix}"), [])?;
+                    }
+                    tx.execute(&format!("DROP TABLE IF EXISTS {table}"), [])?;
+                }
+            }
+            tx.commit()?;
+            Ok::<_, rusqlite::Error>(true)
+        })
+        .await
+    }
+}
+
+/// Whether the trigram indexes exist.
+pub fn has_trigram_index(conn: &Connection) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT COUNT(*) = 3 FROM sqlite_master WHERE type = 'table' AND name IN ('memory_facts_tri', 'knowledge_docs_tri', 'knowledge_chunks_tri')",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// FTS5 expression for a free-text query: every word must match, in any
+/// d/đ spelling. Words are joined with an explicit `AND`, since FTS5 does not
+/// accept implicit AND next to a parenthesized group. A trailing `*` keeps prefix matching and a bare `OR` between
+/// words is kept as an operator. `None` if the input has no words.
+pub fn match_query(input: &str) -> Option<String> {
+    let mut query = String::new();
+    let mut pending_or = false;
+    for raw in input.split_whitespace() {
+        if raw == "OR" {
+            pending_or = !query.is_empty();
+            continue;
+        }
+        let words: Vec<&str> = raw.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).collect();
+        for (i, word) in words.iter().enumerate() {
+            let star = if raw.ends_with('*') && i + 1 == words.len() { "*" } else { "" };
+            let variants: Vec<String> = d_variants(&word.to_lowercase())
+                .into_iter()
+                .map(|v| format!("\"{v}\"{star}"))
+                .collect();
+            let term = if variants.len() == 1 {
+                variants.concat()
+            } else {
+                format!("({})", variants.join(" OR "))
+            };
+            if !query.is_empty() {
+                query.push_str(if std::mem::take(&mut pending_or) { " OR " } else { " AND " });
+            }
+            query.push_str(&term);
+        }
+    }
+    (!query.is_empty()).then_some(query)
+}
+
+/// FTS5 expression matching the words of `input` as one phrase, in any d/đ
+/// spelling. `None` if the input has no words.
+pub fn phrase_query(input: &str) -> Option<String> {
+    let words: Vec<String> = input
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    if words.is_empty() {
+        return None;
+    }
+    let variants: Vec<String> = d_variants(&words.join(" ")).into_iter().map(|v| format!("\"{v}\"")).collect();
+    Some(variants.join(" OR "))
+}
+
+/// Trigram expression matching `input` as a substring, in any d/đ spelling.
+/// `None` if the input is too short for trigrams.
+pub fn substring_query(input: &str) -> Option<String> {
+    let text = input.trim().to_lowercase();
+    if text.chars().count() < MIN_TRIGRAM_CHARS {
+        return None;
+    }
+    let variants: Vec<String> = d_variants(&text)
+        .into_iter()
+        .map(|v| format!("\"{}\"", v.replace('"', "\"\"")))
+        .collect();
+    Some(variants.join(" OR "))
+}
+
+/// Every spelling of `word` with each d/đ as either letter (lowercase input).
+/// Also used on whole phrases, where the cap applies across all words.
+fn d_variants(word: &str) -> Vec<String> {
+    let count = word.chars().filter(|c| matches!(c, 'd' | 'đ')).count();
+    if count == 0 {
+        return vec![word.to_string()];
+    }
+    let with = |pick: &dyn Fn(usize) -> bool| -> String {
+        let mut k = 0;
+        word.chars()
+            .map(|c| {
+                if matches!(c, 'd' | 'đ') {
+                    let letter = if pick(k) { 'đ' } else { 'd' };
+                    k += 1;
+                    letter
+                } else {
+                    c
+                }
+            })
+            .collect()
+    };
+    if count > MAX_D_LETTERS {
+        return vec![with(&|_| false), with(&|_| true)];
+    }
+    (0..1usize << count).map(|mask| with(&|k| mask & (1 << k) != 0)).collect()
+}
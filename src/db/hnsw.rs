@@ -0,0 +1,519 @@
+//! In-memory HNSW graph (Malkov & Yashunin) for cosine nearest-neighbour
+//! search over embeddings, keyed by row id.
+//!
+//! Vectors are held normalized, as full f32s or quantized (see
+//! [`HnswParams::quantization`]), and compared by approximate cosine
+//! similarity ([`Vector::similarity`]). A node's
+//! level is derived from a hash of its id rather than a random draw, which
+//! makes a rebuild of the same rows produce the same graph. Removing a node
+//! reconnects its neighbours among themselves; the slot stays empty until
+//! enough slots are dead to be worth compacting.
+//!
+//! The graph only knows ids and vectors. Which rows go in, and how links are
+//! stored between runs, is [`super::vector_index`]'s business: links are
+//! exposed by id through [`Hnsw::links`] and read back by [`Hnsw::restore`].
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use super::quantization::{Quantization, Vector};
+
+/// Highest level a node can be placed on.
+const MAX_LEVEL: usize = 16;
+
+/// Tuning knobs. Larger values trade build and query time for recall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HnswParams {
+    /// Links kept per node above level 0; level 0 keeps twice as many.
+    pub m: usize,
+    /// Candidates examined when linking a new node.
+    pub ef_construction: usize,
+    /// Candidates examined per query (at least `k`).
+    pub ef_search: usize,
+    /// How vectors are held. Quantized similarities are approximate; callers
+    /// rerank the results against the raw vectors.
+    pub quantization: Quantization,
+}
+
+impl Default for HnswParams {
+    fn default() -> Self {
+        Self { m: 16, ef_construction: 100, ef_search: 64, quantization: Quantization::None }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("vector has {got} dimensions, the index holds {expected}")]
+pub struct DimensionMismatch {
+    pub expected: usize,
+    pub got: usize,
+}
+
+/// A node as stored between runs: its level and, per level, its neighbours' ids.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredNode {
+    pub id: i64,
+    pub level: usize,
+    pub links: Vec<Vec<i64>>,
+}
+
+struct Node {
+    id: i64,
+    vector: Vector,
+    /// Neighbour slots per level, `links.len() == level + 1`.
+    links: Vec<Vec<usize>>,
+}
+
+impl Node {
+    fn level(&self) -> usize {
+        self.links.len() - 1
+    }
+}
+
+/// A slot with its similarity to whatever is being searched for, ordered by
+/// similarity.
+#[derive(Clone, Copy)]
+struct Scored(f32, usize);
+
+impl PartialEq for Scored {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(other.1.cmp(&self.1))
+    }
+}
+
+pub struct Hnsw {
+    params: HnswParams,
+    /// Vector length, 0 while the index is empty.
+    dims: usize,
+    nodes: Vec<Option<Node>>,
+    slots: HashMap<i64, usize>,
+    entry: Option<usize>,
+    dead: usize,
+    /// Ids whose node or links changed since the last [`Hnsw::take_dirty`].
+    dirty: HashSet<i64>,
+}
+
+impl Hnsw {
+    pub fn new(params: HnswParams) -> Self {
+        Self {
+            params,
+            dims: 0,
+            nodes: Vec::new(),
+            slots: HashMap::new(),
+            entry: None,
+            dead: 0,
+            dirty: HashSet::new(),
+        }
+    }
+
+    /// Rebuild a graph from stored nodes and the current vectors, which must
+    /// be encoded in `params.quantization`. `dims` is the stored vector
+    /// length (0 = take it from the vectors).
+    ///
+    /// Stored nodes without a vector (or with one of another length) are
+    /// dropped and vectors without a stored node are inserted; both end up
+    /// in [`Hnsw::take_dirty`] so the stored copy can be brought in line.
+    pub fn restore(
+        params: HnswParams,
+        dims: usize,
+        stored: Vec<StoredNode>,
+        mut vectors: HashMap<i64, Vector>,
+        entry: Option<i64>,
+    ) -> Self {
+        let mut index = Self::new(params);
+        index.dims = if dims > 0 { dims } else { vectors.values().next().map_or(0, Vector::dims) };
+        let mut links = Vec::new();
+        for node in stored {
+            match vectors.remove(&node.id) {
+                Some(vector) if vector.dims() == index.dims && node.links.len() == node.level + 1 => {
+                    index.slots.insert(node.id, index.nodes.len());
+                    index.nodes.push(Some(Node { id: node.id, vector, links: Vec::new() }));
+                    links.push(node.links);
+                }
+                Some(vector) => {
+                    // Unusable node: its vector goes back in through `insert`
+                    vectors.insert(node.id, vector);
+                }
+                None => {
+                    index.dirty.insert(node.id);
+                }
+            }
+        }
+        for (slot, levels) in links.into_iter().enumerate() {
+            let resolved = levels
+                .iter()
+                .map(|ids| ids.iter().filter_map(|id| index.slots.get(id).copied()).collect())
+                .collect();
+            if let Some(node) = index.nodes[slot].as_mut() {
+                node.links = resolved;
+            }
+        }
+        index.entry = entry.and_then(|id| index.slots.get(&id).copied());
+        if index.entry.is_none() {
+            index.entry = index.highest_node();
+        }
+
+        let mut missing: Vec<(i64, Vector)> = vectors.into_iter().collect();
+        missing.sort_by_key(|(id, _)| *id);
+        if index.slots.is_empty() && !missing.iter().any(|(_, v)| v.dims() == index.dims) {
+            index.dims = 0;
+        }
+        for (id, vector) in missing {
+            if index.insert_vector(id, vector).is_err() {
+                index.dirty.insert(id);
+            }
+        }
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn dims(&self) -> usize {
+        self.dims
+    }
+
+    pub fn quantization(&self) -> Quantization {
+        self.params.quantization
+    }
+
+    pub fn contains(&self, id: i64) -> bool {
+        self.slots.contains_key(&id)
+    }
+
+    /// Id of the node searches start from.
+    pub fn entry_id(&self) -> Option<i64> {
+        self.entry.map(|slot| self.node(slot).id)
+    }
+
+    /// Level and per-level neighbour ids of `id`, if it is in the index.
+    pub fn links(&self, id: i64) -> Option<StoredNode> {
+        let node = self.node(*self.slots.get(&id)?);
+        let links = node
+            .links
+            .iter()
+            .map(|level| level.iter().filter_map(|&slot| self.nodes[slot].as_ref().map(|n| n.id)).collect())
+            .collect();
+        Some(StoredNode { id, level: node.level(), links })
+    }
+
+    /// Ids added, removed or relinked since the last call.
+    pub fn take_dirty(&mut self) -> Vec<i64> {
+        let mut ids: Vec<i64> = self.dirty.drain().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Add `id`, or replace its vector if it is already in the index.
+    pub fn insert(&mut self, id: i64, vector: &[f32]) -> Result<(), DimensionMismatch> {
+        self.insert_vector(id, self.params.quantization.encode(vector))
+    }
+
+    /// Like [`Hnsw::insert`], for a vector already encoded in `params.quantization`.
+    pub fn insert_vector(&mut self, id: i64, vector: Vector) -> Result<(), DimensionMismatch> {
+        let dims = vector.dims();
+        if dims == 0 || (self.dims != 0 && dims != self.dims) {
+            return Err(DimensionMismatch { expected: self.dims, got: dims });
+        }
+        debug_assert_eq!(vector.quantization(), self.params.quantization);
+        if let Some(&slot) = self.slots.get(&id) {
+            if self.node(slot).vector == vector {
+                return Ok(());
+            }
+            self.remove(id);
+        }
+        self.dims = dims;
+
+        let level = level_for(id, self.params.m);
+        let slot = self.nodes.len();
+        self.nodes.push(Some(Node { id, vector: vector.clone(), links: vec![Vec::new(); level + 1] }));
+        self.slots.insert(id, slot);
+        self.dirty.insert(id);
+
+        let Some(mut entry) = self.entry else {
+            self.entry = Some(slot);
+            return Ok(());
+        };
+        let top = self.node(entry).level();
+        for l in (level + 1..=top).rev() {
+            entry = self.greedy(&vector, entry, l);
+        }
+        let mut entries = vec![entry];
+        for l in (0..=level.min(top)).rev() {
+            let found = self.search_layer(&vector, &entries, self.params.ef_construction, l);
+            let neighbours = self.select(&found, self.params.m);
+            for &neighbour in &neighbours {
+                self.connect(neighbour, slot, l);
+            }
+            if let Some(node) = self.nodes[slot].as_mut() {
+                node.links[l] = neighbours;
+            }
+            entries = found.iter().map(|s| s.1).collect();
+        }
+        if level > top {
+            self.entry = Some(slot);
+        }
+        Ok(())
+    }
+
+    /// Take `id` out of the index. Returns whether it was there.
+    pub fn remove(&mut self, id: i64) -> bool {
+        let Some(slot) = self.slots.remove(&id) else {
+            return false;
+        };
+        let Some(node) = self.nodes[slot].take() else {
+            return false;
+        };
+        self.dead += 1;
+        self.dirty.insert(id);
+
+        // Each former neighbour is relinked from its own links plus the
+        // removed node's, so the graph stays connected around the hole
+        for (l, removed_links) in node.links.iter().enumerate() {
+            for &neighbour in removed_links {
+                let Some(n) = self.nodes[neighbour].as_ref() else {
+                    continue;
+                };
+                if !n.links.get(l).is_some_and(|links| links.contains(&slot)) {
+                    continue;
+                }
+                let mut candidates: Vec<usize> = n.links[l].clone();
+                candidates.extend(removed_links.iter().copied());
+                candidates.sort_unstable();
+                candidates.dedup();
+                let base = n.vector.clone();
+                let mut scored: Vec<Scored> = candidates
+                    .into_iter()
+                    .filter(|&c| c != neighbour && c != slot)
+                    .filter_map(|c| self.nodes[c].as_ref().map(|other| Scored(base.similarity(&other.vector), c)))
+                    .collect();
+                scored.sort_unstable_by(|a, b| b.cmp(a));
+                let links = self.select(&scored, self.max_links(l));
+                let nid = n.id;
+                if let Some(n) = self.nodes[neighbour].as_mut() {
+                    n.links[l] = links;
+                }
+                self.dirty.insert(nid);
+            }
+        }
+
+        if self.entry == Some(slot) {
+            self.entry = self.highest_node();
+        }
+        if self.slots.is_empty() {
+            self.nodes.clear();
+            self.dead = 0;
+            self.dims = 0;
+        } else if self.dead > 64 && self.dead * 2 > self.nodes.len() {
+            self.compact();
+        }
+        true
+    }
+
+    /// The `k` nearest ids to `query`, most similar first, with their
+    /// (approximate, if quantized) cosine similarity.
+    pub fn search(&self, query: &[f32], k: usize) -> Vec<(i64, f32)> {
+        let Some(mut entry) = self.entry else {
+            return Vec::new();
+        };
+        if k == 0 || query.len() != self.dims {
+            return Vec::new();
+        }
+        let query = self.params.quantization.encode(query);
+        for l in (1..=self.node(entry).level()).rev() {
+            entry = self.greedy(&query, entry, l);
+        }
+        let found = self.search_layer(&query, &[entry], self.params.ef_search.max(k), 0);
+        found.into_iter().take(k).map(|Scored(sim, slot)| (self.node(slot).id, sim)).collect()
+    }
+
+    fn node(&self, slot: usize) -> &Node {
+        self.nodes[slot].as_ref().expect("live slot")
+    }
+
+    fn max_links(&self, level: usize) -> usize {
+        if level == 0 { self.params.m * 2 } else { self.params.m }
+    }
+
+    fn highest_node(&self) -> Option<usize> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, node)| node.as_ref().map(|n| (n.level(), Reverse(slot))))
+            .max()
+            .map(|(_, Reverse(slot))| slot)
+    }
+
+    /// Walk level `level` towards `query` from `start` until no neighbour is closer.
+    fn greedy(&self, query: &Vector, start: usize, level: usize) -> usize {
+        let mut best = Scored(query.similarity(&self.node(start).vector), start);
+        loop {
+            let mut improved = false;
+            for &next in self.node(best.1).links.get(level).into_iter().flatten() {
+                if let Some(node) = self.nodes[next].as_ref() {
+                    let candidate = Scored(query.similarity(&node.vector), next);
+                    if candidate > best {
+                        best = candidate;
+                        improved = true;
+                    }
+                }
+            }
+            if !improved {
+                return best.1;
+            }
+        }
+    }
+
+    /// Best-first search of one level, returning up to `ef` slots, most similar first.
+    fn search_layer(&self, query: &Vector, entries: &[usize], ef: usize, level: usize) -> Vec<Scored> {
+        let mut visited: HashSet<usize> = entries.iter().copied().collect();
+        let mut candidates: BinaryHeap<Scored> = BinaryHeap::new();
+        let mut found: BinaryHeap<Reverse<Scored>> = BinaryHeap::new();
+        for &slot in entries {
+            if let Some(node) = self.nodes[slot].as_ref() {
+                let scored = Scored(query.similarity(&node.vector), slot);
+                candidates.push(scored);
+                found.push(Reverse(scored));
+            }
+        }
+        while found.len() > ef {
+            found.pop();
+        }
+
+        while let Some(current) = candidates.pop() {
+            if let Some(Reverse(worst)) = found.peek()
+                && found.len() >= ef
+                && current < *worst
+            {
+                break;
+            }
+            for &next in self.node(current.1).links.get(level).into_iter().flatten() {
+                if !visited.insert(next) {
+                    continue;
+                }
+                let Some(node) = self.nodes[next].as_ref() else {
+                    continue;
+                };
+                let scored = Scored(query.similarity(&node.vector), next);
+                if found.len() < ef || found.peek().is_some_and(|Reverse(worst)| scored > *worst) {
+                    candidates.push(scored);
+                    found.push(Reverse(scored));
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+        let mut found: Vec<Scored> = found.into_iter().map(|Reverse(s)| s).collect();
+        found.sort_unstable_by(|a, b| b.cmp(a));
+        found
+    }
+
+    /// Neighbour selection heuristic: take candidates (most similar first)
+    /// that are closer to the base than to any already selected, so links
+    /// spread out instead of clustering; fill up with the rest.
+    fn select(&self, candidates: &[Scored], max: usize) -> Vec<usize> {
+        let mut selected: Vec<usize> = Vec::with_capacity(max);
+        let mut skipped = Vec::new();
+        for &Scored(sim, slot) in candidates {
+            if selected.len() >= max {
+                break;
+            }
+            let vector = &self.node(slot).vector;
+            if selected.iter().all(|&s| vector.similarity(&self.node(s).vector) < sim) {
+                selected.push(slot);
+            } else {
+                skipped.push(slot);
+            }
+        }
+        for slot in skipped {
+            if selected.len() >= max {
+                break;
+            }
+            selected.push(slot);
+        }
+        selected
+    }
+
+    /// Link `from` to `to` on `level`, pruning `from`'s links if it has too many.
+    fn connect(&mut self, from: usize, to: usize, level: usize) {
+        let max = self.max_links(level);
+        let node = self.node(from);
+        let mut links = node.links[level].clone();
+        links.push(to);
+        if links.len() > max {
+            let base = &node.vector;
+            let mut scored: Vec<Scored> = links
+                .iter()
+                .filter_map(|&slot| self.nodes[slot].as_ref().map(|n| Scored(base.similarity(&n.vector), slot)))
+                .collect();
+            scored.sort_unstable_by(|a, b| b.cmp(a));
+            links = self.select(&scored, max);
+        }
+        let node = self.nodes[from].as_mut().expect("live slot");
+        node.links[level] = links;
+        let id = node.id;
+        self.dirty.insert(id);
+    }
+
+    /// Drop dead slots and renumber the live ones.
+    fn compact(&mut self) {
+        let mut renumber = vec![usize::MAX; self.nodes.len()];
+        let mut next = 0;
+        for (slot, node) in self.nodes.iter().enumerate() {
+            if node.is_some() {
+                renumber[slot] = next;
+                next += 1;
+            }
+        }
+        let nodes = std::mem::take(&mut self.nodes);
+        self.nodes = nodes
+            .into_iter()
+            .flatten()
+            .map(|mut node| {
+                for links in &mut node.links {
+                    links.retain(|&slot| renumber[slot] != usize::MAX);
+                    for slot in links.iter_mut() {
+                        *slot = renumber[*slot];
+                    }
+                }
+                Some(node)
+            })
+            .collect();
+        for slot in self.slots.values_mut() {
+            *slot = renumber[*slot];
+        }
+        self.entry = self.entry.map(|slot| renumber[slot]);
+        self.dead = 0;
+    }
+}
+
+/// Level for `id`: geometric with ratio 1/m, from a hash of the id.
+fn level_for(id: i64, m: usize) -> usize {
+    // splitmix64
+    let mut z = (id as u64).wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    let uniform = ((z >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+    let level = -uniform.ln() / (m.max(2) as f64).ln();
+    (level as usize).min(MAX_LEVEL)
+}
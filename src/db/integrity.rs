@@ -0,0 +1,202 @@
+//! Whole-database health check behind `/doctor` and `memory-assistant doctor`.
+//!
+//! Besides SQLite's own `integrity_check`, this verifies that every FTS5
+//! index covers exactly the rows of its content table (compared through the
+//! `{table}_docsize` shadow table, since selecting from an external-content
+//! table reads the content table itself), that FTS5's `integrity-check`
+//! passes, and that chunk line ranges still point at the chunk's text in its
+//! document. Repairs rebuild the affected indexes and drop stale chunks; the
+//! startup re-chunking pass (which runs for documents without chunks) then
+//! chunks and embeds those documents again.
+
+use rusqlite::{Connection, params};
+
+use super::fts::has_trigram_index;
+use super::{Database, DbResult};
+
+/// `(fts table, content table)` for every full-text index.
+const FTS_TABLES: &[(&str, &str)] = &[
+    ("memory_facts_fts", "memory_facts"),
+    ("knowledge_docs_fts", "knowledge_documents"),
+    ("knowledge_chunks_fts", "knowledge_chunks"),
+];
+
+/// Trigram indexes, checked only when `FTS_TRIGRAM` created them.
+const TRIGRAM_FTS_TABLES: &[(&str, &str)] = &[
+    ("memory_facts_tri", "memory_facts"),
+    ("knowledge_docs_tri", "knowledge_documents"),
+    ("knowledge_chunks_tri", "knowledge_chunks"),
+];
+
+/// Problems found in one full-text index.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FtsIssue {
+    pub table: String,
+    /// FTS5 `integrity-check` failed: indexed tokens differ from the content.
+    pub corrupt: bool,
+    /// Content rows with no index entry.
+    pub missing_rows: usize,
+    /// Index entries whose content row no longer exists.
+    pub extra_rows: usize,
+}
+
+/// Inconsistencies found (or repaired) in the database.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IntegrityReport {
+    /// Messages from `PRAGMA integrity_check`; empty when it reports "ok".
+    pub sqlite_errors: Vec<String>,
+    /// Full-text indexes with problems.
+    pub fts: Vec<FtsIssue>,
+    /// Documents with a chunk whose line range no longer matches its text.
+    pub stale_chunk_docs: Vec<i64>,
+}
+
+impl IntegrityReport {
+    pub fn is_clean(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// One `- problem` line per finding, for `/doctor` and the CLI.
+impl std::fmt::Display for IntegrityReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut lines: Vec<String> = self.sqlite_errors.iter().map(|e| format!("- SQLite: {e}")).collect();
+        for issue in &self.fts {
+            let mut problems = Vec::new();
+            if issue.corrupt {
+                problems.push("index does not match content".to_string());
+            }
+            if issue.missing_rows > 0 {
+                problems.push(format!("{} row(s) not indexed", issue.missing_rows));
+            }
+            if issue.extra_rows > 0 {
+                problems.push(format!("{} deleted row(s) still indexed", issue.extra_rows));
+            }
+            lines.push(format!("- {}: {}", issue.table, problems.join(", ")));
+        }
+        if !self.stale_chunk_docs.is_empty() {
+            let ids: Vec<String> = self.stale_chunk_docs.iter().map(|id| format!("#{id}")).collect();
+            lines.push(format!("- Chunks out of line with their document: {}", ids.join(", ")));
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+impl Database {
+    /// Run every check. Uses the writer, because FTS5's `integrity-check` is
+    /// issued as an INSERT (it changes nothing).
+    pub async fn check_integrity(&self) -> DbResult<IntegrityReport> {
+        self.write(|conn| check(conn)).await
+    }
+
+    /// Run every check and repair what it can: `REINDEX` after SQLite errors,
+    /// `rebuild` for broken FTS indexes, and deletion of stale chunks so their
+    /// documents are re-chunked. Returns what was found before repairing.
+    pub async fn repair_integrity(&self) -> DbResult<IntegrityReport> {
+        self.write(|conn| {
+            let report = check(conn)?;
+            let tx = conn.transaction()?;
+            if !report.sqlite_errors.is_empty() {
+                tx.execute_batch("REINDEX")?;
+            }
+            for issue in &report.fts {
+                let table = &issue.table;
+                tx.execute(&format!("INSERT INTO {table}({table}) VALUES('rebuild')"), [])?;
+            }
+            for doc_id in &report.stale_chunk_docs {
+                tx.execute("DELETE FROM knowledge_chunks WHERE doc_id = ?1", params![doc_id])?;
+            }
+            tx.commit()?;
+            Ok::<_, rusqlite::Error>(report)
+        })
+        .await
+    }
+}
+
+fn check(conn: &Connection) -> rusqlite::Result<IntegrityReport> {
+    let mut tables = FTS_TABLES.to_vec();
+    if has_trigram_index(conn)? {
+        tables.extend_from_slice(TRIGRAM_FTS_TABLES);
+    }
+    let mut fts = Vec::new();
+    for (table, content) in tables {
+        let issue = check_fts(conn, table, content)?;
+        if issue.corrupt || issue.missing_rows > 0 || issue.extra_rows > 0 {
+            fts.push(issue);
+        }
+    }
+    Ok(IntegrityReport { sqlite_errors: sqlite_errors(conn)?, fts, stale_chunk_docs: stale_chunk_docs(conn)? })
+}
+
+fn sqlite_errors(conn: &Connection) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+    let messages: Vec<String> = rows.collect::<rusqlite::Result<_>>()?;
+    Ok(if messages == ["ok"] { Vec::new() } else { messages })
+}
+
+fn check_fts(conn: &Connection, table: &str, content: &str) -> rusqlite::Result<FtsIssue> {
+    // rank = 1 also compares the index against the content table
+    let corrupt = match conn.execute(&format!("INSERT INTO {table}({table}, rank) VALUES('integrity-check', 1)"), []) {
+        Ok(_) => false,
+        Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == rusqlite::ErrorCode::DatabaseCorrupt => true,
+        Err(e) => return Err(e),
+    };
+    let count = |sql: String| conn.query_row(&sql, [], |row| row.get::<_, i64>(0)).map(|n| n as usize);
+    Ok(FtsIssue {
+        table: table.to_string(),
+        corrupt,
+        missing_rows: count(format!(
+            "SELECT COUNT(*) FROM {content} c WHERE NOT EXISTS (SELECT 1 FROM {table}_docsize d WHERE d.id = c.id)"
+        ))?,
+        extra_rows: count(format!(
+            "SELECT COUNT(*) FROM {table}_docsize d WHERE NOT EXISTS (SELECT 1 FROM {content} c WHERE c.id = d.id)"
+        ))?,
+    })
+}
+
+fn stale_chunk_docs(conn: &Connection) -> rusqlite::Result<Vec<i64>> {
+    let mut stmt = conn.prepare(
+        "SELECT kc.doc_id, kd.content, kc.start_line, kc.end_line, kc.content
+         FROM knowledge_chunks kc JOIN knowledge_documents kd ON kd.id = kc.doc_id
+         ORDER BY kc.doc_id, kc.chunk_index",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, i64>(2)?,
+            row.get::<_, i64>(3)?,
+            row.get::<_, String>(4)?,
+        ))
+    })?;
+
+    let mut stale: Vec<i64> = Vec::new();
+    for row in rows {
+        let (doc_id, document, start_line, end_line, chunk) = row?;
+        if stale.last() == Some(&doc_id) {
+            continue;
+        }
+        // Compare line by line: a short document is stored whole, CRLF line endings included
+        if chunk_text(&document, start_line, end_line).as_deref().map(str::trim) != Some(joined_lines(&chunk).trim()) {
+            stale.push(doc_id);
+        }
+    }
+    Ok(stale)
+}
+
+/// `text` with its lines joined by `\n`, as [`chunk_text`] returns them.
+fn joined_lines(text: &str) -> String {
+    text.lines().collect::<Vec<_>>().join("\n")
+}
+
+/// Lines `start..=end` (1-based) of `document`, or `None` if out of range.
+/// An empty document has one empty line, as the chunker counts it.
+fn chunk_text(document: &str, start: i64, end: i64) -> Option<String> {
+    let lines: Vec<&str> = document.lines().collect();
+    let (start, end) = (usize::try_from(start).ok()?, usize::try_from(end).ok()?);
+    if start == 0 || start > end || end > lines.len().max(1) {
+        return None;
+    }
+    Some(lines[start - 1..end.min(lines.len())].join("\n"))
+}
@@ -0,0 +1,809 @@
+use rusqlite::{Connection, params};
+use tracing::{error, info};
+
+/// A single up-only schema step. Versions are applied in ascending order,
+/// each inside its own transaction together with its `schema_version` row.
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub up: fn(&Connection) -> rusqlite::Result<()>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MigrationError {
+    #[error("migration {version} ({description}) failed: {source}")]
+    Failed {
+        version: i64,
+        description: &'static str,
+        #[source]
+        source: rusqlite::Error,
+    },
+    #[error("database schema v{found} is newer than this binary supports (v{supported})")]
+    TooNew { found: i64, supported: i64 },
+    #[error(transparent)]
+    Encryption(#[from] super::encryption::EncryptionError),
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+}
+
+/// Ordered list of all migrations. Append new entries at the end — never
+/// edit or reorder a migration that has already shipped.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema",
+        up: initial_schema,
+    },
+    Migration {
+        version: 2,
+        description: "memory_facts.embedding column",
+        up: fact_embeddings,
+    },
+    Migration {
+        version: 3,
+        description: "soft delete (deleted_at) for facts and documents",
+        up: soft_delete,
+    },
+    Migration {
+        version: 4,
+        description: "memory_fact_revisions table",
+        up: fact_revisions,
+    },
+    Migration {
+        version: 5,
+        description: "knowledge_document_versions table",
+        up: document_versions,
+    },
+    Migration {
+        version: 6,
+        description: "session summaries and per-owner retention",
+        up: session_retention,
+    },
+    Migration {
+        version: 7,
+        description: "usage_log attribution (user, owner, session, turns, tools)",
+        up: usage_attribution,
+    },
+    Migration {
+        version: 8,
+        description: "spend budget overrides and alert log",
+        up: spend_budgets,
+    },
+    Migration {
+        version: 9,
+        description: "entity_mentions cleanup triggers and indexes",
+        up: entity_mention_integrity,
+    },
+    Migration {
+        version: 10,
+        description: "diacritic-insensitive FTS tokenizer",
+        up: vietnamese_fts,
+    },
+    Migration {
+        version: 11,
+        description: "active session per chat",
+        up: active_sessions,
+    },
+    Migration {
+        version: 12,
+        description: "session_messages.message (serialized provider messages)",
+        up: structured_history,
+    },
+    Migration {
+        version: 13,
+        description: "pending request notifications and approval_log",
+        up: approval_log,
+    },
+    Migration {
+        version: 14,
+        description: "append-only audit_log of knowledge base writes",
+        up: audit_log,
+    },
+    Migration {
+        version: 15,
+        description: "persistent vector indexes and their change log",
+        up: vector_indexes,
+    },
+    Migration {
+        version: 16,
+        description: "quantized embedding copies (embedding_q) and vector_settings",
+        up: quantized_embeddings,
+    },
+    Migration {
+        version: 17,
+        description: "embedding_cache keyed by model, input type and text hash",
+        up: embedding_cache,
+    },
+    Migration {
+        version: 18,
+        description: "embedding_cache index on last_used_at for pruning",
+        up: embedding_cache_lru,
+    },
+    Migration {
+        version: 19,
+        description: "embedding model and dims in vector_settings",
+        up: embedding_model,
+    },
+];
+
+/// Highest schema version known to this binary.
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Schema version currently recorded in the database (0 if none applied yet).
+pub fn current_version(conn: &Connection) -> rusqlite::Result<i64> {
+    conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_version",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Apply all pending migrations. Returns the resulting schema version.
+pub fn run(conn: &mut Connection) -> Result<i64, MigrationError> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );"
+    )?;
+
+    let current = current_version(conn)?;
+    let latest = latest_version();
+    if current > latest {
+        return Err(MigrationError::TooNew { found: current, supported: latest });
+    }
+
+    for m in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let tx = conn.transaction()?;
+        let applied = (m.up)(&tx).and_then(|_| {
+            tx.execute(
+                "INSERT INTO schema_version (version, description) VALUES (?1, ?2)",
+                params![m.version, m.description],
+            )
+            .map(|_| ())
+        });
+        if let Err(source) = applied {
+            error!("Migration {} ({}) failed: {source}", m.version, m.description);
+            return Err(MigrationError::Failed {
+                version: m.version,
+                description: m.description,
+                source,
+            });
+        }
+        tx.commit()?;
+        info!("Applied migration {}: {}", m.version, m.description);
+    }
+
+    Ok(latest)
+}
+
+/// Add a column unless it already exists (databases created before the
+/// migration framework may already carry it).
+pub fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    decl: &str,
+) -> rusqlite::Result<()> {
+    let exists: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+        params![table, column],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        conn.execute_batch(&format!("ALTER TABLE {table} ADD COLUMN {column} {decl};"))?;
+    }
+    Ok(())
+}
+
+// --- Migrations ---
+
+/// v1: every table that existed before versioning. Uses IF NOT EXISTS so that
+/// pre-versioning databases adopt v1 without changes.
+fn initial_schema(conn: &Connection) -> rusqlite::Result<()> {
+    // Memory facts table
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS memory_facts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            fact TEXT NOT NULL,
+            category TEXT NOT NULL DEFAULT 'general',
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            access_count INTEGER NOT NULL DEFAULT 0,
+            last_accessed_at TEXT
+        );
+
+        CREATE VIRTUAL TABLE IF NOT EXISTS memory_facts_fts USING fts5(
+            fact,
+            content='memory_facts',
+            content_rowid='id'
+        );
+
+        CREATE TRIGGER IF NOT EXISTS memory_facts_ai AFTER INSERT ON memory_facts BEGIN
+            INSERT INTO memory_facts_fts(rowid, fact) VALUES (new.id, new.fact);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS memory_facts_ad AFTER DELETE ON memory_facts BEGIN
+            INSERT INTO memory_facts_fts(memory_facts_fts, rowid, fact) VALUES('delete', old.id, old.fact);
+        END;
+        "
+    )?;
+
+    // Knowledge documents table
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS knowledge_documents (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            title TEXT NOT NULL,
+            content TEXT NOT NULL,
+            source TEXT,
+            tags TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+
+        CREATE VIRTUAL TABLE IF NOT EXISTS knowledge_docs_fts USING fts5(
+            title, content,
+            content='knowledge_documents',
+            content_rowid='id'
+        );
+
+        CREATE TRIGGER IF NOT EXISTS knowledge_docs_ai AFTER INSERT ON knowledge_documents BEGIN
+            INSERT INTO knowledge_docs_fts(rowid, title, content) VALUES (new.id, new.title, new.content);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS knowledge_docs_ad AFTER DELETE ON knowledge_documents BEGIN
+            INSERT INTO knowledge_docs_fts(knowledge_docs_fts, rowid, title, content) VALUES('delete', old.id, old.title, old.content);
+        END;
+        "
+    )?;
+
+    // Entities & knowledge graph
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS entities (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            entity_type TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            UNIQUE(user_id, name, entity_type)
+        );
+
+        CREATE TABLE IF NOT EXISTS entity_mentions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            entity_id INTEGER NOT NULL REFERENCES entities(id),
+            source_type TEXT NOT NULL,
+            source_id INTEGER NOT NULL,
+            context TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+        "
+    )?;
+
+    // Knowledge chunks (for semantic search)
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS knowledge_chunks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            doc_id INTEGER NOT NULL REFERENCES knowledge_documents(id) ON DELETE CASCADE,
+            chunk_index INTEGER NOT NULL,
+            start_line INTEGER NOT NULL,
+            end_line INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            embedding BLOB,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            UNIQUE(doc_id, chunk_index)
+        );
+
+        CREATE VIRTUAL TABLE IF NOT EXISTS knowledge_chunks_fts USING fts5(
+            content, content='knowledge_chunks', content_rowid='id'
+        );
+
+        CREATE TRIGGER IF NOT EXISTS knowledge_chunks_ai AFTER INSERT ON knowledge_chunks BEGIN
+            INSERT INTO knowledge_chunks_fts(rowid, content) VALUES (new.id, new.content);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS knowledge_chunks_ad AFTER DELETE ON knowledge_chunks BEGIN
+            INSERT INTO knowledge_chunks_fts(knowledge_chunks_fts, rowid, content) VALUES('delete', old.id, old.content);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS knowledge_chunks_au AFTER UPDATE OF content ON knowledge_chunks BEGIN
+            INSERT INTO knowledge_chunks_fts(knowledge_chunks_fts, rowid, content) VALUES('delete', old.id, old.content);
+            INSERT INTO knowledge_chunks_fts(rowid, content) VALUES (new.id, new.content);
+        END;
+        "
+    )?;
+
+    // Categories (dynamic, per-user)
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS categories (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            UNIQUE(user_id, name)
+        );"
+    )?;
+
+    // Memory ↔ KB links
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS memory_kb_links (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            fact_id INTEGER NOT NULL REFERENCES memory_facts(id) ON DELETE CASCADE,
+            doc_id INTEGER NOT NULL REFERENCES knowledge_documents(id) ON DELETE CASCADE,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            UNIQUE(fact_id, doc_id)
+        );"
+    )?;
+
+    // Fact-to-fact relations (semantic similarity links)
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS fact_relations (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            fact_id_1 INTEGER NOT NULL REFERENCES memory_facts(id) ON DELETE CASCADE,
+            fact_id_2 INTEGER NOT NULL REFERENCES memory_facts(id) ON DELETE CASCADE,
+            similarity REAL NOT NULL,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            UNIQUE(fact_id_1, fact_id_2),
+            CHECK(fact_id_1 < fact_id_2)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_fact_relations_1 ON fact_relations(fact_id_1);
+        CREATE INDEX IF NOT EXISTS idx_fact_relations_2 ON fact_relations(fact_id_2);"
+    )?;
+
+    // Pending approval queue (for non-whitelisted users' write requests in groups)
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS pending_items (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            scope_id INTEGER NOT NULL,
+            requested_by INTEGER NOT NULL,
+            tool_name TEXT NOT NULL,
+            args_json TEXT NOT NULL,
+            summary TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );"
+    )?;
+
+    // Usage log (cost tracking per model)
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS usage_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            model TEXT NOT NULL,
+            provider TEXT NOT NULL,
+            prompt_tokens INTEGER NOT NULL DEFAULT 0,
+            completion_tokens INTEGER NOT NULL DEFAULT 0,
+            cache_creation_tokens INTEGER NOT NULL DEFAULT 0,
+            cache_read_tokens INTEGER NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );"
+    )?;
+
+    // User preferences
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS user_preferences (
+            user_id INTEGER PRIMARY KEY,
+            model TEXT NOT NULL DEFAULT 'claude-haiku-4-5-20251001',
+            updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );"
+    )?;
+
+    // Conversation sessions
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS sessions (
+            id TEXT PRIMARY KEY,
+            user_id INTEGER NOT NULL,
+            title TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            last_active_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+
+        CREATE TABLE IF NOT EXISTS session_messages (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            session_id TEXT NOT NULL REFERENCES sessions(id),
+            role TEXT NOT NULL,
+            content TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+        "
+    )
+}
+
+/// v2: fact embeddings for semantic memory search.
+fn fact_embeddings(conn: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "memory_facts", "embedding", "BLOB")
+}
+
+/// v3: trash bin. Deleted facts and documents keep their row (and FTS entry)
+/// with `deleted_at` set until they are restored or purged.
+fn soft_delete(conn: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "memory_facts", "deleted_at", "TEXT")?;
+    add_column_if_missing(conn, "knowledge_documents", "deleted_at", "TEXT")?;
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_memory_facts_deleted ON memory_facts(deleted_at) WHERE deleted_at IS NOT NULL;
+         CREATE INDEX IF NOT EXISTS idx_knowledge_documents_deleted ON knowledge_documents(deleted_at) WHERE deleted_at IS NOT NULL;"
+    )
+}
+
+/// v4: revision history for fact edits and supersedes. Facts also get an
+/// update trigger so in-place edits keep the FTS index in sync.
+fn fact_revisions(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS memory_fact_revisions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            fact_id INTEGER NOT NULL REFERENCES memory_facts(id) ON DELETE CASCADE,
+            old_text TEXT NOT NULL,
+            new_text TEXT NOT NULL,
+            superseded_by INTEGER REFERENCES memory_facts(id) ON DELETE SET NULL,
+            actor_id INTEGER NOT NULL,
+            tool TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_fact_revisions_fact ON memory_fact_revisions(fact_id);
+        CREATE INDEX IF NOT EXISTS idx_fact_revisions_superseded_by ON memory_fact_revisions(superseded_by);
+
+        CREATE TRIGGER IF NOT EXISTS memory_facts_au AFTER UPDATE OF fact ON memory_facts BEGIN
+            INSERT INTO memory_facts_fts(memory_facts_fts, rowid, fact) VALUES('delete', old.id, old.fact);
+            INSERT INTO memory_facts_fts(rowid, fact) VALUES (new.id, new.fact);
+        END;"
+    )
+}
+
+/// v5: stored document versions, seeded with each existing document as v1.
+/// Documents also get an update trigger so patches keep the FTS index in sync.
+fn document_versions(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS knowledge_document_versions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            doc_id INTEGER NOT NULL REFERENCES knowledge_documents(id) ON DELETE CASCADE,
+            version INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            diff TEXT,
+            actor_id INTEGER,
+            tool TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            UNIQUE(doc_id, version)
+        );
+
+        INSERT OR IGNORE INTO knowledge_document_versions (doc_id, version, content, tool, created_at)
+            SELECT id, 1, content, 'knowledge_save', created_at FROM knowledge_documents;
+
+        CREATE TRIGGER IF NOT EXISTS knowledge_docs_au AFTER UPDATE OF title, content ON knowledge_documents BEGIN
+            INSERT INTO knowledge_docs_fts(knowledge_docs_fts, rowid, title, content) VALUES('delete', old.id, old.title, old.content);
+            INSERT INTO knowledge_docs_fts(rowid, title, content) VALUES (new.id, new.title, new.content);
+        END;"
+    )
+}
+
+/// v6: rolling summary of pruned turns on `sessions`, plus per-owner
+/// retention overrides for `session_messages`.
+fn session_retention(conn: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "sessions", "summary", "TEXT")?;
+    add_column_if_missing(conn, "sessions", "summary_updated_at", "TEXT")?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS session_retention (
+            owner_id INTEGER PRIMARY KEY,
+            max_age_days INTEGER,
+            max_messages INTEGER,
+            summarize INTEGER NOT NULL DEFAULT 0,
+            updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_session_messages_session ON session_messages(session_id, id);"
+    )
+}
+
+/// v7: who each `usage_log` row was spent for. Rows logged before this
+/// migration keep NULLs and show up as unattributed.
+fn usage_attribution(conn: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "usage_log", "user_id", "INTEGER")?;
+    add_column_if_missing(conn, "usage_log", "kb_owner_id", "INTEGER")?;
+    add_column_if_missing(conn, "usage_log", "session_id", "TEXT")?;
+    add_column_if_missing(conn, "usage_log", "turns", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "usage_log", "tools", "TEXT")?;
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_usage_log_created ON usage_log(created_at);
+        CREATE INDEX IF NOT EXISTS idx_usage_log_user ON usage_log(user_id, created_at);
+        CREATE INDEX IF NOT EXISTS idx_usage_log_owner ON usage_log(kb_owner_id, created_at);"
+    )
+}
+
+/// v8: per-owner/per-user overrides of the configured USD budgets, and a
+/// record of which budget alerts have been sent so each fires once a period.
+fn spend_budgets(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS spend_budgets (
+            kind TEXT NOT NULL CHECK (kind IN ('owner', 'user')),
+            subject_id INTEGER NOT NULL,
+            daily_usd REAL,
+            monthly_usd REAL,
+            updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+            PRIMARY KEY (kind, subject_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS budget_alerts (
+            kind TEXT NOT NULL,
+            subject_id INTEGER NOT NULL,
+            period TEXT NOT NULL,
+            sent_at TEXT NOT NULL DEFAULT (datetime('now')),
+            PRIMARY KEY (kind, subject_id, period)
+        );"
+    )
+}
+
+/// v9: `entity_mentions` has no foreign key to its source, so deleting a fact
+/// or document now deletes its mentions by trigger. Dangling rows left by
+/// older versions are reported and repaired by `/doctor`.
+fn entity_mention_integrity(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_entity_mentions_source ON entity_mentions(source_type, source_id);
+        CREATE INDEX IF NOT EXISTS idx_entity_mentions_entity ON entity_mentions(entity_id);
+
+        CREATE TRIGGER IF NOT EXISTS knowledge_docs_mentions_ad AFTER DELETE ON knowledge_documents BEGIN
+            DELETE FROM entity_mentions WHERE source_type = 'document' AND source_id = old.id;
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS memory_facts_mentions_ad AFTER DELETE ON memory_facts BEGIN
+            DELETE FROM entity_mentions WHERE source_type = 'fact' AND source_id = old.id;
+        END;"
+    )
+}
+
+/// v10: rebuild the FTS indexes with `unicode61 remove_diacritics 2` so
+/// Vietnamese tone marks are ignored ("hop" matches "hợp"). The sync triggers
+/// reference the tables by name and keep working once they are recreated.
+fn vietnamese_fts(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "DROP TABLE IF EXISTS memory_facts_fts;
+        CREATE VIRTUAL TABLE memory_facts_fts USING fts5(
+            fact,
+            content='memory_facts',
+            content_rowid='id',
+            tokenize='unicode61 remove_diacritics 2'
+        );
+        INSERT INTO memory_facts_fts(memory_facts_fts) VALUES('rebuild');
+
+        DROP TABLE IF EXISTS knowledge_docs_fts;
+        CREATE VIRTUAL TABLE knowledge_docs_fts USING fts5(
+            title, content,
+            content='knowledge_documents',
+            content_rowid='id',
+            tokenize='unicode61 remove_diacritics 2'
+        );
+        INSERT INTO knowledge_docs_fts(knowledge_docs_fts) VALUES('rebuild');
+
+        DROP TABLE IF EXISTS knowledge_chunks_fts;
+        CREATE VIRTUAL TABLE knowledge_chunks_fts USING fts5(
+            content,
+            content='knowledge_chunks',
+            content_rowid='id',
+            tokenize='unicode61 remove_diacritics 2'
+        );
+        INSERT INTO knowledge_chunks_fts(knowledge_chunks_fts) VALUES('rebuild');"
+    )
+}
+
+/// v11: each owner's active session, switched with `/new` and `/resume`
+/// instead of being inferred from the latest `last_active_at`. Existing owners
+/// keep their most recent session.
+fn active_sessions(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS active_sessions (
+            owner_id INTEGER PRIMARY KEY,
+            session_id TEXT NOT NULL REFERENCES sessions(id),
+            updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+        INSERT OR IGNORE INTO active_sessions (owner_id, session_id)
+            SELECT user_id, id FROM sessions s
+            WHERE id = (
+                SELECT id FROM sessions WHERE user_id = s.user_id
+                ORDER BY last_active_at DESC, rowid DESC LIMIT 1
+            );
+        CREATE INDEX IF NOT EXISTS idx_sessions_user ON sessions(user_id, last_active_at);"
+    )
+}
+
+/// v12: the full `provider::Message` behind each history row, including tool
+/// calls and their results, as JSON. Older rows keep only their text.
+fn structured_history(conn: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "session_messages", "message", "TEXT")
+}
+
+/// v13: when admins were sent a pending request, and a permanent record of
+/// every approval, rejection and expiry with the tool result.
+fn approval_log(conn: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "pending_items", "notified_at", "TEXT")?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS approval_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            pending_id INTEGER NOT NULL,
+            scope_id INTEGER NOT NULL,
+            requested_by INTEGER NOT NULL,
+            tool_name TEXT NOT NULL,
+            args_json TEXT NOT NULL,
+            summary TEXT NOT NULL,
+            requested_at TEXT NOT NULL,
+            decision TEXT NOT NULL,
+            decided_by INTEGER,
+            result TEXT,
+            decided_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+        CREATE INDEX IF NOT EXISTS idx_approval_log_scope ON approval_log(scope_id, id);"
+    )
+}
+
+/// v14: every write tool call with the rows it changed before and after.
+/// Triggers reject updates and deletes; `/undo` appends an entry that points
+/// at the one it reversed, and each entry can be reversed once.
+fn audit_log(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS audit_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            kb_owner_id INTEGER NOT NULL,
+            tool TEXT NOT NULL,
+            args_json TEXT NOT NULL,
+            result TEXT NOT NULL,
+            before_json TEXT NOT NULL,
+            after_json TEXT NOT NULL,
+            undoes INTEGER REFERENCES audit_log(id),
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+        CREATE INDEX IF NOT EXISTS idx_audit_log_owner ON audit_log(kb_owner_id, id);
+        CREATE INDEX IF NOT EXISTS idx_audit_log_user ON audit_log(kb_owner_id, user_id, id);
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_audit_log_undoes ON audit_log(undoes) WHERE undoes IS NOT NULL;
+
+        CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log BEGIN
+            SELECT RAISE(ABORT, 'audit_log is append-only');
+        END;
+        CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log BEGIN
+            SELECT RAISE(ABORT, 'audit_log is append-only');
+        END;"
+    )
+}
+
+/// v15: HNSW graphs over fact and chunk embeddings, one per owner and kind,
+/// and a log of rows whose vectors changed since the graph was last brought
+/// up to date. Triggers only log for owners whose index exists (has a
+/// `vector_index_meta` row); the rest get theirs built from scratch.
+fn vector_indexes(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS vector_index_meta (
+            owner_id INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            dims INTEGER NOT NULL DEFAULT 0,
+            entry_id INTEGER,
+            ready INTEGER NOT NULL DEFAULT 0,
+            updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+            PRIMARY KEY (owner_id, kind)
+        );
+        CREATE TABLE IF NOT EXISTS vector_index_nodes (
+            owner_id INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            item_id INTEGER NOT NULL,
+            level INTEGER NOT NULL,
+            neighbors BLOB NOT NULL,
+            PRIMARY KEY (owner_id, kind, item_id)
+        );
+        CREATE INDEX IF NOT EXISTS idx_vector_index_nodes_item ON vector_index_nodes(kind, item_id);
+        CREATE TABLE IF NOT EXISTS vector_index_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            owner_id INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            item_id INTEGER NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_vector_index_log_owner ON vector_index_log(owner_id, kind, id);
+
+        CREATE TRIGGER IF NOT EXISTS vector_log_fact_insert AFTER INSERT ON memory_facts
+        WHEN NEW.embedding IS NOT NULL BEGIN
+            INSERT INTO vector_index_log (owner_id, kind, item_id)
+            SELECT NEW.user_id, 'fact', NEW.id
+            WHERE EXISTS (SELECT 1 FROM vector_index_meta WHERE owner_id = NEW.user_id AND kind = 'fact');
+        END;
+        CREATE TRIGGER IF NOT EXISTS vector_log_fact_update AFTER UPDATE OF embedding, deleted_at ON memory_facts
+        WHEN OLD.embedding IS NOT NEW.embedding OR OLD.deleted_at IS NOT NEW.deleted_at BEGIN
+            INSERT INTO vector_index_log (owner_id, kind, item_id)
+            SELECT NEW.user_id, 'fact', NEW.id
+            WHERE EXISTS (SELECT 1 FROM vector_index_meta WHERE owner_id = NEW.user_id AND kind = 'fact');
+        END;
+        CREATE TRIGGER IF NOT EXISTS vector_log_fact_delete AFTER DELETE ON memory_facts
+        WHEN OLD.embedding IS NOT NULL BEGIN
+            INSERT INTO vector_index_log (owner_id, kind, item_id)
+            SELECT OLD.user_id, 'fact', OLD.id
+            WHERE EXISTS (SELECT 1 FROM vector_index_meta WHERE owner_id = OLD.user_id AND kind = 'fact');
+        END;
+
+        -- INSERT OR REPLACE deletes the old chunk without firing delete triggers
+        CREATE TRIGGER IF NOT EXISTS vector_log_chunk_replace BEFORE INSERT ON knowledge_chunks BEGIN
+            INSERT INTO vector_index_log (owner_id, kind, item_id)
+            SELECT n.owner_id, 'chunk', n.item_id FROM knowledge_chunks kc
+            JOIN vector_index_nodes n ON n.kind = 'chunk' AND n.item_id = kc.id
+            WHERE kc.doc_id = NEW.doc_id AND kc.chunk_index = NEW.chunk_index;
+        END;
+        CREATE TRIGGER IF NOT EXISTS vector_log_chunk_insert AFTER INSERT ON knowledge_chunks
+        WHEN NEW.embedding IS NOT NULL BEGIN
+            INSERT INTO vector_index_log (owner_id, kind, item_id)
+            SELECT kd.user_id, 'chunk', NEW.id FROM knowledge_documents kd
+            JOIN vector_index_meta m ON m.owner_id = kd.user_id AND m.kind = 'chunk'
+            WHERE kd.id = NEW.doc_id;
+        END;
+        CREATE TRIGGER IF NOT EXISTS vector_log_chunk_update AFTER UPDATE OF embedding ON knowledge_chunks
+        WHEN OLD.embedding IS NOT NEW.embedding BEGIN
+            INSERT INTO vector_index_log (owner_id, kind, item_id)
+            SELECT kd.user_id, 'chunk', NEW.id FROM knowledge_documents kd
+            JOIN vector_index_meta m ON m.owner_id = kd.user_id AND m.kind = 'chunk'
+            WHERE kd.id = NEW.doc_id;
+        END;
+        CREATE TRIGGER IF NOT EXISTS vector_log_chunk_delete AFTER DELETE ON knowledge_chunks BEGIN
+            INSERT INTO vector_index_log (owner_id, kind, item_id)
+            SELECT owner_id, 'chunk', item_id FROM vector_index_nodes WHERE kind = 'chunk' AND item_id = OLD.id;
+        END;
+        CREATE TRIGGER IF NOT EXISTS vector_log_document_trash AFTER UPDATE OF deleted_at ON knowledge_documents
+        WHEN OLD.deleted_at IS NOT NEW.deleted_at BEGIN
+            INSERT INTO vector_index_log (owner_id, kind, item_id)
+            SELECT NEW.user_id, 'chunk', kc.id FROM knowledge_chunks kc
+            WHERE kc.doc_id = NEW.id AND kc.embedding IS NOT NULL
+              AND EXISTS (SELECT 1 FROM vector_index_meta WHERE owner_id = NEW.user_id AND kind = 'chunk');
+        END;"
+    )
+}
+
+/// v16: `embedding_q`, a quantized copy of each embedding in the mode
+/// recorded in `vector_settings` (filled by `Database::set_quantization`).
+/// A write that replaces `embedding` without also setting `embedding_q`
+/// clears the copy, so it is never stale.
+fn quantized_embeddings(conn: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "memory_facts", "embedding_q", "BLOB")?;
+    add_column_if_missing(conn, "knowledge_chunks", "embedding_q", "BLOB")?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS vector_settings (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            quantization TEXT NOT NULL DEFAULT 'none'
+        );
+        CREATE TRIGGER IF NOT EXISTS memory_facts_embedding_q AFTER UPDATE OF embedding ON memory_facts
+        WHEN OLD.embedding IS NOT NEW.embedding AND OLD.embedding_q IS NEW.embedding_q AND NEW.embedding_q IS NOT NULL BEGIN
+            UPDATE memory_facts SET embedding_q = NULL WHERE id = NEW.id;
+        END;
+        CREATE TRIGGER IF NOT EXISTS knowledge_chunks_embedding_q AFTER UPDATE OF embedding ON knowledge_chunks
+        WHEN OLD.embedding IS NOT NEW.embedding AND OLD.embedding_q IS NEW.embedding_q AND NEW.embedding_q IS NOT NULL BEGIN
+            UPDATE knowledge_chunks SET embedding_q = NULL WHERE id = NEW.id;
+        END;"
+    )
+}
+
+/// v17: embeddings already fetched, keyed by model, input type and the
+/// SHA-256 of the text, so identical text is never embedded twice, plus the
+/// running hit/miss counts per model.
+fn embedding_cache(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS embedding_cache (
+            model TEXT NOT NULL,
+            input_type TEXT NOT NULL,
+            text_hash BLOB NOT NULL,
+            embedding BLOB NOT NULL,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            last_used_at TEXT NOT NULL DEFAULT (datetime('now')),
+            PRIMARY KEY (model, input_type, text_hash)
+        ) WITHOUT ROWID;
+        CREATE TABLE IF NOT EXISTS embedding_cache_stats (
+            model TEXT PRIMARY KEY,
+            hits INTEGER NOT NULL DEFAULT 0,
+            misses INTEGER NOT NULL DEFAULT 0
+        );"
+    )
+}
+
+/// v18: index the cache by last use, so the least recently used entries are
+/// found without a scan when it is pruned.
+fn embedding_cache_lru(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch("CREATE INDEX IF NOT EXISTS idx_embedding_cache_last_used ON embedding_cache(last_used_at)")
+}
+
+/// v19: the model (and vector size) the stored embeddings came from, filled
+/// by `Database::check_embedding_model`.
+fn embedding_model(conn: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "vector_settings", "embedding_model", "TEXT")?;
+    add_column_if_missing(conn, "vector_settings", "embedding_dims", "INTEGER")
+}
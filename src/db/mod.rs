@@ -1,1191 +1,1975 @@
-use rusqlite::{Connection, params};
-use std::sync::Mutex;
+mod error;
+pub mod audit;
+pub mod backup;
+pub mod embedding_cache;
+pub mod embedding_model;
+pub mod encryption;
+pub mod entity_graph;
+pub mod export;
+pub mod fts;
+pub mod hnsw;
+pub mod integrity;
+pub mod obsidian;
+pub mod owners;
+pub mod quantization;
+pub mod usage;
+pub mod vector_index;
+pub mod migrations;
+pub mod models;
+mod pool;
+mod repository;
+
+use rusqlite::{Connection, OptionalExtension, Row, params};
+use std::sync::Arc;
 use tracing::info;
 
+pub use error::{DbError, DbResult};
+pub use migrations::MigrationError;
+pub use models::*;
+use pool::ConnectionPool;
+pub use repository::Repository;
+
+/// Database file used by the bot and the CLI subcommands.
+pub const DEFAULT_DB_PATH: &str = "memory-assistant.db";
+
+/// Default number of read-only connections opened next to the writer.
+pub const DEFAULT_READ_CONNECTIONS: usize = 4;
+
+const DEFAULT_CATEGORIES: &[&str] = &[
+    "preference", "decision", "personal", "technical", "project", "workflow", "general",
+];
+
+/// Async handle to the SQLite database. Cheap to clone — all clones share
+/// one connection pool. Every query runs on Tokio's blocking thread pool.
+#[derive(Clone)]
 pub struct Database {
-    conn: Mutex<Connection>,
+    pool: Arc<ConnectionPool>,
+    vectors: Arc<vector_index::IndexCache>,
+    cache_usage: Arc<embedding_cache::CacheUsage>,
 }
 
 impl Database {
-    pub fn open(path: &str) -> Result<Self, rusqlite::Error> {
-        let conn = Connection::open(path)?;
+    /// Open the database and bring its schema up to date.
+    /// Any failed migration aborts with an error instead of being ignored.
+    pub fn open(path: &str) -> Result<Self, MigrationError> {
+        Self::open_with_readers(path, DEFAULT_READ_CONNECTIONS)
+    }
+
+    /// Like [`Database::open`], with an explicit number of read-only connections.
+    pub fn open_with_readers(path: &str, readers: usize) -> Result<Self, MigrationError> {
+        Self::open_encrypted(path, readers, None)
+    }
+
+    /// Like [`Database::open_with_readers`], keying every connection with
+    /// `key` first. Requires the `sqlcipher` feature when `key` is set.
+    pub fn open_encrypted(path: &str, readers: usize, key: Option<&str>) -> Result<Self, MigrationError> {
+        let mut conn = Connection::open(path)?;
+        if let Some(key) = key {
+            encryption::apply_key(&conn, key)?;
+        }
 
         conn.execute_batch("PRAGMA journal_mode=WAL; PRAGMA busy_timeout=5000; PRAGMA foreign_keys=ON;")?;
 
-        // Memory facts table
-        conn.execute_batch(
-            "CREATE TABLE IF NOT EXISTS memory_facts (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                user_id INTEGER NOT NULL,
-                fact TEXT NOT NULL,
-                category TEXT NOT NULL DEFAULT 'general',
-                created_at TEXT NOT NULL DEFAULT (datetime('now')),
-                access_count INTEGER NOT NULL DEFAULT 0,
-                last_accessed_at TEXT
-            );
-
-            CREATE VIRTUAL TABLE IF NOT EXISTS memory_facts_fts USING fts5(
-                fact,
-                content='memory_facts',
-                content_rowid='id'
-            );
-
-            CREATE TRIGGER IF NOT EXISTS memory_facts_ai AFTER INSERT ON memory_facts BEGIN
-                INSERT INTO memory_facts_fts(rowid, fact) VALUES (new.id, new.fact);
-            END;
-
-            CREATE TRIGGER IF NOT EXISTS memory_facts_ad AFTER DELETE ON memory_facts BEGIN
-                INSERT INTO memory_facts_fts(memory_facts_fts, rowid, fact) VALUES('delete', old.id, old.fact);
-            END;
-            "
-        )?;
+        let version = migrations::run(&mut conn)?;
+        audit::install_change_tracking(&conn)?;
 
-        // Add embedding column to memory_facts (idempotent — .ok() ignores "duplicate column" error)
-        conn.execute_batch(
-            "ALTER TABLE memory_facts ADD COLUMN embedding BLOB;"
-        ).ok();
-
-        // Knowledge documents table
-        conn.execute_batch(
-            "CREATE TABLE IF NOT EXISTS knowledge_documents (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                user_id INTEGER NOT NULL,
-                title TEXT NOT NULL,
-                content TEXT NOT NULL,
-                source TEXT,
-                tags TEXT,
-                created_at TEXT NOT NULL DEFAULT (datetime('now'))
-            );
-
-            CREATE VIRTUAL TABLE IF NOT EXISTS knowledge_docs_fts USING fts5(
-                title, content,
-                content='knowledge_documents',
-                content_rowid='id'
-            );
-
-            CREATE TRIGGER IF NOT EXISTS knowledge_docs_ai AFTER INSERT ON knowledge_documents BEGIN
-                INSERT INTO knowledge_docs_fts(rowid, title, content) VALUES (new.id, new.title, new.content);
-            END;
-
-            CREATE TRIGGER IF NOT EXISTS knowledge_docs_ad AFTER DELETE ON knowledge_documents BEGIN
-                INSERT INTO knowledge_docs_fts(knowledge_docs_fts, rowid, title, content) VALUES('delete', old.id, old.title, old.content);
-            END;
-            "
-        )?;
+        let pool = ConnectionPool::new(conn, path, readers, key)?;
+        info!(
+            "Database initialized: {path} (schema v{version}, {} read connections{})",
+            pool.reader_count(),
+            if key.is_some() { ", encrypted" } else { "" }
+        );
+        Ok(Self {
+            pool: Arc::new(pool),
+            vectors: Arc::default(),
+            cache_usage: Arc::default(),
+        })
+    }
 
-        // Entities & knowledge graph
-        conn.execute_batch(
-            "CREATE TABLE IF NOT EXISTS entities (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                user_id INTEGER NOT NULL,
-                name TEXT NOT NULL,
-                entity_type TEXT NOT NULL,
-                created_at TEXT NOT NULL DEFAULT (datetime('now')),
-                UNIQUE(user_id, name, entity_type)
-            );
-
-            CREATE TABLE IF NOT EXISTS entity_mentions (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                entity_id INTEGER NOT NULL REFERENCES entities(id),
-                source_type TEXT NOT NULL,
-                source_id INTEGER NOT NULL,
-                context TEXT,
-                created_at TEXT NOT NULL DEFAULT (datetime('now'))
-            );
-            "
-        )?;
+    /// Run a read-only query on a reader connection.
+    async fn read<T, E, F>(&self, f: F) -> DbResult<T>
+    where
+        T: Send + 'static,
+        E: Into<DbError> + Send + 'static,
+        F: FnOnce(&Connection) -> Result<T, E> + Send + 'static,
+    {
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || pool.with_reader(f))
+            .await?
+            .map_err(Into::into)
+    }
 
-        // Knowledge chunks (for semantic search)
-        conn.execute_batch(
-            "CREATE TABLE IF NOT EXISTS knowledge_chunks (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                doc_id INTEGER NOT NULL REFERENCES knowledge_documents(id) ON DELETE CASCADE,
-                chunk_index INTEGER NOT NULL,
-                start_line INTEGER NOT NULL,
-                end_line INTEGER NOT NULL,
-                content TEXT NOT NULL,
-                embedding BLOB,
-                created_at TEXT NOT NULL DEFAULT (datetime('now')),
-                UNIQUE(doc_id, chunk_index)
-            );
-
-            CREATE VIRTUAL TABLE IF NOT EXISTS knowledge_chunks_fts USING fts5(
-                content, content='knowledge_chunks', content_rowid='id'
-            );
-
-            CREATE TRIGGER IF NOT EXISTS knowledge_chunks_ai AFTER INSERT ON knowledge_chunks BEGIN
-                INSERT INTO knowledge_chunks_fts(rowid, content) VALUES (new.id, new.content);
-            END;
-
-            CREATE TRIGGER IF NOT EXISTS knowledge_chunks_ad AFTER DELETE ON knowledge_chunks BEGIN
-                INSERT INTO knowledge_chunks_fts(knowledge_chunks_fts, rowid, content) VALUES('delete', old.id, old.content);
-            END;
-
-            CREATE TRIGGER IF NOT EXISTS knowledge_chunks_au AFTER UPDATE OF content ON knowledge_chunks BEGIN
-                INSERT INTO knowledge_chunks_fts(knowledge_chunks_fts, rowid, content) VALUES('delete', old.id, old.content);
-                INSERT INTO knowledge_chunks_fts(rowid, content) VALUES (new.id, new.content);
-            END;
-            "
-        )?;
+    /// Run a statement (or transaction) on the single writer connection.
+    async fn write<T, E, F>(&self, f: F) -> DbResult<T>
+    where
+        T: Send + 'static,
+        E: Into<DbError> + Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, E> + Send + 'static,
+    {
+        let pool = self.pool.clone();
+        // Inside `audit::track_changes`, note the rows this write touches
+        let tracker = audit::current_tracker();
+        tokio::task::spawn_blocking(move || {
+            pool.with_writer(|conn| match &tracker {
+                Some(changes) => audit::tracked(conn, changes, f),
+                None => f(conn),
+            })
+        })
+        .await?
+            .map_err(Into::into)
+    }
 
-        // Categories (dynamic, per-user)
-        conn.execute_batch(
-            "CREATE TABLE IF NOT EXISTS categories (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                user_id INTEGER NOT NULL,
-                name TEXT NOT NULL,
-                created_at TEXT NOT NULL DEFAULT (datetime('now')),
-                UNIQUE(user_id, name)
-            );"
-        )?;
+    /// Schema version currently recorded in `schema_version`.
+    pub async fn schema_version(&self) -> DbResult<i64> {
+        self.read(migrations::current_version).await
+    }
+}
 
-        // Memory ↔ KB links
-        conn.execute_batch(
-            "CREATE TABLE IF NOT EXISTS memory_kb_links (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                fact_id INTEGER NOT NULL REFERENCES memory_facts(id) ON DELETE CASCADE,
-                doc_id INTEGER NOT NULL REFERENCES knowledge_documents(id) ON DELETE CASCADE,
-                created_at TEXT NOT NULL DEFAULT (datetime('now')),
-                UNIQUE(fact_id, doc_id)
-            );"
-        )?;
+/// Map an `(id, user_id, title, created_at, last_active_at, summary)` row.
+fn session_from_row(row: &Row) -> rusqlite::Result<Session> {
+    let uid: i64 = row.get(1)?;
+    Ok(Session {
+        id: row.get(0)?,
+        user_id: uid as u64,
+        title: row.get(2)?,
+        created_at: row.get(3)?,
+        last_active_at: row.get(4)?,
+        summary: row.get(5)?,
+    })
+}
 
-        // Fact-to-fact relations (semantic similarity links)
-        conn.execute_batch(
-            "CREATE TABLE IF NOT EXISTS fact_relations (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                fact_id_1 INTEGER NOT NULL REFERENCES memory_facts(id) ON DELETE CASCADE,
-                fact_id_2 INTEGER NOT NULL REFERENCES memory_facts(id) ON DELETE CASCADE,
-                similarity REAL NOT NULL,
-                created_at TEXT NOT NULL DEFAULT (datetime('now')),
-                UNIQUE(fact_id_1, fact_id_2),
-                CHECK(fact_id_1 < fact_id_2)
-            );
-
-            CREATE INDEX IF NOT EXISTS idx_fact_relations_1 ON fact_relations(fact_id_1);
-            CREATE INDEX IF NOT EXISTS idx_fact_relations_2 ON fact_relations(fact_id_2);"
-        )?;
+/// Columns [`session_from_row`] expects, with `sessions` aliased as `s`.
+const SESSION_COLUMNS: &str = "s.id, s.user_id, s.title, s.created_at, s.last_active_at, s.summary";
 
-        // Pending approval queue (for non-whitelisted users' write requests in groups)
-        conn.execute_batch(
-            "CREATE TABLE IF NOT EXISTS pending_items (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                scope_id INTEGER NOT NULL,
-                requested_by INTEGER NOT NULL,
-                tool_name TEXT NOT NULL,
-                args_json TEXT NOT NULL,
-                summary TEXT NOT NULL,
-                created_at TEXT NOT NULL DEFAULT (datetime('now'))
-            );"
-        )?;
+fn load_session(conn: &Connection, id: &str) -> DbResult<Session> {
+    conn.query_row(&format!("SELECT {SESSION_COLUMNS} FROM sessions s WHERE id = ?1"), params![id], session_from_row)
+        .optional()?
+        .ok_or_else(|| DbError::NotFound(format!("Session {id}")))
+}
 
-        // Usage log (cost tracking per model)
-        conn.execute_batch(
-            "CREATE TABLE IF NOT EXISTS usage_log (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                model TEXT NOT NULL,
-                provider TEXT NOT NULL,
-                prompt_tokens INTEGER NOT NULL DEFAULT 0,
-                completion_tokens INTEGER NOT NULL DEFAULT 0,
-                cache_creation_tokens INTEGER NOT NULL DEFAULT 0,
-                cache_read_tokens INTEGER NOT NULL DEFAULT 0,
-                created_at TEXT NOT NULL DEFAULT (datetime('now'))
-            );"
+/// Insert a session for `owner_id` and make it the active one. Ids are
+/// `{owner}-{unix time}`, with a counter appended when several sessions start
+/// within the same second.
+fn insert_active_session(conn: &Connection, owner_id: u64, title: Option<&str>) -> rusqlite::Result<String> {
+    let base = format!("{owner_id}-{}", chrono::Utc::now().timestamp());
+    let mut id = base.clone();
+    for n in 2.. {
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO sessions (id, user_id, title) VALUES (?1, ?2, ?3)",
+            params![&id, owner_id as i64, title],
         )?;
+        if inserted > 0 {
+            break;
+        }
+        id = format!("{base}-{n}");
+    }
+    set_active_session(conn, owner_id, &id)?;
+    Ok(id)
+}
 
-        // User preferences
-        conn.execute_batch(
-            "CREATE TABLE IF NOT EXISTS user_preferences (
-                user_id INTEGER PRIMARY KEY,
-                model TEXT NOT NULL DEFAULT 'claude-haiku-4-5-20251001',
-                updated_at TEXT NOT NULL DEFAULT (datetime('now'))
-            );"
-        )?;
+fn set_active_session(conn: &Connection, owner_id: u64, session_id: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO active_sessions (owner_id, session_id) VALUES (?1, ?2)
+         ON CONFLICT(owner_id) DO UPDATE SET session_id = excluded.session_id, updated_at = datetime('now')",
+        params![owner_id as i64, session_id],
+    )
+    .map(|_| ())
+}
 
-        // Conversation sessions
-        conn.execute_batch(
-            "CREATE TABLE IF NOT EXISTS sessions (
-                id TEXT PRIMARY KEY,
-                user_id INTEGER NOT NULL,
-                title TEXT,
-                created_at TEXT NOT NULL DEFAULT (datetime('now')),
-                last_active_at TEXT NOT NULL DEFAULT (datetime('now'))
-            );
-
-            CREATE TABLE IF NOT EXISTS session_messages (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                session_id TEXT NOT NULL REFERENCES sessions(id),
-                role TEXT NOT NULL,
-                content TEXT NOT NULL,
-                created_at TEXT NOT NULL DEFAULT (datetime('now'))
-            );
-            "
-        )?;
+/// Map an `(id, role, content, created_at, message)` row.
+fn session_message_from_row(row: &Row) -> rusqlite::Result<SessionMessage> {
+    Ok(SessionMessage {
+        id: row.get(0)?,
+        role: row.get(1)?,
+        content: row.get(2)?,
+        created_at: row.get(3)?,
+        message: row.get(4)?,
+    })
+}
 
-        info!("Database initialized: {path}");
-        Ok(Self {
-            conn: Mutex::new(conn),
-        })
-    }
+/// Map an `(id, fact, category)` row.
+fn fact_from_row(row: &Row) -> rusqlite::Result<Fact> {
+    Ok(Fact {
+        id: row.get(0)?,
+        text: row.get(1)?,
+        category: row.get(2)?,
+    })
+}
+
+/// Map an `(id, doc_id, title, content, start_line, end_line, source)` row.
+fn chunk_from_row(row: &Row) -> rusqlite::Result<Chunk> {
+    Ok(Chunk {
+        id: row.get(0)?,
+        doc_id: row.get(1)?,
+        title: row.get(2)?,
+        content: row.get(3)?,
+        start_line: row.get(4)?,
+        end_line: row.get(5)?,
+        source: row.get(6)?,
+    })
+}
+
+/// Map an `(id, title, content, source, tags, created_at)` row.
+fn document_from_row(row: &Row) -> rusqlite::Result<Document> {
+    Ok(Document {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        content: row.get(2)?,
+        source: row.get(3)?,
+        tags: row.get(4)?,
+        created_at: row.get(5)?,
+    })
+}
 
+impl Repository for Database {
     // --- Memory ---
 
-    pub fn save_fact(&self, user_id: u64, fact: &str, category: &str) -> Result<i64, String> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "INSERT INTO memory_facts (user_id, fact, category) VALUES (?1, ?2, ?3)",
-            params![user_id as i64, fact, category],
-        )
-        .map_err(|e| e.to_string())?;
-        Ok(conn.last_insert_rowid())
-    }
-
-    pub fn search_facts(&self, user_id: u64, keyword: &str) -> Result<Vec<(i64, String, String)>, String> {
-        let conn = self.conn.lock().unwrap();
-
-        // Try FTS5 first, fall back to LIKE
-        let results: Vec<(i64, String, String)> = conn
-            .prepare(
-                "SELECT mf.id, mf.fact, mf.category FROM memory_facts mf
-                 JOIN memory_facts_fts fts ON mf.id = fts.rowid
-                 WHERE fts.fact MATCH ?1 AND mf.user_id = ?2
-                 ORDER BY rank LIMIT 20"
-            )
-            .and_then(|mut stmt| {
-                let rows = stmt.query_map(params![keyword, user_id as i64], |row| {
-                    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
-                })?;
-                rows.collect()
-            })
-            .unwrap_or_else(|_| {
-                // Fallback to LIKE
-                conn.prepare(
-                    "SELECT id, fact, category FROM memory_facts
-                     WHERE user_id = ?1 AND fact LIKE '%' || ?2 || '%'
-                     ORDER BY created_at DESC LIMIT 20"
-                )
-                .and_then(|mut stmt| {
-                    let rows = stmt.query_map(params![user_id as i64, keyword], |row| {
-                        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
-                    })?;
+    async fn save_fact(&self, user_id: u64, fact: &str, category: &str) -> DbResult<i64> {
+        let (fact, category) = (fact.to_string(), category.to_string());
+        self.write(move |conn| {
+            conn.execute(
+                "INSERT INTO memory_facts (user_id, fact, category) VALUES (?1, ?2, ?3)",
+                params![user_id as i64, fact, category],
+            )?;
+            Ok::<_, rusqlite::Error>(conn.last_insert_rowid())
+        })
+        .await
+    }
+
+    async fn search_facts(&self, user_id: u64, keyword: &str) -> DbResult<Vec<Fact>> {
+        let keyword = keyword.to_string();
+        let results: Vec<Fact> = self
+            .read(move |conn| {
+                // Word index first, then substrings (trigram index), then LIKE
+                let search = |table: &str, query: &str| -> rusqlite::Result<Vec<Fact>> {
+                    let mut stmt = conn.prepare(&format!(
+                        "SELECT mf.id, mf.fact, mf.category FROM memory_facts mf
+                         JOIN {table} fts ON mf.id = fts.rowid
+                         WHERE {table} MATCH ?1 AND mf.user_id = ?2 AND mf.deleted_at IS NULL
+                         ORDER BY rank LIMIT 20"
+                    ))?;
+                    let rows = stmt.query_map(params![query, user_id as i64], fact_from_row)?;
                     rows.collect()
+                };
+                let results = fts::match_query(&keyword)
+                    .ok_or(rusqlite::Error::InvalidQuery)
+                    .and_then(|q| search("memory_facts_fts", &q))
+                    .and_then(|found| match fts::substring_query(&keyword) {
+                        Some(q) if found.is_empty() && fts::has_trigram_index(conn)? => search("memory_facts_tri", &q),
+                        _ => Ok(found),
+                    })
+                    .unwrap_or_else(|_| {
+                        // Fallback to LIKE
+                        conn.prepare(
+                            "SELECT id, fact, category FROM memory_facts
+                             WHERE user_id = ?1 AND deleted_at IS NULL AND fact LIKE '%' || ?2 || '%'
+                             ORDER BY created_at DESC LIMIT 20"
+                        )
+                        .and_then(|mut stmt| {
+                            let rows = stmt.query_map(params![user_id as i64, keyword], fact_from_row)?;
+                            rows.collect()
+                        })
+                        .unwrap_or_default()
+                    });
+                Ok::<_, rusqlite::Error>(results)
+            })
+            .await?;
+
+        // Update access count (on the writer — readers are query-only)
+        if !results.is_empty() {
+            let ids: Vec<i64> = results.iter().map(|f| f.id).collect();
+            let _ = self
+                .write(move |conn| {
+                    for id in &ids {
+                        conn.execute(
+                            "UPDATE memory_facts SET access_count = access_count + 1, last_accessed_at = datetime('now') WHERE id = ?1",
+                            params![id],
+                        )?;
+                    }
+                    Ok::<_, rusqlite::Error>(())
                 })
-                .unwrap_or_default()
-            });
-
-        // Update access count
-        for (id, _, _) in &results {
-            let _ = conn.execute(
-                "UPDATE memory_facts SET access_count = access_count + 1, last_accessed_at = datetime('now') WHERE id = ?1",
-                params![id],
-            );
+                .await;
         }
 
         Ok(results)
     }
 
-    pub fn list_facts(&self, user_id: u64, category: Option<&str>) -> Result<Vec<(i64, String, String)>, String> {
-        let conn = self.conn.lock().unwrap();
-        let (sql, p): (&str, Vec<Box<dyn rusqlite::types::ToSql>>) = match category {
-            Some(cat) => (
-                "SELECT id, fact, category FROM memory_facts WHERE user_id = ?1 AND category = ?2 ORDER BY created_at DESC LIMIT 30",
-                vec![Box::new(user_id as i64), Box::new(cat.to_string())],
-            ),
-            None => (
-                "SELECT id, fact, category FROM memory_facts WHERE user_id = ?1 ORDER BY created_at DESC LIMIT 30",
-                vec![Box::new(user_id as i64)],
-            ),
-        };
+    async fn list_facts(&self, user_id: u64, category: Option<&str>) -> DbResult<Vec<Fact>> {
+        let category = category.map(str::to_string);
+        self.read(move |conn| {
+            let (sql, p): (&str, Vec<Box<dyn rusqlite::types::ToSql>>) = match category {
+                Some(cat) => (
+                    "SELECT id, fact, category FROM memory_facts WHERE user_id = ?1 AND category = ?2 AND deleted_at IS NULL ORDER BY created_at DESC LIMIT 30",
+                    vec![Box::new(user_id as i64), Box::new(cat)],
+                ),
+                None => (
+                    "SELECT id, fact, category FROM memory_facts WHERE user_id = ?1 AND deleted_at IS NULL ORDER BY created_at DESC LIMIT 30",
+                    vec![Box::new(user_id as i64)],
+                ),
+            };
+
+            let mut stmt = conn.prepare(sql)?;
+            let params_refs: Vec<&dyn rusqlite::types::ToSql> = p.iter().map(|b| b.as_ref()).collect();
+            let rows = stmt.query_map(params_refs.as_slice(), fact_from_row)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await
+    }
 
-        let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
-        let params_refs: Vec<&dyn rusqlite::types::ToSql> = p.iter().map(|b| b.as_ref()).collect();
-        let rows = stmt
-            .query_map(params_refs.as_slice(), |row| {
-                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
-            })
-            .map_err(|e| e.to_string())?;
+    async fn update_fact(&self, user_id: u64, fact_id: i64, new_fact: &str, actor: Actor<'_>) -> DbResult<bool> {
+        let (new_fact, actor_id, tool) = (new_fact.to_string(), actor.user_id, actor.tool.to_string());
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let old_fact: Option<String> = tx
+                .query_row(
+                    "SELECT fact FROM memory_facts WHERE id = ?1 AND user_id = ?2 AND deleted_at IS NULL",
+                    params![fact_id, user_id as i64],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let Some(old_fact) = old_fact else {
+                return Ok(false);
+            };
+            if old_fact != new_fact {
+                // FTS is re-indexed by the memory_facts_au trigger
+                tx.execute("UPDATE memory_facts SET fact = ?1 WHERE id = ?2", params![new_fact, fact_id])?;
+                insert_fact_revision(&tx, fact_id, &old_fact, &new_fact, None, actor_id, &tool)?;
+                entity_graph::refresh_mentions(&tx, "fact", fact_id)?;
+            }
+            tx.commit()?;
+            Ok::<_, rusqlite::Error>(true)
+        })
+        .await
+    }
 
-        rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+    async fn delete_fact(&self, user_id: u64, fact_id: i64) -> DbResult<bool> {
+        self.write(move |conn| {
+            let rows = conn.execute(
+                "UPDATE memory_facts SET deleted_at = datetime('now') WHERE id = ?1 AND user_id = ?2 AND deleted_at IS NULL",
+                params![fact_id, user_id as i64],
+            )?;
+            Ok::<_, rusqlite::Error>(rows > 0)
+        })
+        .await
     }
 
-    pub fn update_fact(&self, user_id: u64, fact_id: i64, new_fact: &str) -> Result<bool, String> {
-        let conn = self.conn.lock().unwrap();
-        let rows = conn
-            .execute(
-                "UPDATE memory_facts SET fact = ?1 WHERE id = ?2 AND user_id = ?3",
-                params![new_fact, fact_id, user_id as i64],
-            )
-            .map_err(|e| e.to_string())?;
-        if rows > 0 {
-            // Re-index FTS
-            let _ = conn.execute(
-                "INSERT INTO memory_facts_fts(memory_facts_fts, rowid, fact) VALUES('delete', ?1, '')",
-                params![fact_id],
-            );
-            let _ = conn.execute(
-                "INSERT INTO memory_facts_fts(rowid, fact) VALUES (?1, ?2)",
-                params![fact_id, new_fact],
-            );
-        }
-        Ok(rows > 0)
+    // --- Fact Revisions ---
+
+    async fn supersede_fact(&self, user_id: u64, old_id: i64, new_id: i64, actor: Actor<'_>) -> DbResult<bool> {
+        let (actor_id, tool) = (actor.user_id, actor.tool.to_string());
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let old_text: Option<String> = tx
+                .query_row(
+                    "SELECT fact FROM memory_facts WHERE id = ?1 AND user_id = ?2 AND deleted_at IS NULL",
+                    params![old_id, user_id as i64],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let Some(old_text) = old_text else {
+                return Ok::<_, DbError>(false);
+            };
+            let new_text: String = tx
+                .query_row(
+                    "SELECT fact FROM memory_facts WHERE id = ?1 AND user_id = ?2",
+                    params![new_id, user_id as i64],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .ok_or_else(|| DbError::NotFound(format!("Memory #{new_id}")))?;
+
+            tx.execute(
+                "UPDATE memory_facts SET deleted_at = datetime('now') WHERE id = ?1",
+                params![old_id],
+            )?;
+            insert_fact_revision(&tx, old_id, &old_text, &new_text, Some(new_id), actor_id, &tool)?;
+            tx.commit()?;
+            Ok(true)
+        })
+        .await
     }
 
-    pub fn delete_fact(&self, user_id: u64, fact_id: i64) -> Result<bool, String> {
-        let conn = self.conn.lock().unwrap();
-        let rows = conn
-            .execute(
-                "DELETE FROM memory_facts WHERE id = ?1 AND user_id = ?2",
-                params![fact_id, user_id as i64],
-            )
-            .map_err(|e| e.to_string())?;
-        Ok(rows > 0)
+    async fn get_fact_history(&self, user_id: u64, fact_id: i64) -> DbResult<Vec<FactRevision>> {
+        self.read(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT r.id, r.fact_id, r.old_text, r.new_text, r.superseded_by, r.actor_id, r.tool, r.created_at
+                 FROM memory_fact_revisions r
+                 JOIN memory_facts mf ON mf.id = r.fact_id
+                 WHERE mf.user_id = ?1 AND (r.fact_id = ?2 OR r.superseded_by = ?2)
+                 ORDER BY r.id DESC LIMIT 50"
+            )?;
+            let rows = stmt.query_map(params![user_id as i64, fact_id], revision_from_row)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await
+    }
+
+    async fn revert_fact(&self, user_id: u64, revision_id: i64, actor: Actor<'_>) -> DbResult<(Fact, Option<i64>)> {
+        let (actor_id, tool) = (actor.user_id, actor.tool.to_string());
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let (fact_id, old_text, superseded_by): (i64, String, Option<i64>) = tx
+                .query_row(
+                    "SELECT r.fact_id, r.old_text, r.superseded_by
+                     FROM memory_fact_revisions r
+                     JOIN memory_facts mf ON mf.id = r.fact_id
+                     WHERE r.id = ?1 AND mf.user_id = ?2",
+                    params![revision_id, user_id as i64],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                )
+                .optional()?
+                .ok_or_else(|| DbError::NotFound(format!("Revision #{revision_id}")))?;
+
+            // Put the old text back; the embedding is stale once the text changes
+            let current: String =
+                tx.query_row("SELECT fact FROM memory_facts WHERE id = ?1", params![fact_id], |row| row.get(0))?;
+            if current != old_text {
+                tx.execute(
+                    "UPDATE memory_facts SET fact = ?1, embedding = NULL WHERE id = ?2",
+                    params![old_text, fact_id],
+                )?;
+                insert_fact_revision(&tx, fact_id, &current, &old_text, None, actor_id, &tool)?;
+                entity_graph::refresh_mentions(&tx, "fact", fact_id)?;
+            }
+            tx.execute("UPDATE memory_facts SET deleted_at = NULL WHERE id = ?1", params![fact_id])?;
+
+            // Undoing a supersede also retires the fact that replaced it
+            let mut replaced = None;
+            if let Some(new_id) = superseded_by {
+                let new_text: Option<String> = tx
+                    .query_row(
+                        "SELECT fact FROM memory_facts WHERE id = ?1 AND deleted_at IS NULL",
+                        params![new_id],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                if let Some(new_text) = new_text {
+                    tx.execute(
+                        "UPDATE memory_facts SET deleted_at = datetime('now') WHERE id = ?1",
+                        params![new_id],
+                    )?;
+                    insert_fact_revision(&tx, new_id, &new_text, &old_text, Some(fact_id), actor_id, &tool)?;
+                    replaced = Some(new_id);
+                }
+            }
+
+            let fact = tx.query_row(
+                "SELECT id, fact, category FROM memory_facts WHERE id = ?1",
+                params![fact_id],
+                fact_from_row,
+            )?;
+            tx.commit()?;
+            Ok::<_, DbError>((fact, replaced))
+        })
+        .await
     }
 
     // --- Fact Embeddings & Relations ---
 
-    pub fn update_fact_embedding(&self, fact_id: i64, embedding: &[u8]) -> Result<(), String> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "UPDATE memory_facts SET embedding = ?1 WHERE id = ?2",
-            params![embedding, fact_id],
-        )
-        .map_err(|e| e.to_string())?;
-        Ok(())
+    async fn update_fact_embedding(&self, fact_id: i64, embedding: &[u8]) -> DbResult<()> {
+        let embedding = embedding.to_vec();
+        self.write(move |conn| {
+            let quantized = quantization::quantized_blob(quantization::current(conn)?, &embedding);
+            conn.execute(
+                "UPDATE memory_facts SET embedding = ?1, embedding_q = ?2 WHERE id = ?3",
+                params![embedding, quantized, fact_id],
+            )
+            .map(|_| ())
+        })
+        .await
     }
 
-    /// Load all fact embeddings for a user (for cosine similarity search).
-    /// Returns (fact_id, fact_text, category, embedding_bytes).
-    pub fn load_all_fact_embeddings(
-        &self,
-        user_id: u64,
-    ) -> Result<Vec<(i64, String, String, Vec<u8>)>, String> {
-        let conn = self.conn.lock().unwrap();
-        conn.prepare(
-            "SELECT id, fact, category, embedding FROM memory_facts
-             WHERE user_id = ?1 AND embedding IS NOT NULL"
-        )
-        .and_then(|mut stmt| {
+    async fn load_all_fact_embeddings(&self, user_id: u64) -> DbResult<Vec<(Fact, Vec<u8>)>> {
+        self.read(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, fact, category, embedding FROM memory_facts
+                 WHERE user_id = ?1 AND embedding IS NOT NULL AND deleted_at IS NULL"
+            )?;
             let rows = stmt.query_map(params![user_id as i64], |row| {
-                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+                Ok((fact_from_row(row)?, row.get(3)?))
             })?;
-            rows.collect()
+            rows.collect::<rusqlite::Result<Vec<_>>>()
         })
-        .map_err(|e| e.to_string())
+        .await
     }
 
-    /// Link two facts with a similarity score. Enforces fact_id_1 < fact_id_2.
-    pub fn link_facts(&self, id_a: i64, id_b: i64, similarity: f32) -> Result<(), String> {
+    async fn link_facts(&self, id_a: i64, id_b: i64, similarity: f32) -> DbResult<()> {
         let (lo, hi) = if id_a < id_b { (id_a, id_b) } else { (id_b, id_a) };
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "INSERT OR REPLACE INTO fact_relations (fact_id_1, fact_id_2, similarity) VALUES (?1, ?2, ?3)",
-            params![lo, hi, similarity as f64],
-        )
-        .map_err(|e| e.to_string())?;
-        Ok(())
-    }
-
-    /// Get facts related to a given fact_id. Returns (related_fact_id, fact_text, category, similarity).
-    pub fn get_related_facts(&self, fact_id: i64) -> Result<Vec<(i64, String, String, f64)>, String> {
-        let conn = self.conn.lock().unwrap();
-        conn.prepare(
-            "SELECT mf.id, mf.fact, mf.category, fr.similarity
-             FROM fact_relations fr
-             JOIN memory_facts mf ON mf.id = CASE
-                 WHEN fr.fact_id_1 = ?1 THEN fr.fact_id_2
-                 ELSE fr.fact_id_1
-             END
-             WHERE fr.fact_id_1 = ?1 OR fr.fact_id_2 = ?1
-             ORDER BY fr.similarity DESC"
-        )
-        .and_then(|mut stmt| {
+        self.write(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO fact_relations (fact_id_1, fact_id_2, similarity) VALUES (?1, ?2, ?3)",
+                params![lo, hi, similarity as f64],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    async fn get_related_facts(&self, fact_id: i64) -> DbResult<Vec<(Fact, f64)>> {
+        self.read(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT mf.id, mf.fact, mf.category, fr.similarity
+                 FROM fact_relations fr
+                 JOIN memory_facts mf ON mf.id = CASE
+                     WHEN fr.fact_id_1 = ?1 THEN fr.fact_id_2
+                     ELSE fr.fact_id_1
+                 END
+                 WHERE (fr.fact_id_1 = ?1 OR fr.fact_id_2 = ?1) AND mf.deleted_at IS NULL
+                 ORDER BY fr.similarity DESC"
+            )?;
             let rows = stmt.query_map(params![fact_id], |row| {
-                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+                Ok((fact_from_row(row)?, row.get(3)?))
             })?;
-            rows.collect()
+            rows.collect::<rusqlite::Result<Vec<_>>>()
         })
-        .map_err(|e| e.to_string())
+        .await
     }
 
-    /// Delete all relations for a given fact_id (used before recomputing after edit).
-    pub fn delete_fact_relations(&self, fact_id: i64) -> Result<(), String> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "DELETE FROM fact_relations WHERE fact_id_1 = ?1 OR fact_id_2 = ?1",
-            params![fact_id],
-        )
-        .map_err(|e| e.to_string())?;
-        Ok(())
+    async fn delete_fact_relations(&self, fact_id: i64) -> DbResult<()> {
+        self.write(move |conn| {
+            conn.execute(
+                "DELETE FROM fact_relations WHERE fact_id_1 = ?1 OR fact_id_2 = ?1",
+                params![fact_id],
+            )
+            .map(|_| ())
+        })
+        .await
     }
 
-    /// Batch-link multiple fact pairs in a single transaction.
-    pub fn link_facts_batch(&self, links: &[(i64, i64, f32)]) -> Result<(), String> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute_batch("BEGIN").map_err(|e| e.to_string())?;
-        for &(id_a, id_b, similarity) in links {
-            let (lo, hi) = if id_a < id_b { (id_a, id_b) } else { (id_b, id_a) };
-            if let Err(e) = conn.execute(
-                "INSERT OR REPLACE INTO fact_relations (fact_id_1, fact_id_2, similarity) VALUES (?1, ?2, ?3)",
-                params![lo, hi, similarity as f64],
-            ) {
-                let _ = conn.execute_batch("ROLLBACK");
-                return Err(e.to_string());
+    async fn link_facts_batch(&self, links: &[(i64, i64, f32)]) -> DbResult<()> {
+        let links = links.to_vec();
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            for (id_a, id_b, similarity) in links {
+                let (lo, hi) = if id_a < id_b { (id_a, id_b) } else { (id_b, id_a) };
+                tx.execute(
+                    "INSERT OR REPLACE INTO fact_relations (fact_id_1, fact_id_2, similarity) VALUES (?1, ?2, ?3)",
+                    params![lo, hi, similarity as f64],
+                )?;
             }
-        }
-        conn.execute_batch("COMMIT").map_err(|e| e.to_string())?;
-        Ok(())
+            tx.commit()
+        })
+        .await
     }
 
-    /// Get facts that don't have embeddings yet. Returns (id, fact_text).
-    pub fn get_unembedded_facts(&self, user_id: u64) -> Result<Vec<(i64, String)>, String> {
-        let conn = self.conn.lock().unwrap();
-        conn.prepare(
-            "SELECT id, fact FROM memory_facts WHERE user_id = ?1 AND embedding IS NULL"
-        )
-        .and_then(|mut stmt| {
-            let rows = stmt.query_map(params![user_id as i64], |row| {
-                Ok((row.get(0)?, row.get(1)?))
-            })?;
-            rows.collect()
+    async fn get_unembedded_facts(&self, user_id: u64) -> DbResult<Vec<Fact>> {
+        self.read(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, fact, category FROM memory_facts WHERE user_id = ?1 AND embedding IS NULL AND deleted_at IS NULL"
+            )?;
+            let rows = stmt.query_map(params![user_id as i64], fact_from_row)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
         })
-        .map_err(|e| e.to_string())
+        .await
     }
 
-    /// Get all user_ids that have facts (for migration across all users).
-    pub fn get_fact_user_ids(&self) -> Result<Vec<u64>, String> {
-        let conn = self.conn.lock().unwrap();
-        conn.prepare("SELECT DISTINCT user_id FROM memory_facts")
-            .and_then(|mut stmt| {
-                let rows = stmt.query_map([], |row| {
-                    let uid: i64 = row.get(0)?;
-                    Ok(uid as u64)
-                })?;
-                rows.collect()
-            })
-            .map_err(|e| e.to_string())
+    async fn get_fact_user_ids(&self) -> DbResult<Vec<u64>> {
+        self.read(|conn| {
+            let mut stmt = conn.prepare("SELECT DISTINCT user_id FROM memory_facts WHERE deleted_at IS NULL")?;
+            let rows = stmt.query_map([], |row| {
+                let uid: i64 = row.get(0)?;
+                Ok(uid as u64)
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await
     }
 
     // --- Categories ---
 
-    const DEFAULT_CATEGORIES: &'static [&'static str] = &[
-        "preference", "decision", "personal", "technical", "project", "workflow", "general",
-    ];
+    async fn ensure_default_categories(&self, user_id: u64) -> DbResult<()> {
+        self.write(move |conn| {
+            for cat in DEFAULT_CATEGORIES {
+                conn.execute(
+                    "INSERT OR IGNORE INTO categories (user_id, name) VALUES (?1, ?2)",
+                    params![user_id as i64, cat],
+                )?;
+            }
+            Ok::<_, rusqlite::Error>(())
+        })
+        .await
+    }
 
-    pub fn ensure_default_categories(&self, user_id: u64) -> Result<(), String> {
-        let conn = self.conn.lock().unwrap();
-        for cat in Self::DEFAULT_CATEGORIES {
-            conn.execute(
-                "INSERT OR IGNORE INTO categories (user_id, name) VALUES (?1, ?2)",
-                params![user_id as i64, cat],
-            )
-            .map_err(|e| e.to_string())?;
-        }
-        Ok(())
+    async fn list_categories(&self, user_id: u64) -> DbResult<Vec<String>> {
+        self.read(move |conn| {
+            let mut stmt = conn.prepare("SELECT name FROM categories WHERE user_id = ?1 ORDER BY name")?;
+            let rows = stmt.query_map(params![user_id as i64], |row| row.get(0))?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await
     }
 
-    pub fn list_categories(&self, user_id: u64) -> Result<Vec<String>, String> {
-        let conn = self.conn.lock().unwrap();
-        conn.prepare("SELECT name FROM categories WHERE user_id = ?1 ORDER BY name")
-            .and_then(|mut stmt| {
-                let rows = stmt.query_map(params![user_id as i64], |row| row.get(0))?;
-                rows.collect()
-            })
-            .map_err(|e| e.to_string())
-    }
-
-    pub fn add_category(&self, user_id: u64, name: &str) -> Result<(), String> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "INSERT INTO categories (user_id, name) VALUES (?1, ?2)",
-            params![user_id as i64, name],
-        )
-        .map_err(|e| {
-            if e.to_string().contains("UNIQUE") {
-                format!("Category '{name}' already exists.")
-            } else {
-                e.to_string()
+    async fn add_category(&self, user_id: u64, name: &str) -> DbResult<()> {
+        let name = name.to_string();
+        self.write(move |conn| {
+            match conn.execute(
+                "INSERT INTO categories (user_id, name) VALUES (?1, ?2)",
+                params![user_id as i64, name],
+            ) {
+                Ok(_) => Ok(()),
+                Err(e) if e.to_string().contains("UNIQUE") => {
+                    Err(DbError::Conflict(format!("Category '{name}' already exists.")))
+                }
+                Err(e) => Err(e.into()),
             }
-        })?;
-        Ok(())
+        })
+        .await
     }
 
-    pub fn delete_category(&self, user_id: u64, name: &str) -> Result<bool, String> {
-        let conn = self.conn.lock().unwrap();
-        // Check if any facts use this category
-        let count: i64 = conn
-            .query_row(
-                "SELECT COUNT(*) FROM memory_facts WHERE user_id = ?1 AND category = ?2",
+    async fn delete_category(&self, user_id: u64, name: &str) -> DbResult<bool> {
+        let name = name.to_string();
+        self.write(move |conn| {
+            // Check if any facts use this category
+            let count: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM memory_facts WHERE user_id = ?1 AND category = ?2 AND deleted_at IS NULL",
                 params![user_id as i64, name],
                 |row| row.get(0),
-            )
-            .map_err(|e| e.to_string())?;
-        if count > 0 {
-            return Err(format!(
-                "Cannot delete category '{name}': {count} fact(s) still use it."
-            ));
-        }
-        let rows = conn
-            .execute(
+            )?;
+            if count > 0 {
+                return Err(DbError::Conflict(format!(
+                    "Cannot delete category '{name}': {count} fact(s) still use it."
+                )));
+            }
+            let rows = conn.execute(
                 "DELETE FROM categories WHERE user_id = ?1 AND name = ?2",
                 params![user_id as i64, name],
-            )
-            .map_err(|e| e.to_string())?;
-        Ok(rows > 0)
+            )?;
+            Ok(rows > 0)
+        })
+        .await
     }
 
     // --- Memory ↔ KB Links ---
 
-    pub fn link_fact_to_doc(&self, fact_id: i64, doc_id: i64) -> Result<(), String> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "INSERT OR IGNORE INTO memory_kb_links (fact_id, doc_id) VALUES (?1, ?2)",
-            params![fact_id, doc_id],
-        )
-        .map_err(|e| e.to_string())?;
-        Ok(())
-    }
-
-    pub fn get_fact_links(&self, fact_id: i64) -> Result<Vec<(i64, String)>, String> {
-        let conn = self.conn.lock().unwrap();
-        conn.prepare(
-            "SELECT kd.id, kd.title FROM memory_kb_links mkl
-             JOIN knowledge_documents kd ON mkl.doc_id = kd.id
-             WHERE mkl.fact_id = ?1"
-        )
-        .and_then(|mut stmt| {
+    async fn link_fact_to_doc(&self, fact_id: i64, doc_id: i64) -> DbResult<()> {
+        self.write(move |conn| {
+            conn.execute(
+                "INSERT OR IGNORE INTO memory_kb_links (fact_id, doc_id) VALUES (?1, ?2)",
+                params![fact_id, doc_id],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    async fn get_fact_links(&self, fact_id: i64) -> DbResult<Vec<(i64, String)>> {
+        self.read(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT kd.id, kd.title FROM memory_kb_links mkl
+                 JOIN knowledge_documents kd ON mkl.doc_id = kd.id
+                 WHERE mkl.fact_id = ?1 AND kd.deleted_at IS NULL"
+            )?;
             let rows = stmt.query_map(params![fact_id], |row| {
                 Ok((row.get(0)?, row.get(1)?))
             })?;
-            rows.collect()
+            rows.collect::<rusqlite::Result<Vec<_>>>()
         })
-        .map_err(|e| e.to_string())
+        .await
     }
 
-    pub fn get_doc_linked_facts(&self, doc_id: i64) -> Result<Vec<(i64, String, String)>, String> {
-        let conn = self.conn.lock().unwrap();
-        conn.prepare(
-            "SELECT mf.id, mf.fact, mf.category FROM memory_kb_links mkl
-             JOIN memory_facts mf ON mkl.fact_id = mf.id
-             WHERE mkl.doc_id = ?1"
-        )
-        .and_then(|mut stmt| {
-            let rows = stmt.query_map(params![doc_id], |row| {
-                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
-            })?;
-            rows.collect()
+    async fn get_doc_linked_facts(&self, doc_id: i64) -> DbResult<Vec<Fact>> {
+        self.read(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT mf.id, mf.fact, mf.category FROM memory_kb_links mkl
+                 JOIN memory_facts mf ON mkl.fact_id = mf.id
+                 WHERE mkl.doc_id = ?1 AND mf.deleted_at IS NULL"
+            )?;
+            let rows = stmt.query_map(params![doc_id], fact_from_row)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
         })
-        .map_err(|e| e.to_string())
+        .await
     }
 
-    // --- Memory context for system prompt ---
-
-    pub fn build_memory_context(&self, user_id: u64) -> String {
-        let facts = self.list_facts(user_id, None).unwrap_or_default();
-        if facts.is_empty() {
-            return String::new();
-        }
+    // --- Conversation history ---
 
-        let mut grouped: std::collections::HashMap<String, Vec<String>> = std::collections::HashMap::new();
-        for (_, fact, category) in &facts {
-            grouped
-                .entry(category.clone())
-                .or_default()
-                .push(fact.clone());
-        }
+    async fn get_or_create_session(&self, user_id: u64) -> DbResult<Session> {
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let active = tx
+                .query_row(
+                    &format!("SELECT {SESSION_COLUMNS} FROM active_sessions a JOIN sessions s ON s.id = a.session_id WHERE a.owner_id = ?1"),
+                    params![user_id as i64],
+                    session_from_row,
+                )
+                .optional()?;
+            let id = match active {
+                Some(session) => {
+                    tx.execute(
+                        "UPDATE sessions SET last_active_at = datetime('now') WHERE id = ?1",
+                        params![&session.id],
+                    )?;
+                    session.id
+                }
+                None => insert_active_session(&tx, user_id, None)?,
+            };
+            let session = load_session(&tx, &id)?;
+            tx.commit()?;
+            Ok::<_, DbError>(session)
+        })
+        .await
+    }
 
-        let mut ctx = String::new();
+    async fn create_session(&self, user_id: u64, title: Option<&str>) -> DbResult<Session> {
+        let title = title.map(str::to_string);
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let id = insert_active_session(&tx, user_id, title.as_deref())?;
+            let session = load_session(&tx, &id)?;
+            tx.commit()?;
+            Ok::<_, DbError>(session)
+        })
+        .await
+    }
 
-        // "preference" category loaded first — communication rules, conventions, identity
-        if let Some(prefs) = grouped.remove("preference") {
-            ctx.push_str("\n--- CORE PREFERENCES (always active, never delete this category) ---\n");
-            for item in &prefs {
-                ctx.push_str(&format!("- {item}\n"));
+    async fn resume_session(&self, user_id: u64, session_id: &str) -> DbResult<Session> {
+        let session_id = session_id.to_string();
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let updated = tx.execute(
+                "UPDATE sessions SET last_active_at = datetime('now') WHERE id = ?1 AND user_id = ?2",
+                params![&session_id, user_id as i64],
+            )?;
+            if updated == 0 {
+                return Err(DbError::NotFound(format!("Session {session_id}")));
             }
-            ctx.push_str("--- END CORE PREFERENCES ---\n");
-        }
+            set_active_session(&tx, user_id, &session_id)?;
+            let session = load_session(&tx, &session_id)?;
+            tx.commit()?;
+            Ok(session)
+        })
+        .await
+    }
 
-        // Other categories
-        if !grouped.is_empty() {
-            ctx.push_str("\n--- MEMORY ---\n");
-            for (cat, items) in &grouped {
-                ctx.push_str(&format!("\n[{cat}]\n"));
-                for item in items {
-                    ctx.push_str(&format!("- {item}\n"));
-                }
+    async fn active_session_id(&self, user_id: u64) -> DbResult<Option<String>> {
+        self.read(move |conn| {
+            conn.query_row(
+                "SELECT session_id FROM active_sessions WHERE owner_id = ?1",
+                params![user_id as i64],
+                |row| row.get(0),
+            )
+            .optional()
+        })
+        .await
+    }
+
+    async fn list_sessions(&self, user_id: u64, limit: usize) -> DbResult<Vec<Session>> {
+        self.read(move |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {SESSION_COLUMNS} FROM sessions s WHERE user_id = ?1
+                 ORDER BY last_active_at DESC, rowid DESC LIMIT ?2"
+            ))?;
+            let rows = stmt.query_map(params![user_id as i64, limit as i64], session_from_row)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await
+    }
+
+    async fn set_session_title(&self, session_id: &str, title: &str) -> DbResult<()> {
+        let (session_id, title) = (session_id.to_string(), title.to_string());
+        self.write(move |conn| {
+            conn.execute("UPDATE sessions SET title = ?2 WHERE id = ?1", params![session_id, title])
+                .map(|_| ())
+        })
+        .await
+    }
+
+    async fn load_history(&self, session_id: &str, max_pairs: usize) -> DbResult<Vec<SessionMessage>> {
+        let session_id = session_id.to_string();
+        self.read(move |conn| {
+            // Everything from the `max_pairs`-th newest user message on, so the
+            // tool calls and results inside each turn come along
+            let mut stmt = conn.prepare(
+                "SELECT id, role, content, created_at, message FROM session_messages
+                 WHERE session_id = ?1 AND id >= COALESCE((
+                    SELECT id FROM session_messages WHERE session_id = ?1 AND role = 'user'
+                    ORDER BY id DESC LIMIT 1 OFFSET ?2
+                 ), 0)
+                 ORDER BY id"
+            )?;
+            let offset = max_pairs.max(1) as i64 - 1;
+            let rows = stmt.query_map(params![session_id, offset], session_message_from_row)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await
+    }
+
+    async fn append_message(&self, session_id: &str, role: &str, content: &str) -> DbResult<()> {
+        let message = NewSessionMessage { role: role.to_string(), content: content.to_string(), message: None };
+        self.append_messages(session_id, vec![message]).await
+    }
+
+    async fn append_messages(&self, session_id: &str, messages: Vec<NewSessionMessage>) -> DbResult<()> {
+        let session_id = session_id.to_string();
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            for m in &messages {
+                tx.execute(
+                    "INSERT INTO session_messages (session_id, role, content, message) VALUES (?1, ?2, ?3, ?4)",
+                    params![session_id, m.role, m.content, m.message],
+                )?;
             }
-            ctx.push_str("\n--- END MEMORY ---\n");
-        }
-        ctx
+            tx.commit()
+        })
+        .await
     }
 
-    // --- Conversation history ---
+    // --- Session Retention ---
+
+    async fn get_session_retention(&self, owner_id: u64) -> DbResult<Option<SessionRetention>> {
+        self.read(move |conn| {
+            conn.query_row(
+                "SELECT max_age_days, max_messages, summarize FROM session_retention WHERE owner_id = ?1",
+                params![owner_id as i64],
+                |row| {
+                    Ok(SessionRetention {
+                        max_age_days: row.get(0)?,
+                        max_messages: row.get(1)?,
+                        summarize: row.get(2)?,
+                    })
+                },
+            )
+            .optional()
+        })
+        .await
+    }
 
-    /// Get or create the active session for a user. Returns session_id.
-    pub fn get_or_create_session(&self, user_id: u64) -> String {
-        let conn = self.conn.lock().unwrap();
-        let existing: Option<String> = conn
-            .query_row(
-                "SELECT id FROM sessions WHERE user_id = ?1 ORDER BY last_active_at DESC LIMIT 1",
-                params![user_id as i64],
-                |row| row.get(0),
+    async fn set_session_retention(&self, owner_id: u64, retention: SessionRetention) -> DbResult<()> {
+        self.write(move |conn| {
+            conn.execute(
+                "INSERT INTO session_retention (owner_id, max_age_days, max_messages, summarize)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT(owner_id) DO UPDATE SET
+                    max_age_days = excluded.max_age_days,
+                    max_messages = excluded.max_messages,
+                    summarize = excluded.summarize,
+                    updated_at = datetime('now')",
+                params![owner_id as i64, retention.max_age_days, retention.max_messages, retention.summarize],
             )
-            .ok();
-
-        if let Some(id) = existing {
-            let _ = conn.execute(
-                "UPDATE sessions SET last_active_at = datetime('now') WHERE id = ?1",
-                params![&id],
-            );
-            return id;
-        }
+            .map(|_| ())
+        })
+        .await
+    }
 
-        let id = format!("{}-{}", user_id, chrono::Utc::now().timestamp());
-        let _ = conn.execute(
-            "INSERT INTO sessions (id, user_id) VALUES (?1, ?2)",
-            params![&id, user_id as i64],
-        );
-        id
-    }
-
-    /// Load recent conversation history for a session (last N user+assistant message pairs).
-    pub fn load_history(&self, session_id: &str, max_pairs: usize) -> Vec<(String, String)> {
-        let conn = self.conn.lock().unwrap();
-        let limit = (max_pairs * 2) as i64;
-        let mut stmt = match conn.prepare(
-            "SELECT role, content FROM session_messages WHERE session_id = ?1 ORDER BY id DESC LIMIT ?2"
-        ) {
-            Ok(s) => s,
-            Err(_) => return vec![],
-        };
-        let rows: Vec<(String, String)> = stmt
-            .query_map(params![session_id, limit], |row| {
-                Ok((row.get(0)?, row.get(1)?))
-            })
-            .map(|r| r.collect::<Result<Vec<_>, _>>().unwrap_or_default())
-            .unwrap_or_default();
+    async fn clear_session_retention(&self, owner_id: u64) -> DbResult<bool> {
+        self.write(move |conn| {
+            conn.execute("DELETE FROM session_retention WHERE owner_id = ?1", params![owner_id as i64])
+                .map(|n| n > 0)
+        })
+        .await
+    }
 
-        let mut result = rows;
-        result.reverse();
-        result
+    async fn list_sessions_with_messages(&self) -> DbResult<Vec<Session>> {
+        self.read(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, user_id, title, created_at, last_active_at, summary FROM sessions s
+                 WHERE EXISTS (SELECT 1 FROM session_messages sm WHERE sm.session_id = s.id)
+                 ORDER BY last_active_at DESC"
+            )?;
+            let rows = stmt.query_map([], session_from_row)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await
     }
 
+    async fn expired_session_messages(
+        &self,
+        session_id: &str,
+        retention: SessionRetention,
+    ) -> DbResult<Vec<SessionMessage>> {
+        let session_id = session_id.to_string();
+        self.read(move |conn| {
+            // Both limits cut at an id, so the expired set is always a prefix of the session
+            let mut stmt = conn.prepare(
+                "SELECT id, role, content, created_at, message FROM session_messages
+                 WHERE session_id = ?1 AND (
+                    (?2 IS NOT NULL AND created_at < datetime('now', '-' || ?2 || ' days'))
+                    OR (?3 IS NOT NULL AND id <= (
+                        SELECT id FROM session_messages WHERE session_id = ?1
+                        ORDER BY id DESC LIMIT 1 OFFSET ?3
+                    ))
+                 )
+                 ORDER BY id"
+            )?;
+            let rows = stmt.query_map(
+                params![session_id, retention.max_age_days, retention.max_messages],
+                session_message_from_row,
+            )?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await
+    }
 
-    /// Append a message to the session history.
-    pub fn append_message(&self, session_id: &str, role: &str, content: &str) {
-        let conn = self.conn.lock().unwrap();
-        let _ = conn.execute(
-            "INSERT INTO session_messages (session_id, role, content) VALUES (?1, ?2, ?3)",
-            params![session_id, role, content],
-        );
+    async fn prune_session_messages(&self, session_id: &str, up_to_id: i64, summary: Option<&str>) -> DbResult<usize> {
+        let (session_id, summary) = (session_id.to_string(), summary.map(str::to_string));
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            if let Some(summary) = &summary {
+                tx.execute(
+                    "UPDATE sessions SET summary = ?2, summary_updated_at = datetime('now') WHERE id = ?1",
+                    params![session_id, summary],
+                )?;
+            }
+            let deleted = tx.execute(
+                "DELETE FROM session_messages WHERE session_id = ?1 AND id <= ?2",
+                params![session_id, up_to_id],
+            )?;
+            tx.commit()?;
+            Ok::<_, rusqlite::Error>(deleted)
+        })
+        .await
     }
 
     // --- Knowledge Documents ---
 
-    pub fn save_document(
+    async fn save_document(
         &self,
         user_id: u64,
         title: &str,
         content: &str,
         source: Option<&str>,
         tags: Option<&str>,
-    ) -> Result<i64, String> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "INSERT INTO knowledge_documents (user_id, title, content, source, tags) VALUES (?1, ?2, ?3, ?4, ?5)",
-            params![user_id as i64, title, content, source, tags],
-        )
-        .map_err(|e| e.to_string())?;
-        Ok(conn.last_insert_rowid())
+    ) -> DbResult<i64> {
+        let (title, content) = (title.to_string(), content.to_string());
+        let (source, tags) = (source.map(str::to_string), tags.map(str::to_string));
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "INSERT INTO knowledge_documents (user_id, title, content, source, tags) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![user_id as i64, title, content, source, tags],
+            )?;
+            let doc_id = tx.last_insert_rowid();
+            tx.execute(
+                "INSERT INTO knowledge_document_versions (doc_id, version, content, tool) VALUES (?1, 1, ?2, 'knowledge_save')",
+                params![doc_id, content],
+            )?;
+            tx.commit()?;
+            Ok::<_, rusqlite::Error>(doc_id)
+        })
+        .await
+    }
+
+    async fn search_documents(&self, user_id: u64, query: &str) -> DbResult<Vec<DocumentMatch>> {
+        let query = query.to_string();
+        self.read(move |conn| {
+            let map_row = |row: &Row| {
+                Ok(DocumentMatch {
+                    id: row.get(0)?,
+                    title: row.get(1)?,
+                    snippet: row.get(2)?,
+                    source: row.get(3)?,
+                })
+            };
+
+            // FTS5 search with snippet, titles weighted over content; word
+            // index first, then substrings (trigram index), then LIKE
+            let search = |table: &str, fts_query: &str| -> rusqlite::Result<Vec<DocumentMatch>> {
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT kd.id, kd.title, snippet({table}, 1, '**', '**', '...', 40), kd.source
+                     FROM knowledge_documents kd
+                     JOIN {table} fts ON kd.id = fts.rowid
+                     WHERE {table} MATCH ?1 AND kd.user_id = ?2 AND kd.deleted_at IS NULL
+                     ORDER BY bm25({table}, {}, 1.0) LIMIT 10",
+                    fts::TITLE_WEIGHT
+                ))?;
+                let rows = stmt.query_map(params![fts_query, user_id as i64], map_row)?;
+                rows.collect()
+            };
+            let results = fts::match_query(&query)
+                .ok_or(rusqlite::Error::InvalidQuery)
+                .and_then(|q| search("knowledge_docs_fts", &q))
+                .and_then(|found| match fts::substring_query(&query) {
+                    Some(q) if found.is_empty() && fts::has_trigram_index(conn)? => search("knowledge_docs_tri", &q),
+                    _ => Ok(found),
+                })
+                .unwrap_or_else(|_| {
+                    // Fallback to LIKE
+                    conn.prepare(
+                        "SELECT id, title, substr(content, 1, 200), source
+                         FROM knowledge_documents
+                         WHERE user_id = ?1 AND deleted_at IS NULL AND (title LIKE '%' || ?2 || '%' OR content LIKE '%' || ?2 || '%')
+                         ORDER BY created_at DESC LIMIT 10"
+                    )
+                    .and_then(|mut stmt| {
+                        let rows = stmt.query_map(params![user_id as i64, query], map_row)?;
+                        rows.collect()
+                    })
+                    .unwrap_or_default()
+                });
+            Ok::<_, rusqlite::Error>(results)
+        })
+        .await
+    }
+
+    async fn list_documents(&self, user_id: u64) -> DbResult<Vec<DocumentSummary>> {
+        self.read(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT kd.id, kd.title, kd.source, kd.created_at,
+                        (SELECT COUNT(*) FROM knowledge_chunks kc WHERE kc.doc_id = kd.id) as chunk_count
+                 FROM knowledge_documents kd
+                 WHERE kd.user_id = ?1 AND kd.deleted_at IS NULL
+                 ORDER BY kd.created_at DESC LIMIT 50"
+            )?;
+            let rows = stmt.query_map(params![user_id as i64], |row| {
+                Ok(DocumentSummary {
+                    id: row.get(0)?,
+                    title: row.get(1)?,
+                    source: row.get(2)?,
+                    created_at: row.get(3)?,
+                    chunk_count: row.get(4)?,
+                })
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await
+    }
+
+    async fn get_document(&self, user_id: u64, doc_id: i64) -> DbResult<Document> {
+        self.read(move |conn| {
+            conn.query_row(
+                "SELECT id, title, content, source, tags, created_at FROM knowledge_documents WHERE id = ?1 AND user_id = ?2 AND deleted_at IS NULL",
+                params![doc_id, user_id as i64],
+                document_from_row,
+            )
+            .optional()?
+            .ok_or_else(|| DbError::NotFound(format!("Document #{doc_id}")))
+        })
+        .await
     }
 
-    pub fn search_documents(
+    async fn delete_document(&self, user_id: u64, doc_id: i64) -> DbResult<bool> {
+        self.write(move |conn| {
+            let rows = conn.execute(
+                "UPDATE knowledge_documents SET deleted_at = datetime('now') WHERE id = ?1 AND user_id = ?2 AND deleted_at IS NULL",
+                params![doc_id, user_id as i64],
+            )?;
+            Ok::<_, rusqlite::Error>(rows > 0)
+        })
+        .await
+    }
+
+    async fn patch_document(
         &self,
         user_id: u64,
-        query: &str,
-    ) -> Result<Vec<(i64, String, String, Option<String>)>, String> {
-        let conn = self.conn.lock().unwrap();
-
-        // FTS5 search with snippet
-        let results = conn
-            .prepare(
-                "SELECT kd.id, kd.title, snippet(knowledge_docs_fts, 1, '**', '**', '...', 40), kd.source
-                 FROM knowledge_documents kd
-                 JOIN knowledge_docs_fts fts ON kd.id = fts.rowid
-                 WHERE knowledge_docs_fts MATCH ?1 AND kd.user_id = ?2
-                 ORDER BY rank LIMIT 10"
+        doc_id: i64,
+        old_text: &str,
+        new_text: &str,
+        actor: Actor<'_>,
+    ) -> DbResult<Vec<i64>> {
+        let (old_text, new_text) = (old_text.to_string(), new_text.to_string());
+        let (actor_id, tool) = (actor.user_id, actor.tool.to_string());
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+
+            // 1. Update document content (FTS is re-indexed by the knowledge_docs_au trigger)
+            let doc_content: String = tx
+                .query_row(
+                    "SELECT content FROM knowledge_documents WHERE id = ?1 AND user_id = ?2 AND deleted_at IS NULL",
+                    params![doc_id, user_id as i64],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .ok_or_else(|| DbError::NotFound(format!("Document #{doc_id}")))?;
+
+            if !doc_content.contains(&old_text) {
+                return Err(DbError::NotFound(format!("Text \"{old_text}\" in document #{doc_id}")));
+            }
+
+            let new_content = doc_content.replace(&old_text, &new_text);
+            tx.execute(
+                "UPDATE knowledge_documents SET content = ?1 WHERE id = ?2",
+                params![&new_content, doc_id],
+            )?;
+            insert_document_version(&tx, doc_id, &doc_content, &new_content, actor_id, &tool)?;
+            entity_graph::refresh_mentions(&tx, "document", doc_id)?;
+
+            // 2. Find and update affected chunks (trigger handles FTS re-index)
+            let chunk_ids: Vec<i64> = {
+                let mut stmt = tx.prepare(
+                    "SELECT id FROM knowledge_chunks WHERE doc_id = ?1 AND content LIKE '%' || ?2 || '%'",
+                )?;
+                let rows = stmt.query_map(params![doc_id, old_text], |row| row.get(0))?;
+                rows.collect::<rusqlite::Result<_>>()?
+            };
+
+            for chunk_id in &chunk_ids {
+                let old_chunk: String = tx.query_row(
+                    "SELECT content FROM knowledge_chunks WHERE id = ?1",
+                    params![chunk_id],
+                    |row| row.get(0),
+                )?;
+                let new_chunk = old_chunk.replace(&old_text, &new_text);
+                tx.execute(
+                    "UPDATE knowledge_chunks SET content = ?1, embedding = NULL WHERE id = ?2",
+                    params![&new_chunk, chunk_id],
+                )?;
+            }
+
+            tx.commit()?;
+            Ok(chunk_ids)
+        })
+        .await
+    }
+
+    async fn get_unchunked_documents(&self) -> DbResult<Vec<Document>> {
+        self.read(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT kd.id, kd.title, kd.content, kd.source, kd.tags, kd.created_at FROM knowledge_documents kd
+                 WHERE kd.deleted_at IS NULL
+                   AND NOT EXISTS (SELECT 1 FROM knowledge_chunks kc WHERE kc.doc_id = kd.id)"
+            )?;
+            let rows = stmt.query_map([], document_from_row)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await
+    }
+
+    // --- Document Versions ---
+
+    async fn list_document_versions(&self, user_id: u64, doc_id: i64) -> DbResult<Vec<DocumentVersion>> {
+        self.read(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT v.doc_id, v.version, v.content, v.diff, v.actor_id, v.tool, v.created_at
+                 FROM knowledge_document_versions v
+                 JOIN knowledge_documents kd ON kd.id = v.doc_id
+                 WHERE v.doc_id = ?1 AND kd.user_id = ?2 AND kd.deleted_at IS NULL
+                 ORDER BY v.version DESC"
+            )?;
+            let rows = stmt.query_map(params![doc_id, user_id as i64], version_from_row)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await
+    }
+
+    async fn get_document_version(&self, user_id: u64, doc_id: i64, version: i64) -> DbResult<DocumentVersion> {
+        self.read(move |conn| {
+            conn.query_row(
+                "SELECT v.doc_id, v.version, v.content, v.diff, v.actor_id, v.tool, v.created_at
+                 FROM knowledge_document_versions v
+                 JOIN knowledge_documents kd ON kd.id = v.doc_id
+                 WHERE v.doc_id = ?1 AND v.version = ?2 AND kd.user_id = ?3 AND kd.deleted_at IS NULL",
+                params![doc_id, version, user_id as i64],
+                version_from_row,
             )
-            .and_then(|mut stmt| {
-                let rows = stmt.query_map(params![query, user_id as i64], |row| {
-                    Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
-                })?;
-                rows.collect()
-            })
-            .unwrap_or_else(|_| {
-                // Fallback to LIKE
-                conn.prepare(
-                    "SELECT id, title, substr(content, 1, 200), source
-                     FROM knowledge_documents
-                     WHERE user_id = ?1 AND (title LIKE '%' || ?2 || '%' OR content LIKE '%' || ?2 || '%')
-                     ORDER BY created_at DESC LIMIT 10"
+            .optional()?
+            .ok_or_else(|| DbError::NotFound(format!("Version {version} of document #{doc_id}")))
+        })
+        .await
+    }
+
+    async fn rollback_document(&self, user_id: u64, doc_id: i64, version: i64, actor: Actor<'_>) -> DbResult<i64> {
+        let (actor_id, tool) = (actor.user_id, actor.tool.to_string());
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let current: String = tx
+                .query_row(
+                    "SELECT content FROM knowledge_documents WHERE id = ?1 AND user_id = ?2 AND deleted_at IS NULL",
+                    params![doc_id, user_id as i64],
+                    |row| row.get(0),
                 )
-                .and_then(|mut stmt| {
-                    let rows = stmt.query_map(params![user_id as i64, query], |row| {
-                        Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
-                    })?;
-                    rows.collect()
-                })
-                .unwrap_or_default()
-            });
+                .optional()?
+                .ok_or_else(|| DbError::NotFound(format!("Document #{doc_id}")))?;
+            let restored: String = tx
+                .query_row(
+                    "SELECT content FROM knowledge_document_versions WHERE doc_id = ?1 AND version = ?2",
+                    params![doc_id, version],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .ok_or_else(|| DbError::NotFound(format!("Version {version} of document #{doc_id}")))?;
+            if restored == current {
+                return Err(DbError::Conflict(format!(
+                    "Document #{doc_id} already matches version {version}."
+                )));
+            }
 
-        Ok(results)
+            tx.execute(
+                "UPDATE knowledge_documents SET content = ?1 WHERE id = ?2",
+                params![&restored, doc_id],
+            )?;
+            tx.execute("DELETE FROM knowledge_chunks WHERE doc_id = ?1", params![doc_id])?;
+            let new_version = insert_document_version(&tx, doc_id, &current, &restored, actor_id, &tool)?;
+            entity_graph::refresh_mentions(&tx, "document", doc_id)?;
+            tx.commit()?;
+            Ok(new_version)
+        })
+        .await
     }
 
-    // --- Entities ---
+    // --- Knowledge Chunks ---
 
-    pub fn save_entity(&self, user_id: u64, name: &str, entity_type: &str) -> Result<i64, String> {
-        let conn = self.conn.lock().unwrap();
-        // INSERT OR IGNORE for unique constraint, then get the id
-        conn.execute(
-            "INSERT OR IGNORE INTO entities (user_id, name, entity_type) VALUES (?1, ?2, ?3)",
-            params![user_id as i64, name, entity_type],
-        )
-        .map_err(|e| e.to_string())?;
+    async fn save_chunks(&self, doc_id: i64, chunks: &[(usize, usize, usize, &str)]) -> DbResult<Vec<i64>> {
+        let chunks: Vec<(usize, usize, usize, String)> = chunks
+            .iter()
+            .map(|&(index, start, end, content)| (index, start, end, content.to_string()))
+            .collect();
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let mut ids = Vec::with_capacity(chunks.len());
+            for (chunk_index, start_line, end_line, content) in &chunks {
+                tx.execute(
+                    "INSERT OR REPLACE INTO knowledge_chunks (doc_id, chunk_index, start_line, end_line, content) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![doc_id, *chunk_index as i64, *start_line as i64, *end_line as i64, content],
+                )?;
+                ids.push(tx.last_insert_rowid());
+            }
+            tx.commit()?;
+            Ok::<_, rusqlite::Error>(ids)
+        })
+        .await
+    }
 
-        conn.query_row(
-            "SELECT id FROM entities WHERE user_id = ?1 AND name = ?2 AND entity_type = ?3",
-            params![user_id as i64, name, entity_type],
-            |row| row.get(0),
-        )
-        .map_err(|e| e.to_string())
+    async fn update_chunk_embeddings(&self, chunk_ids: &[i64], embeddings: &[Vec<u8>]) -> DbResult<()> {
+        let pairs: Vec<(i64, Vec<u8>)> = chunk_ids.iter().copied().zip(embeddings.iter().cloned()).collect();
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let mode = quantization::current(&tx)?;
+            for (id, blob) in &pairs {
+                tx.execute(
+                    "UPDATE knowledge_chunks SET embedding = ?1, embedding_q = ?2 WHERE id = ?3",
+                    params![blob, quantization::quantized_blob(mode, blob), id],
+                )?;
+            }
+            tx.commit()
+        })
+        .await
     }
 
-    pub fn add_entity_mention(
-        &self,
-        entity_id: i64,
-        source_type: &str,
-        source_id: i64,
-        context: Option<&str>,
-    ) -> Result<(), String> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "INSERT INTO entity_mentions (entity_id, source_type, source_id, context) VALUES (?1, ?2, ?3, ?4)",
-            params![entity_id, source_type, source_id, context],
-        )
-        .map_err(|e| e.to_string())?;
-        Ok(())
-    }
-
-    /// List all knowledge documents for a user. Returns (id, title, source, created_at, chunk_count).
-    pub fn list_documents(&self, user_id: u64) -> Result<Vec<(i64, String, Option<String>, String, i64)>, String> {
-        let conn = self.conn.lock().unwrap();
-        conn.prepare(
-            "SELECT kd.id, kd.title, kd.source, kd.created_at,
-                    (SELECT COUNT(*) FROM knowledge_chunks kc WHERE kc.doc_id = kd.id) as chunk_count
-             FROM knowledge_documents kd
-             WHERE kd.user_id = ?1
-             ORDER BY kd.created_at DESC LIMIT 50"
-        )
-        .and_then(|mut stmt| {
+    async fn search_chunks_fts(&self, user_id: u64, query: &str) -> DbResult<Vec<(Chunk, f64)>> {
+        let query = query.to_string();
+        self.read(move |conn| {
+            let search = |table: &str, fts_query: &str| -> rusqlite::Result<Vec<(Chunk, f64)>> {
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT kc.id, kc.doc_id, kd.title, kc.content, kc.start_line, kc.end_line, kd.source, fts.rank
+                     FROM knowledge_chunks kc
+                     JOIN {table} fts ON kc.id = fts.rowid
+                     JOIN knowledge_documents kd ON kc.doc_id = kd.id
+                     WHERE {table} MATCH ?1 AND kd.user_id = ?2 AND kd.deleted_at IS NULL
+                     ORDER BY fts.rank LIMIT 20"
+                ))?;
+                let rows = stmt.query_map(params![fts_query, user_id as i64], |row| {
+                    Ok((chunk_from_row(row)?, row.get(7)?))
+                })?;
+                rows.collect()
+            };
+            let Some(phrase) = fts::phrase_query(&query) else {
+                return Ok(Vec::new());
+            };
+            let found = search("knowledge_chunks_fts", &phrase)?;
+            match fts::substring_query(&query) {
+                Some(q) if found.is_empty() && fts::has_trigram_index(conn)? => search("knowledge_chunks_tri", &q),
+                _ => Ok(found),
+            }
+        })
+        .await
+    }
+
+    async fn load_all_embeddings(&self, user_id: u64) -> DbResult<Vec<(Chunk, Vec<u8>)>> {
+        self.read(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT kc.id, kc.doc_id, kd.title, kc.content, kc.start_line, kc.end_line, kd.source, kc.embedding
+                 FROM knowledge_chunks kc
+                 JOIN knowledge_documents kd ON kc.doc_id = kd.id
+                 WHERE kd.user_id = ?1 AND kc.embedding IS NOT NULL AND kd.deleted_at IS NULL"
+            )?;
             let rows = stmt.query_map(params![user_id as i64], |row| {
-                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))
+                Ok((chunk_from_row(row)?, row.get(7)?))
             })?;
-            rows.collect()
+            rows.collect::<rusqlite::Result<Vec<_>>>()
         })
-        .map_err(|e| e.to_string())
+        .await
     }
 
-    // --- Knowledge Chunks ---
+    async fn get_chunk_content(&self, chunk_id: i64) -> DbResult<String> {
+        self.read(move |conn| {
+            conn.query_row(
+                "SELECT content FROM knowledge_chunks WHERE id = ?1",
+                params![chunk_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| DbError::NotFound(format!("Chunk #{chunk_id}")))
+        })
+        .await
+    }
 
-    pub fn save_chunks(
-        &self,
-        doc_id: i64,
-        chunks: &[(usize, usize, usize, &str)], // (chunk_index, start_line, end_line, content)
-    ) -> Result<Vec<i64>, String> {
-        let conn = self.conn.lock().unwrap();
-        let mut ids = Vec::with_capacity(chunks.len());
-        for &(chunk_index, start_line, end_line, content) in chunks {
+    // --- Entities ---
+
+    async fn save_entity(&self, user_id: u64, name: &str, entity_type: &str) -> DbResult<i64> {
+        let (name, entity_type) = (name.to_string(), entity_type.to_string());
+        self.write(move |conn| {
+            // INSERT OR IGNORE for unique constraint, then get the id
             conn.execute(
-                "INSERT OR REPLACE INTO knowledge_chunks (doc_id, chunk_index, start_line, end_line, content) VALUES (?1, ?2, ?3, ?4, ?5)",
-                params![doc_id, chunk_index as i64, start_line as i64, end_line as i64, content],
+                "INSERT OR IGNORE INTO entities (user_id, name, entity_type) VALUES (?1, ?2, ?3)",
+                params![user_id as i64, name, entity_type],
+            )?;
+
+            conn.query_row(
+                "SELECT id FROM entities WHERE user_id = ?1 AND name = ?2 AND entity_type = ?3",
+                params![user_id as i64, name, entity_type],
+                |row| row.get(0),
             )
-            .map_err(|e| e.to_string())?;
-            ids.push(conn.last_insert_rowid());
-        }
-        Ok(ids)
+        })
+        .await
     }
 
-    pub fn update_chunk_embeddings(
+    async fn add_entity_mention(
         &self,
-        chunk_ids: &[i64],
-        embeddings: &[Vec<u8>],
-    ) -> Result<(), String> {
-        let conn = self.conn.lock().unwrap();
-        for (id, blob) in chunk_ids.iter().zip(embeddings.iter()) {
+        entity_id: i64,
+        source_type: &str,
+        source_id: i64,
+        context: Option<&str>,
+    ) -> DbResult<()> {
+        let (source_type, context) = (source_type.to_string(), context.map(str::to_string));
+        self.write(move |conn| {
             conn.execute(
-                "UPDATE knowledge_chunks SET embedding = ?1 WHERE id = ?2",
-                params![blob, id],
+                "INSERT INTO entity_mentions (entity_id, source_type, source_id, context) VALUES (?1, ?2, ?3, ?4)",
+                params![entity_id, source_type, source_id, context],
             )
-            .map_err(|e| e.to_string())?;
-        }
-        Ok(())
+            .map(|_| ())
+        })
+        .await
     }
 
-    /// FTS5 search on knowledge_chunks. Returns (chunk_id, doc_id, title, content, start_line, end_line, source, rank).
-    pub fn search_chunks_fts(
-        &self,
-        user_id: u64,
-        query: &str,
-    ) -> Result<Vec<(i64, i64, String, String, i64, i64, Option<String>, f64)>, String> {
-        let conn = self.conn.lock().unwrap();
-        // Escape FTS5 special chars by wrapping in double quotes
-        let escaped = format!("\"{}\"", query.replace('"', "\"\""));
-        conn.prepare(
-            "SELECT kc.id, kc.doc_id, kd.title, kc.content, kc.start_line, kc.end_line, kd.source, fts.rank
-             FROM knowledge_chunks kc
-             JOIN knowledge_chunks_fts fts ON kc.id = fts.rowid
-             JOIN knowledge_documents kd ON kc.doc_id = kd.id
-             WHERE knowledge_chunks_fts MATCH ?1 AND kd.user_id = ?2
-             ORDER BY fts.rank LIMIT 20"
-        )
-        .and_then(|mut stmt| {
-            let rows = stmt.query_map(params![escaped, user_id as i64], |row| {
-                Ok((
-                    row.get(0)?,
-                    row.get(1)?,
-                    row.get(2)?,
-                    row.get(3)?,
-                    row.get(4)?,
-                    row.get(5)?,
-                    row.get(6)?,
-                    row.get(7)?,
-                ))
-            })?;
-            rows.collect()
+    async fn search_entities(&self, user_id: u64, query: &str) -> DbResult<Vec<(Entity, Vec<EntityMention>)>> {
+        let query = query.to_string();
+        self.read(move |conn| {
+            // Find matching entities
+            let entities: Vec<Entity> = {
+                let mut stmt = conn.prepare(
+                    "SELECT id, name, entity_type FROM entities
+                     WHERE user_id = ?1 AND name LIKE '%' || ?2 || '%'
+                     ORDER BY name LIMIT 20"
+                )?;
+                let rows = stmt.query_map(params![user_id as i64, query], |row| {
+                    Ok(Entity {
+                        id: row.get(0)?,
+                        name: row.get(1)?,
+                        entity_type: row.get(2)?,
+                    })
+                })?;
+                rows.collect::<rusqlite::Result<_>>()?
+            };
+
+            // For each entity, get its mentions
+            let mut results = Vec::new();
+            for entity in entities {
+                let mentions: Vec<EntityMention> = conn
+                    .prepare(
+                        "SELECT source_type, source_id, context FROM entity_mentions em
+                         WHERE entity_id = ?1
+                           AND CASE source_type
+                               WHEN 'document' THEN EXISTS (SELECT 1 FROM knowledge_documents
+                                                            WHERE id = em.source_id AND deleted_at IS NULL)
+                               WHEN 'fact' THEN EXISTS (SELECT 1 FROM memory_facts
+                                                        WHERE id = em.source_id AND deleted_at IS NULL)
+                               ELSE 0 END
+                         ORDER BY created_at DESC LIMIT 10"
+                    )
+                    .and_then(|mut stmt| {
+                        let rows = stmt.query_map(params![entity.id], |row| {
+                            Ok(EntityMention {
+                                source_type: row.get(0)?,
+                                source_id: row.get(1)?,
+                                context: row.get(2)?,
+                            })
+                        })?;
+                        rows.collect()
+                    })
+                    .unwrap_or_default();
+                results.push((entity, mentions));
+            }
+
+            Ok::<_, rusqlite::Error>(results)
         })
-        .map_err(|e| e.to_string())
+        .await
     }
 
-    /// Load all chunk embeddings for a user (for brute-force cosine similarity).
-    /// Returns (chunk_id, doc_id, title, content, start_line, end_line, source, embedding_bytes).
-    pub fn load_all_embeddings(
-        &self,
-        user_id: u64,
-    ) -> Result<Vec<(i64, i64, String, String, i64, i64, Option<String>, Vec<u8>)>, String> {
-        let conn = self.conn.lock().unwrap();
-        conn.prepare(
-            "SELECT kc.id, kc.doc_id, kd.title, kc.content, kc.start_line, kc.end_line, kd.source, kc.embedding
-             FROM knowledge_chunks kc
-             JOIN knowledge_documents kd ON kc.doc_id = kd.id
-             WHERE kd.user_id = ?1 AND kc.embedding IS NOT NULL"
-        )
-        .and_then(|mut stmt| {
+    // --- Trash ---
+
+    async fn list_trash(&self, user_id: u64) -> DbResult<Vec<TrashItem>> {
+        self.read(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT 'fact', id, fact, deleted_at FROM memory_facts
+                 WHERE user_id = ?1 AND deleted_at IS NOT NULL
+                 UNION ALL
+                 SELECT 'document', id, title, deleted_at FROM knowledge_documents
+                 WHERE user_id = ?1 AND deleted_at IS NOT NULL
+                 ORDER BY 4 DESC, 2 DESC LIMIT 50"
+            )?;
             let rows = stmt.query_map(params![user_id as i64], |row| {
-                Ok((
-                    row.get(0)?,
-                    row.get(1)?,
-                    row.get(2)?,
-                    row.get(3)?,
-                    row.get(4)?,
-                    row.get(5)?,
-                    row.get(6)?,
-                    row.get(7)?,
-                ))
+                let kind: String = row.get(0)?;
+                Ok(TrashItem {
+                    kind: TrashKind::parse(&kind).unwrap_or(TrashKind::Fact),
+                    id: row.get(1)?,
+                    label: row.get(2)?,
+                    deleted_at: row.get(3)?,
+                })
             })?;
-            rows.collect()
+            rows.collect::<rusqlite::Result<Vec<_>>>()
         })
-        .map_err(|e| e.to_string())
+        .await
     }
 
-    /// Get document IDs that have no chunks yet (for migration).
-    pub fn get_unchunked_doc_ids(&self) -> Result<Vec<(i64, String)>, String> {
-        let conn = self.conn.lock().unwrap();
-        conn.prepare(
-            "SELECT kd.id, kd.content FROM knowledge_documents kd
-             WHERE NOT EXISTS (SELECT 1 FROM knowledge_chunks kc WHERE kc.doc_id = kd.id)"
-        )
-        .and_then(|mut stmt| {
-            let rows = stmt.query_map([], |row| {
-                Ok((row.get(0)?, row.get(1)?))
-            })?;
-            rows.collect()
+    async fn restore_from_trash(&self, user_id: u64, kind: TrashKind, id: i64) -> DbResult<bool> {
+        self.write(move |conn| {
+            let rows = conn.execute(
+                &format!(
+                    "UPDATE {} SET deleted_at = NULL WHERE id = ?1 AND user_id = ?2 AND deleted_at IS NOT NULL",
+                    kind.table()
+                ),
+                params![id, user_id as i64],
+            )?;
+            Ok::<_, rusqlite::Error>(rows > 0)
         })
-        .map_err(|e| e.to_string())
+        .await
     }
 
-    pub fn get_chunk_content(&self, chunk_id: i64) -> Result<String, String> {
-        let conn = self.conn.lock().unwrap();
-        conn.query_row(
-            "SELECT content FROM knowledge_chunks WHERE id = ?1",
-            params![chunk_id],
-            |row| row.get(0),
-        )
-        .map_err(|e| e.to_string())
+    async fn purge_trash(&self, user_id: u64, item: Option<(TrashKind, i64)>) -> DbResult<usize> {
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let removed = match item {
+                Some((kind, id)) => purge_trashed(&tx, kind, "user_id = ?1 AND id = ?2", params![user_id as i64, id])?,
+                None => {
+                    purge_trashed(&tx, TrashKind::Fact, "user_id = ?1", params![user_id as i64])?
+                        + purge_trashed(&tx, TrashKind::Document, "user_id = ?1", params![user_id as i64])?
+                }
+            };
+            tx.commit()?;
+            Ok::<_, rusqlite::Error>(removed)
+        })
+        .await
     }
 
-    /// Patch document + affected chunks: find/replace text.
-    /// Returns (updated_doc_content, affected_chunk_ids).
-    pub fn patch_document(
-        &self,
-        user_id: u64,
-        doc_id: i64,
-        old_text: &str,
-        new_text: &str,
-    ) -> Result<Vec<i64>, String> {
-        let conn = self.conn.lock().unwrap();
+    async fn purge_expired_trash(&self, retention_days: u32) -> DbResult<usize> {
+        let cutoff = format!("-{retention_days} days");
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let filter = "deleted_at < datetime('now', ?1)";
+            let removed = purge_trashed(&tx, TrashKind::Fact, filter, params![cutoff])?
+                + purge_trashed(&tx, TrashKind::Document, filter, params![cutoff])?;
+            tx.commit()?;
+            Ok::<_, rusqlite::Error>(removed)
+        })
+        .await
+    }
 
-        // 1. Update document content
-        let doc_content: String = conn
-            .query_row(
-                "SELECT content FROM knowledge_documents WHERE id = ?1 AND user_id = ?2",
-                params![doc_id, user_id as i64],
+    // --- Chat Preferences ---
+
+    async fn get_chat_model(&self, scope_id: u64) -> String {
+        self.read(move |conn| {
+            conn.query_row(
+                "SELECT model FROM user_preferences WHERE user_id = ?1",
+                params![scope_id as i64],
                 |row| row.get(0),
             )
-            .map_err(|_| format!("Document #{doc_id} not found."))?;
+        })
+        .await
+        .unwrap_or_else(|_| crate::provider::model_registry::DEFAULT_MODEL.to_string())
+    }
 
-        if !doc_content.contains(old_text) {
-            return Err(format!("Text \"{old_text}\" not found in document #{doc_id}."));
-        }
+    async fn set_chat_model(&self, scope_id: u64, model_id: &str) -> DbResult<()> {
+        let model_id = model_id.to_string();
+        self.write(move |conn| {
+            conn.execute(
+                "INSERT INTO user_preferences (user_id, model, updated_at) VALUES (?1, ?2, datetime('now'))
+                 ON CONFLICT(user_id) DO UPDATE SET model = ?2, updated_at = datetime('now')",
+                params![scope_id as i64, model_id],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
 
-        let new_content = doc_content.replace(old_text, new_text);
-        conn.execute(
-            "UPDATE knowledge_documents SET content = ?1 WHERE id = ?2",
-            params![&new_content, doc_id],
-        )
-        .map_err(|e| e.to_string())?;
-
-        // Also update FTS for the document
-        let _ = conn.execute(
-            "INSERT INTO knowledge_docs_fts(knowledge_docs_fts, rowid, title, content) VALUES('delete', ?1, '', ?2)",
-            params![doc_id, &doc_content],
-        );
-        let title: String = conn
-            .query_row("SELECT title FROM knowledge_documents WHERE id = ?1", params![doc_id], |row| row.get(0))
-            .unwrap_or_default();
-        let _ = conn.execute(
-            "INSERT INTO knowledge_docs_fts(rowid, title, content) VALUES (?1, ?2, ?3)",
-            params![doc_id, &title, &new_content],
-        );
+    // --- Pending Approval Queue ---
+
+    async fn save_pending(
+        &self,
+        scope_id: u64,
+        requested_by: u64,
+        tool_name: &str,
+        args_json: &str,
+        summary: &str,
+    ) -> DbResult<i64> {
+        let (tool_name, args_json, summary) = (tool_name.to_string(), args_json.to_string(), summary.to_string());
+        self.write(move |conn| {
+            conn.execute(
+                "INSERT INTO pending_items (scope_id, requested_by, tool_name, args_json, summary) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![scope_id as i64, requested_by as i64, tool_name, args_json, summary],
+            )?;
+            Ok::<_, rusqlite::Error>(conn.last_insert_rowid())
+        })
+        .await
+    }
 
-        // 2. Find and update affected chunks (trigger handles FTS re-index)
-        let chunk_ids: Vec<i64> = conn
-            .prepare(
-                "SELECT id FROM knowledge_chunks WHERE doc_id = ?1 AND content LIKE '%' || ?2 || '%'",
+    async fn list_pending(&self, scope_id: u64) -> DbResult<Vec<PendingItem>> {
+        self.read(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, scope_id, requested_by, tool_name, args_json, summary, created_at
+                 FROM pending_items WHERE scope_id = ?1 ORDER BY created_at ASC"
+            )?;
+            let rows = stmt.query_map(params![scope_id as i64], pending_from_row)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await
+    }
+
+    async fn get_pending(&self, id: i64) -> DbResult<PendingItem> {
+        self.read(move |conn| {
+            conn.query_row(
+                "SELECT id, scope_id, requested_by, tool_name, args_json, summary, created_at
+                 FROM pending_items WHERE id = ?1",
+                params![id],
+                pending_from_row,
             )
-            .and_then(|mut stmt| {
-                let rows = stmt.query_map(params![doc_id, old_text], |row| row.get(0))?;
-                rows.collect()
-            })
-            .map_err(|e| e.to_string())?;
+            .optional()?
+            .ok_or_else(|| DbError::NotFound(format!("Pending item #{id}")))
+        })
+        .await
+    }
 
-        for chunk_id in &chunk_ids {
-            let old_chunk: String = conn
+    async fn delete_pending(&self, id: i64) -> DbResult<bool> {
+        self.write(move |conn| {
+            let rows = conn.execute("DELETE FROM pending_items WHERE id = ?1", params![id])?;
+            Ok::<_, rusqlite::Error>(rows > 0)
+        })
+        .await
+    }
+
+    async fn take_pending(
+        &self,
+        scope_id: u64,
+        id: i64,
+        ttl_hours: u32,
+        decision: ApprovalDecision,
+        decided_by: u64,
+    ) -> DbResult<Option<(PendingItem, i64)>> {
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let item = tx
                 .query_row(
-                    "SELECT content FROM knowledge_chunks WHERE id = ?1",
-                    params![chunk_id],
-                    |row| row.get(0),
+                    &format!(
+                        "DELETE FROM pending_items WHERE id = ?1 AND scope_id = ?2
+                           AND (?3 = 0 OR created_at >= datetime('now', '-' || ?3 || ' hours'))
+                         RETURNING {PENDING_COLUMNS}"
+                    ),
+                    params![id, scope_id as i64, ttl_hours],
+                    pending_from_row,
                 )
-                .map_err(|e| e.to_string())?;
-            let new_chunk = old_chunk.replace(old_text, new_text);
-            conn.execute(
-                "UPDATE knowledge_chunks SET content = ?1, embedding = NULL WHERE id = ?2",
-                params![&new_chunk, chunk_id],
-            )
-            .map_err(|e| e.to_string())?;
-        }
-
-        Ok(chunk_ids)
+                .optional()?;
+            let Some(item) = item else {
+                return Ok(None);
+            };
+            insert_approval(&tx, &item, decision, Some(decided_by), None)?;
+            let log_id = tx.last_insert_rowid();
+            tx.commit()?;
+            Ok::<_, rusqlite::Error>(Some((item, log_id)))
+        })
+        .await
     }
 
-    // --- Chat Preferences ---
+    async fn set_approval_result(&self, log_id: i64, result: &str) -> DbResult<()> {
+        let result = result.to_string();
+        self.write(move |conn| {
+            conn.execute("UPDATE approval_log SET result = ?1 WHERE id = ?2", params![result, log_id]).map(|_| ())
+        })
+        .await
+    }
 
-    pub fn get_chat_model(&self, scope_id: u64) -> String {
-        let conn = self.conn.lock().unwrap();
-        conn.query_row(
-            "SELECT model FROM user_preferences WHERE user_id = ?1",
-            params![scope_id as i64],
-            |row| row.get(0),
-        )
-        .unwrap_or_else(|_| crate::provider::model_registry::DEFAULT_MODEL.to_string())
+    async fn list_approvals(&self, scope_id: u64, limit: usize) -> DbResult<Vec<ApprovalRecord>> {
+        self.read(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, pending_id, scope_id, requested_by, tool_name, args_json, summary, requested_at,
+                        decision, decided_by, result, decided_at
+                 FROM approval_log WHERE scope_id = ?1 ORDER BY id DESC LIMIT ?2"
+            )?;
+            let rows = stmt.query_map(params![scope_id as i64, limit as i64], approval_from_row)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await
     }
 
-    pub fn set_chat_model(&self, scope_id: u64, model_id: &str) {
-        let conn = self.conn.lock().unwrap();
-        let _ = conn.execute(
-            "INSERT INTO user_preferences (user_id, model, updated_at) VALUES (?1, ?2, datetime('now'))
-             ON CONFLICT(user_id) DO UPDATE SET model = ?2, updated_at = datetime('now')",
-            params![scope_id as i64, model_id],
-        );
+    async fn expire_pending(&self, ttl_hours: u32) -> DbResult<Vec<PendingItem>> {
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let expired = {
+                let mut stmt = tx.prepare(&format!(
+                    "DELETE FROM pending_items WHERE created_at < datetime('now', '-' || ?1 || ' hours')
+                     RETURNING {PENDING_COLUMNS}"
+                ))?;
+                let rows = stmt.query_map(params![ttl_hours], pending_from_row)?;
+                rows.collect::<rusqlite::Result<Vec<_>>>()?
+            };
+            for item in &expired {
+                insert_approval(&tx, item, ApprovalDecision::Expired, None, None)?;
+            }
+            tx.commit()?;
+            Ok::<_, rusqlite::Error>(expired)
+        })
+        .await
     }
 
-    /// Get full content of a knowledge document by ID.
-    pub fn get_document(&self, user_id: u64, doc_id: i64) -> Result<(String, String, Option<String>, Option<String>), String> {
-        let conn = self.conn.lock().unwrap();
-        conn.query_row(
-            "SELECT title, content, source, tags FROM knowledge_documents WHERE id = ?1 AND user_id = ?2",
-            params![doc_id, user_id as i64],
-            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
-        )
-        .map_err(|e| e.to_string())
+    async fn claim_pending_notifications(&self) -> DbResult<Vec<PendingItem>> {
+        self.write(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "UPDATE pending_items SET notified_at = datetime('now') WHERE notified_at IS NULL
+                 RETURNING {PENDING_COLUMNS}"
+            ))?;
+            let rows = stmt.query_map([], pending_from_row)?;
+            let mut items = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+            items.sort_by_key(|item| item.id);
+            Ok::<_, rusqlite::Error>(items)
+        })
+        .await
     }
 
-    /// Delete a knowledge document (chunks cascade-deleted automatically).
-    pub fn delete_document(&self, user_id: u64, doc_id: i64) -> Result<bool, String> {
-        let conn = self.conn.lock().unwrap();
-        let rows = conn
-            .execute(
-                "DELETE FROM knowledge_documents WHERE id = ?1 AND user_id = ?2",
-                params![doc_id, user_id as i64],
+    // --- Spend Budgets ---
+
+    async fn get_spend_limits(&self, subject: BudgetSubject) -> DbResult<Option<SpendLimits>> {
+        self.read(move |conn| {
+            conn.query_row(
+                "SELECT daily_usd, monthly_usd FROM spend_budgets WHERE kind = ?1 AND subject_id = ?2",
+                params![subject.kind(), subject.id() as i64],
+                |row| Ok(SpendLimits { daily_usd: row.get(0)?, monthly_usd: row.get(1)? }),
             )
-            .map_err(|e| e.to_string())?;
-        Ok(rows > 0)
+            .optional()
+        })
+        .await
     }
 
-    // --- Pending Approval Queue ---
-
-    pub fn save_pending(
-        &self,
-        scope_id: u64,
-        requested_by: u64,
-        tool_name: &str,
-        args_json: &str,
-        summary: &str,
-    ) -> Result<i64, String> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "INSERT INTO pending_items (scope_id, requested_by, tool_name, args_json, summary) VALUES (?1, ?2, ?3, ?4, ?5)",
-            params![scope_id as i64, requested_by as i64, tool_name, args_json, summary],
-        )
-        .map_err(|e| e.to_string())?;
-        Ok(conn.last_insert_rowid())
-    }
-
-    pub fn list_pending(&self, scope_id: u64) -> Result<Vec<(i64, u64, String, String, String)>, String> {
-        let conn = self.conn.lock().unwrap();
-        conn.prepare(
-            "SELECT id, requested_by, tool_name, summary, created_at FROM pending_items WHERE scope_id = ?1 ORDER BY created_at ASC"
-        )
-        .and_then(|mut stmt| {
-            let rows = stmt.query_map(params![scope_id as i64], |row| {
-                let rb: i64 = row.get(1)?;
-                Ok((row.get(0)?, rb as u64, row.get(2)?, row.get(3)?, row.get(4)?))
-            })?;
-            rows.collect()
+    async fn set_spend_limits(&self, subject: BudgetSubject, limits: SpendLimits) -> DbResult<()> {
+        self.write(move |conn| {
+            conn.execute(
+                "INSERT INTO spend_budgets (kind, subject_id, daily_usd, monthly_usd)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT(kind, subject_id) DO UPDATE SET
+                    daily_usd = excluded.daily_usd,
+                    monthly_usd = excluded.monthly_usd,
+                    updated_at = datetime('now')",
+                params![subject.kind(), subject.id() as i64, limits.daily_usd, limits.monthly_usd],
+            )
+            .map(|_| ())
         })
-        .map_err(|e| e.to_string())
+        .await
     }
 
-    pub fn get_pending(&self, id: i64) -> Result<(u64, u64, String, String, String), String> {
-        let conn = self.conn.lock().unwrap();
-        conn.query_row(
-            "SELECT scope_id, requested_by, tool_name, args_json, summary FROM pending_items WHERE id = ?1",
-            params![id],
-            |row| {
-                let s: i64 = row.get(0)?;
-                let r: i64 = row.get(1)?;
-                Ok((s as u64, r as u64, row.get(2)?, row.get(3)?, row.get(4)?))
-            },
-        )
-        .map_err(|e| e.to_string())
+    async fn clear_spend_limits(&self, subject: BudgetSubject) -> DbResult<bool> {
+        self.write(move |conn| {
+            conn.execute(
+                "DELETE FROM spend_budgets WHERE kind = ?1 AND subject_id = ?2",
+                params![subject.kind(), subject.id() as i64],
+            )
+            .map(|n| n > 0)
+        })
+        .await
     }
 
-    pub fn delete_pending(&self, id: i64) -> Result<bool, String> {
-        let conn = self.conn.lock().unwrap();
-        let rows = conn
-            .execute("DELETE FROM pending_items WHERE id = ?1", params![id])
-            .map_err(|e| e.to_string())?;
-        Ok(rows > 0)
+    async fn usage_since(&self, subject: BudgetSubject, since: &str) -> DbResult<Vec<ModelUsage>> {
+        let since = since.to_string();
+        let column = match subject {
+            BudgetSubject::Owner(_) => "kb_owner_id",
+            BudgetSubject::User(_) => "user_id",
+        };
+        self.read(move |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT model, provider,
+                        SUM(prompt_tokens), SUM(completion_tokens),
+                        SUM(cache_creation_tokens), SUM(cache_read_tokens),
+                        COUNT(*)
+                 FROM usage_log
+                 WHERE {column} = ?1 AND created_at >= ?2
+                 GROUP BY model"
+            ))?;
+            let rows = stmt.query_map(params![subject.id() as i64, since], |row| model_usage_from_row(row, 0))?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await
     }
 
-    // --- Entities ---
+    async fn record_budget_alert(&self, subject: BudgetSubject, period: &str) -> DbResult<bool> {
+        let period = period.to_string();
+        self.write(move |conn| {
+            conn.execute(
+                "INSERT OR IGNORE INTO budget_alerts (kind, subject_id, period) VALUES (?1, ?2, ?3)",
+                params![subject.kind(), subject.id() as i64, period],
+            )
+            .map(|n| n > 0)
+        })
+        .await
+    }
 
     // --- Usage Log ---
 
-    pub fn log_usage(
-        &self,
-        model: &str,
-        provider: &str,
-        prompt_tokens: u32,
-        completion_tokens: u32,
-        cache_creation_tokens: u32,
-        cache_read_tokens: u32,
-    ) {
-        let conn = self.conn.lock().unwrap();
-        let _ = conn.execute(
-            "INSERT INTO usage_log (model, provider, prompt_tokens, completion_tokens, cache_creation_tokens, cache_read_tokens) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![model, provider, prompt_tokens, completion_tokens, cache_creation_tokens, cache_read_tokens],
-        );
+    async fn log_usage(&self, entry: &UsageEntry) -> DbResult<()> {
+        let entry = entry.clone();
+        self.write(move |conn| {
+            let tools = (!entry.tools.is_empty()).then(|| entry.tools.join(","));
+            conn.execute(
+                "INSERT INTO usage_log (model, provider, prompt_tokens, completion_tokens, cache_creation_tokens, cache_read_tokens,
+                                        user_id, kb_owner_id, session_id, turns, tools)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    entry.model,
+                    entry.provider,
+                    entry.prompt_tokens,
+                    entry.completion_tokens,
+                    entry.cache_creation_tokens,
+                    entry.cache_read_tokens,
+                    entry.user_id.map(|id| id as i64),
+                    entry.kb_owner_id.map(|id| id as i64),
+                    entry.session_id,
+                    entry.turns,
+                    tools,
+                ],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    async fn get_monthly_usage(&self) -> DbResult<Vec<ModelUsage>> {
+        self.read(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT model, provider,
+                        SUM(prompt_tokens), SUM(completion_tokens),
+                        SUM(cache_creation_tokens), SUM(cache_read_tokens),
+                        COUNT(*)
+                 FROM usage_log
+                 WHERE created_at >= strftime('%Y-%m-01', 'now')
+                 GROUP BY model
+                 ORDER BY SUM(prompt_tokens) + SUM(completion_tokens) DESC"
+            )?;
+            let rows = stmt.query_map([], |row| model_usage_from_row(row, 0))?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await
     }
 
-    /// Get usage summary grouped by model for the current month.
-    pub fn get_monthly_usage(&self) -> Vec<(String, String, u64, u64, u64, u64, u64)> {
-        let conn = self.conn.lock().unwrap();
-        conn.prepare(
-            "SELECT model, provider,
-                    SUM(prompt_tokens), SUM(completion_tokens),
-                    SUM(cache_creation_tokens), SUM(cache_read_tokens),
-                    COUNT(*)
-             FROM usage_log
-             WHERE created_at >= strftime('%Y-%m-01', 'now')
-             GROUP BY model
-             ORDER BY SUM(prompt_tokens) + SUM(completion_tokens) DESC"
-        )
-        .and_then(|mut stmt| {
+    async fn get_monthly_usage_by(&self, group: UsageGroup) -> DbResult<Vec<GroupedUsage>> {
+        let key = match group {
+            UsageGroup::User => "CAST(user_id AS TEXT)",
+            UsageGroup::Chat => "CAST(kb_owner_id AS TEXT)",
+            UsageGroup::Day => "date(created_at)",
+        };
+        self.read(move |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {key} AS grp, model, provider,
+                        SUM(prompt_tokens), SUM(completion_tokens),
+                        SUM(cache_creation_tokens), SUM(cache_read_tokens),
+                        COUNT(*)
+                 FROM usage_log
+                 WHERE created_at >= strftime('%Y-%m-01', 'now')
+                 GROUP BY grp, model
+                 ORDER BY grp, model"
+            ))?;
             let rows = stmt.query_map([], |row| {
-                Ok((
-                    row.get(0)?,
-                    row.get(1)?,
-                    row.get::<_, i64>(2)? as u64,
-                    row.get::<_, i64>(3)? as u64,
-                    row.get::<_, i64>(4)? as u64,
-                    row.get::<_, i64>(5)? as u64,
-                    row.get::<_, i64>(6)? as u64,
-                ))
+                Ok(GroupedUsage { key: row.get(0)?, usage: model_usage_from_row(row, 1)? })
             })?;
-            rows.collect()
+            rows.collect::<rusqlite::Result<Vec<_>>>()
         })
-        .unwrap_or_default()
+        .await
     }
 
-    pub fn search_entities(
-        &self,
-        user_id: u64,
-        query: &str,
-    ) -> Result<Vec<(String, String, Vec<(String, i64, Option<String>)>)>, String> {
-        let conn = self.conn.lock().unwrap();
-
-        // Find matching entities
-        let entities: Vec<(i64, String, String)> = conn
-            .prepare(
-                "SELECT id, name, entity_type FROM entities
-                 WHERE user_id = ?1 AND name LIKE '%' || ?2 || '%'
-                 ORDER BY name LIMIT 20"
-            )
-            .and_then(|mut stmt| {
-                let rows = stmt.query_map(params![user_id as i64, query], |row| {
-                    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
-                })?;
-                rows.collect()
-            })
-            .map_err(|e| e.to_string())?;
-
-        // For each entity, get its mentions
-        let mut results = Vec::new();
-        for (entity_id, name, entity_type) in entities {
-            let mentions: Vec<(String, i64, Option<String>)> = conn
-                .prepare(
-                    "SELECT source_type, source_id, context FROM entity_mentions
-                     WHERE entity_id = ?1 ORDER BY created_at DESC LIMIT 10"
-                )
-                .and_then(|mut stmt| {
-                    let rows = stmt.query_map(params![entity_id], |row| {
-                        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
-                    })?;
-                    rows.collect()
+    async fn list_usage(&self, since: Option<&str>) -> DbResult<Vec<UsageRecord>> {
+        let since = since.map(str::to_string);
+        self.read(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT model, provider, prompt_tokens, completion_tokens, cache_creation_tokens, cache_read_tokens, 1,
+                        id, created_at, user_id, kb_owner_id, session_id, turns, tools
+                 FROM usage_log
+                 WHERE ?1 IS NULL OR created_at >= ?1
+                 ORDER BY id"
+            )?;
+            let rows = stmt.query_map(params![since], |row| {
+                let tools: Option<String> = row.get(13)?;
+                Ok(UsageRecord {
+                    id: row.get(7)?,
+                    created_at: row.get(8)?,
+                    user_id: row.get::<_, Option<i64>>(9)?.map(|id| id as u64),
+                    kb_owner_id: row.get::<_, Option<i64>>(10)?.map(|id| id as u64),
+                    session_id: row.get(11)?,
+                    turns: row.get(12)?,
+                    tools: tools
+                        .map(|t| t.split(',').filter(|s| !s.is_empty()).map(str::to_string).collect())
+                        .unwrap_or_default(),
+                    usage: model_usage_from_row(row, 0)?,
                 })
-                .unwrap_or_default();
-            results.push((name, entity_type, mentions));
-        }
-
-        Ok(results)
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await
     }
 }
+
+/// Map a `(model, provider, prompt, completion, cache_write, cache_read, requests)`
+/// run of columns starting at `start`.
+fn model_usage_from_row(row: &Row, start: usize) -> rusqlite::Result<ModelUsage> {
+    Ok(ModelUsage {
+        model: row.get(start)?,
+        provider: row.get(start + 1)?,
+        prompt_tokens: row.get::<_, i64>(start + 2)? as u64,
+        completion_tokens: row.get::<_, i64>(start + 3)? as u64,
+        cache_creation_tokens: row.get::<_, i64>(start + 4)? as u64,
+        cache_read_tokens: row.get::<_, i64>(start + 5)? as u64,
+        requests: row.get::<_, i64>(start + 6)? as u64,
+    })
+}
+
+/// Record one fact revision, inside the caller's transaction.
+fn insert_fact_revision(
+    conn: &Connection,
+    fact_id: i64,
+    old_text: &str,
+    new_text: &str,
+    superseded_by: Option<i64>,
+    actor_id: u64,
+    tool: &str,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO memory_fact_revisions (fact_id, old_text, new_text, superseded_by, actor_id, tool)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![fact_id, old_text, new_text, superseded_by, actor_id as i64, tool],
+    )
+    .map(|_| ())
+}
+
+/// Map an `(id, fact_id, old_text, new_text, superseded_by, actor_id, tool, created_at)` row.
+fn revision_from_row(row: &Row) -> rusqlite::Result<FactRevision> {
+    let actor_id: i64 = row.get(5)?;
+    Ok(FactRevision {
+        id: row.get(0)?,
+        fact_id: row.get(1)?,
+        old_text: row.get(2)?,
+        new_text: row.get(3)?,
+        superseded_by: row.get(4)?,
+        actor_id: actor_id as u64,
+        tool: row.get(6)?,
+        created_at: row.get(7)?,
+    })
+}
+
+/// Store `content` as the next version of a document, with the diff from `previous`.
+/// Returns the new version number.
+fn insert_document_version(
+    conn: &Connection,
+    doc_id: i64,
+    previous: &str,
+    content: &str,
+    actor_id: u64,
+    tool: &str,
+) -> rusqlite::Result<i64> {
+    let version: i64 = conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) + 1 FROM knowledge_document_versions WHERE doc_id = ?1",
+        params![doc_id],
+        |row| row.get(0),
+    )?;
+    let diff = unified_diff(previous, content, &format!("v{}", version - 1), &format!("v{version}"));
+    conn.execute(
+        "INSERT INTO knowledge_document_versions (doc_id, version, content, diff, actor_id, tool)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![doc_id, version, content, diff, actor_id as i64, tool],
+    )?;
+    Ok(version)
+}
+
+/// Map a `(doc_id, version, content, diff, actor_id, tool, created_at)` row.
+fn version_from_row(row: &Row) -> rusqlite::Result<DocumentVersion> {
+    let actor_id: Option<i64> = row.get(4)?;
+    Ok(DocumentVersion {
+        doc_id: row.get(0)?,
+        version: row.get(1)?,
+        content: row.get(2)?,
+        diff: row.get(3)?,
+        actor_id: actor_id.map(|id| id as u64),
+        tool: row.get(5)?,
+        created_at: row.get(6)?,
+    })
+}
+
+/// Line-based unified diff (3 lines of context) between two texts.
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    similar::TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(old_label, new_label)
+        .to_string()
+}
+
+/// Hard-delete trashed rows of one kind matching `filter`. Entity mentions go
+/// with them by trigger; chunks, KB links and fact relations via `ON DELETE CASCADE`.
+fn purge_trashed(
+    conn: &Connection,
+    kind: TrashKind,
+    filter: &str,
+    params: &[&dyn rusqlite::types::ToSql],
+) -> rusqlite::Result<usize> {
+    conn.execute(
+        &format!("DELETE FROM {} WHERE deleted_at IS NOT NULL AND {filter}", kind.table()),
+        params,
+    )
+}
+
+/// Columns [`pending_from_row`] expects.
+const PENDING_COLUMNS: &str = "id, scope_id, requested_by, tool_name, args_json, summary, created_at";
+
+/// Map an `(id, scope_id, requested_by, tool_name, args_json, summary, created_at)` row.
+fn pending_from_row(row: &Row) -> rusqlite::Result<PendingItem> {
+    let scope_id: i64 = row.get(1)?;
+    let requested_by: i64 = row.get(2)?;
+    Ok(PendingItem {
+        id: row.get(0)?,
+        scope_id: scope_id as u64,
+        requested_by: requested_by as u64,
+        tool_name: row.get(3)?,
+        args_json: row.get(4)?,
+        summary: row.get(5)?,
+        created_at: row.get(6)?,
+    })
+}
+
+fn insert_approval(
+    conn: &Connection,
+    item: &PendingItem,
+    decision: ApprovalDecision,
+    decided_by: Option<u64>,
+    result: Option<&str>,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO approval_log
+            (pending_id, scope_id, requested_by, tool_name, args_json, summary, requested_at, decision, decided_by, result)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            item.id,
+            item.scope_id as i64,
+            item.requested_by as i64,
+            item.tool_name,
+            item.args_json,
+            item.summary,
+            item.created_at,
+            decision.as_str(),
+            decided_by.map(|u| u as i64),
+            result,
+        ],
+    )
+    .map(|_| ())
+}
+
+/// Map an `approval_log` row in column order.
+fn approval_from_row(row: &Row) -> rusqlite::Result<ApprovalRecord> {
+    let decision: String = row.get(8)?;
+    Ok(ApprovalRecord {
+        id: row.get(0)?,
+        pending_id: row.get(1)?,
+        scope_id: row.get::<_, i64>(2)? as u64,
+        requested_by: row.get::<_, i64>(3)? as u64,
+        tool_name: row.get(4)?,
+        args_json: row.get(5)?,
+        summary: row.get(6)?,
+        requested_at: row.get(7)?,
+        decision: ApprovalDecision::parse(&decision).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(8, rusqlite::types::Type::Text, format!("unknown decision {decision}").into())
+        })?,
+        decided_by: row.get::<_, Option<i64>>(9)?.map(|u| u as u64),
+        result: row.get(10)?,
+        decided_at: row.get(11)?,
+    })
+}
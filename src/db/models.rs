@@ -0,0 +1,365 @@
+//! Row types returned by [`Repository`](super::Repository).
+
+/// A short fact in long-term memory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fact {
+    pub id: i64,
+    pub text: String,
+    pub category: String,
+}
+
+/// Who caused a change: the Telegram user and the tool that made it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Actor<'a> {
+    pub user_id: u64,
+    pub tool: &'a str,
+}
+
+/// One recorded change to a fact. `superseded_by` is set when the fact was
+/// replaced by another fact (and moved to the trash) rather than edited.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FactRevision {
+    pub id: i64,
+    pub fact_id: i64,
+    pub old_text: String,
+    pub new_text: String,
+    pub superseded_by: Option<i64>,
+    pub actor_id: u64,
+    pub tool: String,
+    pub created_at: String,
+}
+
+/// A knowledge base document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    pub id: i64,
+    pub title: String,
+    pub content: String,
+    pub source: Option<String>,
+    pub tags: Option<String>,
+    pub created_at: String,
+}
+
+/// A stored snapshot of a document. `diff` is the unified diff from the
+/// previous version (`None` for the first one).
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentVersion {
+    pub doc_id: i64,
+    pub version: i64,
+    pub content: String,
+    pub diff: Option<String>,
+    pub actor_id: Option<u64>,
+    pub tool: String,
+    pub created_at: String,
+}
+
+/// Listing entry for a document, without its content.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentSummary {
+    pub id: i64,
+    pub title: String,
+    pub source: Option<String>,
+    pub created_at: String,
+    pub chunk_count: i64,
+}
+
+/// Document-level FTS hit with a highlighted snippet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentMatch {
+    pub id: i64,
+    pub title: String,
+    pub snippet: String,
+    pub source: Option<String>,
+}
+
+/// A line range of a document, with the parent document's title and source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub id: i64,
+    pub doc_id: i64,
+    pub title: String,
+    pub source: Option<String>,
+    pub content: String,
+    pub start_line: i64,
+    pub end_line: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entity {
+    pub id: i64,
+    pub name: String,
+    pub entity_type: String,
+}
+
+/// Where an entity was seen: `source_type` is "document" or "fact".
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityMention {
+    pub source_type: String,
+    pub source_id: i64,
+    pub context: Option<String>,
+}
+
+/// Kind of row that can sit in the trash bin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrashKind {
+    Fact,
+    Document,
+}
+
+impl TrashKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TrashKind::Fact => "fact",
+            TrashKind::Document => "document",
+        }
+    }
+
+    /// Parse "fact"/"memory" or "document"/"doc".
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "fact" | "memory" => Some(TrashKind::Fact),
+            "document" | "doc" => Some(TrashKind::Document),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn table(self) -> &'static str {
+        match self {
+            TrashKind::Fact => "memory_facts",
+            TrashKind::Document => "knowledge_documents",
+        }
+    }
+}
+
+/// A soft-deleted fact or document. `label` is the fact text or document title.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrashItem {
+    pub kind: TrashKind,
+    pub id: i64,
+    pub label: String,
+    pub deleted_at: String,
+}
+
+/// A write tool call from a non-whitelisted user, waiting for approval.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingItem {
+    pub id: i64,
+    pub scope_id: u64,
+    pub requested_by: u64,
+    pub tool_name: String,
+    pub args_json: String,
+    pub summary: String,
+    pub created_at: String,
+}
+
+/// How a pending request was settled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApprovalDecision {
+    Approved,
+    Rejected,
+    /// Nobody decided within `PENDING_TTL_HOURS`.
+    Expired,
+}
+
+impl ApprovalDecision {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApprovalDecision::Approved => "approved",
+            ApprovalDecision::Rejected => "rejected",
+            ApprovalDecision::Expired => "expired",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "approved" => Some(ApprovalDecision::Approved),
+            "rejected" => Some(ApprovalDecision::Rejected),
+            "expired" => Some(ApprovalDecision::Expired),
+            _ => None,
+        }
+    }
+}
+
+/// An `approval_log` row: a settled pending request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApprovalRecord {
+    pub id: i64,
+    pub pending_id: i64,
+    pub scope_id: u64,
+    pub requested_by: u64,
+    pub tool_name: String,
+    pub args_json: String,
+    pub summary: String,
+    pub requested_at: String,
+    pub decision: ApprovalDecision,
+    /// `None` for expiries.
+    pub decided_by: Option<u64>,
+    /// What the tool returned, for approvals.
+    pub result: Option<String>,
+    pub decided_at: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub id: String,
+    pub user_id: u64,
+    pub title: Option<String>,
+    pub created_at: String,
+    pub last_active_at: String,
+    /// Summary of turns pruned by retention, injected when the session resumes.
+    pub summary: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionMessage {
+    pub id: i64,
+    pub role: String,
+    /// Plain-text rendering, used for summaries, titles and older rows.
+    pub content: String,
+    pub created_at: String,
+    /// Serialized `provider::Message` replayed to the model; `None` for rows
+    /// saved before history kept tool calls.
+    pub message: Option<String>,
+}
+
+/// A history row to append: see [`SessionMessage`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewSessionMessage {
+    pub role: String,
+    pub content: String,
+    pub message: Option<String>,
+}
+
+/// How much of a KB owner's chat history is kept. `None` limits are off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SessionRetention {
+    /// Prune messages older than this many days.
+    pub max_age_days: Option<u32>,
+    /// Keep at most this many of the newest messages per session.
+    pub max_messages: Option<u32>,
+    /// Fold pruned messages into the session summary via an LLM call first.
+    pub summarize: bool,
+}
+
+impl SessionRetention {
+    /// Whether any limit is set.
+    pub fn is_active(&self) -> bool {
+        self.max_age_days.is_some() || self.max_messages.is_some()
+    }
+}
+
+/// Who a spend budget applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BudgetSubject {
+    /// A KB owner: a group chat, or a user's private chat.
+    Owner(u64),
+    /// One Telegram user, across every chat they use the bot in.
+    User(u64),
+}
+
+impl BudgetSubject {
+    /// Value of the `kind` column in `spend_budgets` / `budget_alerts`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            BudgetSubject::Owner(_) => "owner",
+            BudgetSubject::User(_) => "user",
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        match self {
+            BudgetSubject::Owner(id) | BudgetSubject::User(id) => *id,
+        }
+    }
+}
+
+/// USD spend limits for one subject. `None` means no limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SpendLimits {
+    pub daily_usd: Option<f64>,
+    pub monthly_usd: Option<f64>,
+}
+
+impl SpendLimits {
+    /// Whether any limit is set.
+    pub fn is_active(&self) -> bool {
+        self.daily_usd.is_some() || self.monthly_usd.is_some()
+    }
+}
+
+/// Token totals for one model over a period.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelUsage {
+    pub model: String,
+    pub provider: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cache_creation_tokens: u64,
+    pub cache_read_tokens: u64,
+    pub requests: u64,
+}
+
+impl ModelUsage {
+    /// Spend in USD at the model's registry pricing (0 for unknown models).
+    pub fn cost_usd(&self) -> f64 {
+        let (ci, co, cw, cr) = crate::provider::model_registry::calculate_cost(
+            &self.model,
+            self.prompt_tokens,
+            self.completion_tokens,
+            self.cache_creation_tokens,
+            self.cache_read_tokens,
+        );
+        ci + co + cw + cr
+    }
+}
+
+/// One LLM call to record in `usage_log`, with who it was spent for.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsageEntry {
+    pub model: String,
+    pub provider: String,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub cache_creation_tokens: u32,
+    pub cache_read_tokens: u32,
+    /// Telegram user who sent the message. `None` for background work.
+    pub user_id: Option<u64>,
+    /// Chat scope: the group's KB owner, or the user in private chats.
+    pub kb_owner_id: Option<u64>,
+    pub session_id: Option<String>,
+    /// Agent loop turns (LLM round trips) behind this entry.
+    pub turns: u32,
+    /// Distinct tools called while answering.
+    pub tools: Vec<String>,
+}
+
+/// Dimension for [`Repository::get_monthly_usage_by`](super::Repository::get_monthly_usage_by).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageGroup {
+    User,
+    /// Per chat scope (`kb_owner_id`).
+    Chat,
+    /// Per UTC day, keyed `YYYY-MM-DD`.
+    Day,
+}
+
+/// Token totals for one group key and model. `key` is `None` for rows
+/// that carry no attribution (logged before it existed, or background work).
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupedUsage {
+    pub key: Option<String>,
+    pub usage: ModelUsage,
+}
+
+/// A raw `usage_log` row, as written to `/cost export`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageRecord {
+    pub id: i64,
+    pub created_at: String,
+    pub user_id: Option<u64>,
+    pub kb_owner_id: Option<u64>,
+    pub session_id: Option<String>,
+    pub turns: u32,
+    pub tools: Vec<String>,
+    pub usage: ModelUsage,
+}
@@ -0,0 +1,310 @@
+//! Render an [`ExportBundle`] as an Obsidian vault:
+//!
+//! ```text
+//! Documents/<title>.md     one note per document, YAML front matter + content
+//! Facts/<category>.md      one note per category, one block per fact
+//! Entities/<name>.md       one note per entity, linking to what mentions it
+//! ```
+//!
+//! Facts are addressed by block id (`[[Facts/work#^fact-12]]`), so knowledge
+//! links, fact relations and entity mentions all become clickable wikilinks.
+//! Trashed facts and documents are left out.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{Seek, Write};
+
+use super::export::{BundleError, ExportBundle, FactRecord};
+
+const DOCUMENTS_DIR: &str = "Documents";
+const FACTS_DIR: &str = "Facts";
+const ENTITIES_DIR: &str = "Entities";
+const MAX_NAME_CHARS: usize = 100;
+const MAX_ALIAS_CHARS: usize = 60;
+
+impl ExportBundle {
+    /// Every note of the vault, keyed by its path inside the vault.
+    pub fn obsidian_notes(&self) -> BTreeMap<String, String> {
+        Vault::new(self).render()
+    }
+
+    /// Write the vault as a ZIP archive; extract it and open the folder in Obsidian.
+    pub fn write_obsidian_zip<W: Write + Seek>(&self, writer: W) -> Result<(), BundleError> {
+        let mut zip = zip::ZipWriter::new(writer);
+        let options = zip::write::SimpleFileOptions::default();
+        for (path, content) in self.obsidian_notes() {
+            zip.start_file(path, options)?;
+            zip.write_all(content.as_bytes())?;
+        }
+        zip.finish()?;
+        Ok(())
+    }
+}
+
+/// Note names and link targets for the live part of a bundle.
+struct Vault<'a> {
+    bundle: &'a ExportBundle,
+    /// Doc id → note name
+    docs: HashMap<i64, String>,
+    /// Fact id → category note name
+    facts: HashMap<i64, String>,
+    fact_text: HashMap<i64, &'a str>,
+    /// Entity id → note name
+    entities: HashMap<i64, String>,
+}
+
+impl<'a> Vault<'a> {
+    fn new(bundle: &'a ExportBundle) -> Self {
+        let mut names = NameAllocator::default();
+
+        let mut docs = HashMap::new();
+        for doc in bundle.documents.iter().filter(|d| d.deleted_at.is_none()) {
+            let base = sanitize_name(&doc.title);
+            let name = names.allocate(DOCUMENTS_DIR, [base.clone(), format!("{base} ({})", doc.id)]);
+            docs.insert(doc.id, name);
+        }
+
+        let mut categories: HashMap<&str, String> = HashMap::new();
+        let mut facts = HashMap::new();
+        let mut fact_text = HashMap::new();
+        for fact in bundle.facts.iter().filter(|f| f.deleted_at.is_none()) {
+            let name = categories
+                .entry(fact.category.as_str())
+                .or_insert_with(|| {
+                    let base = sanitize_name(&fact.category);
+                    names.allocate(FACTS_DIR, [base.clone(), format!("{base} ({})", fact.id)])
+                })
+                .clone();
+            facts.insert(fact.id, name);
+            fact_text.insert(fact.id, fact.fact.as_str());
+        }
+
+        let mut entities = HashMap::new();
+        for entity in &bundle.entities {
+            let base = sanitize_name(&entity.name);
+            let typed = format!("{base} ({})", sanitize_name(&entity.entity_type));
+            let name = names.allocate(ENTITIES_DIR, [base, typed.clone(), format!("{typed} {}", entity.id)]);
+            entities.insert(entity.id, name);
+        }
+
+        Self { bundle, docs, facts, fact_text, entities }
+    }
+
+    fn doc_link(&self, id: i64) -> Option<String> {
+        let name = self.docs.get(&id)?;
+        Some(format!("[[{DOCUMENTS_DIR}/{name}|{}]]", alias(name)))
+    }
+
+    fn fact_link(&self, id: i64) -> Option<String> {
+        let note = self.facts.get(&id)?;
+        Some(format!("[[{FACTS_DIR}/{note}#^fact-{id}|{}]]", alias(self.fact_text[&id])))
+    }
+
+    fn entity_link(&self, id: i64) -> Option<String> {
+        let name = self.entities.get(&id)?;
+        Some(format!("[[{ENTITIES_DIR}/{name}|{}]]", alias(name)))
+    }
+
+    fn source_link(&self, source_type: &str, id: i64) -> Option<String> {
+        match source_type {
+            "fact" => self.fact_link(id),
+            "document" => self.doc_link(id),
+            _ => None,
+        }
+    }
+
+    fn render(&self) -> BTreeMap<String, String> {
+        let b = self.bundle;
+
+        // Collect links for both ends up front so each note is rendered in one pass
+        let mut doc_facts: HashMap<i64, Vec<String>> = HashMap::new();
+        let mut fact_docs: HashMap<i64, Vec<String>> = HashMap::new();
+        for link in &b.kb_links {
+            if let (Some(fact), Some(doc)) = (self.fact_link(link.fact_id), self.doc_link(link.doc_id)) {
+                doc_facts.entry(link.doc_id).or_default().push(fact);
+                fact_docs.entry(link.fact_id).or_default().push(doc);
+            }
+        }
+
+        let mut related: HashMap<i64, Vec<String>> = HashMap::new();
+        for rel in &b.fact_relations {
+            if let (Some(a), Some(b)) = (self.fact_link(rel.fact_id_1), self.fact_link(rel.fact_id_2)) {
+                related.entry(rel.fact_id_1).or_default().push(format!("{b} ({:.2})", rel.similarity));
+                related.entry(rel.fact_id_2).or_default().push(format!("{a} ({:.2})", rel.similarity));
+            }
+        }
+
+        let mut source_entities: HashMap<(&str, i64), Vec<String>> = HashMap::new();
+        let mut entity_sources: HashMap<i64, Vec<String>> = HashMap::new();
+        for m in &b.mentions {
+            let (Some(entity), Some(source)) = (self.entity_link(m.entity_id), self.source_link(&m.source_type, m.source_id))
+            else {
+                continue;
+            };
+            let line = match m.context.as_deref().map(str::trim).filter(|c| !c.is_empty()) {
+                Some(ctx) => format!("{source} — {}", one_line(ctx)),
+                None => source,
+            };
+            push_unique(entity_sources.entry(m.entity_id).or_default(), line);
+            push_unique(source_entities.entry((m.source_type.as_str(), m.source_id)).or_default(), entity);
+        }
+
+        let mut notes = BTreeMap::new();
+
+        for doc in b.documents.iter().filter(|d| d.deleted_at.is_none()) {
+            let tags: Vec<String> = doc
+                .tags
+                .as_deref()
+                .unwrap_or("")
+                .split(',')
+                .map(|t| t.split_whitespace().collect::<Vec<_>>().join("-"))
+                .filter(|t| !t.is_empty())
+                .collect();
+
+            let mut out = String::from("---\n");
+            out.push_str(&format!("id: {}\n", doc.id));
+            out.push_str(&format!("title: {}\n", yaml_str(&doc.title)));
+            match &doc.source {
+                Some(source) => out.push_str(&format!("source: {}\n", yaml_str(source))),
+                None => out.push_str("source: null\n"),
+            }
+            if tags.is_empty() {
+                out.push_str("tags: []\n");
+            } else {
+                out.push_str("tags:\n");
+                for tag in &tags {
+                    out.push_str(&format!("  - {}\n", yaml_str(tag)));
+                }
+            }
+            out.push_str(&format!("created_at: {}\n", yaml_str(&doc.created_at)));
+            out.push_str("---\n\n");
+            out.push_str(doc.content.trim_end());
+            out.push('\n');
+            push_section(&mut out, "Related facts", doc_facts.get(&doc.id));
+            push_section(&mut out, "Entities", source_entities.get(&("document", doc.id)));
+
+            notes.insert(format!("{DOCUMENTS_DIR}/{}.md", self.docs[&doc.id]), out);
+        }
+
+        let category_created: HashMap<&str, &str> =
+            b.categories.iter().map(|c| (c.name.as_str(), c.created_at.as_str())).collect();
+        let mut by_category: BTreeMap<&str, Vec<&FactRecord>> = BTreeMap::new();
+        for fact in b.facts.iter().filter(|f| f.deleted_at.is_none()) {
+            by_category.entry(fact.category.as_str()).or_default().push(fact);
+        }
+        for (category, facts) in by_category {
+            let mut out = String::from("---\n");
+            out.push_str(&format!("category: {}\n", yaml_str(category)));
+            if let Some(created) = category_created.get(category) {
+                out.push_str(&format!("created_at: {}\n", yaml_str(created)));
+            }
+            out.push_str(&format!("facts: {}\n", facts.len()));
+            out.push_str("---\n\n");
+            for fact in &facts {
+                out.push_str(&format!("- {} ^fact-{}\n", one_line(&fact.fact), fact.id));
+                push_inline(&mut out, "Related", related.get(&fact.id));
+                push_inline(&mut out, "Documents", fact_docs.get(&fact.id));
+                push_inline(&mut out, "Entities", source_entities.get(&("fact", fact.id)));
+            }
+
+            notes.insert(format!("{FACTS_DIR}/{}.md", self.facts[&facts[0].id]), out);
+        }
+
+        for entity in &b.entities {
+            let mut out = String::from("---\n");
+            out.push_str(&format!("name: {}\n", yaml_str(&entity.name)));
+            out.push_str(&format!("type: {}\n", yaml_str(&entity.entity_type)));
+            out.push_str(&format!("created_at: {}\n", yaml_str(&entity.created_at)));
+            out.push_str("---\n");
+            if entity_sources.contains_key(&entity.id) {
+                push_section(&mut out, "Mentioned in", entity_sources.get(&entity.id));
+            } else {
+                out.push_str("\nNot mentioned by any fact or document.\n");
+            }
+
+            notes.insert(format!("{ENTITIES_DIR}/{}.md", self.entities[&entity.id]), out);
+        }
+
+        notes
+    }
+}
+
+/// Hands out file names that are unique per folder, ignoring case like
+/// macOS/Windows file systems (and Obsidian's link resolution) do.
+#[derive(Default)]
+struct NameAllocator {
+    used: HashSet<(&'static str, String)>,
+}
+
+impl NameAllocator {
+    /// First candidate not taken yet; the last one gets a counter if all are.
+    fn allocate<const N: usize>(&mut self, dir: &'static str, candidates: [String; N]) -> String {
+        for candidate in &candidates {
+            if self.used.insert((dir, candidate.to_lowercase())) {
+                return candidate.clone();
+            }
+        }
+        let last = &candidates[N - 1];
+        (2..)
+            .map(|n| format!("{last} {n}"))
+            .find(|name| self.used.insert((dir, name.to_lowercase())))
+            .expect("unbounded counter")
+    }
+}
+
+/// Strip characters Obsidian (or the file system) does not allow in note names.
+fn sanitize_name(s: &str) -> String {
+    let cleaned: String = s
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '#' | '^' | '[' | ']' => ' ',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect();
+    let name = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    let name: String = name.trim_start_matches('.').chars().take(MAX_NAME_CHARS).collect();
+    let name = name.trim().to_string();
+    if name.is_empty() { "Untitled".into() } else { name }
+}
+
+/// Display text for a wikilink: one line, no link syntax, truncated.
+fn alias(s: &str) -> String {
+    let text: String = one_line(s).replace(['|', '[', ']'], " ");
+    if text.chars().count() > MAX_ALIAS_CHARS {
+        let short: String = text.chars().take(MAX_ALIAS_CHARS - 1).collect();
+        format!("{}…", short.trim_end())
+    } else {
+        text
+    }
+}
+
+fn one_line(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// A JSON string literal is also a valid double-quoted YAML scalar.
+fn yaml_str(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or_else(|_| "\"\"".into())
+}
+
+fn push_unique(list: &mut Vec<String>, item: String) {
+    if !list.contains(&item) {
+        list.push(item);
+    }
+}
+
+fn push_section(out: &mut String, heading: &str, links: Option<&Vec<String>>) {
+    let Some(links) = links.filter(|l| !l.is_empty()) else {
+        return;
+    };
+    out.push_str(&format!("\n## {heading}\n\n"));
+    for link in links {
+        out.push_str(&format!("- {link}\n"));
+    }
+}
+
+fn push_inline(out: &mut String, label: &str, links: Option<&Vec<String>>) {
+    if let Some(links) = links.filter(|l| !l.is_empty()) {
+        out.push_str(&format!("    - {label}: {}\n", links.join(", ")));
+    }
+}
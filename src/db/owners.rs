@@ -0,0 +1,400 @@
+//! Optional per-owner database files (`DB_PER_OWNER`).
+//!
+//! In shared mode every owner's data lives in `memory-assistant.db`. In
+//! per-owner mode that main database only keeps cross-owner accounting
+//! (`usage_log`, `spend_budgets`, `budget_alerts`), and each KB owner's facts,
+//! documents, entities, chat history, settings and pending requests live in
+//! `{DB_DATA_DIR}/owner-{id}.db`. A query that forgets its `user_id` filter
+//! then cannot reach another owner's data.
+//!
+//! Owner files carry the full schema and are migrated when opened. Handles
+//! are cached: a file is opened on first use and closed again once it has sat
+//! idle and nothing holds its handle.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rusqlite::{Connection, params};
+use tokio::sync::{Mutex, OnceCell};
+use tracing::{debug, info};
+
+use super::backup::{BackupError, BackupReport, RetentionPolicy};
+use super::quantization::Quantization;
+use super::{Database, DbError, DbResult};
+
+const FILE_PREFIX: &str = "owner-";
+const FILE_SUFFIX: &str = ".db";
+
+/// Tables holding a KB owner's data, parents before children, each with the
+/// condition that selects one owner's rows (`?1`) in the main database.
+/// Accounting tables, the embedding cache and the vector indexes (rebuilt
+/// from the embeddings) are not copied.
+const OWNER_TABLES: &[(&str, &str)] = &[
+    ("categories", "user_id = ?1"),
+    ("memory_facts", "user_id = ?1"),
+    ("knowledge_documents", "user_id = ?1"),
+    ("knowledge_chunks", "doc_id IN (SELECT id FROM main.knowledge_documents WHERE user_id = ?1)"),
+    ("entities", "user_id = ?1"),
+    ("entity_mentions", "entity_id IN (SELECT id FROM main.entities WHERE user_id = ?1)"),
+    ("memory_kb_links", "fact_id IN (SELECT id FROM main.memory_facts WHERE user_id = ?1)"),
+    ("fact_relations", "fact_id_1 IN (SELECT id FROM main.memory_facts WHERE user_id = ?1)"),
+    ("memory_fact_revisions", "fact_id IN (SELECT id FROM main.memory_facts WHERE user_id = ?1)"),
+    ("knowledge_document_versions", "doc_id IN (SELECT id FROM main.knowledge_documents WHERE user_id = ?1)"),
+    ("sessions", "user_id = ?1"),
+    ("session_messages", "session_id IN (SELECT id FROM main.sessions WHERE user_id = ?1)"),
+    ("active_sessions", "owner_id = ?1"),
+    ("session_retention", "owner_id = ?1"),
+    ("user_preferences", "user_id = ?1"),
+    ("pending_items", "scope_id = ?1"),
+    ("approval_log", "scope_id = ?1"),
+    ("audit_log", "kb_owner_id = ?1"),
+];
+
+/// How owner files are found and opened.
+#[derive(Debug, Clone)]
+pub struct OwnerFileOptions {
+    pub dir: PathBuf,
+    pub read_connections: usize,
+    /// SQLCipher key, the same one the main database uses.
+    pub key: Option<String>,
+    /// Keep the trigram indexes in every file (see [`Database::set_trigram_index`]).
+    pub fts_trigram: bool,
+    /// Embedding quantization of every file (see [`Database::set_quantization`]).
+    pub quantization: Quantization,
+    /// Close a cached handle after this long without use.
+    pub idle: Duration,
+}
+
+/// One owner's cached handle. Callers for the same owner wait on `db` while
+/// the file is opened and migrated; other owners are not held up.
+struct OwnerSlot {
+    db: OnceCell<Database>,
+    last_used: std::sync::Mutex<Instant>,
+}
+
+impl OwnerSlot {
+    fn new() -> Self {
+        Self { db: OnceCell::new(), last_used: std::sync::Mutex::new(Instant::now()) }
+    }
+
+    fn touch(&self) {
+        *self.last_used.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_used.lock().unwrap_or_else(|e| e.into_inner()).elapsed()
+    }
+}
+
+struct OwnerFiles {
+    options: OwnerFileOptions,
+    open: Mutex<HashMap<u64, Arc<OwnerSlot>>>,
+}
+
+/// Resolves the database that holds a KB owner's data.
+pub struct OwnerDatabases {
+    main: Database,
+    files: Option<OwnerFiles>,
+}
+
+impl OwnerDatabases {
+    /// Shared mode: every owner uses `main`.
+    pub fn shared(main: Database) -> Self {
+        Self { main, files: None }
+    }
+
+    /// Per-owner mode: owner data goes to files under `options.dir`, which is
+    /// created if missing.
+    pub fn per_owner(main: Database, options: OwnerFileOptions) -> DbResult<Self> {
+        std::fs::create_dir_all(&options.dir)?;
+        Ok(Self { main, files: Some(OwnerFiles { options, open: Mutex::new(HashMap::new()) }) })
+    }
+
+    pub fn is_per_owner(&self) -> bool {
+        self.files.is_some()
+    }
+
+    /// The main database: usage and budgets, and in shared mode everything.
+    pub fn main(&self) -> &Database {
+        &self.main
+    }
+
+    /// The database holding `owner_id`'s data, opening (and migrating) its
+    /// file on first use.
+    pub async fn owner(&self, owner_id: u64) -> DbResult<Database> {
+        let Some(files) = &self.files else {
+            return Ok(self.main.clone());
+        };
+        let slot = {
+            let mut open = files.open.lock().await;
+            open.entry(owner_id).or_insert_with(|| Arc::new(OwnerSlot::new())).clone()
+        };
+        // Only this owner's callers wait here, so a file is never opened twice
+        let db = slot.db.get_or_try_init(|| files.open_file(owner_id)).await?.clone();
+        slot.touch();
+        Ok(db)
+    }
+
+    /// Path of `owner_id`'s file; `None` in shared mode.
+    pub fn owner_path(&self, owner_id: u64) -> Option<PathBuf> {
+        self.files.as_ref().map(|f| owner_path(&f.options.dir, owner_id))
+    }
+
+    /// Owners that have a file, in ascending order. Empty in shared mode.
+    pub fn owner_ids(&self) -> DbResult<Vec<u64>> {
+        match &self.files {
+            Some(files) => Ok(list_owner_files(&files.options.dir)?.into_iter().map(|(id, _)| id).collect()),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Every owner file, opened (and so migrated). Empty in shared mode.
+    pub async fn owner_files(&self) -> DbResult<Vec<(u64, Database)>> {
+        let mut dbs = Vec::new();
+        for id in self.owner_ids()? {
+            dbs.push((id, self.owner(id).await?));
+        }
+        Ok(dbs)
+    }
+
+    /// Every database holding owner data, for jobs that sweep all owners:
+    /// the main database in shared mode, every owner file otherwise.
+    pub async fn owner_data(&self) -> DbResult<Vec<Database>> {
+        if self.files.is_none() {
+            return Ok(vec![self.main.clone()]);
+        }
+        Ok(self.owner_files().await?.into_iter().map(|(_, db)| db).collect())
+    }
+
+    /// Drop cached handles that have been idle past the configured limit and
+    /// are not held anywhere else, closing their connections. Returns how many.
+    pub async fn close_idle(&self) -> usize {
+        let Some(files) = &self.files else {
+            return 0;
+        };
+        let mut open = files.open.lock().await;
+        let mut closed = 0;
+        open.retain(|id, slot| {
+            // A slot still referenced elsewhere has a caller opening or using it
+            if Arc::strong_count(slot) > 1 {
+                return true;
+            }
+            let Some(db) = slot.db.get() else {
+                return false;
+            };
+            if slot.idle_for() < files.options.idle || Arc::strong_count(&db.pool) > 1 {
+                return true;
+            }
+            debug!("Closing idle database for owner {id}");
+            closed += 1;
+            false
+        });
+        closed
+    }
+
+    /// Number of owner files currently open.
+    pub async fn open_count(&self) -> usize {
+        match &self.files {
+            Some(files) => files.open.lock().await.values().filter(|slot| slot.db.initialized()).count(),
+            None => 0,
+        }
+    }
+
+    /// Per-owner mode: copy every owner's rows of the [`OWNER_TABLES`] (knowledge
+    /// base, chat history, settings, pending requests and the approval and
+    /// audit logs) from the main database into its own file, ids unchanged.
+    /// The main database is left as it was. Refuses before copying anything
+    /// if an owner's file already holds data.
+    pub async fn split_from_main(&self) -> DbResult<Vec<(u64, SplitStats)>> {
+        let mut results = Vec::new();
+        let Some(files) = &self.files else {
+            return Ok(results);
+        };
+        let owners = self.main.data_owner_ids().await?;
+        for &owner_id in &owners {
+            if let Some(table) = self.owner(owner_id).await?.first_owner_table_with_rows().await? {
+                return Err(DbError::Conflict(format!(
+                    "{} already has {table} rows; split-owners only fills new files.",
+                    owner_path(&files.options.dir, owner_id).display()
+                )));
+            }
+        }
+        for owner_id in owners {
+            let path = owner_path(&files.options.dir, owner_id);
+            let stats = self.main.copy_owner_into(owner_id, path, files.options.key.clone()).await?;
+            results.push((owner_id, stats));
+        }
+        Ok(results)
+    }
+
+    /// Snapshot the main database into `dir` and each owner file into
+    /// `dir/owner-{id}`, each with its own rotation. One failure does not stop
+    /// the others.
+    pub async fn backup_all(&self, dir: &Path, policy: RetentionPolicy) -> BackupRun {
+        let main = self.main.backup_now(dir, policy).await;
+        let owners = match self.owner_ids() {
+            Ok(ids) => {
+                let mut results = Vec::with_capacity(ids.len());
+                for id in ids {
+                    let result = match self.owner(id).await {
+                        Ok(db) => db.backup_now(&owner_backup_dir(dir, id), policy).await,
+                        Err(e) => Err(e.into()),
+                    };
+                    results.push((id, result));
+                }
+                Ok(results)
+            }
+            Err(e) => Err(e),
+        };
+        BackupRun { main, owners }
+    }
+}
+
+/// Results of [`OwnerDatabases::backup_all`].
+#[derive(Debug)]
+pub struct BackupRun {
+    pub main: Result<BackupReport, BackupError>,
+    /// One result per owner file, or why the owner files could not be listed.
+    pub owners: DbResult<Vec<(u64, Result<BackupReport, BackupError>)>>,
+}
+
+/// Rows copied per table by [`OwnerDatabases::split_from_main`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SplitStats {
+    pub tables: Vec<(&'static str, usize)>,
+}
+
+impl SplitStats {
+    pub fn rows(&self, table: &str) -> usize {
+        self.tables.iter().find(|(t, _)| *t == table).map_or(0, |(_, rows)| *rows)
+    }
+}
+
+impl std::fmt::Display for SplitStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let copied: Vec<String> =
+            self.tables.iter().filter(|(_, rows)| *rows > 0).map(|(table, rows)| format!("{rows} {table}")).collect();
+        if copied.is_empty() { f.write_str("nothing to copy") } else { f.write_str(&copied.join(", ")) }
+    }
+}
+
+impl Database {
+    /// Owners with any data in the [`OWNER_TABLES`], ascending.
+    pub async fn data_owner_ids(&self) -> DbResult<Vec<u64>> {
+        self.read(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT user_id FROM memory_facts UNION SELECT user_id FROM knowledge_documents
+                 UNION SELECT user_id FROM entities UNION SELECT user_id FROM categories
+                 UNION SELECT user_id FROM sessions UNION SELECT owner_id FROM session_retention
+                 UNION SELECT user_id FROM user_preferences UNION SELECT scope_id FROM pending_items
+                 UNION SELECT scope_id FROM approval_log UNION SELECT kb_owner_id FROM audit_log
+                 ORDER BY 1",
+            )?;
+            let rows = stmt.query_map([], |row| row.get::<_, i64>(0).map(|id| id as u64))?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await
+    }
+
+    /// The first of the [`OWNER_TABLES`] that has any rows, if one does.
+    async fn first_owner_table_with_rows(&self) -> DbResult<Option<&'static str>> {
+        self.read(|conn| {
+            for (table, _) in OWNER_TABLES {
+                let sql = format!("SELECT EXISTS (SELECT 1 FROM {table})");
+                if conn.query_row(&sql, [], |row| row.get::<_, bool>(0))? {
+                    return Ok(Some(*table));
+                }
+            }
+            Ok::<_, rusqlite::Error>(None)
+        })
+        .await
+    }
+
+    /// Copy `owner_id`'s rows of the [`OWNER_TABLES`] into the database file
+    /// at `path`, attached to the writer for one transaction.
+    async fn copy_owner_into(&self, owner_id: u64, path: PathBuf, key: Option<String>) -> DbResult<SplitStats> {
+        self.write(move |conn| {
+            let path = path.to_string_lossy().into_owned();
+            match &key {
+                Some(key) => conn.execute("ATTACH DATABASE ?1 AS owner KEY ?2", params![path, key])?,
+                None => conn.execute("ATTACH DATABASE ?1 AS owner", params![path])?,
+            };
+            let copied = copy_owner_tables(conn, owner_id);
+            conn.execute("DETACH DATABASE owner", [])?;
+            copied
+        })
+        .await
+    }
+}
+
+fn copy_owner_tables(conn: &mut Connection, owner_id: u64) -> rusqlite::Result<SplitStats> {
+    let tx = conn.transaction()?;
+    let mut stats = SplitStats::default();
+    for (table, filter) in OWNER_TABLES {
+        // Named columns: tables migrated in different orders need not line up
+        let columns = {
+            let mut stmt = tx.prepare(&format!("SELECT name FROM pragma_table_info('{table}', 'owner')"))?;
+            let names = stmt.query_map([], |row| row.get::<_, String>(0))?;
+            names.collect::<rusqlite::Result<Vec<_>>>()?.join(", ")
+        };
+        let rows = tx.execute(
+            &format!(
+                "INSERT INTO owner.{table} ({columns}) SELECT {columns} FROM main.{table} WHERE {filter} ORDER BY rowid"
+            ),
+            params![owner_id as i64],
+        )?;
+        stats.tables.push((table, rows));
+    }
+    tx.commit()?;
+    Ok(stats)
+}
+
+impl OwnerFiles {
+    async fn open_file(&self, owner_id: u64) -> DbResult<Database> {
+        let path = owner_path(&self.options.dir, owner_id);
+        let readers = self.options.read_connections;
+        let key = self.options.key.clone();
+        let db = tokio::task::spawn_blocking(move || {
+            Database::open_encrypted(&path.to_string_lossy(), readers, key.as_deref())
+        })
+        .await?
+        .map_err(DbError::from)?;
+        if db.set_trigram_index(self.options.fts_trigram).await? {
+            info!("Owner {owner_id}: trigram index {}", if self.options.fts_trigram { "built" } else { "dropped" });
+        }
+        let encoded = db.set_quantization(self.options.quantization).await?;
+        if encoded > 0 {
+            info!("Owner {owner_id}: {encoded} embedding(s) quantized as {}", self.options.quantization.as_str());
+        }
+        Ok(db)
+    }
+}
+
+/// `{dir}/owner-{id}.db`.
+pub fn owner_path(dir: &Path, owner_id: u64) -> PathBuf {
+    dir.join(format!("{FILE_PREFIX}{owner_id}{FILE_SUFFIX}"))
+}
+
+/// Backup directory for one owner's snapshots, inside the main backup directory.
+pub fn owner_backup_dir(dir: &Path, owner_id: u64) -> PathBuf {
+    dir.join(format!("{FILE_PREFIX}{owner_id}"))
+}
+
+/// `(owner, path)` of every owner file in `dir`, ascending by owner. Blocking.
+pub fn list_owner_files(dir: &Path) -> std::io::Result<Vec<(u64, PathBuf)>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if let Some(id) = entry.file_name().to_str().and_then(parse_file_name) {
+            files.push((id, entry.path()));
+        }
+    }
+    files.sort_unstable();
+    Ok(files)
+}
+
+fn parse_file_name(name: &str) -> Option<u64> {
+    name.strip_prefix(FILE_PREFIX)?.strip_suffix(FILE_SUFFIX)?.parse().ok()
+}
@@ -0,0 +1,89 @@
+use rusqlite::{Connection, OpenFlags};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::encryption::{self, EncryptionError};
+
+/// One writer connection plus a set of read-only WAL connections.
+///
+/// SQLite allows a single writer at a time, so all writes go through
+/// `writer`; reads are spread round-robin over `readers` and never wait
+/// on a write in progress (WAL readers see the last committed snapshot).
+/// In-memory databases cannot be shared between connections, so they get
+/// no readers and read through the writer.
+pub struct ConnectionPool {
+    writer: Mutex<Connection>,
+    readers: Vec<Mutex<Connection>>,
+    next_reader: AtomicUsize,
+    /// SQLCipher key every connection was opened with.
+    key: Option<String>,
+}
+
+impl ConnectionPool {
+    pub fn new(
+        writer: Connection,
+        path: &str,
+        reader_count: usize,
+        key: Option<&str>,
+    ) -> Result<Self, EncryptionError> {
+        let reader_count = if is_in_memory(path) { 0 } else { reader_count };
+        let mut readers = Vec::with_capacity(reader_count);
+        for _ in 0..reader_count {
+            let conn = Connection::open_with_flags(
+                path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY
+                    | OpenFlags::SQLITE_OPEN_URI
+                    | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            )?;
+            if let Some(key) = key {
+                encryption::apply_key(&conn, key)?;
+            }
+            conn.execute_batch("PRAGMA busy_timeout=5000; PRAGMA query_only=ON;")?;
+            readers.push(Mutex::new(conn));
+        }
+        Ok(Self {
+            writer: Mutex::new(writer),
+            readers,
+            next_reader: AtomicUsize::new(0),
+            key: key.map(str::to_string),
+        })
+    }
+
+    /// Number of dedicated read-only connections.
+    pub fn reader_count(&self) -> usize {
+        self.readers.len()
+    }
+
+    /// SQLCipher key, if the database is encrypted.
+    pub fn key(&self) -> Option<&str> {
+        self.key.as_deref()
+    }
+
+    /// Run `f` on the writer connection. Blocks — call from `spawn_blocking`.
+    pub fn with_writer<T>(&self, f: impl FnOnce(&mut Connection) -> T) -> T {
+        let mut conn = self.writer.lock().unwrap();
+        f(&mut conn)
+    }
+
+    /// Run `f` on an idle reader, or wait for the next one in rotation.
+    /// Blocks — call from `spawn_blocking`.
+    pub fn with_reader<T>(&self, f: impl FnOnce(&Connection) -> T) -> T {
+        if self.readers.is_empty() {
+            let conn = self.writer.lock().unwrap();
+            return f(&conn);
+        }
+        let start = self.next_reader.fetch_add(1, Ordering::Relaxed);
+        let len = self.readers.len();
+        for i in 0..len {
+            if let Ok(conn) = self.readers[(start + i) % len].try_lock() {
+                return f(&conn);
+            }
+        }
+        let conn = self.readers[start % len].lock().unwrap();
+        f(&conn)
+    }
+}
+
+fn is_in_memory(path: &str) -> bool {
+    path == ":memory:" || path.is_empty() || path.contains("mode=memory")
+}
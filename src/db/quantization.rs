@@ -0,0 +1,278 @@
+//! Quantized copies of embeddings (`EMBEDDING_QUANTIZATION`).
+//!
+//! Raw vectors stay in the `embedding` columns as little-endian f32s. With
+//! int8 or binary quantization on, each row also gets an `embedding_q` copy
+//! that the vector indexes load and traverse instead: int8 keeps one signed
+//! byte per dimension plus a scale (4x smaller), binary keeps the sign bit of
+//! each dimension (32x smaller, compared by Hamming distance). Searches then
+//! rerank their candidates by exact cosine similarity over the raw vectors.
+//!
+//! The mode in use is recorded in `vector_settings`; changing it re-encodes
+//! every row and drops the stored indexes, which were built for the old one.
+
+use std::str::FromStr;
+
+use rusqlite::{Connection, OptionalExtension, params};
+
+use super::{Database, DbResult};
+
+/// How the vector indexes hold embeddings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Quantization {
+    /// Full f32 vectors.
+    #[default]
+    None,
+    /// One signed byte per dimension, scaled per vector.
+    Int8,
+    /// One sign bit per dimension.
+    Binary,
+}
+
+impl Quantization {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Quantization::None => "none",
+            Quantization::Int8 => "int8",
+            Quantization::Binary => "binary",
+        }
+    }
+
+    /// `vector` in this mode, normalized first.
+    pub fn encode(self, vector: &[f32]) -> Vector {
+        let mut unit = vector.to_vec();
+        let norm = unit.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            for x in unit.iter_mut() {
+                *x /= norm;
+            }
+        }
+        match self {
+            Quantization::None => Vector::F32(unit),
+            Quantization::Int8 => {
+                let max = unit.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+                let scale = if max > 0.0 { max / 127.0 } else { 1.0 };
+                let values = unit.iter().map(|x| (x / scale).round().clamp(-127.0, 127.0) as i8).collect();
+                Vector::Int8 { scale, values }
+            }
+            Quantization::Binary => {
+                let mut bits = vec![0u64; unit.len().div_ceil(64)];
+                for (i, x) in unit.iter().enumerate() {
+                    if *x > 0.0 {
+                        bits[i / 64] |= 1 << (i % 64);
+                    }
+                }
+                Vector::Binary { dims: unit.len(), bits }
+            }
+        }
+    }
+}
+
+impl FromStr for Quantization {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "none" | "f32" | "off" => Ok(Quantization::None),
+            "int8" | "i8" => Ok(Quantization::Int8),
+            "binary" | "bit" | "1bit" => Ok(Quantization::Binary),
+            other => Err(format!("unknown embedding quantization '{other}' (expected none, int8 or binary)")),
+        }
+    }
+}
+
+/// A unit vector as a vector index holds it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Vector {
+    F32(Vec<f32>),
+    Int8 { scale: f32, values: Vec<i8> },
+    Binary { dims: usize, bits: Vec<u64> },
+}
+
+const INT8_TAG: u8 = 1;
+const BINARY_TAG: u8 = 2;
+
+impl Vector {
+    pub fn dims(&self) -> usize {
+        match self {
+            Vector::F32(values) => values.len(),
+            Vector::Int8 { values, .. } => values.len(),
+            Vector::Binary { dims, .. } => *dims,
+        }
+    }
+
+    pub fn quantization(&self) -> Quantization {
+        match self {
+            Vector::F32(_) => Quantization::None,
+            Vector::Int8 { .. } => Quantization::Int8,
+            Vector::Binary { .. } => Quantization::Binary,
+        }
+    }
+
+    /// Approximate cosine similarity; 0 between vectors of different modes.
+    /// Binary vectors map Hamming distance onto [-1, 1].
+    pub fn similarity(&self, other: &Vector) -> f32 {
+        match (self, other) {
+            (Vector::F32(a), Vector::F32(b)) => a.iter().zip(b).map(|(x, y)| x * y).sum(),
+            (Vector::Int8 { scale: sa, values: a }, Vector::Int8 { scale: sb, values: b }) => {
+                let dot: i32 = a.iter().zip(b).map(|(x, y)| *x as i32 * *y as i32).sum();
+                dot as f32 * sa * sb
+            }
+            (Vector::Binary { dims, bits: a }, Vector::Binary { bits: b, .. }) if *dims > 0 => {
+                let distance: u32 = a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum();
+                1.0 - 2.0 * distance as f32 / *dims as f32
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// The `embedding_q` blob: a mode tag, then int8 `scale` (f32) and one
+    /// byte per dimension, or binary `dims` (u32) and the sign bits.
+    /// `None` for f32 vectors, which are never stored twice.
+    pub fn to_bytes(&self) -> Option<Vec<u8>> {
+        match self {
+            Vector::F32(_) => None,
+            Vector::Int8 { scale, values } => {
+                let mut out = Vec::with_capacity(5 + values.len());
+                out.push(INT8_TAG);
+                out.extend_from_slice(&scale.to_le_bytes());
+                out.extend(values.iter().map(|v| *v as u8));
+                Some(out)
+            }
+            Vector::Binary { dims, bits } => {
+                let mut out = Vec::with_capacity(5 + dims.div_ceil(8));
+                out.push(BINARY_TAG);
+                out.extend_from_slice(&(*dims as u32).to_le_bytes());
+                let bytes = bits.iter().flat_map(|word| word.to_le_bytes());
+                out.extend(bytes.take(dims.div_ceil(8)));
+                Some(out)
+            }
+        }
+    }
+
+    /// Inverse of [`Vector::to_bytes`]; `None` if the blob is malformed.
+    pub fn from_bytes(blob: &[u8]) -> Option<Vector> {
+        let (&tag, rest) = blob.split_first()?;
+        let (header, body) = rest.split_first_chunk::<4>()?;
+        match tag {
+            INT8_TAG => Some(Vector::Int8 {
+                scale: f32::from_le_bytes(*header),
+                values: body.iter().map(|b| *b as i8).collect(),
+            }),
+            BINARY_TAG => {
+                let dims = u32::from_le_bytes(*header) as usize;
+                if body.len() != dims.div_ceil(8) {
+                    return None;
+                }
+                let bits = body
+                    .chunks(8)
+                    .map(|chunk| {
+                        let mut word = [0u8; 8];
+                        word[..chunk.len()].copy_from_slice(chunk);
+                        u64::from_le_bytes(word)
+                    })
+                    .collect();
+                Some(Vector::Binary { dims, bits })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Exact cosine similarity of two raw vectors, 0 if their lengths differ.
+pub fn exact_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let (mut dot, mut na, mut nb) = (0.0f32, 0.0f32, 0.0f32);
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        na += x * x;
+        nb += y * y;
+    }
+    let denom = na.sqrt() * nb.sqrt();
+    if denom == 0.0 { 0.0 } else { dot / denom }
+}
+
+/// Embeddings are stored as little-endian f32s.
+pub fn decode_f32(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+}
+
+/// The mode recorded in `vector_settings`.
+pub fn current(conn: &Connection) -> rusqlite::Result<Quantization> {
+    let mode: Option<String> = conn
+        .query_row("SELECT quantization FROM vector_settings WHERE id = 1", [], |row| row.get(0))
+        .optional()?;
+    Ok(mode.and_then(|m| m.parse().ok()).unwrap_or_default())
+}
+
+/// `embedding_q` for a raw `embedding` blob under `mode`.
+pub fn quantized_blob(mode: Quantization, embedding: &[u8]) -> Option<Vec<u8>> {
+    match mode {
+        Quantization::None => None,
+        mode => mode.encode(&decode_f32(embedding)).to_bytes(),
+    }
+}
+
+/// Fill `embedding_q` of every row whose copy is missing, under `mode`.
+/// Returns how many rows were encoded.
+fn encode_missing(conn: &Connection, mode: Quantization) -> rusqlite::Result<usize> {
+    let mut encoded = 0;
+    for table in ["memory_facts", "knowledge_chunks"] {
+        let rows: Vec<(i64, Vec<u8>)> = {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT id, embedding FROM {table} WHERE embedding IS NOT NULL AND embedding_q IS NULL"
+            ))?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+        let mut update = conn.prepare(&format!("UPDATE {table} SET embedding_q = ?1 WHERE id = ?2"))?;
+        for (id, embedding) in rows {
+            update.execute(params![quantized_blob(mode, &embedding), id])?;
+            encoded += 1;
+        }
+    }
+    Ok(encoded)
+}
+
+impl Database {
+    /// Switch the stored embeddings to `mode`: record it, (re-)encode every
+    /// row's `embedding_q` and drop the vector indexes so they are rebuilt
+    /// for it. With the mode unchanged, only rows missing their copy (saved
+    /// by an older binary, or imported) are encoded. Returns how many rows
+    /// were encoded.
+    pub async fn set_quantization(&self, mode: Quantization) -> DbResult<usize> {
+        let (changed, encoded) = self
+            .write(move |conn| {
+                let tx = conn.transaction()?;
+                let changed = current(&tx)? != mode;
+                if changed {
+                    tx.execute(
+                        "INSERT INTO vector_settings (id, quantization) VALUES (1, ?1)
+                         ON CONFLICT(id) DO UPDATE SET quantization = excluded.quantization",
+                        params![mode.as_str()],
+                    )?;
+                    tx.execute_batch(
+                        "UPDATE memory_facts SET embedding_q = NULL WHERE embedding_q IS NOT NULL;
+                         UPDATE knowledge_chunks SET embedding_q = NULL WHERE embedding_q IS NOT NULL;
+                         DELETE FROM vector_index_meta;
+                         DELETE FROM vector_index_nodes;
+                         DELETE FROM vector_index_log;",
+                    )?;
+                }
+                let encoded = if mode == Quantization::None { 0 } else { encode_missing(&tx, mode)? };
+                tx.commit()?;
+                Ok::<_, rusqlite::Error>((changed, encoded))
+            })
+            .await?;
+        if changed {
+            self.vectors.clear();
+        }
+        Ok(encoded)
+    }
+
+    /// The embedding quantization this database is set to.
+    pub async fn quantization(&self) -> DbResult<Quantization> {
+        self.read(current).await
+    }
+}
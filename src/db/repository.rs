@@ -0,0 +1,321 @@
+use super::error::DbResult;
+use super::models::*;
+
+/// Storage operations used by tools and the Telegram handler.
+///
+/// [`Database`](super::Database) is the SQLite implementation; an alternative
+/// backend (e.g. in-memory for tests) only needs to implement this trait.
+pub trait Repository: Send + Sync {
+    // --- Memory ---
+
+    fn save_fact(&self, user_id: u64, fact: &str, category: &str) -> impl Future<Output = DbResult<i64>> + Send;
+    /// FTS5 search (diacritic- and d/đ-insensitive, trigram fallback when
+    /// enabled) with LIKE fallback. Bumps `access_count` on every hit.
+    fn search_facts(&self, user_id: u64, keyword: &str) -> impl Future<Output = DbResult<Vec<Fact>>> + Send;
+    fn list_facts(&self, user_id: u64, category: Option<&str>) -> impl Future<Output = DbResult<Vec<Fact>>> + Send;
+    /// Edit a fact in place, recording the old text as a revision.
+    fn update_fact(
+        &self,
+        user_id: u64,
+        fact_id: i64,
+        new_fact: &str,
+        actor: Actor<'_>,
+    ) -> impl Future<Output = DbResult<bool>> + Send;
+    /// Move a fact to the trash.
+    fn delete_fact(&self, user_id: u64, fact_id: i64) -> impl Future<Output = DbResult<bool>> + Send;
+
+    // --- Fact Revisions ---
+
+    /// Move `old_id` to the trash as replaced by `new_id`, recording a revision.
+    /// Returns `false` if `old_id` is not a live fact of the user.
+    fn supersede_fact(
+        &self,
+        user_id: u64,
+        old_id: i64,
+        new_id: i64,
+        actor: Actor<'_>,
+    ) -> impl Future<Output = DbResult<bool>> + Send;
+    /// Revisions of a fact, including the ones where it replaced another fact. Newest first.
+    fn get_fact_history(&self, user_id: u64, fact_id: i64) -> impl Future<Output = DbResult<Vec<FactRevision>>> + Send;
+    /// Undo a revision: put back the old text (restoring the fact from the trash if needed).
+    /// For a supersede, the replacing fact is moved to the trash and its id returned.
+    fn revert_fact(
+        &self,
+        user_id: u64,
+        revision_id: i64,
+        actor: Actor<'_>,
+    ) -> impl Future<Output = DbResult<(Fact, Option<i64>)>> + Send;
+
+    // --- Fact Embeddings & Relations ---
+
+    fn update_fact_embedding(&self, fact_id: i64, embedding: &[u8]) -> impl Future<Output = DbResult<()>> + Send;
+    /// All embedded facts of a user, with their raw embedding bytes.
+    fn load_all_fact_embeddings(&self, user_id: u64) -> impl Future<Output = DbResult<Vec<(Fact, Vec<u8>)>>> + Send;
+    /// Link two facts with a similarity score (stored with the lower id first).
+    fn link_facts(&self, id_a: i64, id_b: i64, similarity: f32) -> impl Future<Output = DbResult<()>> + Send;
+    /// Facts related to `fact_id`, most similar first.
+    fn get_related_facts(&self, fact_id: i64) -> impl Future<Output = DbResult<Vec<(Fact, f64)>>> + Send;
+    fn delete_fact_relations(&self, fact_id: i64) -> impl Future<Output = DbResult<()>> + Send;
+    fn link_facts_batch(&self, links: &[(i64, i64, f32)]) -> impl Future<Output = DbResult<()>> + Send;
+    fn get_unembedded_facts(&self, user_id: u64) -> impl Future<Output = DbResult<Vec<Fact>>> + Send;
+    fn get_fact_user_ids(&self) -> impl Future<Output = DbResult<Vec<u64>>> + Send;
+
+    // --- Categories ---
+
+    fn ensure_default_categories(&self, user_id: u64) -> impl Future<Output = DbResult<()>> + Send;
+    fn list_categories(&self, user_id: u64) -> impl Future<Output = DbResult<Vec<String>>> + Send;
+    /// Fails with [`DbError::Conflict`](super::DbError::Conflict) if the category exists.
+    fn add_category(&self, user_id: u64, name: &str) -> impl Future<Output = DbResult<()>> + Send;
+    /// Fails with [`DbError::Conflict`](super::DbError::Conflict) while facts still use it.
+    fn delete_category(&self, user_id: u64, name: &str) -> impl Future<Output = DbResult<bool>> + Send;
+
+    // --- Memory ↔ KB Links ---
+
+    fn link_fact_to_doc(&self, fact_id: i64, doc_id: i64) -> impl Future<Output = DbResult<()>> + Send;
+    /// Documents linked to a fact, as (doc_id, title).
+    fn get_fact_links(&self, fact_id: i64) -> impl Future<Output = DbResult<Vec<(i64, String)>>> + Send;
+    fn get_doc_linked_facts(&self, doc_id: i64) -> impl Future<Output = DbResult<Vec<Fact>>> + Send;
+
+    // --- Conversation history ---
+
+    /// The owner's active session, created (and made active) if there is none.
+    fn get_or_create_session(&self, user_id: u64) -> impl Future<Output = DbResult<Session>> + Send;
+    /// Start a session and make it the owner's active one.
+    fn create_session(&self, user_id: u64, title: Option<&str>) -> impl Future<Output = DbResult<Session>> + Send;
+    /// Make one of the owner's sessions active. Fails with
+    /// [`DbError::NotFound`](super::DbError::NotFound) if the owner has no such session.
+    fn resume_session(&self, user_id: u64, session_id: &str) -> impl Future<Output = DbResult<Session>> + Send;
+    /// Id of the owner's active session, if one was chosen.
+    fn active_session_id(&self, user_id: u64) -> impl Future<Output = DbResult<Option<String>>> + Send;
+    /// The owner's sessions, most recently active first.
+    fn list_sessions(&self, user_id: u64, limit: usize) -> impl Future<Output = DbResult<Vec<Session>>> + Send;
+    fn set_session_title(&self, session_id: &str, title: &str) -> impl Future<Output = DbResult<()>> + Send;
+    /// The last `max_pairs` turns (each user message with the tool calls and
+    /// replies after it), oldest first.
+    fn load_history(&self, session_id: &str, max_pairs: usize) -> impl Future<Output = DbResult<Vec<SessionMessage>>> + Send;
+    /// Append a text-only row.
+    fn append_message(&self, session_id: &str, role: &str, content: &str) -> impl Future<Output = DbResult<()>> + Send;
+    /// Append rows in order, in one transaction.
+    fn append_messages(
+        &self,
+        session_id: &str,
+        messages: Vec<NewSessionMessage>,
+    ) -> impl Future<Output = DbResult<()>> + Send;
+
+    // --- Session Retention ---
+
+    /// Per-owner retention override, if one was set.
+    fn get_session_retention(&self, owner_id: u64) -> impl Future<Output = DbResult<Option<SessionRetention>>> + Send;
+    fn set_session_retention(&self, owner_id: u64, retention: SessionRetention) -> impl Future<Output = DbResult<()>> + Send;
+    /// Drop the override so the configured default applies again.
+    fn clear_session_retention(&self, owner_id: u64) -> impl Future<Output = DbResult<bool>> + Send;
+    /// Every session that still has messages.
+    fn list_sessions_with_messages(&self) -> impl Future<Output = DbResult<Vec<Session>>> + Send;
+    /// Messages of a session that fall outside `retention`, oldest first.
+    fn expired_session_messages(
+        &self,
+        session_id: &str,
+        retention: SessionRetention,
+    ) -> impl Future<Output = DbResult<Vec<SessionMessage>>> + Send;
+    /// Delete messages up to and including `up_to_id`, storing `summary` as the
+    /// session summary in the same transaction when given. Returns rows deleted.
+    fn prune_session_messages(
+        &self,
+        session_id: &str,
+        up_to_id: i64,
+        summary: Option<&str>,
+    ) -> impl Future<Output = DbResult<usize>> + Send;
+
+    // --- Knowledge Documents ---
+
+    fn save_document(
+        &self,
+        user_id: u64,
+        title: &str,
+        content: &str,
+        source: Option<&str>,
+        tags: Option<&str>,
+    ) -> impl Future<Output = DbResult<i64>> + Send;
+    fn search_documents(&self, user_id: u64, query: &str) -> impl Future<Output = DbResult<Vec<DocumentMatch>>> + Send;
+    fn list_documents(&self, user_id: u64) -> impl Future<Output = DbResult<Vec<DocumentSummary>>> + Send;
+    fn get_document(&self, user_id: u64, doc_id: i64) -> impl Future<Output = DbResult<Document>> + Send;
+    /// Move a document to the trash. Its chunks stay but are hidden from search.
+    fn delete_document(&self, user_id: u64, doc_id: i64) -> impl Future<Output = DbResult<bool>> + Send;
+    /// Find/replace text in a document and its chunks, storing the result as a new
+    /// version. Returns the affected chunk ids, whose embeddings are cleared for re-embedding.
+    fn patch_document(
+        &self,
+        user_id: u64,
+        doc_id: i64,
+        old_text: &str,
+        new_text: &str,
+        actor: Actor<'_>,
+    ) -> impl Future<Output = DbResult<Vec<i64>>> + Send;
+    /// Documents with no chunks yet (for migration).
+    fn get_unchunked_documents(&self) -> impl Future<Output = DbResult<Vec<Document>>> + Send;
+
+    // --- Document Versions ---
+
+    /// Stored versions of a document, newest first.
+    fn list_document_versions(&self, user_id: u64, doc_id: i64) -> impl Future<Output = DbResult<Vec<DocumentVersion>>> + Send;
+    fn get_document_version(
+        &self,
+        user_id: u64,
+        doc_id: i64,
+        version: i64,
+    ) -> impl Future<Output = DbResult<DocumentVersion>> + Send;
+    /// Restore the content of `version` as a new version. The document's chunks are
+    /// dropped and must be rebuilt by the caller. Returns the new version number.
+    fn rollback_document(
+        &self,
+        user_id: u64,
+        doc_id: i64,
+        version: i64,
+        actor: Actor<'_>,
+    ) -> impl Future<Output = DbResult<i64>> + Send;
+
+    // --- Knowledge Chunks ---
+
+    /// Save (chunk_index, start_line, end_line, content) rows. Returns the new chunk ids.
+    fn save_chunks(&self, doc_id: i64, chunks: &[(usize, usize, usize, &str)]) -> impl Future<Output = DbResult<Vec<i64>>> + Send;
+    fn update_chunk_embeddings(&self, chunk_ids: &[i64], embeddings: &[Vec<u8>]) -> impl Future<Output = DbResult<()>> + Send;
+    /// FTS5 phrase search over chunks, with the FTS rank (lower is better).
+    fn search_chunks_fts(&self, user_id: u64, query: &str) -> impl Future<Output = DbResult<Vec<(Chunk, f64)>>> + Send;
+    /// All embedded chunks of a user, with their raw embedding bytes.
+    fn load_all_embeddings(&self, user_id: u64) -> impl Future<Output = DbResult<Vec<(Chunk, Vec<u8>)>>> + Send;
+    fn get_chunk_content(&self, chunk_id: i64) -> impl Future<Output = DbResult<String>> + Send;
+
+    // --- Entities ---
+
+    /// Insert the entity if new. Returns its id either way.
+    fn save_entity(&self, user_id: u64, name: &str, entity_type: &str) -> impl Future<Output = DbResult<i64>> + Send;
+    fn add_entity_mention(
+        &self,
+        entity_id: i64,
+        source_type: &str,
+        source_id: i64,
+        context: Option<&str>,
+    ) -> impl Future<Output = DbResult<()>> + Send;
+    /// Entities whose name contains `query`, each with its latest mentions.
+    fn search_entities(
+        &self,
+        user_id: u64,
+        query: &str,
+    ) -> impl Future<Output = DbResult<Vec<(Entity, Vec<EntityMention>)>>> + Send;
+
+    // --- Trash ---
+
+    /// Soft-deleted facts and documents of a user, most recently deleted first.
+    fn list_trash(&self, user_id: u64) -> impl Future<Output = DbResult<Vec<TrashItem>>> + Send;
+    /// Move an item out of the trash. Returns `false` if it is not in the user's trash.
+    fn restore_from_trash(&self, user_id: u64, kind: TrashKind, id: i64) -> impl Future<Output = DbResult<bool>> + Send;
+    /// Permanently delete one trashed item, or the user's whole trash when `item` is `None`.
+    /// Returns the number of facts and documents removed.
+    fn purge_trash(&self, user_id: u64, item: Option<(TrashKind, i64)>) -> impl Future<Output = DbResult<usize>> + Send;
+    /// Permanently delete everything (of every user) trashed more than `retention_days` ago.
+    fn purge_expired_trash(&self, retention_days: u32) -> impl Future<Output = DbResult<usize>> + Send;
+
+    // --- Chat Preferences ---
+
+    /// Selected model for a chat, or the default model if none is set.
+    fn get_chat_model(&self, scope_id: u64) -> impl Future<Output = String> + Send;
+    fn set_chat_model(&self, scope_id: u64, model_id: &str) -> impl Future<Output = DbResult<()>> + Send;
+
+    // --- Pending Approval Queue ---
+
+    fn save_pending(
+        &self,
+        scope_id: u64,
+        requested_by: u64,
+        tool_name: &str,
+        args_json: &str,
+        summary: &str,
+    ) -> impl Future<Output = DbResult<i64>> + Send;
+    fn list_pending(&self, scope_id: u64) -> impl Future<Output = DbResult<Vec<PendingItem>>> + Send;
+    fn get_pending(&self, id: i64) -> impl Future<Output = DbResult<PendingItem>> + Send;
+    fn delete_pending(&self, id: i64) -> impl Future<Output = DbResult<bool>> + Send;
+    /// Remove a request from the queue and log `decision` for it, in one
+    /// transaction. Returns the request and its `approval_log` id, or `None`
+    /// if the scope has no such request younger than `ttl_hours` (0 = no
+    /// limit), so two admins cannot both act on one request and an expired one
+    /// waits for [`expire_pending`](Self::expire_pending).
+    fn take_pending(
+        &self,
+        scope_id: u64,
+        id: i64,
+        ttl_hours: u32,
+        decision: ApprovalDecision,
+        decided_by: u64,
+    ) -> impl Future<Output = DbResult<Option<(PendingItem, i64)>>> + Send;
+    /// Record what the tool of an approved request returned.
+    fn set_approval_result(&self, log_id: i64, result: &str) -> impl Future<Output = DbResult<()>> + Send;
+    /// Settled requests of a scope, newest first.
+    fn list_approvals(&self, scope_id: u64, limit: usize) -> impl Future<Output = DbResult<Vec<ApprovalRecord>>> + Send;
+    /// Remove requests older than `ttl_hours` and log them as expired, in one transaction.
+    fn expire_pending(&self, ttl_hours: u32) -> impl Future<Output = DbResult<Vec<PendingItem>>> + Send;
+    /// Requests admins have not been told about yet, marked as told.
+    fn claim_pending_notifications(&self) -> impl Future<Output = DbResult<Vec<PendingItem>>> + Send;
+
+    // --- Spend Budgets ---
+
+    /// Budget override for `subject`, if one was set with `/budget`.
+    fn get_spend_limits(&self, subject: BudgetSubject) -> impl Future<Output = DbResult<Option<SpendLimits>>> + Send;
+    fn set_spend_limits(&self, subject: BudgetSubject, limits: SpendLimits) -> impl Future<Output = DbResult<()>> + Send;
+    /// Drop the override so the configured default applies again.
+    fn clear_spend_limits(&self, subject: BudgetSubject) -> impl Future<Output = DbResult<bool>> + Send;
+    /// Usage attributed to `subject` since `since` (`YYYY-MM-DD`), grouped by model.
+    fn usage_since(&self, subject: BudgetSubject, since: &str) -> impl Future<Output = DbResult<Vec<ModelUsage>>> + Send;
+    /// Mark the alert for `subject` in `period` as sent. Returns false if it already was.
+    fn record_budget_alert(&self, subject: BudgetSubject, period: &str) -> impl Future<Output = DbResult<bool>> + Send;
+
+    // --- Usage Log ---
+
+    fn log_usage(&self, entry: &UsageEntry) -> impl Future<Output = DbResult<()>> + Send;
+    /// Usage for the current month, grouped by model.
+    fn get_monthly_usage(&self) -> impl Future<Output = DbResult<Vec<ModelUsage>>> + Send;
+    /// Usage for the current month, grouped by `group` and model.
+    fn get_monthly_usage_by(&self, group: UsageGroup) -> impl Future<Output = DbResult<Vec<GroupedUsage>>> + Send;
+    /// Raw usage rows logged at or after `since` (`YYYY-MM-DD`, `None` = all), oldest first.
+    fn list_usage(&self, since: Option<&str>) -> impl Future<Output = DbResult<Vec<UsageRecord>>> + Send;
+
+    // --- Memory context for system prompt ---
+
+    /// Render a user's facts for the system prompt: preferences first, then by category.
+    fn build_memory_context(&self, user_id: u64) -> impl Future<Output = String> + Send {
+        async move {
+            let facts = self.list_facts(user_id, None).await.unwrap_or_default();
+            if facts.is_empty() {
+                return String::new();
+            }
+
+            let mut grouped: std::collections::HashMap<String, Vec<String>> = std::collections::HashMap::new();
+            for fact in facts {
+                grouped.entry(fact.category).or_default().push(fact.text);
+            }
+
+            let mut ctx = String::new();
+
+            // "preference" category loaded first — communication rules, conventions, identity
+            if let Some(prefs) = grouped.remove("preference") {
+                ctx.push_str("\n--- CORE PREFERENCES (always active, never delete this category) ---\n");
+                for item in &prefs {
+                    ctx.push_str(&format!("- {item}\n"));
+                }
+                ctx.push_str("--- END CORE PREFERENCES ---\n");
+            }
+
+            // Other categories
+            if !grouped.is_empty() {
+                ctx.push_str("\n--- MEMORY ---\n");
+                for (cat, items) in &grouped {
+                    ctx.push_str(&format!("\n[{cat}]\n"));
+                    for item in items {
+                        ctx.push_str(&format!("- {item}\n"));
+                    }
+                }
+                ctx.push_str("\n--- END MEMORY ---\n");
+            }
+            ctx
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! CSV rendering of `usage_log` rows for `/cost export`.
+
+use super::models::UsageRecord;
+
+const CSV_HEADER: &str = "id,created_at,user_id,kb_owner_id,session_id,model,provider,\
+prompt_tokens,completion_tokens,cache_creation_tokens,cache_read_tokens,turns,tools,cost_usd";
+
+/// Render `records` as RFC 4180 CSV with a header row. Tools are joined
+/// with `;` and cost is computed from the model registry pricing.
+pub fn usage_csv(records: &[UsageRecord]) -> String {
+    let mut out = String::from(CSV_HEADER);
+    out.push_str("\r\n");
+    for r in records {
+        let u = &r.usage;
+        let fields = [
+            r.id.to_string(),
+            r.created_at.clone(),
+            r.user_id.map(|id| id.to_string()).unwrap_or_default(),
+            r.kb_owner_id.map(|id| id.to_string()).unwrap_or_default(),
+            r.session_id.clone().unwrap_or_default(),
+            u.model.clone(),
+            u.provider.clone(),
+            u.prompt_tokens.to_string(),
+            u.completion_tokens.to_string(),
+            u.cache_creation_tokens.to_string(),
+            u.cache_read_tokens.to_string(),
+            r.turns.to_string(),
+            r.tools.join(";"),
+            format!("{:.6}", u.cost_usd()),
+        ];
+        let line: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&line.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// Quote a field if it contains a delimiter, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,590 @@
+//! Persistent nearest-neighbour indexes over fact and chunk embeddings.
+//!
+//! Each owner has one [`Hnsw`] graph per [`VectorKind`]. The graph's links
+//! are stored in `vector_index_nodes`; vectors stay where they are, in the
+//! `embedding` columns. Triggers append the ids of rows whose vector was
+//! saved, replaced, trashed, restored or deleted to `vector_index_log`, and
+//! the next search applies those entries before it runs, so the index follows
+//! every write path without callers doing anything.
+//!
+//! A graph is built from scratch when its owner has none yet (or a build was
+//! interrupted), and loaded from the stored links otherwise. Loaded graphs are
+//! kept in memory by the [`Database`] handle until it is dropped.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+
+use rusqlite::{Connection, OptionalExtension, params, params_from_iter};
+use tracing::{info, warn};
+
+use super::hnsw::{Hnsw, HnswParams, StoredNode};
+use super::quantization::{self, Quantization, Vector, decode_f32, exact_similarity};
+use super::{Chunk, Database, DbResult, Fact, chunk_from_row, fact_from_row};
+
+/// Candidates fetched per wanted result when the index is quantized, to be
+/// reranked by exact similarity.
+const RERANK_FACTOR: usize = 4;
+
+/// What an index is built over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VectorKind {
+    /// `memory_facts.embedding` of facts not in the trash.
+    Fact,
+    /// `knowledge_chunks.embedding` of chunks whose document is not in the trash.
+    Chunk,
+}
+
+impl VectorKind {
+    pub const ALL: [VectorKind; 2] = [VectorKind::Fact, VectorKind::Chunk];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            VectorKind::Fact => "fact",
+            VectorKind::Chunk => "chunk",
+        }
+    }
+
+    /// `(id, embedding, embedding_q)` of every live vector of `?1`. The raw
+    /// embedding is only read for rows without a quantized copy.
+    fn vectors_sql(self) -> &'static str {
+        match self {
+            VectorKind::Fact => {
+                "SELECT id, CASE WHEN embedding_q IS NULL THEN embedding END, embedding_q FROM memory_facts
+                 WHERE user_id = ?1 AND embedding IS NOT NULL AND deleted_at IS NULL"
+            }
+            VectorKind::Chunk => {
+                "SELECT kc.id, CASE WHEN kc.embedding_q IS NULL THEN kc.embedding END, kc.embedding_q
+                 FROM knowledge_chunks kc
+                 JOIN knowledge_documents kd ON kc.doc_id = kd.id
+                 WHERE kd.user_id = ?1 AND kc.embedding IS NOT NULL AND kd.deleted_at IS NULL"
+            }
+        }
+    }
+
+    /// Like [`VectorKind::vectors_sql`], for the single row `?2`.
+    fn vector_sql(self) -> &'static str {
+        match self {
+            VectorKind::Fact => {
+                "SELECT id, CASE WHEN embedding_q IS NULL THEN embedding END, embedding_q FROM memory_facts
+                 WHERE user_id = ?1 AND embedding IS NOT NULL AND deleted_at IS NULL AND id = ?2"
+            }
+            VectorKind::Chunk => {
+                "SELECT kc.id, CASE WHEN kc.embedding_q IS NULL THEN kc.embedding END, kc.embedding_q
+                 FROM knowledge_chunks kc
+                 JOIN knowledge_documents kd ON kc.doc_id = kd.id
+                 WHERE kd.user_id = ?1 AND kc.embedding IS NOT NULL AND kd.deleted_at IS NULL AND kc.id = ?2"
+            }
+        }
+    }
+
+    /// Owners with at least one live vector.
+    fn owners_sql(self) -> &'static str {
+        match self {
+            VectorKind::Fact => {
+                "SELECT DISTINCT user_id FROM memory_facts WHERE embedding IS NOT NULL AND deleted_at IS NULL"
+            }
+            VectorKind::Chunk => {
+                "SELECT DISTINCT kd.user_id FROM knowledge_chunks kc
+                 JOIN knowledge_documents kd ON kc.doc_id = kd.id
+                 WHERE kc.embedding IS NOT NULL AND kd.deleted_at IS NULL"
+            }
+        }
+    }
+}
+
+/// Size of one owner's index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VectorIndexStats {
+    pub vectors: usize,
+    pub dims: usize,
+    /// Log entries not applied yet.
+    pub pending: usize,
+}
+
+type Slot = Arc<tokio::sync::Mutex<Option<Hnsw>>>;
+
+/// Graphs loaded by a [`Database`] handle, one lock per owner and kind so a
+/// build for one owner does not hold up searches of another.
+#[derive(Default)]
+pub(super) struct IndexCache {
+    slots: Mutex<HashMap<(u64, VectorKind), Slot>>,
+}
+
+impl IndexCache {
+    fn slot(&self, owner_id: u64, kind: VectorKind) -> Slot {
+        let mut slots = self.slots.lock().unwrap();
+        slots.entry((owner_id, kind)).or_default().clone()
+    }
+
+    /// Forget every loaded graph; the next search of each loads or builds it anew.
+    pub(super) fn clear(&self) {
+        self.slots.lock().unwrap().clear();
+    }
+}
+
+impl Database {
+    /// Facts of `owner_id` most similar to `query`, best first, with their
+    /// cosine similarity.
+    pub async fn nearest_facts(&self, owner_id: u64, query: &[f32], k: usize) -> DbResult<Vec<(Fact, f32)>> {
+        let (hits, mode) = self.vector_search(owner_id, VectorKind::Fact, query, k).await?;
+        let ids: Vec<i64> = hits.iter().map(|(id, _)| *id).collect();
+        let facts = self
+            .read(move |conn| {
+                let sql = format!(
+                    "SELECT id, fact, category, embedding FROM memory_facts
+                     WHERE user_id = ? AND deleted_at IS NULL AND embedding IS NOT NULL AND id IN ({})",
+                    placeholders(ids.len())
+                );
+                let mut stmt = conn.prepare(&sql)?;
+                let args = std::iter::once(owner_id as i64).chain(ids);
+                let rows = stmt.query_map(params_from_iter(args), |row| Ok((fact_from_row(row)?, row.get(3)?)))?;
+                rows.map(|r| r.map(|(fact, blob)| (fact.id, (fact, blob))))
+                    .collect::<rusqlite::Result<HashMap<_, _>>>()
+            })
+            .await?;
+        Ok(rerank(hits, facts, mode, query, k))
+    }
+
+    /// Chunks of `owner_id`'s documents most similar to `query`, best first,
+    /// with their cosine similarity.
+    pub async fn nearest_chunks(&self, owner_id: u64, query: &[f32], k: usize) -> DbResult<Vec<(Chunk, f32)>> {
+        let (hits, mode) = self.vector_search(owner_id, VectorKind::Chunk, query, k).await?;
+        let ids: Vec<i64> = hits.iter().map(|(id, _)| *id).collect();
+        let chunks = self
+            .read(move |conn| {
+                let sql = format!(
+                    "SELECT kc.id, kc.doc_id, kd.title, kc.content, kc.start_line, kc.end_line, kd.source, kc.embedding
+                     FROM knowledge_chunks kc
+                     JOIN knowledge_documents kd ON kc.doc_id = kd.id
+                     WHERE kd.user_id = ? AND kd.deleted_at IS NULL AND kc.embedding IS NOT NULL AND kc.id IN ({})",
+                    placeholders(ids.len())
+                );
+                let mut stmt = conn.prepare(&sql)?;
+                let args = std::iter::once(owner_id as i64).chain(ids);
+                let rows = stmt.query_map(params_from_iter(args), |row| Ok((chunk_from_row(row)?, row.get(7)?)))?;
+                rows.map(|r| r.map(|(chunk, blob)| (chunk.id, (chunk, blob))))
+                    .collect::<rusqlite::Result<HashMap<_, _>>>()
+            })
+            .await?;
+        Ok(rerank(hits, chunks, mode, query, k))
+    }
+
+    /// Build the stored index of every owner and kind that has vectors but no
+    /// usable index (never built, or a build was interrupted). Returns how many
+    /// were built. Built graphs are not kept in memory; the first search loads them.
+    pub async fn ensure_vector_indexes(&self) -> DbResult<usize> {
+        let mut built = 0;
+        for kind in VectorKind::ALL {
+            let owners = self
+                .read(move |conn| {
+                    let mut stmt = conn.prepare(kind.owners_sql())?;
+                    let rows = stmt.query_map([], |row| row.get::<_, i64>(0))?;
+                    rows.collect::<rusqlite::Result<Vec<_>>>()
+                })
+                .await?;
+            for owner_id in owners.into_iter().map(|id| id as u64) {
+                let slot = self.vectors.slot(owner_id, kind);
+                let guard = slot.lock().await;
+                if guard.is_some() || self.vector_index_ready(owner_id, kind).await? {
+                    continue;
+                }
+                self.build_vector_index(owner_id, kind).await?;
+                built += 1;
+            }
+        }
+        Ok(built)
+    }
+
+    /// Size of `owner_id`'s `kind` index, `None` if it has not been built.
+    pub async fn vector_index_stats(&self, owner_id: u64, kind: VectorKind) -> DbResult<Option<VectorIndexStats>> {
+        self.read(move |conn| {
+            let meta = conn
+                .query_row(
+                    "SELECT dims FROM vector_index_meta WHERE owner_id = ?1 AND kind = ?2 AND ready = 1",
+                    params![owner_id as i64, kind.as_str()],
+                    |row| row.get::<_, i64>(0),
+                )
+                .optional()?;
+            let Some(dims) = meta else {
+                return Ok(None);
+            };
+            let count = |table: &str| {
+                conn.query_row(
+                    &format!("SELECT COUNT(*) FROM {table} WHERE owner_id = ?1 AND kind = ?2"),
+                    params![owner_id as i64, kind.as_str()],
+                    |row| row.get::<_, i64>(0),
+                )
+            };
+            Ok::<_, rusqlite::Error>(Some(VectorIndexStats {
+                vectors: count("vector_index_nodes")? as usize,
+                dims: dims as usize,
+                pending: count("vector_index_log")? as usize,
+            }))
+        })
+        .await
+    }
+
+    /// The `k` nearest ids to `query`, after loading or building the index
+    /// and applying pending log entries.
+    ///
+    /// With quantization on, `k * RERANK_FACTOR` candidates are returned,
+    /// with approximate similarities, for the caller to rerank exactly.
+    async fn vector_search(
+        &self,
+        owner_id: u64,
+        kind: VectorKind,
+        query: &[f32],
+        k: usize,
+    ) -> DbResult<(Vec<(i64, f32)>, Quantization)> {
+        let slot = self.vectors.slot(owner_id, kind);
+        let mut guard = slot.lock().await;
+        let index = match guard.take() {
+            Some(index) => index,
+            None => self.load_vector_index(owner_id, kind).await?,
+        };
+        let index = match self.apply_vector_log(owner_id, kind, index).await? {
+            Some(index) => index,
+            None => self.build_vector_index(owner_id, kind).await?,
+        };
+        let mode = index.quantization();
+        let candidates = if mode == Quantization::None { k } else { k * RERANK_FACTOR };
+        let query = query.to_vec();
+        let (index, hits) = tokio::task::spawn_blocking(move || {
+            let hits = index.search(&query, candidates);
+            (index, hits)
+        })
+        .await?;
+        *guard = Some(index);
+        Ok((hits, mode))
+    }
+
+    async fn vector_index_ready(&self, owner_id: u64, kind: VectorKind) -> DbResult<bool> {
+        self.read(move |conn| {
+            conn.query_row(
+                "SELECT EXISTS (SELECT 1 FROM vector_index_meta WHERE owner_id = ?1 AND kind = ?2 AND ready = 1)",
+                params![owner_id as i64, kind.as_str()],
+                |row| row.get(0),
+            )
+        })
+        .await
+    }
+
+    /// Read the stored graph, or build one if there is none.
+    async fn load_vector_index(&self, owner_id: u64, kind: VectorKind) -> DbResult<Hnsw> {
+        let stored = self
+            .read(move |conn| {
+                let meta = conn
+                    .query_row(
+                        "SELECT dims, entry_id FROM vector_index_meta WHERE owner_id = ?1 AND kind = ?2 AND ready = 1",
+                        params![owner_id as i64, kind.as_str()],
+                        |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Option<i64>>(1)?)),
+                    )
+                    .optional()?;
+                let Some((dims, entry)) = meta else {
+                    return Ok(None);
+                };
+                let mut stmt = conn.prepare(
+                    "SELECT item_id, level, neighbors FROM vector_index_nodes WHERE owner_id = ?1 AND kind = ?2",
+                )?;
+                let rows = stmt.query_map(params![owner_id as i64, kind.as_str()], |row| {
+                    let blob: Vec<u8> = row.get(2)?;
+                    Ok(StoredNode { id: row.get(0)?, level: row.get::<_, i64>(1)? as usize, links: decode_links(&blob) })
+                })?;
+                let nodes = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+                let mode = quantization::current(conn)?;
+                let vectors = load_vectors(conn, owner_id, kind, mode)?;
+                Ok::<_, rusqlite::Error>(Some((dims as usize, nodes, vectors, entry, mode)))
+            })
+            .await?;
+        let Some((dims, nodes, vectors, entry, mode)) = stored else {
+            return self.build_vector_index(owner_id, kind).await;
+        };
+
+        let mut index =
+            tokio::task::spawn_blocking(move || Hnsw::restore(params(mode), dims, nodes, vectors, entry))
+                .await?;
+        // Rows missing from (or stale in) the stored graph were fixed while
+        // restoring; store the fix so the next load does not redo it
+        let changed = index.take_dirty();
+        if changed.is_empty() {
+            return Ok(index);
+        }
+        warn!("Vector index {owner_id}/{}: repaired {} stale node(s)", kind.as_str(), changed.len());
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            store_nodes(&tx, owner_id, kind, &index, &changed)?;
+            tx.commit()?;
+            Ok::<_, rusqlite::Error>(index)
+        })
+        .await
+    }
+
+    /// Build `owner_id`'s `kind` graph from every live vector and store it.
+    ///
+    /// The meta row is written (not ready) before the vectors are read, so
+    /// writes made during the build are logged and applied on top of it.
+    async fn build_vector_index(&self, owner_id: u64, kind: VectorKind) -> DbResult<Hnsw> {
+        let logged = self
+            .write(move |conn| {
+                conn.execute(
+                    "INSERT INTO vector_index_meta (owner_id, kind) VALUES (?1, ?2)
+                     ON CONFLICT(owner_id, kind) DO UPDATE SET ready = 0, updated_at = datetime('now')",
+                    params![owner_id as i64, kind.as_str()],
+                )?;
+                conn.query_row(
+                    "SELECT COALESCE(MAX(id), 0) FROM vector_index_log WHERE owner_id = ?1 AND kind = ?2",
+                    params![owner_id as i64, kind.as_str()],
+                    |row| row.get::<_, i64>(0),
+                )
+            })
+            .await?;
+        let (vectors, mode) = self
+            .read(move |conn| {
+                let mode = quantization::current(conn)?;
+                Ok::<_, rusqlite::Error>((load_vectors(conn, owner_id, kind, mode)?, mode))
+            })
+            .await?;
+
+        let started = std::time::Instant::now();
+        let (mut index, skipped) = tokio::task::spawn_blocking(move || {
+            let mut vectors: Vec<(i64, Vector)> = vectors.into_iter().collect();
+            vectors.sort_by_key(|(id, _)| *id);
+            let dims = most_common_dims(&vectors);
+            let mut index = Hnsw::new(params(mode));
+            let mut skipped = 0;
+            for (id, vector) in vectors {
+                if vector.dims() != dims || index.insert_vector(id, vector).is_err() {
+                    skipped += 1;
+                }
+            }
+            (index, skipped)
+        })
+        .await?;
+        if skipped > 0 {
+            warn!(
+                "Vector index {owner_id}/{}: left out {skipped} vector(s) of another length than the rest",
+                kind.as_str()
+            );
+        }
+        info!(
+            "Built vector index {owner_id}/{}: {} vectors in {:.1}s",
+            kind.as_str(),
+            index.len(),
+            started.elapsed().as_secs_f64()
+        );
+
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "DELETE FROM vector_index_nodes WHERE owner_id = ?1 AND kind = ?2",
+                params![owner_id as i64, kind.as_str()],
+            )?;
+            let ids = index.take_dirty();
+            store_nodes(&tx, owner_id, kind, &index, &ids)?;
+            tx.execute(
+                "UPDATE vector_index_meta SET ready = 1 WHERE owner_id = ?1 AND kind = ?2",
+                params![owner_id as i64, kind.as_str()],
+            )?;
+            tx.execute(
+                "DELETE FROM vector_index_log WHERE owner_id = ?1 AND kind = ?2 AND id <= ?3",
+                params![owner_id as i64, kind.as_str(), logged],
+            )?;
+            tx.commit()?;
+            Ok::<_, rusqlite::Error>(index)
+        })
+        .await
+    }
+
+    /// Apply and clear the pending log entries. `None` if a logged vector
+    /// does not fit the graph (the embedding model changed), in which case
+    /// the index has to be built again.
+    async fn apply_vector_log(&self, owner_id: u64, kind: VectorKind, mut index: Hnsw) -> DbResult<Option<Hnsw>> {
+        let pending = self
+            .read(move |conn| {
+                conn.query_row(
+                    "SELECT EXISTS (SELECT 1 FROM vector_index_log WHERE owner_id = ?1 AND kind = ?2)",
+                    params![owner_id as i64, kind.as_str()],
+                    |row| row.get::<_, bool>(0),
+                )
+            })
+            .await?;
+        if !pending {
+            return Ok(Some(index));
+        }
+
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let (last, ids) = {
+                let mut stmt = tx.prepare("SELECT id, item_id FROM vector_index_log WHERE owner_id = ?1 AND kind = ?2")?;
+                let rows = stmt.query_map(params![owner_id as i64, kind.as_str()], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
+                })?;
+                let mut last = 0;
+                let mut ids = BTreeSet::new();
+                for row in rows {
+                    let (id, item_id) = row?;
+                    last = last.max(id);
+                    ids.insert(item_id);
+                }
+                (last, ids)
+            };
+            let mode = quantization::current(&tx)?;
+            if mode != index.quantization() {
+                return Ok(None);
+            }
+            let sql = kind.vector_sql();
+            for id in ids {
+                let vector = tx
+                    .query_row(sql, params![owner_id as i64, id], |row| row_vector(row, mode))
+                    .optional()?
+                    .flatten();
+                match vector {
+                    Some(vector) => {
+                        if index.insert_vector(id, vector).is_err() {
+                            return Ok(None);
+                        }
+                    }
+                    None => {
+                        index.remove(id);
+                    }
+                }
+            }
+            let changed = index.take_dirty();
+            store_nodes(&tx, owner_id, kind, &index, &changed)?;
+            tx.execute(
+                "DELETE FROM vector_index_log WHERE owner_id = ?1 AND kind = ?2 AND id <= ?3",
+                params![owner_id as i64, kind.as_str(), last],
+            )?;
+            tx.commit()?;
+            Ok::<_, rusqlite::Error>(Some(index))
+        })
+        .await
+    }
+}
+
+/// Write the nodes of `ids` as they are in `index` (deleting those no longer
+/// in it) and the graph's entry point and dimensions.
+fn store_nodes(conn: &Connection, owner_id: u64, kind: VectorKind, index: &Hnsw, ids: &[i64]) -> rusqlite::Result<()> {
+    let mut upsert = conn.prepare(
+        "INSERT INTO vector_index_nodes (owner_id, kind, item_id, level, neighbors) VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(owner_id, kind, item_id) DO UPDATE SET level = excluded.level, neighbors = excluded.neighbors",
+    )?;
+    let mut delete =
+        conn.prepare("DELETE FROM vector_index_nodes WHERE owner_id = ?1 AND kind = ?2 AND item_id = ?3")?;
+    for &id in ids {
+        match index.links(id) {
+            Some(node) => {
+                upsert.execute(params![owner_id as i64, kind.as_str(), id, node.level as i64, encode_links(&node.links)])?;
+            }
+            None => {
+                delete.execute(params![owner_id as i64, kind.as_str(), id])?;
+            }
+        }
+    }
+    conn.execute(
+        "UPDATE vector_index_meta SET dims = ?3, entry_id = ?4, updated_at = datetime('now')
+         WHERE owner_id = ?1 AND kind = ?2",
+        params![owner_id as i64, kind.as_str(), index.dims() as i64, index.entry_id()],
+    )?;
+    Ok(())
+}
+
+/// Pair `hits` with their live rows. The similarities of a quantized index
+/// are approximate, so they are recomputed over the raw embeddings and the
+/// candidates re-sorted before keeping the best `k`.
+fn rerank<T>(
+    hits: Vec<(i64, f32)>,
+    mut rows: HashMap<i64, (T, Vec<u8>)>,
+    mode: Quantization,
+    query: &[f32],
+    k: usize,
+) -> Vec<(T, f32)> {
+    let mut found: Vec<(T, f32)> = hits
+        .into_iter()
+        .filter_map(|(id, sim)| {
+            let (item, blob) = rows.remove(&id)?;
+            match mode {
+                Quantization::None => Some((item, sim)),
+                _ => Some((item, exact_similarity(query, &decode_f32(&blob)))),
+            }
+        })
+        .collect();
+    if mode != Quantization::None {
+        found.sort_by(|a, b| b.1.total_cmp(&a.1));
+        found.truncate(k);
+    }
+    found
+}
+
+fn params(mode: Quantization) -> HnswParams {
+    HnswParams { quantization: mode, ..HnswParams::default() }
+}
+
+fn load_vectors(
+    conn: &Connection,
+    owner_id: u64,
+    kind: VectorKind,
+    mode: Quantization,
+) -> rusqlite::Result<HashMap<i64, Vector>> {
+    let mut stmt = conn.prepare(kind.vectors_sql())?;
+    let rows = stmt.query_map(params![owner_id as i64], |row| Ok((row.get::<_, i64>(0)?, row_vector(row, mode)?)))?;
+    let mut vectors = HashMap::new();
+    for row in rows {
+        if let (id, Some(vector)) = row? {
+            vectors.insert(id, vector);
+        }
+    }
+    Ok(vectors)
+}
+
+/// The vector of a `(id, embedding, embedding_q)` row in `mode`: the stored
+/// copy if it is in that mode, else the raw embedding encoded on the spot.
+fn row_vector(row: &rusqlite::Row, mode: Quantization) -> rusqlite::Result<Option<Vector>> {
+    let quantized: Option<Vec<u8>> = row.get(2)?;
+    if let Some(vector) = quantized.as_deref().and_then(Vector::from_bytes)
+        && vector.quantization() == mode
+    {
+        return Ok(Some(vector));
+    }
+    let raw: Option<Vec<u8>> = row.get(1)?;
+    Ok(raw.map(|blob| mode.encode(&decode_f32(&blob))))
+}
+
+/// The vector length most rows have; the others were embedded by another model.
+fn most_common_dims(vectors: &[(i64, Vector)]) -> usize {
+    let mut counts: HashMap<usize, usize> = HashMap::new();
+    for (_, vector) in vectors {
+        *counts.entry(vector.dims()).or_default() += 1;
+    }
+    counts.into_iter().max_by_key(|&(dims, count)| (count, dims)).map_or(0, |(dims, _)| dims)
+}
+
+/// Per level: a little-endian u32 count, then that many i64 ids.
+fn encode_links(levels: &[Vec<i64>]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(levels.iter().map(|l| 4 + l.len() * 8).sum());
+    for level in levels {
+        out.extend_from_slice(&(level.len() as u32).to_le_bytes());
+        for id in level {
+            out.extend_from_slice(&id.to_le_bytes());
+        }
+    }
+    out
+}
+
+/// Inverse of [`encode_links`]; a truncated blob yields the levels read so far.
+fn decode_links(mut blob: &[u8]) -> Vec<Vec<i64>> {
+    let mut levels = Vec::new();
+    while let Some((count, rest)) = blob.split_first_chunk::<4>() {
+        let count = u32::from_le_bytes(*count) as usize;
+        let Some(ids) = rest.get(..count * 8) else {
+            break;
+        };
+        levels.push(ids.chunks_exact(8).map(|b| i64::from_le_bytes(b.try_into().unwrap())).collect());
+        blob = &rest[count * 8..];
+    }
+    levels
+}
+
+fn placeholders(n: usize) -> String {
+    vec!["?"; n].join(", ")
+}
@@ -1,8 +1,9 @@
 pub mod db;
 pub mod tools;
 pub mod config;
+pub mod cli;
 pub mod telegram;
+pub mod agent;
+pub mod provider;
 
-mod agent;
-mod provider;
 mod skills;
@@ -9,6 +9,15 @@ async fn main() {
         )
         .init();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(result) = memory_assistant::cli::run(&args).await {
+        if let Err(e) = result {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let config = Config::from_env();
 
     tracing::info!("Memory Assistant v{}", env!("CARGO_PKG_VERSION"));
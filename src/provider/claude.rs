@@ -8,6 +8,12 @@ pub struct ClaudeProvider {
     client: Client,
 }
 
+impl Default for ClaudeProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ClaudeProvider {
     pub fn new() -> Self {
         Self {
@@ -23,10 +23,20 @@ pub enum Role {
     Tool,
 }
 
+/// Untagged, so stored history is read back by trying variants in order:
+/// `ToolResultWithImage` must stay ahead of `ImageWithText`, whose fields it
+/// is a superset of.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum MessageContent {
     Text(String),
+    ToolResultWithImage {
+        tool_call_id: String,
+        name: String,
+        text: String,
+        image_base64: String,
+        media_type: String,
+    },
     ImageWithText {
         text: String,
         /// Base64-encoded image data
@@ -44,13 +54,6 @@ pub enum MessageContent {
         name: String,
         content: String,
     },
-    ToolResultWithImage {
-        tool_call_id: String,
-        name: String,
-        text: String,
-        image_base64: String,
-        media_type: String,
-    },
     AssistantWithToolCalls {
         text: Option<String>,
         tool_calls: Vec<ToolCall>,
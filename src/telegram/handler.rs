@@ -3,16 +3,21 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicI64, Ordering};
 use teloxide::prelude::*;
-use teloxide::types::{BotCommand, ChatAction, ParseMode};
+use teloxide::types::{BotCommand, ChatAction, InlineKeyboardButton, InlineKeyboardMarkup, ParseMode};
 use tokio::sync::Mutex as TokioMutex;
-use tracing::{error, info};
+use tracing::{debug, error, info, warn};
 
-use crate::agent::{AgentLoop, AgentProgress};
+use crate::agent::approval::{ApprovalPolicy, approve_pending, reject_pending};
+use crate::agent::{history, AgentLoop, AgentProgress};
 use crate::config::Config;
-use crate::db::Database;
+use crate::db::owners::{OwnerDatabases, OwnerFileOptions};
+use crate::db::{BudgetSubject, Database, Repository, UsageEntry, UsageGroup};
 use crate::provider::{Message, MessageContent, ProviderPool, Role};
 use crate::skills;
-use crate::tools::EmbeddingClient;
+use crate::tools::embedding::{CachedEmbedder, DisabledEmbedder};
+use crate::tools::{BudgetDecision, BudgetUsage, Embedder};
+use crate::tools::file_crypto::FileCipher;
+use crate::tools::uploads::save_file_to_disk;
 
 use super::formatter;
 
@@ -33,12 +38,15 @@ struct MediaGroupData {
 
 struct AppState {
     pool: ProviderPool,
-    db: Database,
+    /// Main database (usage, budgets) and the per-owner files, if enabled.
+    dbs: OwnerDatabases,
     config: Config,
     base_prompt: String,
     telegram_token: String,
     bot_username: String,
-    embedding_client: Option<EmbeddingClient>,
+    embedder: Box<dyn Embedder>,
+    /// Seals uploads saved to disk when `ENCRYPTION_KEY` is set.
+    file_cipher: Option<FileCipher>,
     media_groups: TokioMutex<HashMap<String, MediaGroupData>>,
 }
 
@@ -53,18 +61,63 @@ pub async fn run_bot(config: Config) {
         config.deepseek_api_key.clone(),
     );
 
-    let db = Database::open("memory-assistant.db").expect("Failed to open database");
-
-    // Init embedding client if VOYAGE_API_KEY is set
-    let embedding_client = config.voyage_api_key.as_ref().map(|key| {
-        info!("Voyage AI embedding client initialized (model: {})", config.voyage_model);
-        EmbeddingClient::new(key.clone(), config.voyage_model.clone())
-    });
-
-    let embedding_status = if embedding_client.is_some() {
-        format!("Voyage AI ({}) — ACTIVE", config.voyage_model)
+    let db = Database::open_encrypted(
+        crate::db::DEFAULT_DB_PATH,
+        config.db_read_connections,
+        config.encryption_key.as_deref(),
+    )
+    .expect("Failed to open database");
+    match db.set_trigram_index(config.fts_trigram).await {
+        Ok(true) => info!("Trigram search index {}", if config.fts_trigram { "built" } else { "dropped" }),
+        Ok(false) => {}
+        Err(e) => error!("Failed to update trigram search index: {e}"),
+    }
+    let quantization = config.embedding_quantization;
+    match db.set_quantization(quantization).await {
+        Ok(0) => {}
+        Ok(n) => info!("Quantized {n} embedding(s) as {}", quantization.as_str()),
+        Err(e) => error!("Failed to quantize embeddings: {e}"),
+    }
+    let dbs = if config.db_per_owner {
+        let options = OwnerFileOptions {
+            dir: config.db_data_dir.clone().into(),
+            read_connections: config.db_read_connections,
+            key: config.encryption_key.clone(),
+            fts_trigram: config.fts_trigram,
+            quantization,
+            idle: std::time::Duration::from_secs(config.db_idle_minutes * 60),
+        };
+        let dbs = OwnerDatabases::per_owner(db, options).expect("Failed to create DB_DATA_DIR");
+        // Bring every owner file's schema up to date now rather than on first use
+        let files = dbs.owner_files().await.expect("Failed to open owner databases");
+        info!("Per-owner databases: {} file(s) in {}", files.len(), config.db_data_dir);
+        dbs
     } else {
-        "DISABLED (no API key)".to_string()
+        OwnerDatabases::shared(db)
+    };
+    let file_cipher = config
+        .encryption_key
+        .as_deref()
+        .map(|key| FileCipher::for_dir(key, &crate::tools::uploads::documents_dir()))
+        .transpose()
+        .expect("Failed to load the file encryption salt");
+
+    // Init the embedding backend, behind the cache in the main database
+    let embedder: Box<dyn Embedder> = match &config.embedding {
+        Some(backend) => {
+            info!("Embedding backend initialized: {}", backend.describe());
+            Box::new(CachedEmbedder::new(backend.build(), dbs.main().clone()))
+        }
+        None => Box::new(DisabledEmbedder),
+    };
+    // Vectors from another model are cleared here and re-embedded by the migrations below
+    if embedder.is_enabled() {
+        check_embedding_model(&dbs, embedder.as_ref()).await;
+    }
+
+    let embedding_status = match &config.embedding {
+        Some(backend) => format!("{} — ACTIVE", backend.describe()),
+        None => "DISABLED (no embedding backend configured)".to_string(),
     };
 
     let base_prompt = format!("\
@@ -313,8 +366,16 @@ Examples:
   Use when updating an existing fact by ID.
   The assistant must not claim the memory was updated unless `memory_edit` succeeded.
 
+- memory_history
+  Use to see how a fact changed over time (edits and replacements by memory_save).
+
+- memory_revert
+  Use to undo a wrong edit or replacement, with a revision ID from memory_history.
+  The assistant must not claim the fact was restored unless `memory_revert` succeeded.
+
 - memory_delete
   Use only when explicitly requested by the user.
+  Deleted facts go to the trash and can be restored with trash_restore.
   The assistant must not claim deletion unless `memory_delete` succeeded.
 
 - category_list
@@ -351,6 +412,16 @@ Examples:
   Do NOT overwrite the entire document unless necessary.
   The assistant must not claim the document was updated unless `knowledge_patch` succeeded.
 
+- knowledge_versions
+  Use to see the edit history of a document (every save, patch and rollback is a version).
+
+- knowledge_diff
+  Use to show what changed between two versions of a document.
+
+- knowledge_rollback
+  Use to restore an earlier version when a patch was wrong.
+  The assistant must not claim the document was restored unless `knowledge_rollback` succeeded.
+
 
 - knowledge_delete
   Use only when explicitly requested by the user or when cleaning invalid data.
@@ -358,6 +429,20 @@ Examples:
 
 ---
 
+### TRASH
+
+- trash_list
+  Use when the user asks what was deleted, or wants to recover something.
+
+- trash_restore
+  Use to bring back a deleted fact or document (type + ID from trash_list).
+
+- trash_purge
+  Permanently deletes trashed items. Use only when explicitly requested.
+  Without type/id it empties the whole trash.
+
+---
+
 ### ENTITY & RELATIONSHIPS
 
 - entity_search
@@ -537,12 +622,13 @@ Accuracy, consistency, and trust are more important than verbosity.");
 
     let state = Arc::new(AppState {
         pool,
-        db,
+        dbs,
         config: config.clone(),
         base_prompt,
         telegram_token: config.telegram_bot_token.clone(),
         bot_username,
-        embedding_client,
+        embedder,
+        file_cipher,
         media_groups: TokioMutex::new(HashMap::new()),
     });
 
@@ -562,6 +648,70 @@ Accuracy, consistency, and trust are more important than verbosity.");
         });
     }
 
+    // Build the vector indexes of owners that have embeddings but no index yet
+    if state.embedder.is_enabled() {
+        let state_clone = state.clone();
+        tokio::spawn(async move {
+            build_vector_indexes(&state_clone).await;
+        });
+    }
+
+    // Purge trash past its retention period, at startup and then hourly
+    if config.trash_retention_days > 0 {
+        let state_clone = state.clone();
+        tokio::spawn(async move {
+            purge_expired_trash(&state_clone).await;
+        });
+    }
+
+    // Expire undecided pending requests, at startup and then hourly
+    if config.pending_ttl_hours > 0 {
+        let (bot_clone, state_clone) = (bot.clone(), state.clone());
+        tokio::spawn(async move {
+            expire_pending_requests(&bot_clone, &state_clone).await;
+        });
+    }
+
+    // Prune chat history past its retention, at startup and then hourly
+    {
+        let state_clone = state.clone();
+        tokio::spawn(async move {
+            prune_session_history(&state_clone).await;
+        });
+    }
+
+    // Drop entities nobody mentions any more, at startup and then daily
+    {
+        let state_clone = state.clone();
+        tokio::spawn(async move {
+            collect_orphan_entities(&state_clone).await;
+        });
+    }
+
+    // Write embedding cache hit counts every few minutes and prune the cache
+    if state.embedder.is_enabled() {
+        let state_clone = state.clone();
+        tokio::spawn(async move {
+            maintain_embedding_cache(&state_clone).await;
+        });
+    }
+
+    // Close owner files nobody has used for DB_IDLE_MINUTES
+    if state.dbs.is_per_owner() {
+        let state_clone = state.clone();
+        tokio::spawn(async move {
+            close_idle_databases(&state_clone).await;
+        });
+    }
+
+    // Scheduled snapshots into BACKUP_DIR
+    if config.backup_interval_hours > 0 {
+        let state_clone = state.clone();
+        tokio::spawn(async move {
+            scheduled_backups(&state_clone).await;
+        });
+    }
+
     info!(
         "Memory Assistant bot started. Allowed users: {:?}, Allowed groups: {:?}",
         config.allowed_users, config.allowed_groups
@@ -572,7 +722,16 @@ Accuracy, consistency, and trust are more important than verbosity.");
         BotCommand::new("start", "Bot info & status"),
         BotCommand::new("help", "Show available commands"),
         BotCommand::new("memory", "View saved memories"),
+        BotCommand::new("new", "Start a new conversation"),
+        BotCommand::new("sessions", "List recent conversations"),
+        BotCommand::new("resume", "Switch back to a conversation"),
         BotCommand::new("model", "Switch AI model"),
+        BotCommand::new("export", "Export the knowledge base"),
+        BotCommand::new("backup", "Snapshot the database or list backups"),
+        BotCommand::new("retention", "Chat history retention for this chat"),
+        BotCommand::new("budget", "Spend budgets for this chat and you"),
+        BotCommand::new("doctor", "Check the knowledge graph and database for problems"),
+        BotCommand::new("undo", "Undo your last change to the knowledge base"),
         BotCommand::new("pending", "View pending approval requests"),
         BotCommand::new("approve", "Approve a pending request"),
         BotCommand::new("reject", "Reject a pending request"),
@@ -583,7 +742,9 @@ Accuracy, consistency, and trust are more important than verbosity.");
         info!("Bot commands menu registered");
     }
 
-    let handler = Update::filter_message().endpoint(handle_message);
+    let handler = dptree::entry()
+        .branch(Update::filter_message().endpoint(handle_message))
+        .branch(Update::filter_callback_query().endpoint(handle_callback_query));
 
     Dispatcher::builder(bot, handler)
         .dependencies(dptree::deps![state])
@@ -629,77 +790,6 @@ fn resolve_saved_path(saved_path: Option<PathBuf>, kb_owner_id: u64, file_name:
         })
 }
 
-/// Save uploaded file to ~/documents/{user_id}/{file_name}.
-/// Creates directories if needed. Skips if identical content already saved.
-/// Adds _1, _2... suffix if same name but different content.
-async fn save_file_to_disk(user_id: u64, file_name: &str, data: &[u8]) -> Option<PathBuf> {
-    let home = match std::env::var("HOME") {
-        Ok(h) => h,
-        Err(_) => return None,
-    };
-    let dir = PathBuf::from(&home).join("documents").join(user_id.to_string());
-
-    // Dedup: hash content, use .checksums/ dir with atomic create_new
-    use std::hash::{Hash, Hasher};
-    let mut hasher = std::collections::hash_map::DefaultHasher::new();
-    data.hash(&mut hasher);
-    let hash_hex = format!("{:016x}", hasher.finish());
-
-    let checksums_dir = dir.join(".checksums");
-    if let Err(e) = tokio::fs::create_dir_all(&checksums_dir).await {
-        error!("Failed to create checksums dir: {e}");
-        return None;
-    }
-
-    match tokio::fs::OpenOptions::new()
-        .write(true)
-        .create_new(true)
-        .open(checksums_dir.join(&hash_hex))
-        .await
-    {
-        Ok(_) => {} // New content, proceed to save
-        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
-            info!("Duplicate file skipped: {file_name} (hash {hash_hex})");
-            return None;
-        }
-        Err(_) => {} // Can't check, save anyway
-    }
-
-    let path = Path::new(file_name);
-    let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
-    let ext = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
-
-    let mut dest = dir.join(file_name);
-    let mut counter = 1u32;
-    loop {
-        match tokio::fs::OpenOptions::new()
-            .write(true)
-            .create_new(true)
-            .open(&dest)
-            .await
-        {
-            Ok(file) => {
-                use tokio::io::AsyncWriteExt;
-                let mut file = file;
-                if let Err(e) = file.write_all(data).await {
-                    error!("Failed to write file {}: {e}", dest.display());
-                    return None;
-                }
-                info!("File saved: {}", dest.display());
-                return Some(dest);
-            }
-            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
-                dest = dir.join(format!("{stem}_{counter}{ext}"));
-                counter += 1;
-            }
-            Err(e) => {
-                error!("Failed to create file {}: {e}", dest.display());
-                return None;
-            }
-        }
-    }
-}
-
 /// Get display name for a Telegram user
 fn get_display_name(user: &teloxide::types::User) -> String {
     if let Some(last) = &user.last_name {
@@ -846,7 +936,7 @@ async fn handle_photo(
 
     // Save to disk (group files → shared dir, private → personal dir)
     let photo_name = file_path.rsplit('/').next().unwrap_or("photo.jpg");
-    let saved_path = save_file_to_disk(kb_owner_id, photo_name, &image_bytes).await;
+    let saved_path = save_file_to_disk(kb_owner_id, photo_name, &image_bytes, state.file_cipher.as_ref()).await;
 
     let media_type = detect_media_type(file_path, photo_name);
 
@@ -960,7 +1050,7 @@ async fn handle_document(
     };
 
     // Save to disk (group files → shared dir, private → personal dir)
-    save_file_to_disk(kb_owner_id, file_name, &file_bytes).await;
+    save_file_to_disk(kb_owner_id, file_name, &file_bytes, state.file_cipher.as_ref()).await;
 
     if is_image {
         // Handle as image
@@ -1097,6 +1187,19 @@ async fn run_agent_with_direct_content(
     run_agent_and_respond_inner(msg.chat.id, bot, state, user_id, history_text, user_content, true).await
 }
 
+/// This chat's database (see [`OwnerDatabases::owner`]). If it cannot be
+/// opened the chat is told and `None` returned.
+async fn chat_db(bot: &Bot, chat_id: ChatId, state: &AppState, kb_owner_id: u64) -> ResponseResult<Option<Database>> {
+    match state.dbs.owner(kb_owner_id).await {
+        Ok(db) => Ok(Some(db)),
+        Err(e) => {
+            error!("Failed to open database for {kb_owner_id}: {e}");
+            bot.send_message(chat_id, format!("Error: {e}")).await?;
+            Ok(None)
+        }
+    }
+}
+
 async fn run_agent_and_respond_inner(
     chat_id: ChatId,
     bot: &Bot,
@@ -1112,6 +1215,39 @@ async fn run_agent_and_respond_inner(
     } else {
         user_id
     };
+
+    let Some(db) = chat_db(bot, chat_id, state, kb_owner_id).await? else {
+        return Ok(());
+    };
+
+    // Load model preference scoped to chat (private=user_id, group=chat_id)
+    let mut model = db.get_chat_model(kb_owner_id).await;
+
+    // Spend budgets: refuse, or answer with the cheaper fallback model
+    let mut budget_note = String::new();
+    match crate::tools::check_budget(state.dbs.main(), &state.config.budget, user_id, kb_owner_id, chrono::Utc::now()).await {
+        Ok(check) => {
+            notify_budget_alerts(bot, state, &check.alerts);
+            match check.decision {
+                BudgetDecision::Allow => {}
+                BudgetDecision::Fallback { model: fallback, exceeded } => {
+                    let label = crate::provider::model_registry::resolve_model(&fallback).map_or(fallback.as_str(), |m| m.label);
+                    budget_note = format!("\n\n⚠️ {} — answered with {label}.", describe_budget(&exceeded));
+                    model = fallback;
+                }
+                BudgetDecision::Refuse(exceeded) => {
+                    bot.send_message(
+                        chat_id,
+                        format!("⛔ {}. Try again when the period resets or ask an admin to raise it (/budget).", describe_budget(&exceeded)),
+                    )
+                    .await?;
+                    return Ok(());
+                }
+            }
+        }
+        Err(e) => error!("Budget check failed for {kb_owner_id}/{user_id}: {e}"),
+    }
+
     // Send initial progress message
     let _ = bot.send_chat_action(chat_id, ChatAction::Typing).await;
     let progress_msg = bot.send_message(chat_id, "Thinking...").await?;
@@ -1157,11 +1293,8 @@ async fn run_agent_and_respond_inner(
         });
     };
 
-    // Load model preference scoped to chat (private=user_id, group=chat_id)
-    let model = state.db.get_chat_model(kb_owner_id);
-
     // Build system prompt with memory and file path scoped to KB owner
-    let memory_ctx = state.db.build_memory_context(kb_owner_id);
+    let memory_ctx = db.build_memory_context(kb_owner_id).await;
     let user_prompt = state.base_prompt.replace("{USER_ID}", &kb_owner_id.to_string());
     let mut system_prompt = skills::build_system_prompt(&user_prompt, &memory_ctx);
 
@@ -1169,12 +1302,12 @@ async fn run_agent_and_respond_inner(
     if !has_direct_content && !history_text.is_empty() {
         let (knowledge_results, memory_results) = tokio::join!(
             crate::tools::knowledge_search(
-                &state.db,
+                &db,
                 kb_owner_id,
                 history_text,
-                state.embedding_client.as_ref(),
+                state.embedder.as_ref(),
             ),
-            crate::tools::memory_search(&state.db, kb_owner_id, history_text, state.embedding_client.as_ref()),
+            crate::tools::memory_search(&db, kb_owner_id, history_text, state.embedder.as_ref()),
         );
 
         let mut rag_ctx = String::new();
@@ -1196,43 +1329,55 @@ async fn run_agent_and_respond_inner(
     }
 
     // Load conversation history (group → shared session, private → personal session)
-    let session_id = state.db.get_or_create_session(kb_owner_id);
-    let raw_history = state.db.load_history(&session_id, 6);
-    let history: Vec<Message> = raw_history
-        .into_iter()
-        .filter_map(|(role, content)| {
-            let r = match role.as_str() {
-                "user" => Role::User,
-                "assistant" => Role::Assistant,
-                _ => return None,
-            };
-            Some(Message { role: r, content: MessageContent::Text(content) })
-        })
-        .collect();
+    let session = match db.get_or_create_session(kb_owner_id).await {
+        Ok(session) => Some(session),
+        Err(e) => {
+            error!("Failed to open session for {kb_owner_id}: {e}");
+            None
+        }
+    };
+    let session_id = session
+        .as_ref()
+        .map_or_else(|| format!("{}-{}", kb_owner_id, chrono::Utc::now().timestamp()), |s| s.id.clone());
+    if let Some(summary) = session.as_ref().and_then(|s| s.summary.as_deref()) {
+        system_prompt.push_str("\n\n--- EARLIER IN THIS CONVERSATION (summary of pruned turns) ---\n");
+        system_prompt.push_str(summary);
+    }
+    let raw_history = db.load_history(&session_id, 6).await.unwrap_or_default();
+    let history = history::replay(raw_history);
 
-    // Save user message to history (text representation)
-    state.db.append_message(&session_id, "user", history_text);
+    // Save the user message to history as sent, with its short text form for summaries
+    let limits = &state.config.history_limits;
+    let user_message = Message { role: Role::User, content: user_content };
+    let stored = history::to_stored(&user_message, Some(history_text), limits);
+    let _ = db.append_messages(&session_id, vec![stored]).await;
 
     // Run agent loop
     let start = std::time::Instant::now();
     let result = AgentLoop::run(
         &state.pool,
         &system_prompt,
-        user_content,
+        user_message.content,
         user_id,
         kb_owner_id,
-        &state.db,
+        &db,
         state.config.max_agent_turns,
         history,
-        state.embedding_client.as_ref(),
+        state.embedder.as_ref(),
+        state.file_cipher.as_ref(),
         &model,
         on_progress,
-        &state.config.allowed_users,
+        ApprovalPolicy::from_config(&state.config),
     )
     .await;
 
     let elapsed_secs = start.elapsed().as_secs_f64();
 
+    // Write requests this user queued go to the admins
+    if !state.config.allowed_users.is_empty() && !state.config.allowed_users.contains(&user_id) {
+        notify_pending_requests(bot, state, &db);
+    }
+
     // Stop typing indicator
     typing_active.store(false, Ordering::Relaxed);
     typing_handle.abort();
@@ -1246,17 +1391,30 @@ async fn run_agent_and_respond_inner(
             };
 
             // Log usage for cost tracking
-            state.db.log_usage(
-                &model,
-                &agent_result.provider,
-                agent_result.usage.prompt_tokens,
-                agent_result.usage.completion_tokens,
-                agent_result.usage.cache_creation_tokens,
-                agent_result.usage.cache_read_tokens,
-            );
-
-            // Save assistant response to history
-            state.db.append_message(&session_id, "assistant", &cleaned);
+            let entry = UsageEntry {
+                model: model.clone(),
+                provider: agent_result.provider.clone(),
+                prompt_tokens: agent_result.usage.prompt_tokens,
+                completion_tokens: agent_result.usage.completion_tokens,
+                cache_creation_tokens: agent_result.usage.cache_creation_tokens,
+                cache_read_tokens: agent_result.usage.cache_read_tokens,
+                user_id: Some(user_id),
+                kb_owner_id: Some(kb_owner_id),
+                session_id: Some(session_id.clone()),
+                turns: agent_result.turns as u32,
+                tools: agent_result.tools_used.clone(),
+            };
+            let _ = state.dbs.main().log_usage(&entry).await;
+
+            // Save the tool calls, their results and the reply to history
+            let reply = Message { role: Role::Assistant, content: MessageContent::Text(cleaned.clone()) };
+            let turn: Vec<_> = agent_result
+                .tool_messages
+                .iter()
+                .chain([&reply])
+                .map(|m| history::to_stored(m, None, limits))
+                .collect();
+            let _ = db.append_messages(&session_id, turn).await;
 
             // Build final response with footer
             let footer = formatter::format_tools_footer(
@@ -1266,7 +1424,7 @@ async fn run_agent_and_respond_inner(
                 &agent_result.provider,
                 agent_result.turns,
             );
-            let full_response = format!("{cleaned}{footer}");
+            let full_response = format!("{cleaned}{budget_note}{footer}");
 
             let chunks = formatter::split_message(&full_response, 4096);
 
@@ -1284,6 +1442,13 @@ async fn run_agent_and_respond_inner(
                     let _ = bot.send_message(chat_id, chunk).await;
                 }
             }
+
+            // Name an untitled session once it has an exchange to go on
+            if let Some(session) = session.filter(|s| s.title.is_none())
+                && let Err(e) = crate::tools::title_session(&state.pool, &db, state.dbs.main(), &session, &model).await
+            {
+                warn!("Failed to title session {}: {e}", session.id);
+            }
         }
         Err(err) => {
             error!("Agent error: {err}");
@@ -1294,6 +1459,43 @@ async fn run_agent_and_respond_inner(
     Ok(())
 }
 
+/// DM every whitelisted user about budgets that crossed the alert threshold.
+fn notify_budget_alerts(bot: &Bot, state: &AppState, alerts: &[BudgetUsage]) {
+    if alerts.is_empty() {
+        return;
+    }
+    if state.config.allowed_users.is_empty() {
+        warn!("Budget alert with no whitelisted users to notify: {}", describe_budget(&alerts[0]));
+        return;
+    }
+    let text = alerts
+        .iter()
+        .map(|a| format!("⚠️ Budget alert: {}", describe_budget(a)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let bot = bot.clone();
+    let admins = state.config.allowed_users.clone();
+    tokio::spawn(async move {
+        for admin in admins {
+            if let Err(e) = bot.send_message(ChatId(admin as i64), &text).await {
+                warn!("Failed to DM budget alert to {admin}: {e}");
+            }
+        }
+    });
+}
+
+/// "chat 123 used $4.10 of its $5.00 daily budget (82%)"
+fn describe_budget(usage: &BudgetUsage) -> String {
+    format!(
+        "{} used ${:.2} of its ${:.2} {} budget ({:.0}%)",
+        crate::tools::describe_subject(usage.subject),
+        usage.spent_usd,
+        usage.limit_usd,
+        usage.period.label(),
+        usage.percent()
+    )
+}
+
 /// Extract text content from file bytes for prompt injection.
 /// Supports documents (PDF/DOCX/XLSX), text files, and returns error string for unsupported.
 fn extract_file_for_prompt(file_name: &str, data: &[u8]) -> String {
@@ -1463,7 +1665,7 @@ async fn process_media_group(
         };
 
         // Save to disk
-        let saved_path = save_file_to_disk(group_data.kb_owner_id, &file.file_name, &bytes).await;
+        let saved_path = save_file_to_disk(group_data.kb_owner_id, &file.file_name, &bytes, state.file_cipher.as_ref()).await;
 
         if file.is_image {
             let media_type = detect_media_type(&file_path, &file.file_name);
@@ -1518,7 +1720,7 @@ async fn process_media_group(
 async fn handle_command(
     msg: &teloxide::types::Message,
     bot: &Bot,
-    state: &AppState,
+    state: &Arc<AppState>,
     text: &str,
     user_id: u64,
     kb_owner_id: u64,
@@ -1526,6 +1728,7 @@ async fn handle_command(
     match text.split_whitespace().next().unwrap_or("") {
         "/start" => {
             let key_count = state.config.claude_keys.len();
+            let embeddings = embedding_status_line(state).await;
             bot.send_message(
                 msg.chat.id,
                 format!(
@@ -1533,6 +1736,7 @@ async fn handle_command(
                     Your private knowledge assistant.\n\
                     Send text, photos, or files to remember and analyze.\n\n\
                     API keys: {key_count} (round-robin)\n\
+                    {embeddings}\n\
                     /help for commands"
                 ),
             )
@@ -1545,9 +1749,21 @@ async fn handle_command(
                  /help — Show commands\n\
                  /memory — List saved memories\n\
                  /category — List memory categories\n\
+                 /new [title] — Start a new conversation\n\
+                 /sessions — List recent conversations\n\
+                 /resume <id> — Switch back to a conversation\n\
                  /model — Switch AI model\n\
                  /cost — View usage costs this month\n\
-                 /pending — View pending requests\n\
+                 /cost users|chats|days — Costs broken down this month\n\
+                 /cost export [all] — Download the usage log as CSV\n\
+                 /export [embeddings] — Download the knowledge base as a ZIP\n\
+                 /export obsidian — Download it as an Obsidian vault\n\
+                 /backup now|list — Snapshot the database / list backups\n\
+                 /retention — Chat history retention for this chat\n\
+                 /budget — Spend budgets for this chat and you\n\
+                 /doctor [fix] — Check (and repair) this chat's knowledge graph and the database\n\
+                 /undo — Undo your last change to this chat's knowledge base\n\
+                 /pending [log] — View pending requests / latest decisions\n\
                  /approve <id> — Approve a request\n\
                  /reject <id> — Reject a request\n\n\
                  Supported input:\n\
@@ -1562,13 +1778,16 @@ async fn handle_command(
             .await?;
         }
         "/memory" => {
-            let facts = state.db.list_facts(kb_owner_id, None).unwrap_or_default();
+            let Some(db) = chat_db(bot, msg.chat.id, state, kb_owner_id).await? else {
+                return Ok(());
+            };
+            let facts = db.list_facts(kb_owner_id, None).await.unwrap_or_default();
             if facts.is_empty() {
                 bot.send_message(msg.chat.id, "No memories saved yet.").await?;
             } else {
                 let output: String = facts
                     .iter()
-                    .map(|(id, fact, cat)| format!("[{id}] [{cat}] {fact}"))
+                    .map(|f| format!("[{}] [{}] {}", f.id, f.category, f.text))
                     .collect::<Vec<_>>()
                     .join("\n");
                 for chunk in formatter::split_message(&output, 4096) {
@@ -1577,8 +1796,11 @@ async fn handle_command(
             }
         }
         "/category" => {
-            let _ = state.db.ensure_default_categories(kb_owner_id);
-            let cats = state.db.list_categories(kb_owner_id).unwrap_or_default();
+            let Some(db) = chat_db(bot, msg.chat.id, state, kb_owner_id).await? else {
+                return Ok(());
+            };
+            let _ = db.ensure_default_categories(kb_owner_id).await;
+            let cats = db.list_categories(kb_owner_id).await.unwrap_or_default();
             if cats.is_empty() {
                 bot.send_message(msg.chat.id, "No categories.").await?;
             } else {
@@ -1595,19 +1817,46 @@ async fn handle_command(
                 handle_model_command(msg, bot, state, text, kb_owner_id).await?;
             }
         }
+        "/new" => {
+            handle_new_command(msg, bot, state, text, kb_owner_id).await?;
+        }
+        "/sessions" => {
+            handle_sessions_command(msg, bot, state, kb_owner_id).await?;
+        }
+        "/resume" => {
+            handle_resume_command(msg, bot, state, text, kb_owner_id).await?;
+        }
         "/cost" => {
-            handle_cost_command(msg, bot, state).await?;
+            handle_cost_command(msg, bot, state, text, user_id).await?;
+        }
+        "/export" => {
+            handle_export_command(msg, bot, state, text, user_id, kb_owner_id).await?;
+        }
+        "/backup" => {
+            handle_backup_command(msg, bot, state, text, user_id).await?;
+        }
+        "/retention" => {
+            handle_retention_command(msg, bot, state, text, user_id, kb_owner_id).await?;
+        }
+        "/budget" => {
+            handle_budget_command(msg, bot, state, text, user_id, kb_owner_id).await?;
+        }
+        "/doctor" => {
+            handle_doctor_command(msg, bot, state, text, user_id, kb_owner_id).await?;
+        }
+        "/undo" => {
+            handle_undo_command(msg, bot, state, user_id, kb_owner_id).await?;
         }
         cmd if cmd.starts_with("/pending") => {
-            handle_pending_command(msg, bot, state, user_id, kb_owner_id).await?;
+            handle_pending_command(msg, bot, state, text, user_id, kb_owner_id).await?;
         }
         cmd if cmd.starts_with("/approve") => {
             let id_str = text.strip_prefix("/approve").unwrap_or("").trim();
-            handle_approve_command(msg, bot, state, user_id, id_str).await?;
+            handle_approve_command(msg, bot, state, user_id, kb_owner_id, id_str).await?;
         }
         cmd if cmd.starts_with("/reject") => {
             let id_str = text.strip_prefix("/reject").unwrap_or("").trim();
-            handle_reject_command(msg, bot, state, user_id, id_str).await?;
+            handle_reject_command(msg, bot, state, user_id, kb_owner_id, id_str).await?;
         }
         _ => {
             bot.send_message(msg.chat.id, "Unknown command. /help")
@@ -1617,147 +1866,856 @@ async fn handle_command(
     Ok(())
 }
 
-async fn handle_pending_command(
+/// `/export [embeddings|obsidian]` — send this chat's knowledge base as a ZIP
+/// bundle, or rendered as an Obsidian vault.
+async fn handle_export_command(
     msg: &teloxide::types::Message,
     bot: &Bot,
     state: &AppState,
+    text: &str,
     user_id: u64,
     kb_owner_id: u64,
 ) -> ResponseResult<()> {
-    let items = state.db.list_pending(kb_owner_id).unwrap_or_default();
-    if items.is_empty() {
-        bot.send_message(msg.chat.id, "No pending requests.").await?;
+    if !state.config.allowed_users.is_empty()
+        && !state.config.allowed_users.contains(&user_id)
+    {
+        bot.send_message(msg.chat.id, "Only whitelisted users can export the knowledge base.").await?;
         return Ok(());
     }
-    let is_whitelisted = state.config.allowed_users.is_empty()
-        || state.config.allowed_users.contains(&user_id);
-    let mut lines: Vec<String> = vec!["Pending requests:".into()];
-    for (id, requested_by, _tool, summary, created_at) in &items {
-        lines.push(format!("#{id} | user {requested_by} | {summary}\n  {created_at}"));
-    }
-    if is_whitelisted {
-        lines.push("\nUse /approve <id> or /reject <id>".into());
+    let obsidian = text.split_whitespace().any(|w| w == "obsidian");
+    let with_embeddings = !obsidian && text.split_whitespace().any(|w| w == "embeddings");
+
+    let Some(db) = chat_db(bot, msg.chat.id, state, kb_owner_id).await? else {
+        return Ok(());
+    };
+    let bundle = match db.export_owner(kb_owner_id, with_embeddings).await {
+        Ok(b) => b,
+        Err(e) => {
+            bot.send_message(msg.chat.id, format!("Export failed: {e}")).await?;
+            return Ok(());
+        }
+    };
+    let mut buf = std::io::Cursor::new(Vec::new());
+    let written = if obsidian { bundle.write_obsidian_zip(&mut buf) } else { bundle.write_zip(&mut buf) };
+    if let Err(e) = written {
+        bot.send_message(msg.chat.id, format!("Export failed: {e}")).await?;
+        return Ok(());
     }
-    bot.send_message(msg.chat.id, lines.join("\n")).await?;
+
+    let caption = format!(
+        "{} facts, {} documents, {} entities{}",
+        bundle.facts.len(),
+        bundle.documents.len(),
+        bundle.entities.len(),
+        if with_embeddings { " (with embeddings)" } else if obsidian { " (Obsidian vault)" } else { "" }
+    );
+    let file_name = if obsidian {
+        crate::cli::vault_file_name(kb_owner_id)
+    } else {
+        crate::cli::export_file_name(kb_owner_id)
+    };
+    let file = teloxide::types::InputFile::memory(buf.into_inner()).file_name(file_name);
+    bot.send_document(msg.chat.id, file).caption(caption).await?;
     Ok(())
 }
 
-async fn handle_approve_command(
+/// `/backup now|list` — snapshot the database or list snapshots (whitelisted users only).
+async fn handle_backup_command(
     msg: &teloxide::types::Message,
     bot: &Bot,
     state: &AppState,
+    text: &str,
     user_id: u64,
-    id_str: &str,
 ) -> ResponseResult<()> {
+    use crate::db::backup;
+
     if !state.config.allowed_users.is_empty()
         && !state.config.allowed_users.contains(&user_id)
     {
-        bot.send_message(msg.chat.id, "Only whitelisted users can approve requests.").await?;
+        bot.send_message(msg.chat.id, "Only whitelisted users can manage backups.").await?;
         return Ok(());
     }
-    let id: i64 = match id_str.parse() {
-        Ok(v) => v,
-        Err(_) => {
-            bot.send_message(msg.chat.id, "Usage: /approve <id>").await?;
-            return Ok(());
-        }
-    };
-    let (scope_id, _requested_by, tool_name, args_json, summary) = match state.db.get_pending(id) {
-        Ok(v) => v,
-        Err(_) => {
-            bot.send_message(msg.chat.id, &format!("Pending #{id} not found.")).await?;
-            return Ok(());
+    let dir = Path::new(&state.config.backup_dir);
+
+    let reply = match text.split_whitespace().nth(1) {
+        Some("now") => {
+            let run = state.dbs.backup_all(dir, backup_policy(&state.config)).await;
+            let describe = |label: String, result: &Result<backup::BackupReport, backup::BackupError>| match result {
+                Ok(report) => format!(
+                    "{label}Backup saved: {} ({}), integrity check ok.{}",
+                    report.backup.path.display(),
+                    format_bytes(report.backup.size_bytes),
+                    if report.removed.is_empty() {
+                        String::new()
+                    } else {
+                        format!(" Rotated out {} old snapshot(s).", report.removed.len())
+                    }
+                ),
+                Err(e) => format!("{label}Backup failed: {e}"),
+            };
+            let mut lines = vec![describe(String::new(), &run.main)];
+            match &run.owners {
+                Ok(owners) => lines.extend(owners.iter().map(|(id, result)| describe(format!("Owner {id}: "), result))),
+                Err(e) => lines.push(format!("Owner databases could not be listed, none backed up: {e}")),
+            }
+            lines.join("\n")
         }
+        Some("list") => match backup::list_backups(dir) {
+            Ok(list) if list.is_empty() => format!("No backups in {}.", dir.display()),
+            Ok(list) => {
+                let mut lines = vec![format!("{} backup(s) in {}:", list.len(), dir.display())];
+                for b in &list {
+                    let name = b.path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+                    lines.push(format!(
+                        "- {} UTC — {name} ({})",
+                        b.created_at.format("%Y-%m-%d %H:%M"),
+                        format_bytes(b.size_bytes)
+                    ));
+                }
+                if state.dbs.is_per_owner() {
+                    lines.push(format!("Owner databases are snapshotted into {}/owner-<id>/.", dir.display()));
+                }
+                lines.join("\n")
+            }
+            Err(e) => format!("Failed to list backups: {e}"),
+        },
+        _ => "Usage: /backup now | /backup list".to_string(),
     };
-
-    // Execute the original tool with kb_owner_id = scope_id
-    use crate::agent::{ToolRegistry, ToolOutput};
-    let output = ToolRegistry::execute(
-        &tool_name,
-        &args_json,
-        user_id,       // approver as actor
-        scope_id,      // original scope
-        &state.db,
-        &state.pool,
-        state.embedding_client.as_ref(),
-        &state.config.allowed_users, // approver is whitelisted, will pass check
-    )
-    .await;
-
-    let result_text = match output {
-        ToolOutput::Text(t) => t,
-        ToolOutput::Image { text, .. } => text,
-    };
-
-    let _ = state.db.delete_pending(id);
-    bot.send_message(
-        msg.chat.id,
-        format!("Approved #{id}: {summary}\n{result_text}"),
-    )
-    .await?;
+    for chunk in formatter::split_message(&reply, 4096) {
+        bot.send_message(msg.chat.id, chunk).await?;
+    }
     Ok(())
 }
 
-async fn handle_reject_command(
+/// `/retention [days <n>|messages <n>|summarize on|off|default]` — view or
+/// change how much chat history this chat keeps (0 turns a limit off).
+async fn handle_retention_command(
     msg: &teloxide::types::Message,
     bot: &Bot,
     state: &AppState,
+    text: &str,
     user_id: u64,
-    id_str: &str,
+    kb_owner_id: u64,
 ) -> ResponseResult<()> {
-    if !state.config.allowed_users.is_empty()
+    let Some(db) = chat_db(bot, msg.chat.id, state, kb_owner_id).await? else {
+        return Ok(());
+    };
+    let args: Vec<&str> = text.split_whitespace().skip(1).collect();
+    let current = db.get_session_retention(kb_owner_id).await.unwrap_or(None);
+
+    if !args.is_empty()
+        && !state.config.allowed_users.is_empty()
         && !state.config.allowed_users.contains(&user_id)
     {
-        bot.send_message(msg.chat.id, "Only whitelisted users can reject requests.").await?;
+        bot.send_message(msg.chat.id, "Only whitelisted users can change retention.").await?;
         return Ok(());
     }
-    let id: i64 = match id_str.parse() {
-        Ok(v) => v,
-        Err(_) => {
-            bot.send_message(msg.chat.id, "Usage: /reject <id>").await?;
-            return Ok(());
-        }
-    };
-    match state.db.delete_pending(id) {
-        Ok(true) => {
-            bot.send_message(msg.chat.id, &format!("Rejected and removed #{id}.")).await?;
+
+    let mut updated = current.unwrap_or(state.config.session_retention);
+    let reply = match args.as_slice() {
+        [] => {
+            let source = if current.is_some() { "this chat" } else { "default" };
+            format!(
+                "Chat history retention ({source}):\n{}\n\n\
+                 Change with /retention days <n> | messages <n> | summarize on|off | default",
+                describe_retention(&current.unwrap_or(state.config.session_retention))
+            )
         }
-        _ => {
-            bot.send_message(msg.chat.id, &format!("Pending #{id} not found.")).await?;
+        ["default"] => match db.clear_session_retention(kb_owner_id).await {
+            Ok(_) => format!(
+                "Using the default retention again:\n{}",
+                describe_retention(&state.config.session_retention)
+            ),
+            Err(e) => format!("Error: {e}"),
+        },
+        [field @ ("days" | "messages"), n] => match n.parse::<u32>() {
+            Ok(n) => {
+                let limit = (n > 0).then_some(n);
+                if *field == "days" {
+                    updated.max_age_days = limit;
+                } else {
+                    updated.max_messages = limit;
+                }
+                save_retention(&db, kb_owner_id, updated).await
+            }
+            Err(_) => "Usage: /retention days <n> | messages <n> (0 = no limit)".to_string(),
+        },
+        ["summarize", flag @ ("on" | "off")] => {
+            updated.summarize = *flag == "on";
+            save_retention(&db, kb_owner_id, updated).await
         }
-    }
+        _ => "Usage: /retention [days <n> | messages <n> | summarize on|off | default]".to_string(),
+    };
+    bot.send_message(msg.chat.id, reply).await?;
     Ok(())
 }
 
-async fn handle_cost_command(
+async fn save_retention(db: &Database, kb_owner_id: u64, retention: crate::db::SessionRetention) -> String {
+    match db.set_session_retention(kb_owner_id, retention).await {
+        Ok(()) => format!("Retention for this chat:\n{}", describe_retention(&retention)),
+        Err(e) => format!("Error: {e}"),
+    }
+}
+
+fn describe_retention(r: &crate::db::SessionRetention) -> String {
+    let age = r.max_age_days.map_or("no limit".to_string(), |d| format!("{d} days"));
+    let count = r.max_messages.map_or("no limit".to_string(), |n| format!("{n} messages"));
+    format!(
+        "- Max age: {age}\n- Max messages: {count}\n- Summarize pruned turns: {}",
+        if r.summarize { "on" } else { "off" }
+    )
+}
+
+async fn handle_budget_command(
     msg: &teloxide::types::Message,
     bot: &Bot,
     state: &AppState,
-) -> Result<(), teloxide::RequestError> {
-    use crate::provider::model_registry;
+    text: &str,
+    user_id: u64,
+    kb_owner_id: u64,
+) -> ResponseResult<()> {
+    const USAGE: &str = "Usage: /budget [chat | user <id>] [daily <usd> | monthly <usd> | default] (0 = no limit)";
+    let args: Vec<&str> = text.split_whitespace().skip(1).collect();
 
-    let usage_data = state.db.get_monthly_usage();
-    if usage_data.is_empty() {
-        bot.send_message(msg.chat.id, "📊 No usage recorded this month.").await?;
+    if !args.is_empty()
+        && !state.config.allowed_users.is_empty()
+        && !state.config.allowed_users.contains(&user_id)
+    {
+        bot.send_message(msg.chat.id, "Only whitelisted users can change budgets.").await?;
         return Ok(());
     }
 
-    let now = chrono::Utc::now();
-    let month_label = now.format("%m/%Y");
+    let (subject, change) = match args.as_slice() {
+        [] => {
+            let mut lines = Vec::new();
+            for subject in [BudgetSubject::Owner(kb_owner_id), BudgetSubject::User(user_id)] {
+                lines.push(describe_budgets(state, subject).await);
+            }
+            lines.push(format!(
+                "Over budget: {}\n\n{USAGE}",
+                match state.config.budget.action {
+                    crate::tools::OverBudgetAction::Fallback => format!("answer with {}", state.config.budget.fallback_model),
+                    crate::tools::OverBudgetAction::Refuse => "refuse".to_string(),
+                }
+            ));
+            bot.send_message(msg.chat.id, lines.join("\n\n")).await?;
+            return Ok(());
+        }
+        ["chat", rest @ ..] => (BudgetSubject::Owner(kb_owner_id), rest),
+        ["user", id, rest @ ..] => match id.parse() {
+            Ok(id) => (BudgetSubject::User(id), rest),
+            Err(_) => {
+                bot.send_message(msg.chat.id, USAGE).await?;
+                return Ok(());
+            }
+        },
+        _ => {
+            bot.send_message(msg.chat.id, USAGE).await?;
+            return Ok(());
+        }
+    };
+
+    let reply = match change {
+        [] => describe_budgets(state, subject).await,
+        ["default"] => match state.dbs.main().clear_spend_limits(subject).await {
+            Ok(_) => describe_budgets(state, subject).await,
+            Err(e) => format!("Error: {e}"),
+        },
+        [period @ ("daily" | "monthly"), usd] => match usd.trim_start_matches('$').parse::<f64>() {
+            Ok(usd) if usd >= 0.0 => {
+                let mut limits = crate::tools::effective_limits(state.dbs.main(), &state.config.budget, subject)
+                    .await
+                    .unwrap_or_default();
+                let limit = (usd > 0.0).then_some(usd);
+                if *period == "daily" {
+                    limits.daily_usd = limit;
+                } else {
+                    limits.monthly_usd = limit;
+                }
+                match state.dbs.main().set_spend_limits(subject, limits).await {
+                    Ok(()) => describe_budgets(state, subject).await,
+                    Err(e) => format!("Error: {e}"),
+                }
+            }
+            _ => USAGE.to_string(),
+        },
+        _ => USAGE.to_string(),
+    };
+    bot.send_message(msg.chat.id, reply).await?;
+    Ok(())
+}
+
+/// Limits in force for `subject` and what has been spent against them.
+async fn describe_budgets(state: &AppState, subject: BudgetSubject) -> String {
+    let policy = &state.config.budget;
+    let custom = matches!(state.dbs.main().get_spend_limits(subject).await, Ok(Some(_)));
+    let usage = crate::tools::budget_usage(state.dbs.main(), policy, subject, chrono::Utc::now())
+        .await
+        .unwrap_or_default();
+    let mut lines = vec![format!(
+        "Budget for {} ({}):",
+        crate::tools::describe_subject(subject),
+        if custom { "custom" } else { "default" }
+    )];
+    if usage.is_empty() {
+        lines.push("- No limit".to_string());
+    }
+    for u in &usage {
+        lines.push(format!("- {}: ${:.2} / ${:.2} ({:.0}%)", u.period.label(), u.spent_usd, u.limit_usd, u.percent()));
+    }
+    lines.join("\n")
+}
+
+/// The embedding model and how much its cache has saved, for `/start`.
+async fn embedding_status_line(state: &AppState) -> String {
+    let client = state.embedder.as_ref();
+    if !client.is_enabled() {
+        return "Embeddings: off (no EMBEDDING_BACKEND or VOYAGE_API_KEY)".to_string();
+    }
+    match state.dbs.main().embedding_cache_stats().await {
+        Ok(stats) => format!(
+            "Embeddings: {} — cache {} entries ({:.1} MB), {} hits / {} misses ({:.0}% hit rate)",
+            client.model(),
+            stats.entries,
+            stats.bytes as f64 / (1024.0 * 1024.0),
+            stats.hits,
+            stats.misses,
+            stats.hit_rate() * 100.0
+        ),
+        Err(e) => {
+            error!("Embedding cache stats failed: {e}");
+            format!("Embeddings: {}", client.model())
+        }
+    }
+}
+
+async fn handle_doctor_command(
+    msg: &teloxide::types::Message,
+    bot: &Bot,
+    state: &Arc<AppState>,
+    text: &str,
+    user_id: u64,
+    kb_owner_id: u64,
+) -> ResponseResult<()> {
+    let fix = text.split_whitespace().nth(1) == Some("fix");
+    let is_admin = state.config.allowed_users.is_empty() || state.config.allowed_users.contains(&user_id);
+    if fix && !is_admin {
+        bot.send_message(msg.chat.id, "Only whitelisted users can run repairs.").await?;
+        return Ok(());
+    }
+
+    let Some(db) = chat_db(bot, msg.chat.id, state, kb_owner_id).await? else {
+        return Ok(());
+    };
+    let result = if fix {
+        db.repair_entity_graph(Some(kb_owner_id)).await
+    } else {
+        db.check_entity_graph(Some(kb_owner_id)).await
+    };
+    let mut problems = matches!(&result, Ok(report) if !report.is_clean());
+    let mut reply = match result {
+        Ok(report) if report.is_clean() => "🩺 Knowledge graph: no problems found.".to_string(),
+        Ok(report) => format!(
+            "🩺 Knowledge graph{}:\n\
+             - Mentions of deleted sources: {}\n\
+             - Mentions no longer in their source: {}\n\
+             - Entities with no mentions: {}",
+            if fix { " — repaired" } else { "" },
+            report.dangling_mentions,
+            report.stale_mentions,
+            report.orphan_entities,
+        ),
+        Err(e) => format!("Error: {e}"),
+    };
+
+    // The main database is shared by every chat, so only admins see its checks
+    let mut databases = Vec::new();
+    if is_admin {
+        let label = if state.dbs.is_per_owner() { "Main database" } else { "Database" };
+        databases.push((label, state.dbs.main().clone()));
+    }
+    if state.dbs.is_per_owner() {
+        databases.push(("This chat's database", db));
+    }
+    for (label, db) in databases {
+        let result = if fix { db.repair_integrity().await } else { db.check_integrity().await };
+        reply.push_str("\n\n");
+        match result {
+            Ok(report) if report.is_clean() => reply.push_str(&format!("🗄 {label}: no problems found.")),
+            Ok(report) => {
+                problems = true;
+                reply.push_str(&format!("🗄 {label}{}:\n{report}", if fix { " — repaired" } else { "" }));
+                if fix && !report.stale_chunk_docs.is_empty() {
+                    let state_clone = state.clone();
+                    tokio::spawn(async move {
+                        migrate_unchunked_docs(&state_clone).await;
+                    });
+                }
+            }
+            Err(e) => reply.push_str(&format!("🗄 {label} check failed: {e}")),
+        }
+    }
+    if problems && !fix {
+        reply.push_str("\n\nRun /doctor fix to repair.");
+    }
+    bot.send_message(msg.chat.id, reply).await?;
+    Ok(())
+}
+
+fn backup_policy(config: &Config) -> crate::db::backup::RetentionPolicy {
+    crate::db::backup::RetentionPolicy {
+        daily: config.backup_keep_daily,
+        weekly: config.backup_keep_weekly,
+    }
+}
+
+fn format_bytes(n: u64) -> String {
+    if n >= 1024 * 1024 {
+        format!("{:.1} MB", n as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{:.1} KB", n as f64 / 1024.0)
+    }
+}
+
+/// Sessions listed by `/sessions`.
+const SESSION_LIST_LIMIT: usize = 10;
+
+/// `/new [title]` — start a new conversation in this chat. Without a title,
+/// one is generated from its first exchange.
+async fn handle_new_command(
+    msg: &teloxide::types::Message,
+    bot: &Bot,
+    state: &AppState,
+    text: &str,
+    kb_owner_id: u64,
+) -> ResponseResult<()> {
+    let Some(db) = chat_db(bot, msg.chat.id, state, kb_owner_id).await? else {
+        return Ok(());
+    };
+    let title = text.strip_prefix("/new").unwrap_or("").trim();
+    let reply = match db.create_session(kb_owner_id, (!title.is_empty()).then_some(title)).await {
+        Ok(session) => format!(
+            "Started a new conversation{}.
+Earlier ones: /sessions",
+            session.title.map(|t| format!(": {t}")).unwrap_or_default()
+        ),
+        Err(e) => format!("Error: {e}"),
+    };
+    bot.send_message(msg.chat.id, reply).await?;
+    Ok(())
+}
+
+/// `/sessions` — this chat's recent conversations, the active one marked.
+async fn handle_sessions_command(
+    msg: &teloxide::types::Message,
+    bot: &Bot,
+    state: &AppState,
+    kb_owner_id: u64,
+) -> ResponseResult<()> {
+    let Some(db) = chat_db(bot, msg.chat.id, state, kb_owner_id).await? else {
+        return Ok(());
+    };
+    let (sessions, active) = match tokio::try_join!(
+        db.list_sessions(kb_owner_id, SESSION_LIST_LIMIT),
+        db.active_session_id(kb_owner_id)
+    ) {
+        Ok(found) => found,
+        Err(e) => {
+            bot.send_message(msg.chat.id, format!("Error: {e}")).await?;
+            return Ok(());
+        }
+    };
+    if sessions.is_empty() {
+        bot.send_message(msg.chat.id, "No conversations yet.").await?;
+        return Ok(());
+    }
+    let mut lines: Vec<String> = vec!["Conversations (most recent first):".into()];
+    for session in &sessions {
+        let marker = if active.as_deref() == Some(session.id.as_str()) { " (active)" } else { "" };
+        lines.push(format!(
+            "{}{marker} | {}
+  started {} | last active {}",
+            session.id,
+            session.title.as_deref().unwrap_or("(untitled)"),
+            short_timestamp(&session.created_at),
+            short_timestamp(&session.last_active_at),
+        ));
+    }
+    lines.push("
+Switch with /resume <id>, start another with /new".into());
+    bot.send_message(msg.chat.id, lines.join("\n")).await?;
+    Ok(())
+}
+
+/// `/resume <id>` — make an earlier conversation of this chat the active one.
+async fn handle_resume_command(
+    msg: &teloxide::types::Message,
+    bot: &Bot,
+    state: &AppState,
+    text: &str,
+    kb_owner_id: u64,
+) -> ResponseResult<()> {
+    let id = text.strip_prefix("/resume").unwrap_or("").trim();
+    if id.is_empty() {
+        bot.send_message(msg.chat.id, "Usage: /resume <id> (see /sessions)").await?;
+        return Ok(());
+    }
+    let Some(db) = chat_db(bot, msg.chat.id, state, kb_owner_id).await? else {
+        return Ok(());
+    };
+    let reply = match db.resume_session(kb_owner_id, id).await {
+        Ok(session) => format!(
+            "Resumed {}: {}",
+            session.id,
+            session.title.as_deref().unwrap_or("(untitled)")
+        ),
+        Err(crate::db::DbError::NotFound(_)) => format!("No conversation {id} in this chat. See /sessions"),
+        Err(e) => format!("Error: {e}"),
+    };
+    bot.send_message(msg.chat.id, reply).await?;
+    Ok(())
+}
+
+/// `/undo` — reverse the caller's most recent knowledge base write in this chat.
+async fn handle_undo_command(
+    msg: &teloxide::types::Message,
+    bot: &Bot,
+    state: &AppState,
+    user_id: u64,
+    kb_owner_id: u64,
+) -> ResponseResult<()> {
+    let Some(db) = chat_db(bot, msg.chat.id, state, kb_owner_id).await? else {
+        return Ok(());
+    };
+    let reply = crate::tools::undo_last_write(&db, user_id, kb_owner_id, state.embedder.as_ref()).await;
+    bot.send_message(msg.chat.id, reply).await?;
+    Ok(())
+}
+
+/// `YYYY-MM-DD HH:MM` from an SQLite `datetime()` value.
+fn short_timestamp(ts: &str) -> &str {
+    ts.get(..16).unwrap_or(ts)
+}
+
+/// Decisions listed by `/pending log`.
+const APPROVAL_LOG_LIMIT: usize = 10;
+
+/// `/pending [log]` — this chat's waiting requests, or its latest decisions.
+async fn handle_pending_command(
+    msg: &teloxide::types::Message,
+    bot: &Bot,
+    state: &AppState,
+    text: &str,
+    user_id: u64,
+    kb_owner_id: u64,
+) -> ResponseResult<()> {
+    let Some(db) = chat_db(bot, msg.chat.id, state, kb_owner_id).await? else {
+        return Ok(());
+    };
+    if text.split_whitespace().nth(1) == Some("log") {
+        let records = db.list_approvals(kb_owner_id, APPROVAL_LOG_LIMIT).await.unwrap_or_default();
+        if records.is_empty() {
+            bot.send_message(msg.chat.id, "No decided requests yet.").await?;
+            return Ok(());
+        }
+        let mut lines: Vec<String> = vec!["Latest decisions:".into()];
+        for r in &records {
+            let by = r.decided_by.map(|u| format!(" by user {u}")).unwrap_or_default();
+            lines.push(format!(
+                "#{} {}{by} | user {} | {}\n  {}",
+                r.pending_id,
+                r.decision.as_str(),
+                r.requested_by,
+                r.summary,
+                short_timestamp(&r.decided_at)
+            ));
+        }
+        bot.send_message(msg.chat.id, lines.join("\n")).await?;
+        return Ok(());
+    }
+
+    let items = db.list_pending(kb_owner_id).await.unwrap_or_default();
+    if items.is_empty() {
+        bot.send_message(msg.chat.id, "No pending requests.").await?;
+        return Ok(());
+    }
+    let is_whitelisted = state.config.allowed_users.is_empty()
+        || state.config.allowed_users.contains(&user_id);
+    let mut lines: Vec<String> = vec!["Pending requests:".into()];
+    for item in &items {
+        let expiry = pending_expiry(&item.created_at, state.config.pending_ttl_hours)
+            .map(|t| format!(", expires {t}"))
+            .unwrap_or_default();
+        lines.push(format!(
+            "#{} | user {} | {}\n  {}{expiry}",
+            item.id, item.requested_by, item.summary, item.created_at
+        ));
+    }
+    if is_whitelisted {
+        lines.push("\nUse /approve <id> or /reject <id>".into());
+    }
+    bot.send_message(msg.chat.id, lines.join("\n")).await?;
+    Ok(())
+}
+
+async fn handle_approve_command(
+    msg: &teloxide::types::Message,
+    bot: &Bot,
+    state: &AppState,
+    user_id: u64,
+    kb_owner_id: u64,
+    id_str: &str,
+) -> ResponseResult<()> {
+    if !state.config.allowed_users.is_empty()
+        && !state.config.allowed_users.contains(&user_id)
+    {
+        bot.send_message(msg.chat.id, "Only whitelisted users can approve requests.").await?;
+        return Ok(());
+    }
+    let id: i64 = match id_str.parse() {
+        Ok(v) => v,
+        Err(_) => {
+            bot.send_message(msg.chat.id, "Usage: /approve <id>").await?;
+            return Ok(());
+        }
+    };
+    // Pending requests live with the chat's data, so they are approved from that chat
+    let Some(db) = chat_db(bot, msg.chat.id, state, kb_owner_id).await? else {
+        return Ok(());
+    };
+    let reply = match approve_pending(
+        &db,
+        &state.pool,
+        state.embedder.as_ref(),
+        ApprovalPolicy::from_config(&state.config),
+        kb_owner_id,
+        id,
+        user_id,
+    )
+    .await
+    {
+        Ok(Some((item, result_text))) => format!("Approved #{id}: {}\n{result_text}", item.summary),
+        Ok(None) => format!("Pending #{id} not found."),
+        Err(e) => format!("Error: {e}"),
+    };
+    bot.send_message(msg.chat.id, reply).await?;
+    Ok(())
+}
+
+async fn handle_reject_command(
+    msg: &teloxide::types::Message,
+    bot: &Bot,
+    state: &AppState,
+    user_id: u64,
+    kb_owner_id: u64,
+    id_str: &str,
+) -> ResponseResult<()> {
+    if !state.config.allowed_users.is_empty()
+        && !state.config.allowed_users.contains(&user_id)
+    {
+        bot.send_message(msg.chat.id, "Only whitelisted users can reject requests.").await?;
+        return Ok(());
+    }
+    let id: i64 = match id_str.parse() {
+        Ok(v) => v,
+        Err(_) => {
+            bot.send_message(msg.chat.id, "Usage: /reject <id>").await?;
+            return Ok(());
+        }
+    };
+    let Some(db) = chat_db(bot, msg.chat.id, state, kb_owner_id).await? else {
+        return Ok(());
+    };
+    let reply = match reject_pending(&db, ApprovalPolicy::from_config(&state.config), kb_owner_id, id, user_id).await {
+        Ok(Some(_)) => format!("Rejected and removed #{id}."),
+        Ok(None) => format!("Pending #{id} not found."),
+        Err(e) => format!("Error: {e}"),
+    };
+    bot.send_message(msg.chat.id, reply).await?;
+    Ok(())
+}
+
+/// DM every whitelisted user the requests in `db` they have not seen yet,
+/// with Approve/Reject buttons.
+fn notify_pending_requests(bot: &Bot, state: &AppState, db: &Database) {
+    let (bot, db, admins) = (bot.clone(), db.clone(), state.config.allowed_users.clone());
+    let ttl_hours = state.config.pending_ttl_hours;
+    tokio::spawn(async move {
+        let items = match db.claim_pending_notifications().await {
+            Ok(items) => items,
+            Err(e) => {
+                error!("Failed to load pending requests to notify: {e}");
+                return;
+            }
+        };
+        for item in &items {
+            let text = format!(
+                "Approval request #{} in chat {} from user {}:\n{}{}",
+                item.id,
+                item.scope_id,
+                item.requested_by,
+                item.summary,
+                pending_expiry(&item.created_at, ttl_hours).map(|t| format!("\nExpires {t} UTC")).unwrap_or_default(),
+            );
+            let buttons = InlineKeyboardMarkup::new(vec![vec![
+                InlineKeyboardButton::callback("Approve", format!("pending:approve:{}:{}", item.scope_id, item.id)),
+                InlineKeyboardButton::callback("Reject", format!("pending:reject:{}:{}", item.scope_id, item.id)),
+            ]]);
+            for admin in &admins {
+                if let Err(e) = bot.send_message(ChatId(*admin as i64), &text).reply_markup(buttons.clone()).await {
+                    warn!("Failed to DM pending request #{} to {admin}: {e}", item.id);
+                }
+            }
+        }
+    });
+}
+
+/// Approve/Reject buttons on a pending request DM.
+async fn handle_callback_query(q: CallbackQuery, bot: Bot, state: Arc<AppState>) -> ResponseResult<()> {
+    let parts: Vec<&str> = q.data.as_deref().unwrap_or("").split(':').collect();
+    let ["pending", action @ ("approve" | "reject"), scope, id] = parts.as_slice() else {
+        bot.answer_callback_query(q.id).await?;
+        return Ok(());
+    };
+    let (Ok(scope_id), Ok(id)) = (scope.parse::<u64>(), id.parse::<i64>()) else {
+        bot.answer_callback_query(q.id).await?;
+        return Ok(());
+    };
+    let user_id = q.from.id.0;
+    if !state.config.allowed_users.is_empty() && !state.config.allowed_users.contains(&user_id) {
+        bot.answer_callback_query(q.id).text("Only whitelisted users can decide requests.").await?;
+        return Ok(());
+    }
+
+    let db = match state.dbs.owner(scope_id).await {
+        Ok(db) => db,
+        Err(e) => {
+            error!("Failed to open database for {scope_id}: {e}");
+            bot.answer_callback_query(q.id).text(format!("Error: {e}")).await?;
+            return Ok(());
+        }
+    };
+    let policy = ApprovalPolicy::from_config(&state.config);
+    let decided = if *action == "approve" {
+        approve_pending(&db, &state.pool, state.embedder.as_ref(), policy, scope_id, id, user_id)
+            .await
+            .map(|done| done.map(|(item, result)| (item, format!("✅ Approved by {}", get_display_name(&q.from)), Some(result))))
+    } else {
+        reject_pending(&db, policy, scope_id, id, user_id)
+            .await
+            .map(|done| done.map(|item| (item, format!("❌ Rejected by {}", get_display_name(&q.from)), None)))
+    };
+
+    let (item, outcome, result) = match decided {
+        Ok(Some(decided)) => decided,
+        Ok(None) => {
+            bot.answer_callback_query(q.id).text(format!("#{id} was already decided or has expired.")).await?;
+            return Ok(());
+        }
+        Err(e) => {
+            bot.answer_callback_query(q.id).text(format!("Error: {e}")).await?;
+            return Ok(());
+        }
+    };
+    bot.answer_callback_query(q.id).text(outcome.clone()).await?;
+    if let Some(message) = q.message.as_ref().and_then(|m| m.regular_message()) {
+        let text = format!("{}\n\n{outcome}", message.text().unwrap_or_default());
+        let _ = bot.edit_message_text(message.chat.id, message.id, text).await;
+    }
+    let mut notice = format!("{outcome}: request #{id} ({})", item.summary);
+    if let Some(result) = result {
+        notice.push('\n');
+        notice.push_str(&result);
+    }
+    for chunk in formatter::split_message(&notice, 4096) {
+        if let Err(e) = bot.send_message(scope_chat(scope_id), chunk).await {
+            warn!("Failed to tell chat {scope_id} about #{id}: {e}");
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Chat a pending request came from. Only group members can queue requests
+/// (private chats outside the whitelist are refused), and a group's scope is
+/// its chat id without the sign.
+fn scope_chat(scope_id: u64) -> ChatId {
+    ChatId(-(scope_id as i64))
+}
+
+/// When a request created at `created_at` expires, `None` without a TTL.
+fn pending_expiry(created_at: &str, ttl_hours: u32) -> Option<String> {
+    if ttl_hours == 0 {
+        return None;
+    }
+    let created = chrono::NaiveDateTime::parse_from_str(created_at, "%Y-%m-%d %H:%M:%S").ok()?;
+    let expires = created + chrono::Duration::hours(ttl_hours as i64);
+    Some(expires.format("%Y-%m-%d %H:%M").to_string())
+}
+
+async fn handle_cost_command(
+    msg: &teloxide::types::Message,
+    bot: &Bot,
+    state: &AppState,
+    text: &str,
+    user_id: u64,
+) -> ResponseResult<()> {
+    let mut args = text.split_whitespace().skip(1);
+    let sub = args.next();
+    if sub.is_some()
+        && !state.config.allowed_users.is_empty()
+        && !state.config.allowed_users.contains(&user_id)
+    {
+        bot.send_message(msg.chat.id, "Only whitelisted users can view cost breakdowns.").await?;
+        return Ok(());
+    }
+    match sub {
+        None => send_monthly_cost(msg, bot, state).await,
+        Some("users") => send_cost_breakdown(msg, bot, state, UsageGroup::User).await,
+        Some("chats") => send_cost_breakdown(msg, bot, state, UsageGroup::Chat).await,
+        Some("days") => send_cost_breakdown(msg, bot, state, UsageGroup::Day).await,
+        Some("export") => send_usage_csv(msg, bot, state, args.next() == Some("all")).await,
+        Some(_) => {
+            bot.send_message(msg.chat.id, "Usage: /cost [users|chats|days|export [all]]").await?;
+            Ok(())
+        }
+    }
+}
+
+/// Spend this month per model.
+async fn send_monthly_cost(
+    msg: &teloxide::types::Message,
+    bot: &Bot,
+    state: &AppState,
+) -> ResponseResult<()> {
+    use crate::provider::model_registry;
+
+    let usage_data = state.dbs.main().get_monthly_usage().await.unwrap_or_default();
+    if usage_data.is_empty() {
+        bot.send_message(msg.chat.id, "📊 No usage recorded this month.").await?;
+        return Ok(());
+    }
+
+    let now = chrono::Utc::now();
+    let month_label = now.format("%m/%Y");
     let mut lines = vec![format!("📊 Chi phí sử dụng (tháng {month_label})\n")];
     let mut grand_total = 0.0f64;
     let mut total_requests = 0u64;
 
-    for (model, _provider, prompt, completion, cache_write, cache_read, requests) in &usage_data {
+    for usage in &usage_data {
         let (ci, co, cw, cr) = model_registry::calculate_cost(
-            model, *prompt, *completion, *cache_write, *cache_read,
+            &usage.model,
+            usage.prompt_tokens,
+            usage.completion_tokens,
+            usage.cache_creation_tokens,
+            usage.cache_read_tokens,
         );
         let subtotal = ci + co + cw + cr;
         grand_total += subtotal;
-        total_requests += requests;
+        total_requests += usage.requests;
 
-        let model_info = model_registry::resolve_model(model);
-        let label = model_info.map(|m| m.label).unwrap_or(model.as_str());
+        let model_info = model_registry::resolve_model(&usage.model);
+        let label = model_info.map(|m| m.label).unwrap_or(usage.model.as_str());
         let pricing_label = model_info
             .map(|m| {
                 let (pi, po, _pw, pr) = m.pricing;
@@ -1769,11 +2727,11 @@ async fn handle_cost_command(
             })
             .unwrap_or_default();
 
-        lines.push(format!("{label}{pricing_label} — {requests} req"));
-        lines.push(format!("  Input: {} tokens (${:.4})", format_tokens(*prompt), ci));
-        lines.push(format!("  Output: {} tokens (${:.4})", format_tokens(*completion), co));
-        lines.push(format!("  Cache write: {} tokens (${:.4})", format_tokens(*cache_write), cw));
-        lines.push(format!("  Cache read: {} tokens (${:.4})", format_tokens(*cache_read), cr));
+        lines.push(format!("{label}{pricing_label} — {} req", usage.requests));
+        lines.push(format!("  Input: {} tokens (${:.4})", format_tokens(usage.prompt_tokens), ci));
+        lines.push(format!("  Output: {} tokens (${:.4})", format_tokens(usage.completion_tokens), co));
+        lines.push(format!("  Cache write: {} tokens (${:.4})", format_tokens(usage.cache_creation_tokens), cw));
+        lines.push(format!("  Cache read: {} tokens (${:.4})", format_tokens(usage.cache_read_tokens), cr));
         lines.push(format!("  → Subtotal: ${:.4}\n", subtotal));
     }
 
@@ -1784,6 +2742,97 @@ async fn handle_cost_command(
     Ok(())
 }
 
+/// Spend this month per user, chat or day, with the models behind each.
+async fn send_cost_breakdown(
+    msg: &teloxide::types::Message,
+    bot: &Bot,
+    state: &AppState,
+    group: UsageGroup,
+) -> ResponseResult<()> {
+    use crate::provider::model_registry;
+
+    let rows = state.dbs.main().get_monthly_usage_by(group).await.unwrap_or_default();
+    if rows.is_empty() {
+        bot.send_message(msg.chat.id, "📊 No usage recorded this month.").await?;
+        return Ok(());
+    }
+
+    // key -> (cost, requests, per-model lines); rows arrive sorted by key
+    let mut groups: Vec<(Option<String>, f64, u64, Vec<String>)> = Vec::new();
+    for row in &rows {
+        if groups.last().is_none_or(|g| g.0 != row.key) {
+            groups.push((row.key.clone(), 0.0, 0, Vec::new()));
+        }
+        let group = groups.last_mut().expect("pushed above");
+        let cost = row.usage.cost_usd();
+        group.1 += cost;
+        group.2 += row.usage.requests;
+        let label = model_registry::resolve_model(&row.usage.model)
+            .map(|m| m.label)
+            .unwrap_or(row.usage.model.as_str());
+        let tokens = row.usage.prompt_tokens + row.usage.completion_tokens;
+        group.3.push(format!("  {label}: ${cost:.4} — {} req, {} tokens", row.usage.requests, format_tokens(tokens)));
+    }
+    // Days read best in order; users and chats by who spent the most
+    if group != UsageGroup::Day {
+        groups.sort_by(|a, b| b.1.total_cmp(&a.1));
+    }
+
+    let (title, prefix) = match group {
+        UsageGroup::User => ("người dùng", "User "),
+        UsageGroup::Chat => ("chat", "Chat "),
+        UsageGroup::Day => ("ngày", ""),
+    };
+    let month_label = chrono::Utc::now().format("%m/%Y");
+    let mut lines = vec![format!("📊 Chi phí theo {title} (tháng {month_label})\n")];
+    let mut grand_total = 0.0f64;
+    for (key, cost, requests, models) in &groups {
+        grand_total += cost;
+        let name = key.as_deref().map(|k| format!("{prefix}{k}")).unwrap_or_else(|| "Unattributed".to_string());
+        lines.push(format!("{name} — ${cost:.4} ({requests} req)"));
+        lines.extend(models.iter().cloned());
+    }
+    lines.push(format!("\n💰 Tổng tháng: ${:.4}", grand_total));
+
+    for chunk in formatter::split_message(&lines.join("\n"), 4096) {
+        bot.send_message(msg.chat.id, &chunk).await?;
+    }
+    Ok(())
+}
+
+/// Send the usage log as CSV: this month, or everything with `all`.
+async fn send_usage_csv(
+    msg: &teloxide::types::Message,
+    bot: &Bot,
+    state: &AppState,
+    all: bool,
+) -> ResponseResult<()> {
+    let now = chrono::Utc::now();
+    let since = (!all).then(|| now.format("%Y-%m-01").to_string());
+    let records = match state.dbs.main().list_usage(since.as_deref()).await {
+        Ok(r) => r,
+        Err(e) => {
+            bot.send_message(msg.chat.id, format!("Export failed: {e}")).await?;
+            return Ok(());
+        }
+    };
+    if records.is_empty() {
+        bot.send_message(msg.chat.id, "📊 No usage recorded.").await?;
+        return Ok(());
+    }
+
+    let csv = crate::db::usage::usage_csv(&records);
+    let file_name = if all {
+        format!("usage-all-{}.csv", now.format("%Y%m%d"))
+    } else {
+        format!("usage-{}.csv", now.format("%Y-%m"))
+    };
+    let caption = format!("{} usage rows{}", records.len(), if all { "" } else { " this month" });
+    let file = teloxide::types::InputFile::memory(csv.into_bytes()).file_name(file_name);
+    bot.send_document(msg.chat.id, file).caption(caption).await?;
+    Ok(())
+}
+
 fn format_tokens(n: u64) -> String {
     if n >= 1_000_000 {
         format!("{:.1}M", n as f64 / 1_000_000.0)
@@ -1804,10 +2853,13 @@ async fn handle_model_command(
     use crate::provider::model_registry;
 
     let arg = text.strip_prefix("/model").unwrap_or("").trim();
+    let Some(db) = chat_db(bot, msg.chat.id, state, scope_id).await? else {
+        return Ok(());
+    };
 
     if arg.is_empty() {
         // Show current model + list available
-        let current = state.db.get_chat_model(scope_id);
+        let current = db.get_chat_model(scope_id).await;
         let current_info = model_registry::resolve_model(&current);
         let current_label = current_info.map(|m| m.label).unwrap_or("Unknown");
 
@@ -1850,7 +2902,7 @@ async fn handle_model_command(
                     return Ok(());
                 }
 
-                state.db.set_chat_model(scope_id, model_info.id);
+                let _ = db.set_chat_model(scope_id, model_info.id).await;
                 bot.send_message(
                     msg.chat.id,
                     format!("Model switched to *{}* (`{}`)", model_info.label, model_info.id),
@@ -1923,12 +2975,265 @@ async fn pdf_to_images(data: &[u8]) -> Option<Vec<Vec<u8>>> {
     if images.is_empty() { None } else { Some(images) }
 }
 
-/// Migrate existing documents that don't have chunks yet.
+/// Periodically purge trashed facts/documents older than `TRASH_RETENTION_DAYS`.
+/// Expire pending requests older than `PENDING_TTL_HOURS`, hourly, and tell
+/// the chats they came from.
+async fn expire_pending_requests(bot: &Bot, state: &AppState) {
+    let ttl_hours = state.config.pending_ttl_hours;
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+    loop {
+        interval.tick().await;
+        let dbs = match state.dbs.owner_data().await {
+            Ok(dbs) => dbs,
+            Err(e) => {
+                error!("Pending: failed to open databases: {e}");
+                continue;
+            }
+        };
+        for db in dbs {
+            let expired = match db.expire_pending(ttl_hours).await {
+                Ok(expired) => expired,
+                Err(e) => {
+                    error!("Pending: expiry failed: {e}");
+                    continue;
+                }
+            };
+            for item in expired {
+                info!("Pending: request #{} in {} expired", item.id, item.scope_id);
+                let text = format!("⌛ Request #{} expired without a decision: {}", item.id, item.summary);
+                if let Err(e) = bot.send_message(scope_chat(item.scope_id), text).await {
+                    warn!("Failed to tell chat {} about expired #{}: {e}", item.scope_id, item.id);
+                }
+            }
+        }
+    }
+}
+
+async fn purge_expired_trash(state: &AppState) {
+    let days = state.config.trash_retention_days;
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+    loop {
+        interval.tick().await;
+        let dbs = match state.dbs.owner_data().await {
+            Ok(dbs) => dbs,
+            Err(e) => {
+                error!("Trash: failed to open databases: {e}");
+                continue;
+            }
+        };
+        for db in dbs {
+            match db.purge_expired_trash(days).await {
+                Ok(0) => {}
+                Ok(n) => info!("Trash: purged {n} item(s) older than {days} days"),
+                Err(e) => error!("Trash: purge failed: {e}"),
+            }
+        }
+    }
+}
+
+/// Apply each chat's history retention (its `/retention` override, else the
+/// configured default) to every session with messages, hourly.
+async fn prune_session_history(state: &AppState) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+    loop {
+        interval.tick().await;
+        let dbs = match state.dbs.owner_data().await {
+            Ok(dbs) => dbs,
+            Err(e) => {
+                error!("Retention: failed to open databases: {e}");
+                continue;
+            }
+        };
+        for db in &dbs {
+            let sessions = match db.list_sessions_with_messages().await {
+                Ok(s) => s,
+                Err(e) => {
+                    error!("Retention: failed to list sessions: {e}");
+                    continue;
+                }
+            };
+            for session in &sessions {
+                let retention = match db.get_session_retention(session.user_id).await {
+                    Ok(Some(r)) => r,
+                    Ok(None) => state.config.session_retention,
+                    Err(e) => {
+                        error!("Retention: failed to load policy for {}: {e}", session.user_id);
+                        continue;
+                    }
+                };
+                let model = db.get_chat_model(session.user_id).await;
+                let usage_db = state.dbs.main();
+                match crate::tools::apply_session_retention(
+                    &state.pool,
+                    db,
+                    usage_db,
+                    session,
+                    retention,
+                    &model,
+                    &state.config.budget,
+                )
+                .await
+                {
+                    Ok(outcome) if outcome.pruned > 0 => info!(
+                        "Retention: pruned {} message(s) from session {}{}",
+                        outcome.pruned,
+                        session.id,
+                        if outcome.summarized { " (summarized)" } else { "" }
+                    ),
+                    Ok(_) => {}
+                    Err(e) => error!("Retention: session {} failed: {e}", session.id),
+                }
+            }
+        }
+    }
+}
+
+/// Garbage-collect entities left without mentions once a day.
+async fn collect_orphan_entities(state: &AppState) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(24 * 3600));
+    loop {
+        interval.tick().await;
+        let dbs = match state.dbs.owner_data().await {
+            Ok(dbs) => dbs,
+            Err(e) => {
+                error!("Entities: failed to open databases: {e}");
+                continue;
+            }
+        };
+        for db in dbs {
+            match db.gc_orphan_entities(None).await {
+                Ok(0) => {}
+                Ok(n) => info!("Entities: removed {n} orphaned entit{}", if n == 1 { "y" } else { "ies" }),
+                Err(e) => error!("Entities: GC failed: {e}"),
+            }
+        }
+    }
+}
+
+/// Flush embedding cache hits and misses every 5 minutes, then drop entries past
+/// `EMBEDDING_CACHE_MAX_AGE_DAYS` and the least recently used beyond `EMBEDDING_CACHE_MAX_ENTRIES`.
+async fn maintain_embedding_cache(state: &AppState) {
+    let db = state.dbs.main();
+    let config = &state.config;
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(300));
+    loop {
+        interval.tick().await;
+        if let Err(e) = db.flush_embedding_cache_usage().await {
+            error!("Embedding cache: failed to record usage: {e}");
+            continue;
+        }
+        match db.prune_embedding_cache(config.embedding_cache_max_entries, config.embedding_cache_max_age_days).await {
+            Ok(0) => {}
+            Ok(n) => info!("Embedding cache: pruned {n} entr{}", if n == 1 { "y" } else { "ies" }),
+            Err(e) => error!("Embedding cache: prune failed: {e}"),
+        }
+    }
+}
+
+/// Close cached owner files that have sat idle, every minute.
+async fn close_idle_databases(state: &AppState) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+    loop {
+        interval.tick().await;
+        let closed = state.dbs.close_idle().await;
+        if closed > 0 {
+            debug!("Closed {closed} idle owner database(s), {} still open", state.dbs.open_count().await);
+        }
+    }
+}
+
+/// Snapshot the database (and every owner file) every `BACKUP_INTERVAL_HOURS`,
+/// counting from the newest existing snapshot so restarts don't trigger an extra one.
+async fn scheduled_backups(state: &AppState) {
+    use crate::db::backup;
+
+    let dir = Path::new(&state.config.backup_dir);
+    let every = std::time::Duration::from_secs(state.config.backup_interval_hours * 3600);
+    let since_last = backup::list_backups(dir)
+        .ok()
+        .and_then(|list| list.first().map(|b| b.created_at))
+        .and_then(|last| (chrono::Utc::now().naive_utc() - last).to_std().ok());
+    let first = since_last.map_or(std::time::Duration::ZERO, |age| every.saturating_sub(age));
+
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + first, every);
+    loop {
+        interval.tick().await;
+        let run = state.dbs.backup_all(dir, backup_policy(&state.config)).await;
+        let owners = match run.owners {
+            Ok(owners) => owners.into_iter().map(|(_, result)| result).collect(),
+            Err(e) => {
+                error!("Backup: failed to list owner databases: {e}");
+                Vec::new()
+            }
+        };
+        for result in std::iter::once(run.main).chain(owners) {
+            match result {
+                Ok(report) => info!(
+                    "Backup: wrote {} ({} bytes), rotated out {}",
+                    report.backup.path.display(),
+                    report.backup.size_bytes,
+                    report.removed.len()
+                ),
+                Err(e) => error!("Backup: failed: {e}"),
+            }
+        }
+    }
+}
+
+/// Clear the stored vectors of every database whose embeddings came from
+/// another model than `embedder`'s, so they are embedded again.
+async fn check_embedding_model(dbs: &OwnerDatabases, embedder: &dyn Embedder) {
+    use crate::db::embedding_model::ModelCheck;
+
+    let dims = match embedder.embed_batch(&["embedding dimensions"], "document").await {
+        Ok(vectors) if !vectors.is_empty() => vectors[0].len(),
+        Ok(_) => return,
+        Err(e) => {
+            warn!("Could not check stored embeddings against {}: {e}", embedder.model());
+            return;
+        }
+    };
+    let dbs = match dbs.owner_data().await {
+        Ok(dbs) => dbs,
+        Err(e) => {
+            error!("Embedding model check: failed to open databases: {e}");
+            return;
+        }
+    };
+    for db in &dbs {
+        match db.check_embedding_model(embedder.model(), dims).await {
+            Ok(ModelCheck::Cleared { previous, facts, chunks }) => warn!(
+                "Embedding model changed from {previous} to {} ({dims} dims): cleared {facts} fact and {chunks} chunk \
+                 embedding(s) and the vector indexes; they are re-embedded in the background",
+                embedder.model()
+            ),
+            Ok(_) => {}
+            Err(e) => error!("Embedding model check failed: {e}"),
+        }
+    }
+}
+
+/// Migrate existing documents that don't have chunks yet, and embed chunks
+/// left without an embedding, in every database.
 async fn migrate_unchunked_docs(state: &AppState) {
+    match state.dbs.owner_data().await {
+        Ok(dbs) => {
+            for db in &dbs {
+                migrate_unchunked_docs_in(state, db).await;
+                if state.embedder.is_enabled() {
+                    embed_missing_chunks(state.embedder.as_ref(), db).await;
+                }
+            }
+        }
+        Err(e) => error!("Migration: failed to open databases: {e}"),
+    }
+}
+
+async fn migrate_unchunked_docs_in(state: &AppState, db: &Database) {
     use crate::tools::embedding::embedding_to_bytes;
     use crate::tools::knowledge::chunk_document;
 
-    let docs = match state.db.get_unchunked_doc_ids() {
+    let docs = match db.get_unchunked_documents().await {
         Ok(d) => d,
         Err(e) => {
             error!("Migration: failed to get unchunked docs: {e}");
@@ -1942,14 +3247,15 @@ async fn migrate_unchunked_docs(state: &AppState) {
 
     info!("Migration: chunking {} existing documents", docs.len());
 
-    for (doc_id, content) in &docs {
-        let chunks = chunk_document(content);
+    for doc in &docs {
+        let doc_id = doc.id;
+        let chunks = chunk_document(&doc.content);
         let chunk_data: Vec<(usize, usize, usize, &str)> = chunks
             .iter()
             .map(|c| (c.chunk_index, c.start_line, c.end_line, c.content.as_str()))
             .collect();
 
-        let chunk_ids = match state.db.save_chunks(*doc_id, &chunk_data) {
+        let chunk_ids = match db.save_chunks(doc_id, &chunk_data).await {
             Ok(ids) => ids,
             Err(e) => {
                 error!("Migration: failed to save chunks for doc {doc_id}: {e}");
@@ -1957,8 +3263,9 @@ async fn migrate_unchunked_docs(state: &AppState) {
             }
         };
 
-        // Embed if client available
-        if let Some(client) = &state.embedding_client {
+        // Embed if a backend is configured
+        let client = state.embedder.as_ref();
+        if client.is_enabled() {
             let texts: Vec<&str> = chunks.iter().map(|c| c.content.as_str()).collect();
             for (batch_start, batch_ids) in chunk_ids.chunks(128).enumerate() {
                 let start = batch_start * 128;
@@ -1969,7 +3276,7 @@ async fn migrate_unchunked_docs(state: &AppState) {
                     Ok(embeddings) => {
                         let blobs: Vec<Vec<u8>> =
                             embeddings.iter().map(|e| embedding_to_bytes(e)).collect();
-                        if let Err(e) = state.db.update_chunk_embeddings(batch_ids, &blobs) {
+                        if let Err(e) = db.update_chunk_embeddings(batch_ids, &blobs).await {
                             error!("Migration: failed to save embeddings: {e}");
                         }
                     }
@@ -1987,17 +3294,85 @@ async fn migrate_unchunked_docs(state: &AppState) {
     info!("Migration: completed chunking {} documents", docs.len());
 }
 
+/// Embed chunks that have no embedding (the model changed, or embedding
+/// failed when they were saved), 128 at a time.
+async fn embed_missing_chunks(client: &dyn Embedder, db: &Database) {
+    use crate::tools::embedding::embedding_to_bytes;
+
+    let mut embedded = 0;
+    loop {
+        let chunks = match db.get_unembedded_chunks(128).await {
+            Ok(chunks) if !chunks.is_empty() => chunks,
+            Ok(_) => break,
+            Err(e) => {
+                error!("Migration: failed to get unembedded chunks: {e}");
+                break;
+            }
+        };
+        let ids: Vec<i64> = chunks.iter().map(|(id, _)| *id).collect();
+        let texts: Vec<&str> = chunks.iter().map(|(_, content)| content.as_str()).collect();
+        let saved = match client.embed_batch(&texts, "document").await {
+            Ok(embeddings) if embeddings.len() != ids.len() => {
+                Err(format!("{} embeddings for {} chunks", embeddings.len(), ids.len()))
+            }
+            Ok(embeddings) => {
+                let blobs: Vec<Vec<u8>> = embeddings.iter().map(|e| embedding_to_bytes(e)).collect();
+                db.update_chunk_embeddings(&ids, &blobs).await.map_err(|e| e.to_string())
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = saved {
+            // Stop rather than retry the same chunks forever; the next start picks them up
+            error!("Migration: chunk embedding failed: {e}");
+            break;
+        }
+        embedded += ids.len();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    if embedded > 0 {
+        info!("Migration: embedded {embedded} chunk(s)");
+    }
+}
+
+/// Build missing vector indexes in every database, so the first search after
+/// an upgrade (or a lost index) does not pay for the build.
+async fn build_vector_indexes(state: &AppState) {
+    let dbs = match state.dbs.owner_data().await {
+        Ok(dbs) => dbs,
+        Err(e) => {
+            error!("Vector indexes: failed to open databases: {e}");
+            return;
+        }
+    };
+    for db in &dbs {
+        match db.ensure_vector_indexes().await {
+            Ok(0) => {}
+            Ok(built) => info!("Vector indexes: built {built} missing index(es)"),
+            Err(e) => error!("Vector indexes: build failed: {e}"),
+        }
+    }
+}
+
 /// Migrate existing facts that don't have embeddings yet, then compute relations
-/// only for newly embedded facts.
+/// only for newly embedded facts. Runs over every database.
 async fn migrate_fact_embeddings(state: &AppState) {
-    use crate::tools::embedding::{embedding_to_bytes, bytes_to_embedding, cosine_similarity};
+    if !state.embedder.is_enabled() {
+        return;
+    }
+    match state.dbs.owner_data().await {
+        Ok(dbs) => {
+            for db in &dbs {
+                migrate_fact_embeddings_in(state.embedder.as_ref(), db).await;
+            }
+        }
+        Err(e) => error!("Fact embedding migration: failed to open databases: {e}"),
+    }
+}
 
-    let client = match &state.embedding_client {
-        Some(c) => c,
-        None => return,
-    };
+async fn migrate_fact_embeddings_in(client: &dyn Embedder, db: &Database) {
+    use crate::tools::embedding::{embedding_to_bytes, bytes_to_embedding, cosine_similarity};
 
-    let user_ids = match state.db.get_fact_user_ids() {
+    let user_ids = match db.get_fact_user_ids().await {
         Ok(ids) => ids,
         Err(e) => {
             error!("Fact embedding migration: failed to get user_ids: {e}");
@@ -2006,7 +3381,7 @@ async fn migrate_fact_embeddings(state: &AppState) {
     };
 
     for user_id in user_ids {
-        let facts = match state.db.get_unembedded_facts(user_id) {
+        let facts = match db.get_unembedded_facts(user_id).await {
             Ok(f) => f,
             Err(e) => {
                 error!("Fact embedding migration: failed to get facts for user {user_id}: {e}");
@@ -2023,20 +3398,20 @@ async fn migrate_fact_embeddings(state: &AppState) {
         let mut new_fact_ids: Vec<i64> = Vec::new();
 
         for batch in facts.chunks(128) {
-            let texts: Vec<&str> = batch.iter().map(|(_, text)| text.as_str()).collect();
+            let texts: Vec<&str> = batch.iter().map(|f| f.text.as_str()).collect();
             match client.embed_batch(&texts, "document").await {
                 Ok(embeddings) => {
-                    for ((fact_id, _), emb) in batch.iter().zip(embeddings.iter()) {
+                    for (fact, emb) in batch.iter().zip(embeddings.iter()) {
                         let blob = embedding_to_bytes(emb);
-                        if let Err(e) = state.db.update_fact_embedding(*fact_id, &blob) {
-                            error!("Fact embedding migration: failed to save embedding for fact {fact_id}: {e}");
+                        if let Err(e) = db.update_fact_embedding(fact.id, &blob).await {
+                            error!("Fact embedding migration: failed to save embedding for fact {}: {e}", fact.id);
                         } else {
-                            new_fact_ids.push(*fact_id);
+                            new_fact_ids.push(fact.id);
                         }
                     }
                 }
                 Err(e) => {
-                    error!("Fact embedding migration: Voyage API failed for user {user_id}: {e}");
+                    error!("Fact embedding migration: embedding failed for user {user_id}: {e}");
                     break;
                 }
             }
@@ -2053,14 +3428,14 @@ async fn migrate_fact_embeddings(state: &AppState) {
             new_fact_ids.len()
         );
 
-        let all_facts = match state.db.load_all_fact_embeddings(user_id) {
+        let all_facts = match db.load_all_fact_embeddings(user_id).await {
             Ok(f) => f,
             Err(_) => continue,
         };
 
         let all_embeddings: Vec<(i64, Vec<f32>)> = all_facts
             .iter()
-            .map(|(id, _, _, blob)| (*id, bytes_to_embedding(blob)))
+            .map(|(fact, blob)| (fact.id, bytes_to_embedding(blob)))
             .collect();
 
         // Run cosine on blocking thread to avoid starving tokio
@@ -2093,7 +3468,7 @@ async fn migrate_fact_embeddings(state: &AppState) {
         .unwrap_or_default();
 
         if !links.is_empty() {
-            if let Err(e) = state.db.link_facts_batch(&links) {
+            if let Err(e) = db.link_facts_batch(&links).await {
                 error!("Fact embedding migration: failed to batch-link facts: {e}");
             }
         }
@@ -0,0 +1,160 @@
+use crate::db::audit::{AuditEntry, AuditFilter, AuditState};
+use crate::db::{Database, Repository};
+use crate::tools::Embedder;
+
+use super::knowledge::index_document;
+use super::memory::reembed_fact;
+
+/// Entries listed when the model gives no limit.
+const DEFAULT_SEARCH_LIMIT: usize = 10;
+const MAX_SEARCH_LIMIT: usize = 50;
+/// Characters of arguments, results and texts shown per entry.
+const PREVIEW_CHARS: usize = 80;
+
+pub async fn audit_search(
+    db: &Database,
+    kb_owner_id: u64,
+    query: Option<&str>,
+    tool: Option<&str>,
+    user_id: Option<u64>,
+    limit: Option<usize>,
+) -> String {
+    let filter = AuditFilter {
+        user_id,
+        tool: tool.filter(|t| !t.is_empty()).map(str::to_string),
+        text: query.filter(|q| !q.is_empty()).map(str::to_string),
+    };
+    let limit = limit.unwrap_or(DEFAULT_SEARCH_LIMIT).clamp(1, MAX_SEARCH_LIMIT);
+    match db.search_audit(kb_owner_id, filter, limit).await {
+        Ok(entries) if entries.is_empty() => "No audit entries found.".into(),
+        Ok(entries) => {
+            let mut out = format!("{} audit entry(ies), newest first:\n", entries.len());
+            for entry in &entries {
+                out.push_str(&describe_entry(entry));
+            }
+            out
+        }
+        Err(e) => format!("Error: {e}"),
+    }
+}
+
+/// Reverse `user_id`'s most recent write to the knowledge base (`/undo`),
+/// then re-embed the facts and re-index the documents whose text came back.
+pub async fn undo_last_write(
+    db: &Database,
+    user_id: u64,
+    kb_owner_id: u64,
+    embedder: &dyn Embedder,
+) -> String {
+    let undo = match db.undo_last_write(user_id, kb_owner_id).await {
+        Ok(Some(undo)) => undo,
+        Ok(None) => return "Nothing to undo.".into(),
+        Err(e) => return format!("Error: {e}"),
+    };
+    let (before, after) = (&undo.entry.before, &undo.entry.after);
+
+    for fact in after.facts.iter().filter(|f| !f.trashed) {
+        if before.facts.iter().any(|old| old.id == fact.id && old.text != fact.text) {
+            reembed_fact(db, kb_owner_id, fact.id, &fact.text, embedder).await;
+        }
+    }
+    for doc in after.documents.iter().filter(|d| !d.trashed) {
+        if before.documents.iter().any(|old| old.id == doc.id && old.version != doc.version) {
+            // Unchunked documents are picked up again by the startup migration
+            if let Ok(document) = db.get_document(kb_owner_id, doc.id).await
+                && let Err(e) = index_document(db, doc.id, &document.content, embedder).await
+            {
+                tracing::warn!("Re-chunking document #{} after undo failed: {e}", doc.id);
+            }
+        }
+    }
+
+    let mut out = format!("Undid #{} ({}):", undo.reversed.id, undo.reversed.tool);
+    for line in describe_changes(before, after) {
+        out.push_str(&format!("\n- {line}"));
+    }
+    out
+}
+
+/// A header line, then one indented line per changed row.
+fn describe_entry(entry: &AuditEntry) -> String {
+    let mut out = format!(
+        "#{} | {} | user {} | {} {}",
+        entry.id,
+        entry.created_at,
+        entry.user_id,
+        entry.tool,
+        preview(&entry.args_json)
+    );
+    if let Some(undo_id) = entry.undone_by {
+        out.push_str(&format!(" [undone by #{undo_id}]"));
+    }
+    out.push('\n');
+    let changes = describe_changes(&entry.before, &entry.after);
+    if changes.is_empty() {
+        out.push_str(&format!("  no changes: {}\n", preview(&entry.result)));
+    }
+    for line in changes {
+        out.push_str(&format!("  {line}\n"));
+    }
+    out
+}
+
+/// One line per fact, document and category that differs between the sides.
+pub fn describe_changes(before: &AuditState, after: &AuditState) -> Vec<String> {
+    let mut lines = Vec::new();
+    let trash = |trashed: bool| if trashed { "moved to trash" } else { "restored from trash" };
+
+    for new in &after.facts {
+        let Some(old) = before.facts.iter().find(|f| f.id == new.id) else {
+            lines.push(format!("memory #{} saved: \"{}\"", new.id, preview(&new.text)));
+            continue;
+        };
+        if old.text != new.text {
+            lines.push(format!("memory #{} edited: \"{}\" → \"{}\"", new.id, preview(&old.text), preview(&new.text)));
+        }
+        if old.category != new.category {
+            lines.push(format!("memory #{} category: {} → {}", new.id, old.category, new.category));
+        }
+        if old.trashed != new.trashed {
+            lines.push(format!("memory #{} {}", new.id, trash(new.trashed)));
+        }
+    }
+    for old in before.facts.iter().filter(|f| !after.facts.iter().any(|new| new.id == f.id)) {
+        lines.push(format!("memory #{} permanently deleted: \"{}\"", old.id, preview(&old.text)));
+    }
+
+    for new in &after.documents {
+        let Some(old) = before.documents.iter().find(|d| d.id == new.id) else {
+            lines.push(format!("document #{} saved: \"{}\"", new.id, new.title));
+            continue;
+        };
+        if old.title != new.title {
+            lines.push(format!("document #{} renamed: \"{}\" → \"{}\"", new.id, old.title, new.title));
+        }
+        if old.version != new.version {
+            lines.push(format!("document #{} \"{}\": v{} → v{}", new.id, new.title, old.version, new.version));
+        }
+        if (&old.source, &old.tags) != (&new.source, &new.tags) {
+            lines.push(format!("document #{} source/tags changed", new.id));
+        }
+        if old.trashed != new.trashed {
+            lines.push(format!("document #{} {}", new.id, trash(new.trashed)));
+        }
+    }
+    for old in before.documents.iter().filter(|d| !after.documents.iter().any(|new| new.id == d.id)) {
+        lines.push(format!("document #{} permanently deleted: \"{}\"", old.id, old.title));
+    }
+
+    lines.extend(after.categories.iter().map(|name| format!("category '{name}' added")));
+    lines.extend(before.categories.iter().map(|name| format!("category '{name}' deleted")));
+    lines
+}
+
+fn preview(text: &str) -> String {
+    let mut out: String = text.chars().take(PREVIEW_CHARS).collect();
+    if text.chars().count() > PREVIEW_CHARS {
+        out.push('…');
+    }
+    out
+}
@@ -0,0 +1,160 @@
+use chrono::{DateTime, Utc};
+
+use crate::db::{BudgetSubject, Database, DbResult, Repository, SpendLimits};
+
+/// What to do with a request once a budget is used up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverBudgetAction {
+    /// Keep answering, but with the cheaper fallback model.
+    #[default]
+    Fallback,
+    /// Refuse until the period rolls over or the budget is raised.
+    Refuse,
+}
+
+impl OverBudgetAction {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "fallback" => Some(Self::Fallback),
+            "refuse" => Some(Self::Refuse),
+            _ => None,
+        }
+    }
+}
+
+/// Configured budgets. Owner and user limits are defaults that `/budget`
+/// overrides per subject.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetPolicy {
+    pub owner: SpendLimits,
+    pub user: SpendLimits,
+    pub action: OverBudgetAction,
+    pub fallback_model: String,
+    /// Share of a budget (in percent) at which admins are alerted.
+    pub alert_percent: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetPeriod {
+    Day,
+    Month,
+}
+
+impl BudgetPeriod {
+    /// First day of the period containing `now`, as `YYYY-MM-DD`.
+    pub fn start(&self, now: DateTime<Utc>) -> String {
+        match self {
+            BudgetPeriod::Day => now.format("%Y-%m-%d").to_string(),
+            BudgetPeriod::Month => now.format("%Y-%m-01").to_string(),
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            BudgetPeriod::Day => "daily",
+            BudgetPeriod::Month => "monthly",
+        }
+    }
+}
+
+/// Spend against one limit in the current period.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetUsage {
+    pub subject: BudgetSubject,
+    pub period: BudgetPeriod,
+    pub spent_usd: f64,
+    pub limit_usd: f64,
+}
+
+impl BudgetUsage {
+    pub fn percent(&self) -> f64 {
+        if self.limit_usd > 0.0 { self.spent_usd / self.limit_usd * 100.0 } else { 0.0 }
+    }
+
+    pub fn exceeded(&self) -> bool {
+        self.spent_usd >= self.limit_usd
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BudgetDecision {
+    Allow,
+    /// Over budget: answer with `model` instead of the chat's model.
+    Fallback { model: String, exceeded: BudgetUsage },
+    Refuse(BudgetUsage),
+}
+
+/// Result of [`check_budget`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetCheck {
+    pub decision: BudgetDecision,
+    /// Limits that just crossed the alert threshold. Each is returned once
+    /// per period, so the caller can notify admins without repeating itself.
+    pub alerts: Vec<BudgetUsage>,
+}
+
+/// Limits in force for `subject`: its override, or the configured default.
+pub async fn effective_limits(db: &Database, policy: &BudgetPolicy, subject: BudgetSubject) -> DbResult<SpendLimits> {
+    let default = match subject {
+        BudgetSubject::Owner(_) => policy.owner,
+        BudgetSubject::User(_) => policy.user,
+    };
+    Ok(db.get_spend_limits(subject).await?.unwrap_or(default))
+}
+
+/// Spend against each limit in force for `subject`, daily first.
+pub async fn budget_usage(
+    db: &Database,
+    policy: &BudgetPolicy,
+    subject: BudgetSubject,
+    now: DateTime<Utc>,
+) -> DbResult<Vec<BudgetUsage>> {
+    let limits = effective_limits(db, policy, subject).await?;
+    let mut usage = Vec::new();
+    for (period, limit) in [(BudgetPeriod::Day, limits.daily_usd), (BudgetPeriod::Month, limits.monthly_usd)] {
+        let Some(limit_usd) = limit else { continue };
+        let rows = db.usage_since(subject, &period.start(now)).await?;
+        let spent_usd = rows.iter().map(|u| u.cost_usd()).sum();
+        usage.push(BudgetUsage { subject, period, spent_usd, limit_usd });
+    }
+    Ok(usage)
+}
+
+/// Check the chat's and the user's budgets before answering a message.
+pub async fn check_budget(
+    db: &Database,
+    policy: &BudgetPolicy,
+    user_id: u64,
+    kb_owner_id: u64,
+    now: DateTime<Utc>,
+) -> DbResult<BudgetCheck> {
+    let mut usage = budget_usage(db, policy, BudgetSubject::Owner(kb_owner_id), now).await?;
+    usage.extend(budget_usage(db, policy, BudgetSubject::User(user_id), now).await?);
+
+    let mut alerts = Vec::new();
+    for u in &usage {
+        if u.percent() >= policy.alert_percent as f64 {
+            let period = format!("{}:{}", u.period.label(), u.period.start(now));
+            if db.record_budget_alert(u.subject, &period).await? {
+                alerts.push(u.clone());
+            }
+        }
+    }
+
+    let decision = match usage.into_iter().find(BudgetUsage::exceeded) {
+        None => BudgetDecision::Allow,
+        Some(exceeded) => match policy.action {
+            OverBudgetAction::Fallback => BudgetDecision::Fallback { model: policy.fallback_model.clone(), exceeded },
+            OverBudgetAction::Refuse => BudgetDecision::Refuse(exceeded),
+        },
+    };
+    Ok(BudgetCheck { decision, alerts })
+}
+
+/// "chat 123" / "user 456", for messages.
+pub fn describe_subject(subject: BudgetSubject) -> String {
+    match subject {
+        BudgetSubject::Owner(id) => format!("chat {id}"),
+        BudgetSubject::User(id) => format!("user {id}"),
+    }
+}
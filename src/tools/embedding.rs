@@ -1,19 +1,95 @@
+//! Embedding backends behind [`Embedder`]: Voyage AI, any OpenAI-compatible
+//! `/embeddings` endpoint (OpenAI, Gemini, Ollama, llama.cpp) and a
+//! deterministic hashing embedder for offline tests. [`CachedEmbedder`] puts
+//! the `embedding_cache` table in front of any of them; [`DisabledEmbedder`]
+//! stands in when none is configured.
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+
 use serde::{Deserialize, Serialize};
-use tracing::debug;
+use tracing::{debug, warn};
 
-pub struct EmbeddingClient {
-    client: reqwest::Client,
-    api_key: String,
-    model: String,
+use crate::db::Database;
+use crate::db::embedding_cache::{TextHash, text_hash};
+
+/// What [`Embedder::embed_batch`] returns.
+pub type EmbedFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<Vec<f32>>, String>> + Send + 'a>>;
+
+/// Turns text into vectors. Call sites take `&dyn Embedder`, whichever
+/// backend `EMBEDDING_BACKEND` picked, and skip the semantic path when it is
+/// not [`enabled`](Embedder::is_enabled).
+pub trait Embedder: Send + Sync {
+    /// Model name. Part of the cache key, so it must differ between models
+    /// whose vectors differ.
+    fn model(&self) -> &str;
+
+    /// Embed a batch of texts, one vector per text in order. `input_type`
+    /// should be "document" or "query"; backends without the distinction ignore it.
+    fn embed_batch<'a>(&'a self, texts: &'a [&'a str], input_type: &'a str) -> EmbedFuture<'a>;
+
+    /// False only for [`DisabledEmbedder`].
+    fn is_enabled(&self) -> bool {
+        true
+    }
+}
+
+impl dyn Embedder + '_ {
+    /// Embed a single query text.
+    pub async fn embed_query(&self, text: &str) -> Result<Vec<f32>, String> {
+        let results = self.embed_batch(&[text], "query").await?;
+        results
+            .into_iter()
+            .next()
+            .ok_or_else(|| "Empty embedding response".to_string())
+    }
+}
+
+/// Which backend to embed with, from `EMBEDDING_BACKEND` and friends.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmbeddingBackend {
+    Voyage { api_key: String, model: String },
+    /// `POST {base_url}/embeddings` in the OpenAI format.
+    OpenAiCompatible { base_url: String, api_key: Option<String>, model: String },
+    Hashing { dims: usize },
+}
+
+impl EmbeddingBackend {
+    pub fn build(&self) -> Box<dyn Embedder> {
+        match self {
+            EmbeddingBackend::Voyage { api_key, model } => Box::new(VoyageEmbedder::new(api_key.clone(), model.clone())),
+            EmbeddingBackend::OpenAiCompatible { base_url, api_key, model } => {
+                Box::new(OpenAiEmbedder::new(base_url.clone(), api_key.clone(), model.clone()))
+            }
+            EmbeddingBackend::Hashing { dims } => Box::new(HashingEmbedder::new(*dims)),
+        }
+    }
+
+    /// For logs and the system prompt.
+    pub fn describe(&self) -> String {
+        match self {
+            EmbeddingBackend::Voyage { model, .. } => format!("Voyage AI ({model})"),
+            EmbeddingBackend::OpenAiCompatible { base_url, model, .. } => format!("{model} at {base_url}"),
+            EmbeddingBackend::Hashing { dims } => format!("hashing ({dims} dims, offline)"),
+        }
+    }
 }
 
 #[derive(Serialize)]
-struct EmbeddingRequest<'a> {
+struct VoyageRequest<'a> {
     model: &'a str,
     input: &'a [&'a str],
     input_type: &'a str,
 }
 
+#[derive(Serialize)]
+struct OpenAiRequest<'a> {
+    model: &'a str,
+    input: &'a [&'a str],
+    encoding_format: &'static str,
+}
+
 #[derive(Deserialize)]
 struct EmbeddingResponse {
     data: Vec<EmbeddingData>,
@@ -21,10 +97,47 @@ struct EmbeddingResponse {
 
 #[derive(Deserialize)]
 struct EmbeddingData {
+    #[serde(default)]
+    index: usize,
     embedding: Vec<f32>,
 }
 
-impl EmbeddingClient {
+/// POST `body` to an embeddings endpoint; both Voyage and the OpenAI format
+/// answer with `data[].embedding`.
+async fn post_embeddings(
+    client: &reqwest::Client,
+    url: &str,
+    api_key: Option<&str>,
+    body: &impl Serialize,
+    api: &str,
+) -> Result<Vec<Vec<f32>>, String> {
+    let mut request = client.post(url).json(body);
+    if let Some(key) = api_key {
+        request = request.header("Authorization", format!("Bearer {key}"));
+    }
+    let resp = request.send().await.map_err(|e| format!("{api} API request failed: {e}"))?;
+
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        return Err(format!("{api} API error {status}: {body}"));
+    }
+
+    let mut result: EmbeddingResponse = resp
+        .json()
+        .await
+        .map_err(|e| format!("{api} API parse error: {e}"))?;
+    result.data.sort_by_key(|d| d.index);
+    Ok(result.data.into_iter().map(|d| d.embedding).collect())
+}
+
+pub struct VoyageEmbedder {
+    client: reqwest::Client,
+    api_key: String,
+    model: String,
+}
+
+impl VoyageEmbedder {
     pub fn new(api_key: String, model: String) -> Self {
         Self {
             client: reqwest::Client::new(),
@@ -33,59 +146,229 @@ impl EmbeddingClient {
         }
     }
 
-    /// Embed a batch of texts. `input_type` should be "document" or "query".
-    pub async fn embed_batch(
-        &self,
-        texts: &[&str],
-        input_type: &str,
-    ) -> Result<Vec<Vec<f32>>, String> {
+    async fn request(&self, texts: &[&str], input_type: &str) -> Result<Vec<Vec<f32>>, String> {
         if texts.is_empty() {
             return Ok(vec![]);
         }
-
         debug!(
             "Embedding {} texts (type={}, model={})",
             texts.len(),
             input_type,
             self.model
         );
-
-        let body = EmbeddingRequest {
+        let body = VoyageRequest {
             model: &self.model,
             input: texts,
             input_type,
         };
+        post_embeddings(
+            &self.client,
+            "https://api.voyageai.com/v1/embeddings",
+            Some(&self.api_key),
+            &body,
+            "Voyage",
+        )
+        .await
+    }
+}
+
+impl Embedder for VoyageEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn embed_batch<'a>(&'a self, texts: &'a [&'a str], input_type: &'a str) -> EmbedFuture<'a> {
+        Box::pin(self.request(texts, input_type))
+    }
+}
+
+/// OpenAI's `/v1/embeddings`, or any server speaking it: Gemini's OpenAI
+/// endpoint, Ollama, llama.cpp's `llama-server`.
+pub struct OpenAiEmbedder {
+    client: reqwest::Client,
+    /// Up to and including the version, e.g. `https://api.openai.com/v1`.
+    base_url: String,
+    /// Local servers usually need none.
+    api_key: Option<String>,
+    model: String,
+}
+
+impl OpenAiEmbedder {
+    pub fn new(base_url: String, api_key: Option<String>, model: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            model,
+        }
+    }
+
+    async fn request(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, String> {
+        if texts.is_empty() {
+            return Ok(vec![]);
+        }
+        debug!("Embedding {} texts (model={}, base_url={})", texts.len(), self.model, self.base_url);
+        let body = OpenAiRequest {
+            model: &self.model,
+            input: texts,
+            encoding_format: "float",
+        };
+        let url = format!("{}/embeddings", self.base_url);
+        post_embeddings(&self.client, &url, self.api_key.as_deref(), &body, "Embedding").await
+    }
+}
 
-        let resp = self
-            .client
-            .post("https://api.voyageai.com/v1/embeddings")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| format!("Voyage API request failed: {e}"))?;
-
-        let status = resp.status();
-        if !status.is_success() {
-            let body = resp.text().await.unwrap_or_default();
-            return Err(format!("Voyage API error {status}: {body}"));
+impl Embedder for OpenAiEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn embed_batch<'a>(&'a self, texts: &'a [&'a str], _input_type: &'a str) -> EmbedFuture<'a> {
+        Box::pin(self.request(texts))
+    }
+}
+
+/// Feature hashing of the lowercased words: the same text always gets the
+/// same vector and texts sharing words come out similar. No network and no
+/// model, so it is only good for offline tests and trying the bot out.
+pub struct HashingEmbedder {
+    dims: usize,
+    model: String,
+}
+
+impl HashingEmbedder {
+    pub fn new(dims: usize) -> Self {
+        let dims = dims.max(1);
+        Self {
+            dims,
+            model: format!("hashing-{dims}"),
         }
+    }
 
-        let result: EmbeddingResponse = resp
-            .json()
-            .await
-            .map_err(|e| format!("Voyage API parse error: {e}"))?;
+    pub fn embed(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0f32; self.dims];
+        let lower = text.to_lowercase();
+        for word in lower.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+            // FNV-1a: stable across builds and platforms, unlike std's hasher
+            let hash = word
+                .bytes()
+                .fold(0xcbf2_9ce4_8422_2325u64, |h, b| (h ^ b as u64).wrapping_mul(0x0100_0000_01b3));
+            let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+            vector[(hash % self.dims as u64) as usize] += sign;
+        }
+        let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|x| *x /= norm);
+        }
+        vector
+    }
+}
 
-        Ok(result.data.into_iter().map(|d| d.embedding).collect())
+impl Embedder for HashingEmbedder {
+    fn model(&self) -> &str {
+        &self.model
     }
 
-    /// Embed a single query text.
-    pub async fn embed_query(&self, text: &str) -> Result<Vec<f32>, String> {
-        let results = self.embed_batch(&[text], "query").await?;
-        results
+    fn embed_batch<'a>(&'a self, texts: &'a [&'a str], _input_type: &'a str) -> EmbedFuture<'a> {
+        Box::pin(std::future::ready(Ok(texts.iter().map(|t| self.embed(t)).collect())))
+    }
+}
+
+/// The embedder when no backend is configured: searches fall back to
+/// keywords and nothing is embedded. Every call fails.
+pub struct DisabledEmbedder;
+
+impl Embedder for DisabledEmbedder {
+    fn model(&self) -> &str {
+        "disabled"
+    }
+
+    fn embed_batch<'a>(&'a self, _texts: &'a [&'a str], _input_type: &'a str) -> EmbedFuture<'a> {
+        Box::pin(std::future::ready(Err("No embedding backend configured".to_string())))
+    }
+
+    fn is_enabled(&self) -> bool {
+        false
+    }
+}
+
+/// Looks texts up in `embedding_cache` before asking the wrapped backend, and
+/// stores what it returns there. Search queries go straight to the backend:
+/// they rarely repeat and would only fill the cache. A cache that cannot be
+/// read or written is logged and bypassed.
+pub struct CachedEmbedder {
+    inner: Box<dyn Embedder>,
+    /// Database holding `embedding_cache`.
+    db: Database,
+}
+
+impl CachedEmbedder {
+    pub fn new(inner: Box<dyn Embedder>, db: Database) -> Self {
+        Self { inner, db }
+    }
+
+    async fn embed_cached(&self, texts: &[&str], input_type: &str) -> Result<Vec<Vec<f32>>, String> {
+        if texts.is_empty() {
+            return Ok(vec![]);
+        }
+        if input_type == "query" {
+            return self.inner.embed_batch(texts, input_type).await;
+        }
+        let model = self.inner.model();
+        let hashes: Vec<TextHash> = texts.iter().map(|t| text_hash(t)).collect();
+        let mut distinct = hashes.clone();
+        distinct.sort_unstable();
+        distinct.dedup();
+        let cached = match self.db.cached_embeddings(model, input_type, &distinct).await {
+            Ok(cached) => cached,
+            Err(e) => {
+                warn!("Embedding cache lookup failed: {e}");
+                return self.inner.embed_batch(texts, input_type).await;
+            }
+        };
+        let mut known: HashMap<TextHash, Vec<f32>> = distinct
             .into_iter()
-            .next()
-            .ok_or_else(|| "Empty embedding response".to_string())
+            .zip(cached)
+            .filter_map(|(hash, blob)| blob.map(|b| (hash, bytes_to_embedding(&b))))
+            .collect();
+
+        // Each missing text once, however often it appears in the batch
+        let mut seen = HashSet::new();
+        let missing: Vec<(TextHash, &str)> = hashes
+            .iter()
+            .zip(texts)
+            .filter(|(hash, _)| !known.contains_key(*hash) && seen.insert(**hash))
+            .map(|(hash, text)| (*hash, *text))
+            .collect();
+        debug!("Embedding cache: {} of {} texts cached", texts.len() - missing.len(), texts.len());
+
+        if !missing.is_empty() {
+            let inputs: Vec<&str> = missing.iter().map(|(_, text)| *text).collect();
+            let fetched = self.inner.embed_batch(&inputs, input_type).await?;
+            if fetched.len() != inputs.len() {
+                return Err(format!("{model} returned {} embeddings for {} texts", fetched.len(), inputs.len()));
+            }
+            let entries = missing.iter().zip(&fetched).map(|((hash, _), e)| (*hash, embedding_to_bytes(e))).collect();
+            if let Err(e) = self.db.cache_embeddings(model, input_type, entries).await {
+                warn!("Embedding cache write failed: {e}");
+            }
+            known.extend(missing.into_iter().map(|(hash, _)| hash).zip(fetched));
+        }
+        Ok(hashes.iter().map(|hash| known[hash].clone()).collect())
+    }
+}
+
+impl Embedder for CachedEmbedder {
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.inner.is_enabled()
+    }
+
+    fn embed_batch<'a>(&'a self, texts: &'a [&'a str], input_type: &'a str) -> EmbedFuture<'a> {
+        Box::pin(self.embed_cached(texts, input_type))
     }
 }
 
@@ -1,6 +1,7 @@
 use tracing::{debug, warn};
 
-use crate::db::Database;
+use crate::db::entity_graph::mention_context;
+use crate::db::{Database, Repository};
 use crate::provider::{Message, MessageContent, ProviderPool, Role};
 
 const EXTRACTION_PROMPT: &str = r#"Extract named entities from the following text. Return ONLY a JSON array of objects with "name" and "type" fields.
@@ -59,11 +60,11 @@ pub async fn extract_and_link_entities(
 
     // Save each entity and link to source
     for (name, entity_type) in &entities {
-        match db.save_entity(user_id, name, entity_type) {
+        match db.save_entity(user_id, name, entity_type).await {
             Ok(entity_id) => {
                 // Build a short context snippet
-                let context = build_context_snippet(text, name);
-                let _ = db.add_entity_mention(entity_id, source_type, source_id, context.as_deref());
+                let context = mention_context(text, name);
+                let _ = db.add_entity_mention(entity_id, source_type, source_id, context.as_deref()).await;
             }
             Err(e) => {
                 warn!("Failed to save entity '{name}': {e}");
@@ -109,23 +110,3 @@ fn parse_entities(text: &str) -> Vec<(String, String)> {
         })
         .collect()
 }
-
-/// Extract a short context snippet around the entity name in the text.
-fn build_context_snippet(text: &str, entity_name: &str) -> Option<String> {
-    // Use char-based indexing to avoid panics on multibyte characters (CJK, etc.)
-    let chars: Vec<char> = text.chars().collect();
-    let name_chars: Vec<char> = entity_name.to_lowercase().chars().collect();
-    let lower_chars: Vec<char> = text.to_lowercase().chars().collect();
-
-    // Find entity name position in char indices
-    let pos = lower_chars
-        .windows(name_chars.len())
-        .position(|w| w == name_chars.as_slice())?;
-
-    let context_chars = 30; // chars (not bytes) of context around the match
-    let start = pos.saturating_sub(context_chars);
-    let end = (pos + name_chars.len() + context_chars).min(chars.len());
-
-    let snippet: String = chars[start..end].iter().collect();
-    Some(snippet)
-}
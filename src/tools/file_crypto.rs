@@ -0,0 +1,208 @@
+//! At-rest encryption for uploads saved under `~/documents/{owner}`.
+//!
+//! Files are sealed with XChaCha20-Poly1305 under a key derived from
+//! `ENCRYPTION_KEY` with Argon2id and a random salt made once per install
+//! (kept in `SALT_FILE` at the top of the upload folders). An encrypted file
+//! is `MAGIC || salt || nonce || ciphertext`, so plaintext files written
+//! before encryption was enabled are still told apart, and a file stays
+//! readable with the key alone if the salt file is lost.
+//!
+//! Files sealed before the salt was introduced (`LEGACY_MAGIC`, key = one
+//! SHA-256 of `ENCRYPTION_KEY`) are still opened; `rotate-key` rewrites them
+//! in the current format.
+
+use std::path::Path;
+
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use sha2::{Digest, Sha256};
+
+const MAGIC: &[u8; 8] = b"MAENC\x00\x02\x00";
+const LEGACY_MAGIC: &[u8; 8] = b"MAENC\x00\x01\x00";
+pub const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+/// Per-install salt, in the directory holding the owners' upload folders.
+pub const SALT_FILE: &str = ".file-key-salt";
+/// Directory names skipped when re-encrypting (dedup markers, not content).
+const SKIP_DIRS: &[&str] = &[".checksums"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum FileCryptoError {
+    #[error("file is not encrypted")]
+    NotEncrypted,
+    #[error("file could not be decrypted (wrong key or corrupted)")]
+    Decrypt,
+    #[error("file is encrypted and ENCRYPTION_KEY is not set")]
+    NoKey,
+    #[error("{SALT_FILE} is corrupted (expected {SALT_LEN} bytes)")]
+    BadSalt,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Seals and opens file contents with the key derived from `ENCRYPTION_KEY`.
+pub struct FileCipher {
+    key: String,
+    salt: [u8; SALT_LEN],
+    cipher: XChaCha20Poly1305,
+}
+
+impl FileCipher {
+    /// Derive the file key from `key` with Argon2id under `salt`.
+    pub fn new(key: &str, salt: [u8; SALT_LEN]) -> Self {
+        Self { key: key.to_string(), salt, cipher: derive(key, &salt) }
+    }
+
+    /// [`FileCipher::new`] with the install salt kept in `dir`, made on first use.
+    pub fn for_dir(key: &str, dir: &Path) -> Result<Self, FileCryptoError> {
+        Ok(Self::new(key, install_salt(dir)?))
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let sealed = self
+            .cipher
+            .encrypt(&nonce, plaintext)
+            .expect("XChaCha20-Poly1305 encryption is infallible for in-memory buffers");
+        let mut out = Vec::with_capacity(MAGIC.len() + SALT_LEN + NONCE_LEN + sealed.len());
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&self.salt);
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&sealed);
+        out
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, FileCryptoError> {
+        let (cipher, nonce, sealed) = if is_legacy(data) {
+            let (nonce, sealed) = data[LEGACY_MAGIC.len()..].split_at(NONCE_LEN);
+            (legacy_cipher(&self.key), nonce, sealed)
+        } else if is_encrypted(data) {
+            let (salt, rest) = data[MAGIC.len()..].split_at(SALT_LEN);
+            let (nonce, sealed) = rest.split_at(NONCE_LEN);
+            // Sealed under another install salt (the salt file was replaced): derive that key
+            let cipher = if salt == self.salt { self.cipher.clone() } else { derive(&self.key, salt) };
+            (cipher, nonce, sealed)
+        } else {
+            return Err(FileCryptoError::NotEncrypted);
+        };
+        cipher.decrypt(XNonce::from_slice(nonce), sealed).map_err(|_| FileCryptoError::Decrypt)
+    }
+}
+
+fn derive(key: &str, salt: &[u8]) -> XChaCha20Poly1305 {
+    let mut derived = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(key.as_bytes(), salt, &mut derived)
+        .expect("Argon2id accepts a 16-byte salt and a 32-byte output");
+    XChaCha20Poly1305::new(&derived.into())
+}
+
+/// The key files were sealed with before the install salt.
+fn legacy_cipher(key: &str) -> XChaCha20Poly1305 {
+    let digest = Sha256::new()
+        .chain_update(b"memory-assistant file encryption v1\0")
+        .chain_update(key.as_bytes())
+        .finalize();
+    XChaCha20Poly1305::new(&digest)
+}
+
+/// The salt in `dir/SALT_FILE`, written with a random one if there is none yet.
+pub fn install_salt(dir: &Path) -> Result<[u8; SALT_LEN], FileCryptoError> {
+    let path = dir.join(SALT_FILE);
+    match std::fs::read(&path) {
+        Ok(bytes) => return bytes.try_into().map_err(|_| FileCryptoError::BadSalt),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    std::fs::create_dir_all(dir)?;
+    // create_new: if another process got there first, use its salt
+    match std::fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+        Ok(mut file) => {
+            use std::io::Write;
+            file.write_all(&salt)?;
+            file.sync_all()?;
+            Ok(salt)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => install_salt(dir),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Whether `data` starts with an encrypted-file header, current or legacy.
+pub fn is_encrypted(data: &[u8]) -> bool {
+    is_legacy(data) || (data.len() >= MAGIC.len() + SALT_LEN + NONCE_LEN && data.starts_with(MAGIC))
+}
+
+fn is_legacy(data: &[u8]) -> bool {
+    data.len() >= LEGACY_MAGIC.len() + NONCE_LEN && data.starts_with(LEGACY_MAGIC)
+}
+
+/// Counts from [`reencrypt_dir`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReencryptStats {
+    pub rewritten: usize,
+    pub unchanged: usize,
+}
+
+/// Move every file under `dir` from `old` to `new` (`None` = plaintext).
+/// Plaintext files are encrypted too, so this also turns encryption on for
+/// files saved before it was enabled. Each file is replaced atomically.
+pub fn reencrypt_dir(
+    dir: &Path,
+    old: Option<&FileCipher>,
+    new: Option<&FileCipher>,
+) -> Result<ReencryptStats, FileCryptoError> {
+    let mut stats = ReencryptStats::default();
+    if !dir.exists() {
+        return Ok(stats);
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if SKIP_DIRS.iter().any(|d| entry.file_name() == *d) {
+                continue;
+            }
+            let sub = reencrypt_dir(&path, old, new)?;
+            stats.rewritten += sub.rewritten;
+            stats.unchanged += sub.unchanged;
+            continue;
+        }
+        if !file_type.is_file() || entry.file_name() == SALT_FILE {
+            continue;
+        }
+
+        let data = std::fs::read(&path)?;
+        let was_encrypted = is_encrypted(&data);
+        let plaintext = if !was_encrypted {
+            data
+        } else if let Some(plain) = old.and_then(|c| c.decrypt(&data).ok()) {
+            plain
+        } else if new.is_some_and(|c| c.decrypt(&data).is_ok()) {
+            // Already rotated by an earlier, interrupted run
+            stats.unchanged += 1;
+            continue;
+        } else {
+            return Err(FileCryptoError::Decrypt);
+        };
+        let output = match new {
+            Some(new) => new.encrypt(&plaintext),
+            None if was_encrypted => plaintext,
+            None => {
+                stats.unchanged += 1;
+                continue;
+            }
+        };
+
+        let tmp = path.with_file_name(format!(".{}.tmp", entry.file_name().to_string_lossy()));
+        std::fs::write(&tmp, &output)?;
+        std::fs::rename(&tmp, &path)?;
+        stats.rewritten += 1;
+    }
+    Ok(stats)
+}
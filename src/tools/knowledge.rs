@@ -1,9 +1,7 @@
 use std::collections::HashMap;
 
-use crate::db::Database;
-use crate::tools::embedding::{
-    EmbeddingClient, bytes_to_embedding, cosine_similarity, embedding_to_bytes,
-};
+use crate::db::{self, Actor, Database, Repository};
+use crate::tools::embedding::{Embedder, embedding_to_bytes};
 
 // --- Chunking ---
 
@@ -110,24 +108,59 @@ pub async fn knowledge_save(
     content: &str,
     source: Option<&str>,
     tags: Option<&str>,
-    embedding_client: Option<&EmbeddingClient>,
+    embedder: &dyn Embedder,
 ) -> Result<(i64, String), String> {
     if title.is_empty() || content.is_empty() {
         return Err("Title and content are required".into());
     }
-    let doc_id = db.save_document(user_id, title, content, source, tags)?;
+    let doc_id = db
+        .save_document(user_id, title, content, source, tags)
+        .await
+        .map_err(|e| e.to_string())?;
 
-    // Chunk the document
+    let chunk_count = index_document(db, doc_id, content, embedder)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // Auto-link: search existing memory facts related to this doc
+    let mut linked_facts = Vec::new();
+    if let Ok(facts) = db.search_facts(user_id, title).await {
+        for fact in facts.iter().take(5) {
+            if db.link_fact_to_doc(fact.id, doc_id).await.is_ok() {
+                linked_facts.push(format!("#{} {}", fact.id, fact.text));
+            }
+        }
+    }
+
+    let mut msg = format!("Saved document (ID: {doc_id}): \"{title}\" — {chunk_count} chunks");
+    if !linked_facts.is_empty() {
+        msg.push_str(&format!(
+            "\n📎 Auto-linked {} memory fact(s): {}",
+            linked_facts.len(),
+            linked_facts.join(", ")
+        ));
+    }
+
+    Ok((doc_id, msg))
+}
+
+/// Chunk a document and embed the chunks (if an embedding backend is configured).
+/// Embedding failures are only logged — unembedded chunks still work with FTS.
+pub(crate) async fn index_document(
+    db: &Database,
+    doc_id: i64,
+    content: &str,
+    embedder: &dyn Embedder,
+) -> db::DbResult<usize> {
     let chunks = chunk_document(content);
     let chunk_data: Vec<(usize, usize, usize, &str)> = chunks
         .iter()
         .map(|c| (c.chunk_index, c.start_line, c.end_line, c.content.as_str()))
         .collect();
-    let chunk_ids = db.save_chunks(doc_id, &chunk_data)?;
-    let chunk_count = chunk_ids.len();
+    let chunk_ids = db.save_chunks(doc_id, &chunk_data).await?;
 
-    // Embed if client available
-    if let Some(client) = embedding_client {
+    // Embed if a backend is configured
+    if embedder.is_enabled() {
         let texts: Vec<&str> = chunks.iter().map(|c| c.content.as_str()).collect();
         // Batch in groups of 128
         for (batch_start, batch_ids) in chunk_ids.chunks(128).enumerate() {
@@ -135,11 +168,11 @@ pub async fn knowledge_save(
             let end = (start + 128).min(texts.len());
             let batch_texts = &texts[start..end];
 
-            match client.embed_batch(batch_texts, "document").await {
+            match embedder.embed_batch(batch_texts, "document").await {
                 Ok(embeddings) => {
                     let blobs: Vec<Vec<u8>> =
                         embeddings.iter().map(|e| embedding_to_bytes(e)).collect();
-                    if let Err(e) = db.update_chunk_embeddings(batch_ids, &blobs) {
+                    if let Err(e) = db.update_chunk_embeddings(batch_ids, &blobs).await {
                         tracing::warn!("Failed to save embeddings: {e}");
                     }
                 }
@@ -150,26 +183,7 @@ pub async fn knowledge_save(
         }
     }
 
-    // Auto-link: search existing memory facts related to this doc
-    let mut linked_facts = Vec::new();
-    if let Ok(facts) = db.search_facts(user_id, title) {
-        for (fact_id, fact_text, _cat) in facts.iter().take(5) {
-            if db.link_fact_to_doc(*fact_id, doc_id).is_ok() {
-                linked_facts.push(format!("#{fact_id} {fact_text}"));
-            }
-        }
-    }
-
-    let mut msg = format!("Saved document (ID: {doc_id}): \"{title}\" — {chunk_count} chunks");
-    if !linked_facts.is_empty() {
-        msg.push_str(&format!(
-            "\n📎 Auto-linked {} memory fact(s): {}",
-            linked_facts.len(),
-            linked_facts.join(", ")
-        ));
-    }
-
-    Ok((doc_id, msg))
+    Ok(chunk_ids.len())
 }
 
 // --- Knowledge Patch ---
@@ -177,35 +191,35 @@ pub async fn knowledge_save(
 pub async fn knowledge_patch(
     db: &Database,
     user_id: u64,
+    actor_id: u64,
     doc_id: i64,
     old_text: &str,
     new_text: &str,
-    embedding_client: Option<&EmbeddingClient>,
+    embedder: &dyn Embedder,
 ) -> String {
     // 1. Patch document + chunks in DB
-    let affected_chunk_ids = match db.patch_document(user_id, doc_id, old_text, new_text) {
+    let actor = Actor { user_id: actor_id, tool: "knowledge_patch" };
+    let affected_chunk_ids = match db.patch_document(user_id, doc_id, old_text, new_text, actor).await {
         Ok(ids) => ids,
         Err(e) => return format!("Error: {e}"),
     };
 
     // 2. Re-embed affected chunks
-    if !affected_chunk_ids.is_empty() {
-        if let Some(client) = embedding_client {
-            // Read updated chunk contents
-            let mut texts = Vec::new();
-            for chunk_id in &affected_chunk_ids {
-                if let Ok(content) = db.get_chunk_content(*chunk_id) {
-                    texts.push((*chunk_id, content));
-                }
+    if !affected_chunk_ids.is_empty() && embedder.is_enabled() {
+        // Read updated chunk contents
+        let mut texts = Vec::new();
+        for chunk_id in &affected_chunk_ids {
+            if let Ok(content) = db.get_chunk_content(*chunk_id).await {
+                texts.push((*chunk_id, content));
             }
-            let text_refs: Vec<&str> = texts.iter().map(|(_, t)| t.as_str()).collect();
-            if let Ok(embeddings) = client.embed_batch(&text_refs, "document").await {
-                let ids: Vec<i64> = texts.iter().map(|(id, _)| *id).collect();
-                let blobs: Vec<Vec<u8>> =
-                    embeddings.iter().map(|e| embedding_to_bytes(e)).collect();
-                if let Err(e) = db.update_chunk_embeddings(&ids, &blobs) {
-                    tracing::warn!("Failed to re-embed patched chunks: {e}");
-                }
+        }
+        let text_refs: Vec<&str> = texts.iter().map(|(_, t)| t.as_str()).collect();
+        if let Ok(embeddings) = embedder.embed_batch(&text_refs, "document").await {
+            let ids: Vec<i64> = texts.iter().map(|(id, _)| *id).collect();
+            let blobs: Vec<Vec<u8>> =
+                embeddings.iter().map(|e| embedding_to_bytes(e)).collect();
+            if let Err(e) = db.update_chunk_embeddings(&ids, &blobs).await {
+                tracing::warn!("Failed to re-embed patched chunks: {e}");
             }
         }
     }
@@ -216,17 +230,113 @@ pub async fn knowledge_patch(
     )
 }
 
+// --- Versions ---
+
+/// Max characters of diff output returned to the model.
+const MAX_DIFF_CHARS: usize = 6000;
+
+pub async fn knowledge_versions(db: &Database, user_id: u64, doc_id: i64) -> String {
+    match db.list_document_versions(user_id, doc_id).await {
+        Ok(versions) if versions.is_empty() => format!("Document #{doc_id} not found or has no versions."),
+        Ok(versions) => {
+            let mut out = format!("{} version(s) of document #{doc_id} (newest first):\n", versions.len());
+            for v in &versions {
+                let by = v.actor_id.map(|id| format!(" by user {id}")).unwrap_or_default();
+                let (added, removed) = v.diff.as_deref().map(diff_stats).unwrap_or((0, 0));
+                out.push_str(&format!(
+                    "v{} | {} | {}{by} | +{added} -{removed} lines\n",
+                    v.version, v.created_at, v.tool
+                ));
+            }
+            out.push_str("Use knowledge_diff to compare versions, knowledge_rollback to restore one.");
+            out
+        }
+        Err(e) => format!("Error: {e}"),
+    }
+}
+
+/// Diff `from_version` against `to_version`, or against the latest version if not given.
+pub async fn knowledge_diff(
+    db: &Database,
+    user_id: u64,
+    doc_id: i64,
+    from_version: i64,
+    to_version: Option<i64>,
+) -> String {
+    let to_version = match to_version {
+        Some(v) => v,
+        None => match db.list_document_versions(user_id, doc_id).await {
+            Ok(versions) if !versions.is_empty() => versions[0].version,
+            Ok(_) => return format!("Document #{doc_id} not found."),
+            Err(e) => return format!("Error: {e}"),
+        },
+    };
+    let (from, to) = match (
+        db.get_document_version(user_id, doc_id, from_version).await,
+        db.get_document_version(user_id, doc_id, to_version).await,
+    ) {
+        (Ok(from), Ok(to)) => (from, to),
+        (Err(e), _) | (_, Err(e)) => return format!("Error: {e}"),
+    };
+
+    let diff = db::unified_diff(&from.content, &to.content, &format!("v{from_version}"), &format!("v{to_version}"));
+    if diff.is_empty() {
+        return format!("Versions v{from_version} and v{to_version} of document #{doc_id} are identical.");
+    }
+    if diff.chars().count() > MAX_DIFF_CHARS {
+        let truncated: String = diff.chars().take(MAX_DIFF_CHARS).collect();
+        return format!("{truncated}\n... (diff truncated)");
+    }
+    diff
+}
+
+/// Restore an old version's content, then re-chunk and re-embed the document.
+pub async fn knowledge_rollback(
+    db: &Database,
+    user_id: u64,
+    actor_id: u64,
+    doc_id: i64,
+    version: i64,
+    embedder: &dyn Embedder,
+) -> String {
+    let actor = Actor { user_id: actor_id, tool: "knowledge_rollback" };
+    let new_version = match db.rollback_document(user_id, doc_id, version, actor).await {
+        Ok(v) => v,
+        Err(e) => return format!("Error: {e}"),
+    };
+    let content = match db.get_document_version(user_id, doc_id, new_version).await {
+        Ok(v) => v.content,
+        Err(e) => return format!("Error: {e}"),
+    };
+    match index_document(db, doc_id, &content, embedder).await {
+        Ok(chunk_count) => format!(
+            "Rolled back document #{doc_id} to v{version} (saved as v{new_version}) — {chunk_count} chunks re-indexed"
+        ),
+        // Unchunked documents are picked up again by the startup migration
+        Err(e) => format!("Rolled back document #{doc_id} to v{version} (saved as v{new_version}), but re-chunking failed: {e}"),
+    }
+}
+
+/// Count added and removed lines in a unified diff.
+fn diff_stats(diff: &str) -> (usize, usize) {
+    diff.lines().fold((0, 0), |(added, removed), line| {
+        if line.starts_with("+++") || line.starts_with("---") {
+            (added, removed)
+        } else if line.starts_with('+') {
+            (added + 1, removed)
+        } else if line.starts_with('-') {
+            (added, removed + 1)
+        } else {
+            (added, removed)
+        }
+    })
+}
+
 // --- Hybrid Search ---
 
 /// Search result combining FTS and vector scores.
 struct SearchHit {
-    _chunk_id: i64,
-    doc_id: i64,
-    title: String,
-    content: String,
-    start_line: i64,
-    end_line: i64,
-    source: Option<String>,
+    chunk: db::Chunk,
     fts_score: f64,
     vector_score: f64,
 }
@@ -235,7 +345,7 @@ pub async fn knowledge_search(
     db: &Database,
     user_id: u64,
     query: &str,
-    embedding_client: Option<&EmbeddingClient>,
+    embedder: &dyn Embedder,
 ) -> String {
     if query.is_empty() {
         return "Error: query cannot be empty".into();
@@ -244,31 +354,24 @@ pub async fn knowledge_search(
     let mut hits: HashMap<i64, SearchHit> = HashMap::new();
 
     // 1. FTS5 search on chunks
-    match db.search_chunks_fts(user_id, query) {
+    match db.search_chunks_fts(user_id, query).await {
         Ok(results) => {
             if !results.is_empty() {
                 // Normalize FTS ranks (they're negative, more negative = better match)
                 let max_rank = results
                     .iter()
-                    .map(|r| r.7.abs())
+                    .map(|(_, rank)| rank.abs())
                     .fold(f64::MIN, f64::max);
-                for (chunk_id, doc_id, title, content, start_line, end_line, source, rank) in results
-                {
+                for (chunk, rank) in results {
                     let normalized = if max_rank > 0.0 {
                         rank.abs() / max_rank
                     } else {
                         0.0
                     };
                     hits.insert(
-                        chunk_id,
+                        chunk.id,
                         SearchHit {
-                            _chunk_id: chunk_id,
-                            doc_id,
-                            title,
-                            content,
-                            start_line,
-                            end_line,
-                            source,
+                            chunk,
                             fts_score: normalized,
                             vector_score: 0.0,
                         },
@@ -279,52 +382,26 @@ pub async fn knowledge_search(
         Err(e) => {
             tracing::warn!("FTS search failed: {e}");
             // Fall back to old document-level search
-            return fallback_document_search(db, user_id, query);
+            return fallback_document_search(db, user_id, query).await;
         }
     }
 
-    // 2. Vector search (if embedding client available)
-    if let Some(client) = embedding_client {
-        if let Ok(query_embedding) = client.embed_query(query).await {
-            if let Ok(all_chunks) = db.load_all_embeddings(user_id) {
-                let mut scored: Vec<(i64, i64, String, String, i64, i64, Option<String>, f32)> =
-                    all_chunks
-                        .into_iter()
-                        .map(
-                            |(chunk_id, doc_id, title, content, start_line, end_line, source, blob)| {
-                                let emb = bytes_to_embedding(&blob);
-                                let sim = cosine_similarity(&query_embedding, &emb);
-                                (chunk_id, doc_id, title, content, start_line, end_line, source, sim)
-                            },
-                        )
-                        .collect();
-
-                scored.sort_by(|a, b| b.7.partial_cmp(&a.7).unwrap_or(std::cmp::Ordering::Equal));
-                let top = &scored[..scored.len().min(20)];
-
-                if let Some(max_sim) = top.first().map(|s| s.7) {
-                    if max_sim > 0.0 {
-                        for (chunk_id, doc_id, title, content, start_line, end_line, source, sim) in
-                            top
-                        {
-                            let normalized = *sim / max_sim;
-                            hits.entry(*chunk_id)
-                                .and_modify(|h| h.vector_score = normalized as f64)
-                                .or_insert_with(|| SearchHit {
-                                    _chunk_id: *chunk_id,
-                                    doc_id: *doc_id,
-                                    title: title.clone(),
-                                    content: content.clone(),
-                                    start_line: *start_line,
-                                    end_line: *end_line,
-                                    source: source.clone(),
-                                    fts_score: 0.0,
-                                    vector_score: normalized as f64,
-                                });
-                        }
-                    }
-                }
-            }
+    // 2. Vector search (if an embedding backend is configured)
+    if embedder.is_enabled()
+        && let Ok(query_embedding) = embedder.embed_query(query).await
+        && let Ok(scored) = db.nearest_chunks(user_id, &query_embedding, 20).await
+        && let Some(max_sim) = scored.first().map(|s| s.1)
+        && max_sim > 0.0
+    {
+        for (chunk, sim) in scored {
+            let normalized = sim / max_sim;
+            hits.entry(chunk.id)
+                .and_modify(|h| h.vector_score = normalized as f64)
+                .or_insert_with(|| SearchHit {
+                    chunk,
+                    fts_score: 0.0,
+                    vector_score: normalized as f64,
+                });
         }
     }
 
@@ -356,22 +433,23 @@ pub async fn knowledge_search(
     let mut output_lines: Vec<String> = Vec::new();
 
     for hit in results {
-        let key = (hit.doc_id, hit.start_line, hit.end_line);
+        let chunk = &hit.chunk;
+        let key = (chunk.doc_id, chunk.start_line, chunk.end_line);
         if seen_ranges.contains(&key) {
             continue;
         }
         seen_ranges.push(key);
 
-        let src = hit.source.as_deref().unwrap_or("no source");
-        let line_range = if hit.start_line == hit.end_line {
-            format!("dòng {}", hit.start_line)
+        let src = chunk.source.as_deref().unwrap_or("no source");
+        let line_range = if chunk.start_line == chunk.end_line {
+            format!("dòng {}", chunk.start_line)
         } else {
-            format!("dòng {}-{}", hit.start_line, hit.end_line)
+            format!("dòng {}-{}", chunk.start_line, chunk.end_line)
         };
 
         output_lines.push(format!(
             "[{}] {} ({})\n  {}\n  Source: {}",
-            hit.doc_id, hit.title, line_range, hit.content, src
+            chunk.doc_id, chunk.title, line_range, chunk.content, src
         ));
 
         if output_lines.len() >= 10 {
@@ -382,15 +460,15 @@ pub async fn knowledge_search(
     output_lines.join("\n\n")
 }
 
-fn fallback_document_search(db: &Database, user_id: u64, query: &str) -> String {
-    match db.search_documents(user_id, query) {
+async fn fallback_document_search(db: &Database, user_id: u64, query: &str) -> String {
+    match db.search_documents(user_id, query).await {
         Ok(results) if results.is_empty() => "No documents found.".into(),
         Ok(results) => {
             let lines: Vec<String> = results
                 .iter()
-                .map(|(id, title, snippet, source)| {
-                    let src = source.as_deref().unwrap_or("no source");
-                    format!("[{id}] {title}\n  {snippet}\n  Source: {src}")
+                .map(|doc| {
+                    let src = doc.source.as_deref().unwrap_or("no source");
+                    format!("[{}] {}\n  {}\n  Source: {src}", doc.id, doc.title, doc.snippet)
                 })
                 .collect();
             lines.join("\n\n")
@@ -400,14 +478,17 @@ fn fallback_document_search(db: &Database, user_id: u64, query: &str) -> String
 }
 
 pub async fn knowledge_list(db: &Database, user_id: u64) -> String {
-    match db.list_documents(user_id) {
+    match db.list_documents(user_id).await {
         Ok(docs) if docs.is_empty() => "No documents saved yet.".into(),
         Ok(docs) => {
             let lines: Vec<String> = docs
                 .iter()
-                .map(|(id, title, source, created_at, chunk_count)| {
-                    let src = source.as_deref().unwrap_or("no source");
-                    format!("[{id}] {title}  ({chunk_count} chunks)\n  Source: {src}  |  Saved: {created_at}")
+                .map(|doc| {
+                    let src = doc.source.as_deref().unwrap_or("no source");
+                    format!(
+                        "[{}] {}  ({} chunks)\n  Source: {src}  |  Saved: {}",
+                        doc.id, doc.title, doc.chunk_count, doc.created_at
+                    )
                 })
                 .collect();
             format!("{} documents:\n\n{}", docs.len(), lines.join("\n\n"))
@@ -420,19 +501,22 @@ pub async fn entity_search(db: &Database, user_id: u64, query: &str) -> String {
     if query.is_empty() {
         return "Error: query cannot be empty".into();
     }
-    match db.search_entities(user_id, query) {
+    match db.search_entities(user_id, query).await {
         Ok(results) if results.is_empty() => "No entities found.".into(),
         Ok(results) => {
             let lines: Vec<String> = results
                 .iter()
-                .map(|(name, entity_type, mentions)| {
-                    let mut line = format!("{name} [{entity_type}]");
+                .map(|(entity, mentions)| {
+                    let mut line = format!("{} [{}]", entity.name, entity.entity_type);
                     if mentions.is_empty() {
                         line.push_str(" — no mentions");
                     } else {
-                        for (src_type, src_id, context) in mentions {
-                            let ctx = context.as_deref().unwrap_or("(no context)");
-                            line.push_str(&format!("\n  - {src_type} #{src_id}: {ctx}"));
+                        for mention in mentions {
+                            let ctx = mention.context.as_deref().unwrap_or("(no context)");
+                            line.push_str(&format!(
+                                "\n  - {} #{}: {ctx}",
+                                mention.source_type, mention.source_id
+                            ));
                         }
                     }
                     line
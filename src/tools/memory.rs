@@ -1,62 +1,74 @@
-use crate::db::Database;
+use crate::db::{Actor, Database, Fact, Repository};
 use std::collections::BTreeSet;
 
+/// Similarity above which a saved or edited fact is linked to an existing one.
+const RELATED_THRESHOLD: f32 = 0.75;
+/// Links made per saved or edited fact.
+const MAX_RELATED: usize = 3;
+
 pub async fn memory_save(
     db: &Database,
     user_id: u64,
+    actor_id: u64,
     fact: &str,
     category: &str,
-    embedding_client: Option<&crate::tools::EmbeddingClient>,
+    embedder: &dyn crate::tools::Embedder,
 ) -> String {
     if fact.is_empty() {
         return "Error: fact cannot be empty".into();
     }
 
     // Ensure default categories exist
-    let _ = db.ensure_default_categories(user_id);
+    let _ = db.ensure_default_categories(user_id).await;
 
-    // 1. Find and delete superseded facts (same category, FTS match) BEFORE saving
-    let mut deleted = Vec::new();
-    if let Ok(old_facts) = db.search_facts(user_id, fact) {
-        for (old_id, old_fact, old_cat) in &old_facts {
-            if old_cat != category {
-                continue;
-            }
-            if db.delete_fact(user_id, *old_id).unwrap_or(false) {
-                deleted.push(format!("#{old_id} {old_fact}"));
-            }
-            if deleted.len() >= 3 {
-                break;
-            }
-        }
-    }
+    // 1. Find superseded facts (same category, FTS match) BEFORE saving
+    let old_facts: Vec<_> = db
+        .search_facts(user_id, fact)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|old| old.category == category)
+        .collect();
 
     // 2. Save new fact
-    let fact_id = match db.save_fact(user_id, fact, category) {
+    let fact_id = match db.save_fact(user_id, fact, category).await {
         Ok(id) => id,
         Err(e) => return format!("Error saving: {e}"),
     };
 
+    // 3. Move superseded facts to the trash, recording the replacement
+    let actor = Actor { user_id: actor_id, tool: "memory_save" };
+    let mut deleted = Vec::new();
+    for old in &old_facts {
+        if db.supersede_fact(user_id, old.id, fact_id, actor).await.unwrap_or(false) {
+            deleted.push(format!("#{} {}", old.id, old.text));
+        }
+        if deleted.len() >= 3 {
+            break;
+        }
+    }
+
     let mut msg = format!("Saved (ID: {fact_id}): \"{fact}\" [{category}]");
 
     if !deleted.is_empty() {
         msg.push_str(&format!("\n🗑️ Superseded: {}", deleted.join(", ")));
+        msg.push_str("\n↩️ Use memory_history / memory_revert if a fact was replaced by mistake.");
     }
 
-    // 3. Auto-link: search KB chunks for related docs
-    if let Ok(chunks) = db.search_chunks_fts(user_id, fact) {
+    // 4. Auto-link: search KB chunks for related docs
+    if let Ok(chunks) = db.search_chunks_fts(user_id, fact).await {
         let mut doc_ids = BTreeSet::new();
         let mut doc_titles: Vec<(i64, String)> = Vec::new();
-        for (_chunk_id, doc_id, title, _, _, _, _, _) in &chunks {
-            if doc_ids.insert(*doc_id) {
-                doc_titles.push((*doc_id, title.clone()));
+        for (chunk, _) in &chunks {
+            if doc_ids.insert(chunk.doc_id) {
+                doc_titles.push((chunk.doc_id, chunk.title.clone()));
                 if doc_ids.len() >= 3 {
                     break;
                 }
             }
         }
         for (doc_id, _) in &doc_titles {
-            let _ = db.link_fact_to_doc(fact_id, *doc_id);
+            let _ = db.link_fact_to_doc(fact_id, *doc_id).await;
         }
         if !doc_titles.is_empty() {
             let titles: Vec<String> = doc_titles
@@ -68,50 +80,27 @@ pub async fn memory_save(
         }
     }
 
-    // 4. Embed fact + auto-link related facts
-    if let Some(client) = embedding_client {
-        match client.embed_batch(&[fact], "document").await {
+    // 5. Embed fact + auto-link related facts
+    if embedder.is_enabled() {
+        match embedder.embed_batch(&[fact], "document").await {
             Ok(embeddings) if !embeddings.is_empty() => {
                 let embedding = &embeddings[0];
                 let blob = crate::tools::embedding::embedding_to_bytes(embedding);
-                let _ = db.update_fact_embedding(fact_id, &blob);
+                let _ = db.update_fact_embedding(fact_id, &blob).await;
 
                 // Find similar existing facts and create links
-                if let Ok(all_facts) = db.load_all_fact_embeddings(user_id) {
-                    let mut similarities: Vec<(i64, f32)> = all_facts
-                        .iter()
-                        .filter(|(id, _, _, _)| *id != fact_id)
-                        .map(|(id, _, _, emb_blob)| {
-                            let emb = crate::tools::embedding::bytes_to_embedding(emb_blob);
-                            let sim =
-                                crate::tools::embedding::cosine_similarity(embedding, &emb);
-                            (*id, sim)
-                        })
-                        .filter(|(_, sim)| *sim > 0.75)
-                        .collect();
-
-                    similarities.sort_by(|a, b| {
-                        b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal)
-                    });
-                    let top_links = &similarities[..similarities.len().min(3)];
-
-                    let mut linked_facts = Vec::new();
-                    for (related_id, sim) in top_links {
-                        if db.link_facts(fact_id, *related_id, *sim).is_ok() {
-                            // Look up text from all_facts instead of extra DB query
-                            if let Some((_, text, _, _)) = all_facts.iter().find(|(id, _, _, _)| *id == *related_id) {
-                                linked_facts
-                                    .push(format!("#{related_id} {text} ({sim:.2})"));
-                            }
-                        }
+                let mut linked_facts = Vec::new();
+                for (related, sim) in related_facts(db, user_id, fact_id, embedding).await {
+                    if db.link_facts(fact_id, related.id, sim).await.is_ok() {
+                        linked_facts.push(format!("#{} {} ({sim:.2})", related.id, related.text));
                     }
+                }
 
-                    if !linked_facts.is_empty() {
-                        msg.push_str(&format!(
-                            "\n🔗 Related: {}",
-                            linked_facts.join(", ")
-                        ));
-                    }
+                if !linked_facts.is_empty() {
+                    msg.push_str(&format!(
+                        "\n🔗 Related: {}",
+                        linked_facts.join(", ")
+                    ));
                 }
             }
             Ok(_) => {}
@@ -124,11 +113,119 @@ pub async fn memory_save(
     msg
 }
 
+pub async fn memory_edit(
+    db: &Database,
+    user_id: u64,
+    actor_id: u64,
+    id: i64,
+    new_fact: &str,
+    embedder: &dyn crate::tools::Embedder,
+) -> String {
+    if new_fact.is_empty() {
+        return "Error: new_fact cannot be empty".into();
+    }
+    let actor = Actor { user_id: actor_id, tool: "memory_edit" };
+    match db.update_fact(user_id, id, new_fact, actor).await {
+        Ok(true) => {
+            reembed_fact(db, user_id, id, new_fact, embedder).await;
+            format!("Updated memory #{id}: \"{new_fact}\"")
+        }
+        Ok(false) => format!("Memory #{id} not found."),
+        Err(e) => format!("Error: {e}"),
+    }
+}
+
+pub async fn memory_history(db: &Database, user_id: u64, fact_id: i64) -> String {
+    match db.get_fact_history(user_id, fact_id).await {
+        Ok(revisions) if revisions.is_empty() => format!("No revisions recorded for memory #{fact_id}."),
+        Ok(revisions) => {
+            let mut out = format!("{} revision(s) for memory #{fact_id}:\n", revisions.len());
+            for rev in &revisions {
+                let change = match rev.superseded_by {
+                    Some(new_id) => format!("#{} replaced by #{new_id}", rev.fact_id),
+                    None => format!("#{} edited", rev.fact_id),
+                };
+                out.push_str(&format!(
+                    "r{} | {} | {change} by user {} via {}\n  - \"{}\"\n  + \"{}\"\n",
+                    rev.id, rev.created_at, rev.actor_id, rev.tool, rev.old_text, rev.new_text
+                ));
+            }
+            out.push_str("Use memory_revert with a revision ID to restore the old text.");
+            out
+        }
+        Err(e) => format!("Error: {e}"),
+    }
+}
+
+pub async fn memory_revert(
+    db: &Database,
+    user_id: u64,
+    actor_id: u64,
+    revision_id: i64,
+    embedder: &dyn crate::tools::Embedder,
+) -> String {
+    let actor = Actor { user_id: actor_id, tool: "memory_revert" };
+    match db.revert_fact(user_id, revision_id, actor).await {
+        Ok((fact, replaced)) => {
+            reembed_fact(db, user_id, fact.id, &fact.text, embedder).await;
+            let mut msg = format!("Reverted r{revision_id}: memory #{} is now \"{}\" [{}]", fact.id, fact.text, fact.category);
+            if let Some(new_id) = replaced {
+                msg.push_str(&format!("\n🗑️ Moved replacing memory #{new_id} to trash."));
+            }
+            msg
+        }
+        Err(e) => format!("Error: {e}"),
+    }
+}
+
+/// Facts of `user_id` similar enough to `embedding` to be linked to fact
+/// `fact_id`, most similar first.
+async fn related_facts(db: &Database, user_id: u64, fact_id: i64, embedding: &[f32]) -> Vec<(Fact, f32)> {
+    match db.nearest_facts(user_id, embedding, MAX_RELATED + 1).await {
+        Ok(nearest) => nearest
+            .into_iter()
+            .filter(|(fact, sim)| fact.id != fact_id && *sim > RELATED_THRESHOLD)
+            .take(MAX_RELATED)
+            .collect(),
+        Err(e) => {
+            tracing::warn!("Related fact search failed: {e}");
+            Vec::new()
+        }
+    }
+}
+
+/// Re-embed a fact after its text changed and recompute its related-fact links.
+pub(crate) async fn reembed_fact(
+    db: &Database,
+    user_id: u64,
+    id: i64,
+    text: &str,
+    embedder: &dyn crate::tools::Embedder,
+) {
+    if !embedder.is_enabled() {
+        return;
+    }
+    let Ok(embeddings) = embedder.embed_batch(&[text], "document").await else {
+        return;
+    };
+    let Some(emb) = embeddings.first() else {
+        return;
+    };
+    let blob = crate::tools::embedding::embedding_to_bytes(emb);
+    let _ = db.update_fact_embedding(id, &blob).await;
+
+    // Delete old relations and recompute
+    let _ = db.delete_fact_relations(id).await;
+    for (related, sim) in related_facts(db, user_id, id, emb).await {
+        let _ = db.link_facts(id, related.id, sim).await;
+    }
+}
+
 pub async fn memory_search(
     db: &Database,
     user_id: u64,
     keyword: &str,
-    embedding_client: Option<&crate::tools::EmbeddingClient>,
+    embedder: &dyn crate::tools::Embedder,
 ) -> String {
     if keyword.is_empty() {
         return "Error: keyword cannot be empty".into();
@@ -147,20 +244,20 @@ pub async fn memory_search(
     let mut hits: HashMap<i64, FactHit> = HashMap::new();
 
     // 1. FTS5 search
-    if let Ok(results) = db.search_facts(user_id, keyword) {
+    if let Ok(results) = db.search_facts(user_id, keyword).await {
         let count = results.len() as f64;
-        for (i, (id, fact, cat)) in results.into_iter().enumerate() {
+        for (i, fact) in results.into_iter().enumerate() {
             let score = if count > 0.0 {
                 1.0 - (i as f64 / count)
             } else {
                 0.0
             };
             hits.insert(
-                id,
+                fact.id,
                 FactHit {
-                    id,
-                    fact,
-                    category: cat,
+                    id: fact.id,
+                    fact: fact.text,
+                    category: fact.category,
                     fts_score: score,
                     vector_score: 0.0,
                 },
@@ -168,41 +265,24 @@ pub async fn memory_search(
         }
     }
 
-    // 2. Vector search (if embedding client available)
-    if let Some(client) = embedding_client {
-        if let Ok(query_emb) = client.embed_query(keyword).await {
-            if let Ok(all_facts) = db.load_all_fact_embeddings(user_id) {
-                let mut scored: Vec<(i64, String, String, f32)> = all_facts
-                    .into_iter()
-                    .map(|(id, fact, cat, blob)| {
-                        let emb = crate::tools::embedding::bytes_to_embedding(&blob);
-                        let sim = crate::tools::embedding::cosine_similarity(&query_emb, &emb);
-                        (id, fact, cat, sim)
-                    })
-                    .collect();
-
-                scored.sort_by(|a, b| {
-                    b.3.partial_cmp(&a.3).unwrap_or(std::cmp::Ordering::Equal)
+    // 2. Vector search (if an embedding backend is configured)
+    if embedder.is_enabled()
+        && let Ok(query_emb) = embedder.embed_query(keyword).await
+        && let Ok(top) = db.nearest_facts(user_id, &query_emb, 20).await
+        && let Some(max_sim) = top.first().map(|s| s.1)
+        && max_sim > 0.0
+    {
+        for (fact, sim) in top {
+            let normalized = sim / max_sim;
+            hits.entry(fact.id)
+                .and_modify(|h| h.vector_score = normalized as f64)
+                .or_insert_with(|| FactHit {
+                    id: fact.id,
+                    fact: fact.text,
+                    category: fact.category,
+                    fts_score: 0.0,
+                    vector_score: normalized as f64,
                 });
-                let top = &scored[..scored.len().min(20)];
-
-                if let Some(max_sim) = top.first().map(|s| s.3) {
-                    if max_sim > 0.0 {
-                        for (id, fact, cat, sim) in top {
-                            let normalized = *sim / max_sim;
-                            hits.entry(*id)
-                                .and_modify(|h| h.vector_score = normalized as f64)
-                                .or_insert_with(|| FactHit {
-                                    id: *id,
-                                    fact: fact.clone(),
-                                    category: cat.clone(),
-                                    fts_score: 0.0,
-                                    vector_score: normalized as f64,
-                                });
-                        }
-                    }
-                }
-            }
         }
     }
 
@@ -230,53 +310,52 @@ pub async fn memory_search(
     });
 
     // 4. Format top 20 results with related facts
-    let lines: Vec<String> = results
-        .iter()
-        .take(20)
-        .map(|h| format_fact_with_links(db, h.id, &h.fact, &h.category))
-        .collect();
+    let mut lines: Vec<String> = Vec::new();
+    for h in results.iter().take(20) {
+        lines.push(format_fact_with_links(db, h.id, &h.fact, &h.category).await);
+    }
     lines.join("\n")
 }
 
 pub async fn memory_list(db: &Database, user_id: u64, category: Option<&str>) -> String {
-    match db.list_facts(user_id, category) {
+    match db.list_facts(user_id, category).await {
         Ok(results) if results.is_empty() => "No facts saved yet.".into(),
         Ok(results) => {
-            let lines: Vec<String> = results
-                .iter()
-                .map(|(id, fact, cat)| format_fact_with_links(db, *id, fact, cat))
-                .collect();
+            let mut lines: Vec<String> = Vec::new();
+            for fact in &results {
+                lines.push(format_fact_with_links(db, fact.id, &fact.text, &fact.category).await);
+            }
             lines.join("\n")
         }
         Err(e) => format!("Error listing: {e}"),
     }
 }
 
-fn format_fact_with_links(db: &Database, id: i64, fact: &str, cat: &str) -> String {
+async fn format_fact_with_links(db: &Database, id: i64, fact: &str, cat: &str) -> String {
     let mut line = format!("[{id}] [{cat}] {fact}");
 
     // KB doc links
-    if let Ok(links) = db.get_fact_links(id) {
-        if !links.is_empty() {
-            let titles: Vec<String> =
-                links.iter().map(|(did, t)| format!("#{did} {t}")).collect();
-            line.push_str(&format!(" -> KB: {}", titles.join(", ")));
-        }
+    if let Ok(links) = db.get_fact_links(id).await
+        && !links.is_empty()
+    {
+        let titles: Vec<String> =
+            links.iter().map(|(did, t)| format!("#{did} {t}")).collect();
+        line.push_str(&format!(" -> KB: {}", titles.join(", ")));
     }
 
     // Related facts
-    if let Ok(related) = db.get_related_facts(id) {
-        if !related.is_empty() {
-            let related_strs: Vec<String> = related
-                .iter()
-                .take(3)
-                .map(|(rid, rfact, _cat, sim)| {
-                    let preview: String = rfact.chars().take(50).collect();
-                    format!("#{rid} {preview}({sim:.2})")
-                })
-                .collect();
-            line.push_str(&format!(" -> Related: {}", related_strs.join(", ")));
-        }
+    if let Ok(related) = db.get_related_facts(id).await
+        && !related.is_empty()
+    {
+        let related_strs: Vec<String> = related
+            .iter()
+            .take(3)
+            .map(|(related, sim)| {
+                let preview: String = related.text.chars().take(50).collect();
+                format!("#{} {preview}({sim:.2})", related.id)
+            })
+            .collect();
+        line.push_str(&format!(" -> Related: {}", related_strs.join(", ")));
     }
 
     line
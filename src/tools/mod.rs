@@ -3,12 +3,32 @@ mod datetime;
 pub mod knowledge;
 mod entity_extractor;
 mod system;
+mod trash;
+mod audit;
+mod session_retention;
+mod session_title;
+mod budget;
 pub mod file_extract;
 pub mod embedding;
+pub mod file_crypto;
+pub mod uploads;
 
-pub use memory::{memory_save, memory_search, memory_list};
+pub use memory::{memory_save, memory_search, memory_list, memory_edit, memory_history, memory_revert};
 pub use datetime::get_datetime;
-pub use knowledge::{knowledge_save, knowledge_search, knowledge_list, knowledge_patch, entity_search};
+pub use knowledge::{
+    knowledge_save, knowledge_search, knowledge_list, knowledge_patch, knowledge_versions, knowledge_diff,
+    knowledge_rollback, entity_search,
+};
 pub use entity_extractor::extract_and_link_entities;
+pub use trash::{trash_list, trash_restore, trash_purge};
+pub use audit::{audit_search, describe_changes, undo_last_write};
+pub use session_retention::{
+    apply_session_retention, summarize_expired, transcript_batches, RetentionOutcome, MAX_SUMMARY_BATCHES,
+};
+pub use session_title::title_session;
+pub use budget::{
+    budget_usage, check_budget, describe_subject, effective_limits, BudgetCheck, BudgetDecision, BudgetPeriod,
+    BudgetPolicy, BudgetUsage, OverBudgetAction,
+};
 pub use system::{bash_exec, file_read, file_write, file_list, grep_search, glob_search};
-pub use embedding::EmbeddingClient;
+pub use embedding::Embedder;
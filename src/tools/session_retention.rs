@@ -0,0 +1,166 @@
+use tracing::{debug, info, warn};
+
+use super::budget::{BudgetPolicy, budget_usage};
+use crate::db::{BudgetSubject, Database, DbResult, Repository, Session, SessionMessage, SessionRetention, UsageEntry};
+use crate::provider::{Message, MessageContent, ProviderPool, Role};
+
+const SUMMARY_PROMPT: &str = r#"You maintain a running summary of a chat between a user and their personal assistant. Older turns are about to be deleted; fold them into the summary so nothing important is lost.
+
+Rules:
+- Keep names, decisions, open questions, commitments and preferences
+- Drop greetings, small talk and anything already covered
+- Write in the language the conversation uses
+- At most 15 short bullet points
+- Return ONLY the updated summary"#;
+
+/// Per-message cap inside the summarization prompt.
+const MAX_MESSAGE_CHARS: usize = 800;
+/// Cap on the transcript sent in one summarization call; longer backlogs are
+/// folded in over several calls.
+const MAX_TRANSCRIPT_CHARS: usize = 12_000;
+/// Summarization calls per session and pass; a longer backlog waits for the
+/// next pass instead of spending on dozens of calls at once.
+pub const MAX_SUMMARY_BATCHES: usize = 4;
+
+/// What one retention pass did to a session.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetentionOutcome {
+    pub pruned: usize,
+    pub summarized: bool,
+}
+
+/// Apply `retention` to one session. With `summarize` on, the expired turns
+/// are folded into the session summary first (see [`summarize_expired`]).
+/// The summary calls are logged to `usage_db`, which differs from `db` when
+/// owners have their own files, and count against the owner's budget.
+pub async fn apply_session_retention(
+    pool: &ProviderPool,
+    db: &Database,
+    usage_db: &Database,
+    session: &Session,
+    retention: SessionRetention,
+    model: &str,
+    budget: &BudgetPolicy,
+) -> DbResult<RetentionOutcome> {
+    if !retention.is_active() {
+        return Ok(RetentionOutcome::default());
+    }
+    let expired = db.expired_session_messages(&session.id, retention).await?;
+    let Some(last) = expired.last() else {
+        return Ok(RetentionOutcome::default());
+    };
+
+    if !retention.summarize {
+        let pruned = db.prune_session_messages(&session.id, last.id, None).await?;
+        debug!("Session {}: pruned {pruned} message(s)", session.id);
+        return Ok(RetentionOutcome { pruned, summarized: false });
+    }
+
+    let summarize = |summary: Option<String>, batch: Vec<SessionMessage>| async move {
+        summarize_messages(pool, usage_db, session, summary.as_deref(), &batch, model).await
+    };
+    let outcome = summarize_expired(db, usage_db, session, &expired, budget, summarize).await?;
+    debug!("Session {}: pruned {} message(s)", session.id, outcome.pruned);
+    Ok(outcome)
+}
+
+/// Fold `expired` into the session summary with `summarize`, oldest first and
+/// in batches that fit one prompt, at most [`MAX_SUMMARY_BATCHES`] of them.
+/// Each batch is deleted only once its summary is saved. The owner's budget
+/// (spend in `usage_db`) is checked before every call; if it is used up, or a
+/// call fails (`None`), the rest is kept for the next pass.
+pub async fn summarize_expired<F>(
+    db: &Database,
+    usage_db: &Database,
+    session: &Session,
+    expired: &[SessionMessage],
+    budget: &BudgetPolicy,
+    mut summarize: impl FnMut(Option<String>, Vec<SessionMessage>) -> F,
+) -> DbResult<RetentionOutcome>
+where
+    F: Future<Output = Option<String>>,
+{
+    let mut outcome = RetentionOutcome::default();
+    let mut summary = session.summary.clone();
+    for batch in transcript_batches(expired).into_iter().take(MAX_SUMMARY_BATCHES) {
+        let now = chrono::Utc::now();
+        let usage = budget_usage(usage_db, budget, BudgetSubject::Owner(session.user_id), now).await?;
+        if usage.iter().any(|u| u.exceeded()) {
+            info!("Session {}: owner budget used up, summarizing later", session.id);
+            break;
+        }
+        let Some(updated) = summarize(summary.clone(), batch.to_vec()).await else {
+            break;
+        };
+        let up_to = batch[batch.len() - 1].id;
+        outcome.pruned += db.prune_session_messages(&session.id, up_to, Some(&updated)).await?;
+        outcome.summarized = true;
+        summary = Some(updated);
+    }
+    Ok(outcome)
+}
+
+fn transcript_line(m: &SessionMessage) -> String {
+    let text: String = m.content.chars().take(MAX_MESSAGE_CHARS).collect();
+    format!("[{}] {}: {text}\n", m.created_at, m.role)
+}
+
+/// Split `messages` into consecutive runs whose transcript fits in
+/// `MAX_TRANSCRIPT_CHARS`; every run holds at least one message.
+pub fn transcript_batches(messages: &[SessionMessage]) -> Vec<&[SessionMessage]> {
+    let mut batches = Vec::new();
+    let (mut start, mut len) = (0, 0);
+    for (i, m) in messages.iter().enumerate() {
+        let line = transcript_line(m).len();
+        if i > start && len + line > MAX_TRANSCRIPT_CHARS {
+            batches.push(&messages[start..i]);
+            (start, len) = (i, 0);
+        }
+        len += line;
+    }
+    if start < messages.len() {
+        batches.push(&messages[start..]);
+    }
+    batches
+}
+
+/// Ask the LLM to merge `messages` into `summary`. Returns `None` on failure.
+async fn summarize_messages(
+    pool: &ProviderPool,
+    usage_db: &Database,
+    session: &Session,
+    summary: Option<&str>,
+    messages: &[SessionMessage],
+    model: &str,
+) -> Option<String> {
+    let transcript: String = messages.iter().map(transcript_line).collect();
+    let prompt = format!(
+        "{SUMMARY_PROMPT}\n\nCurrent summary:\n{}\n\nTurns to fold in:\n{transcript}",
+        summary.unwrap_or("(none)")
+    );
+    let request = vec![Message { role: Role::User, content: MessageContent::Text(prompt) }];
+
+    match pool.chat(&request, &[], model).await {
+        Ok((response, provider)) => {
+            let u = &response.usage;
+            let entry = UsageEntry {
+                model: model.to_string(),
+                provider,
+                prompt_tokens: u.prompt_tokens,
+                completion_tokens: u.completion_tokens,
+                cache_creation_tokens: u.cache_creation_tokens,
+                cache_read_tokens: u.cache_read_tokens,
+                kb_owner_id: Some(session.user_id),
+                session_id: Some(session.id.clone()),
+                turns: 1,
+                ..Default::default()
+            };
+            let _ = usage_db.log_usage(&entry).await;
+            response.content.map(|s| s.trim().to_string()).filter(|s| !s.is_empty())
+        }
+        Err(e) => {
+            warn!("Session summary failed: {e}");
+            None
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use tracing::warn;
+
+use crate::db::{Database, DbResult, Repository, Session, UsageEntry};
+use crate::provider::{Message, MessageContent, ProviderPool, Role};
+
+const TITLE_PROMPT: &str = r#"Give this conversation a short title so the user can find it again in a list of chats.
+
+Rules:
+- 2 to 6 words naming the topic
+- Write in the language the conversation uses
+- No quotes, no trailing punctuation
+- Return ONLY the title"#;
+
+/// Messages of the session shown to the titler.
+const TITLE_PAIRS: usize = 2;
+/// Per-message cap inside the title prompt.
+const MAX_MESSAGE_CHARS: usize = 500;
+/// Longest title kept.
+const MAX_TITLE_CHARS: usize = 60;
+
+/// Name an untitled session after its opening turns and store the title.
+/// Returns `None` (and leaves the session untitled, to retry after the next
+/// reply) when there is nothing to go on or the LLM call fails. The call is
+/// logged to `usage_db`, which differs from `db` when owners have their own files.
+pub async fn title_session(
+    pool: &ProviderPool,
+    db: &Database,
+    usage_db: &Database,
+    session: &Session,
+    model: &str,
+) -> DbResult<Option<String>> {
+    let messages = db.load_history(&session.id, TITLE_PAIRS).await?;
+    if messages.is_empty() {
+        return Ok(None);
+    }
+    let transcript: String = messages
+        .iter()
+        .map(|m| format!("{}: {}\n", m.role, m.content.chars().take(MAX_MESSAGE_CHARS).collect::<String>()))
+        .collect();
+    let prompt = format!("{TITLE_PROMPT}\n\nConversation:\n{transcript}");
+    let request = vec![Message { role: Role::User, content: MessageContent::Text(prompt) }];
+
+    let response = match pool.chat(&request, &[], model).await {
+        Ok((response, provider)) => {
+            let u = &response.usage;
+            let entry = UsageEntry {
+                model: model.to_string(),
+                provider,
+                prompt_tokens: u.prompt_tokens,
+                completion_tokens: u.completion_tokens,
+                cache_creation_tokens: u.cache_creation_tokens,
+                cache_read_tokens: u.cache_read_tokens,
+                kb_owner_id: Some(session.user_id),
+                session_id: Some(session.id.clone()),
+                turns: 1,
+                ..Default::default()
+            };
+            let _ = usage_db.log_usage(&entry).await;
+            response.content.unwrap_or_default()
+        }
+        Err(e) => {
+            warn!("Session title failed: {e}");
+            return Ok(None);
+        }
+    };
+
+    let Some(title) = clean_title(&response) else {
+        return Ok(None);
+    };
+    db.set_session_title(&session.id, &title).await?;
+    Ok(Some(title))
+}
+
+/// First line of the reply without wrapping quotes or a trailing full stop,
+/// capped at [`MAX_TITLE_CHARS`].
+fn clean_title(raw: &str) -> Option<String> {
+    let line = raw.lines().map(str::trim).find(|l| !l.is_empty())?;
+    let line = line.trim_matches(|c: char| matches!(c, '"' | '\'' | '`' | '*' | '“' | '”')).trim_end_matches('.');
+    let title: String = line.trim().chars().take(MAX_TITLE_CHARS).collect();
+    (!title.is_empty()).then_some(title)
+}
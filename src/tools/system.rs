@@ -1,6 +1,7 @@
 use std::path::{Path, PathBuf};
 use tokio::process::Command;
 use tracing::{info, warn};
+use crate::tools::file_crypto::FileCipher;
 
 /// Maximum output size for command results (chars).
 const MAX_OUTPUT: usize = 30_000;
@@ -88,7 +89,8 @@ pub async fn bash_exec(command: &str, timeout_secs: Option<u64>) -> String {
 // ---------- file_read ----------
 
 /// Read a file from the filesystem with optional line range.
-pub async fn file_read(path: &str, offset: Option<usize>, limit: Option<usize>) -> String {
+/// Uploads sealed with `cipher` are decrypted first.
+pub async fn file_read(path: &str, offset: Option<usize>, limit: Option<usize>, cipher: Option<&FileCipher>) -> String {
     if path.is_empty() {
         return "Error: path cannot be empty".into();
     }
@@ -114,7 +116,11 @@ pub async fn file_read(path: &str, offset: Option<usize>, limit: Option<usize>)
         _ => {}
     }
 
-    match tokio::fs::read_to_string(&p).await {
+    let content = match super::uploads::read_file(&p, cipher).await {
+        Ok(bytes) => String::from_utf8(bytes).map_err(|_| "stream did not contain valid UTF-8".to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    match content {
         Ok(content) => {
             let lines: Vec<&str> = content.lines().collect();
             let start = offset.unwrap_or(0);
@@ -0,0 +1,49 @@
+use crate::db::{Database, Repository, TrashKind};
+
+pub async fn trash_list(db: &Database, user_id: u64) -> String {
+    match db.list_trash(user_id).await {
+        Ok(items) if items.is_empty() => "Trash is empty.".into(),
+        Ok(items) => {
+            let mut out = format!("{} item(s) in trash:\n", items.len());
+            for item in &items {
+                let label: String = item.label.chars().take(80).collect();
+                out.push_str(&format!(
+                    "- {} #{}: {label} (deleted {})\n",
+                    item.kind.as_str(), item.id, item.deleted_at
+                ));
+            }
+            out
+        }
+        Err(e) => format!("Error: {e}"),
+    }
+}
+
+pub async fn trash_restore(db: &Database, user_id: u64, kind: &str, id: i64) -> String {
+    let Some(kind) = TrashKind::parse(kind) else {
+        return "Error: type must be 'fact' or 'document'".into();
+    };
+    match db.restore_from_trash(user_id, kind, id).await {
+        Ok(true) => format!("Restored {} #{id} from trash.", kind.as_str()),
+        Ok(false) => format!("No {} #{id} in the trash.", kind.as_str()),
+        Err(e) => format!("Error: {e}"),
+    }
+}
+
+/// Purge one item when both `kind` and `id` are given, otherwise empty the whole trash.
+pub async fn trash_purge(db: &Database, user_id: u64, kind: Option<&str>, id: Option<i64>) -> String {
+    let item = match (kind, id) {
+        (Some(kind), Some(id)) => match TrashKind::parse(kind) {
+            Some(kind) => Some((kind, id)),
+            None => return "Error: type must be 'fact' or 'document'".into(),
+        },
+        (None, None) => None,
+        _ => return "Error: give both type and id to purge one item, or neither to empty the trash".into(),
+    };
+    match (db.purge_trash(user_id, item).await, item) {
+        (Ok(0), Some((kind, id))) => format!("No {} #{id} in the trash.", kind.as_str()),
+        (Ok(_), Some((kind, id))) => format!("Permanently deleted {} #{id}.", kind.as_str()),
+        (Ok(0), None) => "Trash is already empty.".into(),
+        (Ok(n), None) => format!("Permanently deleted {n} item(s) from trash."),
+        (Err(e), _) => format!("Error: {e}"),
+    }
+}
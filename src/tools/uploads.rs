@@ -0,0 +1,110 @@
+//! Files users upload, kept under `~/documents/{owner}`: written once per
+//! distinct content, sealed with the [`FileCipher`] when `ENCRYPTION_KEY` is
+//! set, and opened again by the tools that read them back.
+
+use std::path::{Path, PathBuf};
+
+use tracing::{error, info};
+
+use super::file_crypto::{FileCipher, FileCryptoError, is_encrypted};
+
+/// Root of the per-owner upload folders (`~/documents`), where the file key salt is kept too.
+pub fn documents_dir() -> PathBuf {
+    PathBuf::from(std::env::var("HOME").unwrap_or_default()).join("documents")
+}
+
+/// Save uploaded file to ~/documents/{user_id}/{file_name}, encrypted when a cipher is given.
+/// Creates directories if needed. Skips if identical content already saved.
+/// Adds _1, _2... suffix if same name but different content.
+pub async fn save_file_to_disk(
+    user_id: u64,
+    file_name: &str,
+    data: &[u8],
+    cipher: Option<&FileCipher>,
+) -> Option<PathBuf> {
+    let home = match std::env::var("HOME") {
+        Ok(h) => h,
+        Err(_) => return None,
+    };
+    let dir = PathBuf::from(&home).join("documents").join(user_id.to_string());
+
+    // Dedup: hash content, use .checksums/ dir with atomic create_new
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    data.hash(&mut hasher);
+    let hash_hex = format!("{:016x}", hasher.finish());
+
+    let checksums_dir = dir.join(".checksums");
+    if let Err(e) = tokio::fs::create_dir_all(&checksums_dir).await {
+        error!("Failed to create checksums dir: {e}");
+        return None;
+    }
+
+    match tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(checksums_dir.join(&hash_hex))
+        .await
+    {
+        Ok(_) => {} // New content, proceed to save
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            info!("Duplicate file skipped: {file_name} (hash {hash_hex})");
+            return None;
+        }
+        Err(_) => {} // Can't check, save anyway
+    }
+
+    let path = Path::new(file_name);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let ext = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+
+    let mut dest = dir.join(file_name);
+    let mut counter = 1u32;
+    loop {
+        match tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&dest)
+            .await
+        {
+            Ok(file) => {
+                use tokio::io::AsyncWriteExt;
+                let mut file = file;
+                let sealed = cipher.map(|c| c.encrypt(data));
+                // tokio finishes the write in the background unless flushed
+                let written = match file.write_all(sealed.as_deref().unwrap_or(data)).await {
+                    Ok(()) => file.flush().await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = written {
+                    error!("Failed to write file {}: {e}", dest.display());
+                    return None;
+                }
+                info!("File saved: {}", dest.display());
+                return Some(dest);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                dest = dir.join(format!("{stem}_{counter}{ext}"));
+                counter += 1;
+            }
+            Err(e) => {
+                error!("Failed to create file {}: {e}", dest.display());
+                return None;
+            }
+        }
+    }
+}
+
+/// Read a file a tool was pointed at, decrypting it if it is a sealed upload.
+/// Plaintext files (saved before encryption was on, or not uploads at all)
+/// are returned as they are.
+pub async fn read_file(path: &Path, cipher: Option<&FileCipher>) -> Result<Vec<u8>, FileCryptoError> {
+    let data = tokio::fs::read(path).await?;
+    if !is_encrypted(&data) {
+        return Ok(data);
+    }
+    match cipher {
+        Some(cipher) => cipher.decrypt(&data),
+        None => Err(FileCryptoError::NoKey),
+    }
+}
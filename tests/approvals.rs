@@ -0,0 +1,93 @@
+use memory_assistant::agent::approval::{ApprovalPolicy, approve_pending, reject_pending};
+use memory_assistant::db::{ApprovalDecision, Database, Repository};
+use memory_assistant::provider::ProviderPool;
+use memory_assistant::tools::embedding::DisabledEmbedder;
+
+const GROUP: u64 = 1001234567890;
+const ADMIN: u64 = 10;
+const MEMBER: u64 = 99;
+const POLICY: ApprovalPolicy<'static> = ApprovalPolicy { allowed_users: &[ADMIN], pending_ttl_hours: 48 };
+
+fn pool() -> ProviderPool {
+    ProviderPool::new(Vec::new(), None, None, None, None)
+}
+
+#[tokio::test]
+async fn approval_runs_the_tool_once_and_is_logged() {
+    let db = Database::open(":memory:").unwrap();
+    let id = db
+        .save_pending(GROUP, MEMBER, "category_add", r#"{"name":"recipes"}"#, "[category_add] \"recipes\"")
+        .await
+        .unwrap();
+
+    let (item, result) = approve_pending(&db, &pool(), &DisabledEmbedder, POLICY, GROUP, id, ADMIN).await.unwrap().unwrap();
+    assert_eq!(item.requested_by, MEMBER);
+    assert!(db.list_categories(GROUP).await.unwrap().contains(&"recipes".to_string()), "{result}");
+
+    // A second click (or a second admin) finds nothing left to decide
+    assert!(approve_pending(&db, &pool(), &DisabledEmbedder, POLICY, GROUP, id, ADMIN).await.unwrap().is_none());
+    assert!(reject_pending(&db, POLICY, GROUP, id, ADMIN).await.unwrap().is_none());
+
+    let log = db.list_approvals(GROUP, 10).await.unwrap();
+    assert_eq!(log.len(), 1);
+    assert_eq!((log[0].pending_id, log[0].decision, log[0].decided_by), (id, ApprovalDecision::Approved, Some(ADMIN)));
+    assert_eq!(log[0].result.as_deref(), Some(result.as_str()));
+    assert_eq!(log[0].tool_name, "category_add");
+}
+
+#[tokio::test]
+async fn rejection_is_logged_and_scoped_to_its_chat() {
+    let db = Database::open(":memory:").unwrap();
+    let id = db.save_pending(GROUP, MEMBER, "memory_save", "{}", "save a fact").await.unwrap();
+
+    assert!(reject_pending(&db, POLICY, 42, id, ADMIN).await.unwrap().is_none(), "another chat cannot decide it");
+    assert!(db.list_approvals(42, 10).await.unwrap().is_empty());
+
+    let item = reject_pending(&db, POLICY, GROUP, id, ADMIN).await.unwrap().unwrap();
+    assert_eq!(item.summary, "save a fact");
+    assert!(db.list_pending(GROUP).await.unwrap().is_empty());
+    let log = db.list_approvals(GROUP, 10).await.unwrap();
+    assert_eq!((log[0].decision, log[0].result.clone()), (ApprovalDecision::Rejected, None));
+}
+
+#[tokio::test]
+async fn old_requests_expire_into_the_log() {
+    let path = std::env::temp_dir().join(format!("ma-test-{}-approvals-expiry.db", std::process::id()));
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+    }
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+    let old = db.save_pending(GROUP, MEMBER, "memory_save", "{}", "old request").await.unwrap();
+    let fresh = db.save_pending(GROUP, MEMBER, "memory_save", "{}", "fresh request").await.unwrap();
+    {
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute("UPDATE pending_items SET created_at = datetime('now', '-3 days') WHERE id = ?1", [old])
+            .unwrap();
+    }
+
+    // Past the TTL but not swept yet: it can no longer be decided
+    assert!(approve_pending(&db, &pool(), &DisabledEmbedder, POLICY, GROUP, old, ADMIN).await.unwrap().is_none());
+    assert!(db.list_approvals(GROUP, 10).await.unwrap().is_empty());
+
+    let expired = db.expire_pending(48).await.unwrap();
+    assert_eq!(expired.iter().map(|i| i.id).collect::<Vec<_>>(), [old]);
+    assert_eq!(db.list_pending(GROUP).await.unwrap().iter().map(|i| i.id).collect::<Vec<_>>(), [fresh]);
+
+    let log = db.list_approvals(GROUP, 10).await.unwrap();
+    assert_eq!((log[0].pending_id, log[0].decision, log[0].decided_by), (old, ApprovalDecision::Expired, None));
+    assert!(reject_pending(&db, POLICY, GROUP, old, ADMIN).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn admins_are_notified_of_each_request_once() {
+    let db = Database::open(":memory:").unwrap();
+    let first = db.save_pending(GROUP, MEMBER, "memory_save", "{}", "one").await.unwrap();
+    let second = db.save_pending(GROUP, MEMBER, "memory_save", "{}", "two").await.unwrap();
+
+    let claimed = db.claim_pending_notifications().await.unwrap();
+    assert_eq!(claimed.iter().map(|i| i.id).collect::<Vec<_>>(), [first, second]);
+    assert!(db.claim_pending_notifications().await.unwrap().is_empty());
+
+    let third = db.save_pending(GROUP, MEMBER, "memory_save", "{}", "three").await.unwrap();
+    assert_eq!(db.claim_pending_notifications().await.unwrap()[0].id, third);
+}
@@ -0,0 +1,150 @@
+mod common;
+
+use common::temp_db_path;
+use memory_assistant::agent::approval::ApprovalPolicy;
+use memory_assistant::agent::{ToolOutput, ToolRegistry};
+use memory_assistant::db::audit::{self, AuditFilter, UNDO_TOOL};
+use memory_assistant::db::{Actor, Database, DbError, Repository};
+use memory_assistant::provider::ProviderPool;
+use memory_assistant::tools::embedding::DisabledEmbedder;
+
+const OWNER: u64 = 7;
+const ALICE: u64 = 10;
+const BOB: u64 = 11;
+const ALICE_EDIT: Actor<'static> = Actor { user_id: ALICE, tool: "memory_edit" };
+
+async fn run(db: &Database, user_id: u64, tool: &str, args: serde_json::Value) -> String {
+    let pool = ProviderPool::new(Vec::new(), None, None, None, None);
+    let approvals = ApprovalPolicy::default();
+    match ToolRegistry::execute(tool, &args.to_string(), user_id, OWNER, db, &pool, &DisabledEmbedder, None, approvals).await {
+        ToolOutput::Text(text) => text,
+        ToolOutput::Image { text, .. } => text,
+    }
+}
+
+#[tokio::test]
+async fn write_tools_are_logged_with_the_rows_they_changed() {
+    let db = Database::open(":memory:").unwrap();
+    let fact_id = db.save_fact(OWNER, "Coffee at 9", "general").await.unwrap();
+
+    run(&db, ALICE, "memory_edit", serde_json::json!({ "id": fact_id, "new_fact": "Coffee at 10" })).await;
+    run(&db, ALICE, "memory_list", serde_json::json!({})).await;
+    run(&db, BOB, "category_add", serde_json::json!({ "name": "recipes" })).await;
+
+    let entries = db.search_audit(OWNER, AuditFilter::default(), 10).await.unwrap();
+    let tools: Vec<&str> = entries.iter().map(|e| e.tool.as_str()).collect();
+    assert_eq!(tools, ["category_add", "memory_edit"], "read tools are not logged");
+
+    let edit = &entries[1];
+    assert_eq!((edit.user_id, edit.kb_owner_id), (ALICE, OWNER));
+    assert_eq!(edit.before.facts[0].text, "Coffee at 9");
+    assert_eq!(edit.after.facts[0].text, "Coffee at 10");
+    assert!(edit.result.starts_with("Updated memory"), "{}", edit.result);
+    // Default categories created on the way are part of the category_add entry, not the edit
+    assert!(edit.before.categories.is_empty() && edit.after.categories.is_empty());
+    assert!(entries[0].after.categories.contains(&"recipes".to_string()));
+
+    let filter = AuditFilter { user_id: Some(ALICE), text: Some("Coffee at 10".into()), ..Default::default() };
+    let found = db.search_audit(OWNER, filter, 10).await.unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].id, edit.id);
+}
+
+#[tokio::test]
+async fn writes_from_other_tasks_are_not_attributed() {
+    let db = Database::open(":memory:").unwrap();
+    let fact_id = db.save_fact(OWNER, "Coffee at 9", "general").await.unwrap();
+
+    let (_, changes) = audit::track_changes(async {
+        db.update_fact(OWNER, fact_id, "Coffee at 10", ALICE_EDIT).await.unwrap();
+        // Another chat writing to the same knowledge base meanwhile
+        let other = db.clone();
+        tokio::spawn(async move { other.save_fact(OWNER, "Gym on Fridays", "health").await.unwrap() }).await.unwrap();
+        db.update_fact(OWNER, fact_id, "Coffee at 11", ALICE_EDIT).await.unwrap();
+    })
+    .await;
+
+    let (before, after) = changes.states(OWNER);
+    assert_eq!(before.facts.len(), 1);
+    assert_eq!((before.facts[0].text.as_str(), after.facts[0].text.as_str()), ("Coffee at 9", "Coffee at 11"));
+    assert_eq!(after.facts.len(), 1, "the other chat's fact is not in this entry");
+    assert!(after.categories.is_empty(), "nor is its new category");
+}
+
+#[tokio::test]
+async fn undo_walks_back_the_callers_writes() {
+    let db = Database::open(":memory:").unwrap();
+    let fact_id = db.save_fact(OWNER, "Standup at 9", "work").await.unwrap();
+
+    let saved = run(&db, ALICE, "memory_save", serde_json::json!({ "fact": "Gym on Fridays", "category": "health" })).await;
+    run(&db, ALICE, "memory_edit", serde_json::json!({ "id": fact_id, "new_fact": "Standup at 10" })).await;
+    run(&db, ALICE, "memory_delete", serde_json::json!({ "id": 999 })).await;
+
+    // The failed delete changed nothing, so the edit is undone first
+    let undo = db.undo_last_write(ALICE, OWNER).await.unwrap().unwrap();
+    assert_eq!(undo.reversed.tool, "memory_edit");
+    assert_eq!(undo.reversed.undone_by, Some(undo.entry.id));
+    assert_eq!((undo.entry.tool.as_str(), undo.entry.undoes), (UNDO_TOOL, Some(undo.reversed.id)));
+    let facts = db.list_facts(OWNER, Some("work")).await.unwrap();
+    assert_eq!(facts[0].text, "Standup at 9");
+    let history = db.get_fact_history(OWNER, fact_id).await.unwrap();
+    assert_eq!(history[0].tool, UNDO_TOOL);
+
+    // Then the save: the new fact goes to the trash
+    let undo = db.undo_last_write(ALICE, OWNER).await.unwrap().unwrap();
+    assert_eq!(undo.reversed.tool, "memory_save", "{saved}");
+    assert!(db.list_facts(OWNER, Some("health")).await.unwrap().is_empty());
+    assert_eq!(db.list_trash(OWNER).await.unwrap().len(), 1);
+
+    assert!(db.undo_last_write(ALICE, OWNER).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn undo_refuses_rows_changed_since() {
+    let db = Database::open(":memory:").unwrap();
+    let fact_id = db.save_fact(OWNER, "Lunch at noon", "general").await.unwrap();
+
+    run(&db, ALICE, "memory_edit", serde_json::json!({ "id": fact_id, "new_fact": "Lunch at 1" })).await;
+    run(&db, BOB, "memory_edit", serde_json::json!({ "id": fact_id, "new_fact": "Lunch at 2" })).await;
+
+    // Bob's edit is Bob's to undo; Alice's would overwrite it
+    match db.undo_last_write(ALICE, OWNER).await {
+        Err(DbError::Conflict(message)) => assert!(message.contains("has changed since"), "{message}"),
+        other => panic!("expected a conflict, got {other:?}"),
+    }
+    db.undo_last_write(BOB, OWNER).await.unwrap().unwrap();
+    db.undo_last_write(ALICE, OWNER).await.unwrap().unwrap();
+    assert_eq!(db.list_facts(OWNER, None).await.unwrap()[0].text, "Lunch at noon");
+}
+
+#[tokio::test]
+async fn undo_restores_document_text_and_category_deletes() {
+    let db = Database::open(":memory:").unwrap();
+    let doc_id = db.save_document(OWNER, "Recipe", "Two eggs and flour", None, None).await.unwrap();
+    db.add_category(OWNER, "drafts").await.unwrap();
+
+    run(&db, ALICE, "knowledge_patch", serde_json::json!({ "doc_id": doc_id, "old_text": "Two", "new_text": "Three" })).await;
+    run(&db, ALICE, "category_delete", serde_json::json!({ "name": "drafts" })).await;
+
+    db.undo_last_write(ALICE, OWNER).await.unwrap().unwrap();
+    assert!(db.list_categories(OWNER).await.unwrap().contains(&"drafts".to_string()));
+
+    db.undo_last_write(ALICE, OWNER).await.unwrap().unwrap();
+    assert_eq!(db.get_document(OWNER, doc_id).await.unwrap().content, "Two eggs and flour");
+    let versions = db.list_document_versions(OWNER, doc_id).await.unwrap();
+    assert_eq!((versions[0].version, versions[0].tool.as_str()), (3, UNDO_TOOL));
+}
+
+#[tokio::test]
+async fn audit_log_is_append_only() {
+    let path = temp_db_path("append-only");
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+    run(&db, ALICE, "category_add", serde_json::json!({ "name": "recipes" })).await;
+    drop(db);
+
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    assert!(conn.execute("UPDATE audit_log SET result = 'edited'", []).is_err());
+    assert!(conn.execute("DELETE FROM audit_log", []).is_err());
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM audit_log", [], |row| row.get(0)).unwrap();
+    assert_eq!(count, 1);
+}
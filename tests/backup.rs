@@ -0,0 +1,89 @@
+use std::path::PathBuf;
+
+use chrono::NaiveDateTime;
+use memory_assistant::db::backup::{self, BackupError, BackupInfo, RetentionPolicy};
+use memory_assistant::db::{Database, Repository};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ma-backup-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn info(stamp: &str) -> BackupInfo {
+    BackupInfo {
+        path: PathBuf::from(stamp),
+        created_at: NaiveDateTime::parse_from_str(stamp, "%Y-%m-%d %H:%M").unwrap(),
+        size_bytes: 0,
+    }
+}
+
+#[tokio::test]
+async fn snapshot_is_verified_and_readable() {
+    let dir = temp_dir("snapshot");
+    let db = Database::open(dir.join("live.db").to_str().unwrap()).unwrap();
+    db.save_fact(1, "backups run nightly", "workflow").await.unwrap();
+
+    let policy = RetentionPolicy { daily: 7, weekly: 4 };
+    let report = db.backup_now(&dir.join("backups"), policy).await.unwrap();
+    assert!(report.removed.is_empty());
+    assert!(report.backup.size_bytes > 0);
+
+    let listed = backup::list_backups(&dir.join("backups")).unwrap();
+    assert_eq!(listed, vec![report.backup.clone()]);
+
+    let copy = Database::open(report.backup.path.to_str().unwrap()).unwrap();
+    assert_eq!(copy.list_facts(1, None).await.unwrap()[0].text, "backups run nightly");
+}
+
+#[tokio::test]
+async fn corrupt_snapshot_fails_verification() {
+    let dir = temp_dir("corrupt");
+    let db = Database::open(":memory:").unwrap();
+    let path = dir.join("copy.db");
+    db.backup_to(&path).await.unwrap();
+    backup::verify_backup(&path, None).unwrap();
+
+    // Scribble over the first b-tree page after the header
+    let mut bytes = std::fs::read(&path).unwrap();
+    let page_size = u16::from_be_bytes([bytes[16], bytes[17]]) as usize;
+    bytes[page_size..page_size * 2].fill(0xAB);
+    std::fs::write(&path, bytes).unwrap();
+    assert!(matches!(
+        backup::verify_backup(&path, None),
+        Err(BackupError::Corrupt { .. } | BackupError::Sqlite(_))
+    ));
+}
+
+#[test]
+fn rotation_keeps_newest_per_day_and_week() {
+    // Newest first, as list_backups returns them
+    let backups = [
+        info("2025-03-12 18:00"), // Wed, week 11
+        info("2025-03-12 06:00"), // same day, older
+        info("2025-03-11 06:00"), // Tue, week 11
+        info("2025-03-09 06:00"), // Sun, week 10
+        info("2025-03-02 06:00"), // Sun, week 9
+        info("2025-02-23 06:00"), // Sun, week 8
+    ];
+    let expired: Vec<_> = backup::expired_backups(&backups, RetentionPolicy { daily: 2, weekly: 3 })
+        .into_iter()
+        .map(|b| b.path.to_str().unwrap())
+        .collect();
+    assert_eq!(expired, ["2025-03-12 06:00", "2025-02-23 06:00"]);
+
+    let none_kept = backup::expired_backups(&backups, RetentionPolicy { daily: 0, weekly: 0 });
+    assert_eq!(none_kept.len(), backups.len());
+}
+
+#[test]
+fn rotation_ignores_unrelated_files() {
+    let dir = temp_dir("rotate");
+    for name in ["memory-assistant-20250301-060000.db", "memory-assistant-20250302-060000.db", "notes.txt"] {
+        std::fs::write(dir.join(name), b"x").unwrap();
+    }
+    let removed = backup::rotate_backups(&dir, RetentionPolicy { daily: 1, weekly: 0 }).unwrap();
+    assert_eq!(removed, [dir.join("memory-assistant-20250301-060000.db")]);
+    assert!(dir.join("notes.txt").exists());
+}
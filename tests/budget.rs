@@ -0,0 +1,96 @@
+use memory_assistant::db::{BudgetSubject, Database, Repository, SpendLimits, UsageEntry};
+use memory_assistant::tools::{BudgetDecision, BudgetPeriod, BudgetPolicy, OverBudgetAction, check_budget};
+
+fn policy(action: OverBudgetAction) -> BudgetPolicy {
+    BudgetPolicy {
+        owner: SpendLimits { daily_usd: Some(2.0), monthly_usd: None },
+        user: SpendLimits::default(),
+        action,
+        fallback_model: "gpt-5-mini".into(),
+        alert_percent: 80,
+    }
+}
+
+/// Log `dollars` of Haiku input ($1 per 1M tokens) for `user` in `owner`'s chat.
+async fn spend(db: &Database, user: u64, owner: u64, dollars: u32) {
+    let entry = UsageEntry {
+        model: "claude-haiku-4-5-20251001".into(),
+        provider: "claude".into(),
+        prompt_tokens: dollars * 1_000_000,
+        user_id: Some(user),
+        kb_owner_id: Some(owner),
+        ..Default::default()
+    };
+    db.log_usage(&entry).await.unwrap();
+}
+
+#[tokio::test]
+async fn under_budget_is_allowed_and_alerts_once() {
+    let db = Database::open(":memory:").unwrap();
+    let policy = policy(OverBudgetAction::Refuse);
+    let now = chrono::Utc::now();
+
+    let check = check_budget(&db, &policy, 1, 100, now).await.unwrap();
+    assert_eq!(check.decision, BudgetDecision::Allow);
+    assert!(check.alerts.is_empty());
+
+    // $1 of $2 is below the 80% threshold
+    spend(&db, 1, 100, 1).await;
+    assert!(check_budget(&db, &policy, 1, 100, now).await.unwrap().alerts.is_empty());
+
+    let mut owner_only = policy.clone();
+    owner_only.owner.daily_usd = Some(1.2);
+    let check = check_budget(&db, &owner_only, 1, 100, now).await.unwrap();
+    assert_eq!(check.decision, BudgetDecision::Allow);
+    assert_eq!(check.alerts.len(), 1);
+    assert_eq!((check.alerts[0].subject, check.alerts[0].period), (BudgetSubject::Owner(100), BudgetPeriod::Day));
+
+    // Already alerted for today
+    assert!(check_budget(&db, &owner_only, 1, 100, now).await.unwrap().alerts.is_empty());
+}
+
+#[tokio::test]
+async fn over_budget_falls_back_or_refuses() {
+    let db = Database::open(":memory:").unwrap();
+    let now = chrono::Utc::now();
+    spend(&db, 1, 100, 2).await;
+
+    let check = check_budget(&db, &policy(OverBudgetAction::Fallback), 1, 100, now).await.unwrap();
+    match check.decision {
+        BudgetDecision::Fallback { model, exceeded } => {
+            assert_eq!(model, "gpt-5-mini");
+            assert_eq!(exceeded.subject, BudgetSubject::Owner(100));
+            assert!((exceeded.spent_usd - 2.0).abs() < 1e-9);
+        }
+        other => panic!("expected fallback, got {other:?}"),
+    }
+
+    let check = check_budget(&db, &policy(OverBudgetAction::Refuse), 1, 100, now).await.unwrap();
+    assert!(matches!(check.decision, BudgetDecision::Refuse(_)));
+
+    // Another chat has its own owner budget
+    let check = check_budget(&db, &policy(OverBudgetAction::Refuse), 1, 200, now).await.unwrap();
+    assert_eq!(check.decision, BudgetDecision::Allow);
+}
+
+#[tokio::test]
+async fn user_overrides_replace_the_default() {
+    let db = Database::open(":memory:").unwrap();
+    let now = chrono::Utc::now();
+    let policy = policy(OverBudgetAction::Refuse);
+    // Spent across two chats, each under the per-chat limit
+    spend(&db, 7, 100, 1).await;
+    spend(&db, 7, 200, 1).await;
+    assert_eq!(check_budget(&db, &policy, 7, 300, now).await.unwrap().decision, BudgetDecision::Allow);
+
+    let limits = SpendLimits { daily_usd: None, monthly_usd: Some(1.5) };
+    db.set_spend_limits(BudgetSubject::User(7), limits).await.unwrap();
+    assert_eq!(db.get_spend_limits(BudgetSubject::User(7)).await.unwrap(), Some(limits));
+    match check_budget(&db, &policy, 7, 300, now).await.unwrap().decision {
+        BudgetDecision::Refuse(u) => assert_eq!((u.subject, u.period), (BudgetSubject::User(7), BudgetPeriod::Month)),
+        other => panic!("expected refusal, got {other:?}"),
+    }
+
+    assert!(db.clear_spend_limits(BudgetSubject::User(7)).await.unwrap());
+    assert_eq!(check_budget(&db, &policy, 7, 300, now).await.unwrap().decision, BudgetDecision::Allow);
+}
@@ -1,4 +1,4 @@
-use memory_assistant::db::Database;
+use memory_assistant::db::{Database, Repository};
 use memory_assistant::tools::embedding::{cosine_similarity, embedding_to_bytes, bytes_to_embedding};
 use memory_assistant::tools::knowledge::chunk_document;
 
@@ -88,13 +88,13 @@ fn chunk_emoji_and_special_chars() {
 
 // --- DB chunk tests ---
 
-#[test]
-fn db_save_and_search_chunks() {
+#[tokio::test]
+async fn db_save_and_search_chunks() {
     let db = Database::open(":memory:").expect("open in-memory db");
     let user_id = 1u64;
 
     // Save a document
-    let doc_id = db.save_document(user_id, "Hợp đồng ABC", "full content here", None, None).unwrap();
+    let doc_id = db.save_document(user_id, "Hợp đồng ABC", "full content here", None, None).await.unwrap();
 
     // Save chunks
     let chunks = vec![
@@ -102,51 +102,51 @@ fn db_save_and_search_chunks() {
         (1, 6, 10, "Điều 2: Quyền nghĩa vụ các bên tham gia"),
         (2, 11, 15, "Điều 3: Phương thức thanh toán chuyển khoản"),
     ];
-    let ids = db.save_chunks(doc_id, &chunks).unwrap();
+    let ids = db.save_chunks(doc_id, &chunks).await.unwrap();
     assert_eq!(ids.len(), 3);
 
     // FTS search
-    let results = db.search_chunks_fts(user_id, "thanh toán").unwrap();
+    let results = db.search_chunks_fts(user_id, "thanh toán").await.unwrap();
     assert!(!results.is_empty(), "FTS should find 'thanh toán'");
 
-    let first = &results[0];
-    assert_eq!(first.1, doc_id); // doc_id matches
-    assert!(first.4 > 0); // start_line > 0
+    let (first, _rank) = &results[0];
+    assert_eq!(first.doc_id, doc_id);
+    assert!(first.start_line > 0);
 }
 
-#[test]
-fn db_unchunked_docs() {
+#[tokio::test]
+async fn db_unchunked_docs() {
     let db = Database::open(":memory:").expect("open in-memory db");
     let user_id = 1u64;
 
-    let doc_id = db.save_document(user_id, "Test", "content", None, None).unwrap();
-    let unchunked = db.get_unchunked_doc_ids().unwrap();
+    let doc_id = db.save_document(user_id, "Test", "content", None, None).await.unwrap();
+    let unchunked = db.get_unchunked_documents().await.unwrap();
     assert_eq!(unchunked.len(), 1);
-    assert_eq!(unchunked[0].0, doc_id);
+    assert_eq!(unchunked[0].id, doc_id);
 
     // After saving chunks, should not appear
-    db.save_chunks(doc_id, &[(0, 1, 1, "content")]).unwrap();
-    let unchunked = db.get_unchunked_doc_ids().unwrap();
+    db.save_chunks(doc_id, &[(0, 1, 1, "content")]).await.unwrap();
+    let unchunked = db.get_unchunked_documents().await.unwrap();
     assert!(unchunked.is_empty());
 }
 
-#[test]
-fn db_update_and_load_embeddings() {
+#[tokio::test]
+async fn db_update_and_load_embeddings() {
     let db = Database::open(":memory:").expect("open in-memory db");
     let user_id = 1u64;
 
-    let doc_id = db.save_document(user_id, "Test", "content", None, None).unwrap();
-    let ids = db.save_chunks(doc_id, &[(0, 1, 1, "chunk text")]).unwrap();
+    let doc_id = db.save_document(user_id, "Test", "content", None, None).await.unwrap();
+    let ids = db.save_chunks(doc_id, &[(0, 1, 1, "chunk text")]).await.unwrap();
 
     // Create fake embedding
     let fake_emb: Vec<f32> = (0..128).map(|i| i as f32 / 128.0).collect();
     let blob = embedding_to_bytes(&fake_emb);
-    db.update_chunk_embeddings(&ids, &[blob]).unwrap();
+    db.update_chunk_embeddings(&ids, &[blob]).await.unwrap();
 
     // Load and verify
-    let loaded = db.load_all_embeddings(user_id).unwrap();
+    let loaded = db.load_all_embeddings(user_id).await.unwrap();
     assert_eq!(loaded.len(), 1);
-    let recovered = bytes_to_embedding(&loaded[0].7);
+    let recovered = bytes_to_embedding(&loaded[0].1);
     assert_eq!(recovered.len(), 128);
     assert!((recovered[0] - 0.0).abs() < 1e-6);
     assert!((recovered[1] - 1.0/128.0).abs() < 1e-6);
@@ -0,0 +1,27 @@
+//! Helpers shared by the integration tests (`mod common;`).
+
+// Each test binary compiles its own copy and uses only some of these
+#![allow(dead_code)]
+
+/// A fresh database path under the temp dir, unique to this test binary and
+/// process, with any leftover database and WAL files removed.
+pub fn temp_db_path(name: &str) -> std::path::PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("ma-{}-{}-{name}.db", env!("CARGO_CRATE_NAME"), std::process::id()));
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+    }
+    path
+}
+
+/// Deterministic pseudo-random vectors (xorshift), components in [-1, 1).
+pub fn random_vectors(count: usize, dims: usize, seed: u64) -> Vec<Vec<f32>> {
+    let mut state = seed | 1;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+    };
+    (0..count).map(|_| (0..dims).map(|_| next()).collect()).collect()
+}
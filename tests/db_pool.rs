@@ -0,0 +1,71 @@
+mod common;
+
+use common::temp_db_path;
+use memory_assistant::db::{Actor, Database, Repository};
+
+const ACTOR: Actor<'static> = Actor { user_id: 1, tool: "test" };
+
+#[tokio::test]
+async fn readers_see_committed_writes() {
+    let path = temp_db_path("visibility");
+    let db = Database::open_with_readers(path.to_str().unwrap(), 2).unwrap();
+
+    let id = db.save_fact(1, "written on the writer", "general").await.unwrap();
+    let facts = db.list_facts(1, None).await.unwrap();
+    assert_eq!(facts.len(), 1);
+    assert_eq!(facts[0].id, id);
+
+    db.update_fact(1, id, "edited on the writer", ACTOR).await.unwrap();
+    let facts = db.list_facts(1, None).await.unwrap();
+    assert_eq!(facts[0].text, "edited on the writer");
+}
+
+#[tokio::test]
+async fn search_updates_access_count_through_writer() {
+    // search_facts reads on a query-only connection, then bumps access_count on the writer
+    let path = temp_db_path("access");
+    let db = Database::open_with_readers(path.to_str().unwrap(), 1).unwrap();
+
+    db.save_fact(1, "rust ownership rules", "technical").await.unwrap();
+    let results = db.search_facts(1, "ownership").await.unwrap();
+    assert_eq!(results.len(), 1);
+
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    let count: i64 = conn
+        .query_row("SELECT access_count FROM memory_facts WHERE id = ?1", [results[0].id], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 1);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_reads_and_writes() {
+    let path = temp_db_path("concurrent");
+    let db = Database::open_with_readers(path.to_str().unwrap(), 3).unwrap();
+    let doc_id = db.save_document(1, "Pool", "line one\nline two", None, None).await.unwrap();
+    db.save_chunks(doc_id, &[(0, 1, 2, "connection pool chunk")]).await.unwrap();
+
+    let mut handles = Vec::new();
+    for i in 0..32 {
+        let db = db.clone();
+        handles.push(tokio::spawn(async move {
+            if i % 4 == 0 {
+                db.save_fact(1, &format!("fact {i}"), "general").await.map(|_| ())
+            } else {
+                db.search_chunks_fts(1, "pool").await.map(|rows| assert_eq!(rows.len(), 1))
+            }
+        }));
+    }
+    for handle in handles {
+        handle.await.unwrap().unwrap();
+    }
+
+    assert_eq!(db.list_facts(1, None).await.unwrap().len(), 8);
+}
+
+#[tokio::test]
+async fn in_memory_database_reads_through_writer() {
+    // :memory: databases are private to one connection, so no readers are opened
+    let db = Database::open_with_readers(":memory:", 4).unwrap();
+    db.save_fact(1, "only visible to the writer connection", "general").await.unwrap();
+    assert_eq!(db.list_facts(1, None).await.unwrap().len(), 1);
+}
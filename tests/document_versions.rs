@@ -0,0 +1,65 @@
+use memory_assistant::db::{Actor, Database, DbError, Repository};
+use memory_assistant::tools::embedding::DisabledEmbedder;
+use memory_assistant::tools::{knowledge_diff, knowledge_rollback, knowledge_save};
+
+const PATCHER: Actor<'static> = Actor { user_id: 7, tool: "knowledge_patch" };
+
+#[tokio::test]
+async fn save_and_patch_create_versions_with_diffs() {
+    let db = Database::open(":memory:").unwrap();
+    let doc_id = db.save_document(1, "Runbook", "step one\nstep two\nstep three", None, None).await.unwrap();
+    db.patch_document(1, doc_id, "step two", "step 2", PATCHER).await.unwrap();
+
+    let versions = db.list_document_versions(1, doc_id).await.unwrap();
+    assert_eq!(versions.len(), 2);
+    let (latest, first) = (&versions[0], &versions[1]);
+    assert_eq!((first.version, first.tool.as_str(), first.diff.as_deref()), (1, "knowledge_save", None));
+    assert_eq!((latest.version, latest.actor_id), (2, Some(7)));
+    assert_eq!(latest.content, "step one\nstep 2\nstep three");
+
+    let diff = latest.diff.as_deref().unwrap();
+    assert!(diff.contains("--- v1\n+++ v2\n"), "{diff}");
+    assert!(diff.contains("-step two\n+step 2\n"), "{diff}");
+
+    // The patched document is found under its new text
+    assert_eq!(db.search_documents(1, "three").await.unwrap().len(), 1);
+    assert!(db.search_documents(1, "\"step two\"").await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn rollback_restores_content_and_rechunks() {
+    let db = Database::open(":memory:").unwrap();
+    let (doc_id, _) = knowledge_save(&db, 1, "Notes", "alpha\nbeta", None, None, &DisabledEmbedder).await.unwrap();
+    db.patch_document(1, doc_id, "beta", "gamma", PATCHER).await.unwrap();
+
+    let msg = knowledge_rollback(&db, 1, 7, doc_id, 1, &DisabledEmbedder).await;
+    assert!(msg.contains("saved as v3"), "{msg}");
+
+    let doc = db.get_document(1, doc_id).await.unwrap();
+    assert_eq!(doc.content, "alpha\nbeta");
+    let chunks = db.search_chunks_fts(1, "beta").await.unwrap();
+    assert_eq!(chunks.len(), 1);
+    assert!(db.search_chunks_fts(1, "gamma").await.unwrap().is_empty());
+
+    let v3 = db.get_document_version(1, doc_id, 3).await.unwrap();
+    assert_eq!((v3.tool.as_str(), v3.actor_id), ("knowledge_rollback", Some(7)));
+
+    // Rolling back to content the document already has is refused
+    let err = db.rollback_document(1, doc_id, 3, PATCHER).await.unwrap_err();
+    assert!(matches!(err, DbError::Conflict(_)));
+}
+
+#[tokio::test]
+async fn diff_between_any_two_versions() {
+    let db = Database::open(":memory:").unwrap();
+    let doc_id = db.save_document(1, "Plan", "a\nb\nc", None, None).await.unwrap();
+    db.patch_document(1, doc_id, "a", "A", PATCHER).await.unwrap();
+    db.patch_document(1, doc_id, "c", "C", PATCHER).await.unwrap();
+
+    let diff = knowledge_diff(&db, 1, doc_id, 1, None).await;
+    assert!(diff.contains("--- v1\n+++ v3\n"), "{diff}");
+    assert!(diff.contains("-a\n") && diff.contains("+C"), "{diff}");
+
+    assert!(knowledge_diff(&db, 1, doc_id, 2, Some(2)).await.contains("identical"));
+    assert!(knowledge_diff(&db, 2, doc_id, 1, None).await.contains("not found"));
+}
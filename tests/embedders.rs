@@ -0,0 +1,98 @@
+use memory_assistant::agent::approval::ApprovalPolicy;
+use memory_assistant::agent::{ToolOutput, ToolRegistry};
+use memory_assistant::db::embedding_model::ModelCheck;
+use memory_assistant::db::vector_index::VectorKind;
+use memory_assistant::db::{Database, Repository};
+use memory_assistant::provider::ProviderPool;
+use memory_assistant::tools::embedding::{
+    CachedEmbedder, Embedder, EmbeddingBackend, HashingEmbedder, cosine_similarity,
+};
+
+const OWNER: u64 = 7;
+
+async fn run(db: &Database, embedder: &dyn Embedder, tool: &str, args: serde_json::Value) -> String {
+    let pool = ProviderPool::new(Vec::new(), None, None, None, None);
+    let approvals = ApprovalPolicy::default();
+    match ToolRegistry::execute(tool, &args.to_string(), OWNER, OWNER, db, &pool, embedder, None, approvals).await {
+        ToolOutput::Text(text) => text,
+        ToolOutput::Image { text, .. } => text,
+    }
+}
+
+#[tokio::test]
+async fn hashing_embedder_is_deterministic_and_word_based() {
+    let embedder = HashingEmbedder::new(64);
+    assert_eq!(embedder.model(), "hashing-64");
+
+    let texts = ["Coffee at 9, every morning!", "coffee at 9 every morning", "Quarterly tax filing deadline"];
+    let vectors = embedder.embed_batch(&texts, "document").await.unwrap();
+    assert_eq!(vectors.len(), 3);
+    assert_eq!(vectors[0].len(), 64);
+    assert_eq!(vectors[0], vectors[1], "case and punctuation are ignored");
+    assert!((vectors[0].iter().map(|x| x * x).sum::<f32>() - 1.0).abs() < 1e-5);
+
+    let dyn_embedder: &dyn Embedder = &embedder;
+    let query = dyn_embedder.embed_query("morning coffee").await.unwrap();
+    assert!(cosine_similarity(&query, &vectors[0]) > cosine_similarity(&query, &vectors[2]));
+    assert_eq!(HashingEmbedder::new(64).embed("morning coffee"), query, "same vector in every process");
+    assert!(embedder.embed_batch(&[], "query").await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn tools_embed_through_the_configured_backend() {
+    let db = Database::open(":memory:").unwrap();
+    let backend = EmbeddingBackend::Hashing { dims: 128 };
+    assert_eq!(backend.describe(), "hashing (128 dims, offline)");
+    let embedder = CachedEmbedder::new(backend.build(), db.clone());
+
+    let saved = run(&db, &embedder, "memory_save", serde_json::json!({ "fact": "Coffee at 9 every morning" })).await;
+    assert!(saved.starts_with("Saved"), "{saved}");
+    assert!(db.get_unembedded_facts(OWNER).await.unwrap().is_empty());
+    let found = run(&db, &embedder, "memory_search", serde_json::json!({ "keyword": "morning coffee" })).await;
+    assert!(found.contains("Coffee at 9"), "{found}");
+
+    let content = "# Recipe\n\nTwo eggs, flour and butter.\n";
+    let saved = run(&db, &embedder, "knowledge_save", serde_json::json!({ "title": "Pancakes", "content": content }))
+        .await;
+    assert!(!saved.starts_with("Error"), "{saved}");
+    let chunks = db.load_all_embeddings(OWNER).await.unwrap();
+    assert!(!chunks.is_empty());
+    assert!(chunks.iter().all(|(_, blob)| blob.len() == 128 * 4));
+
+    // Saving the same fact again is served from the cache
+    let before = db.embedding_cache_stats().await.unwrap();
+    run(&db, &embedder, "memory_save", serde_json::json!({ "fact": "Coffee at 9 every morning" })).await;
+    let after = db.embedding_cache_stats().await.unwrap();
+    assert_eq!((after.hits - before.hits, after.entries), (1, before.entries));
+}
+
+#[tokio::test]
+async fn switching_models_clears_the_old_vectors() {
+    let db = Database::open(":memory:").unwrap();
+    let small = HashingEmbedder::new(64);
+    run(&db, &small, "memory_save", serde_json::json!({ "fact": "Coffee at 9 every morning" })).await;
+    let content = "# Recipe\n\nTwo eggs, flour and butter.\n";
+    run(&db, &small, "knowledge_save", serde_json::json!({ "title": "Pancakes", "content": content })).await;
+    assert_eq!(db.nearest_facts(OWNER, &small.embed("coffee"), 1).await.unwrap().len(), 1);
+
+    // Databases from before the model was recorded adopt it when the sizes agree
+    assert_eq!(db.check_embedding_model("hashing-64", 64).await.unwrap(), ModelCheck::Recorded);
+    assert_eq!(db.check_embedding_model("hashing-64", 64).await.unwrap(), ModelCheck::Unchanged);
+
+    let chunks = db.load_all_embeddings(OWNER).await.unwrap().len();
+    let check = db.check_embedding_model("hashing-128", 128).await.unwrap();
+    let previous = "hashing-64 (64 dims)".to_string();
+    assert_eq!(check, ModelCheck::Cleared { previous, facts: 1, chunks });
+    assert_eq!(db.get_unembedded_facts(OWNER).await.unwrap().len(), 1);
+    assert_eq!(db.get_unembedded_chunks(100).await.unwrap().len(), chunks);
+    assert!(db.load_all_embeddings(OWNER).await.unwrap().is_empty());
+    assert_eq!(db.vector_index_stats(OWNER, VectorKind::Fact).await.unwrap(), None);
+    assert_eq!(db.check_embedding_model("hashing-128", 128).await.unwrap(), ModelCheck::Unchanged);
+
+    // An unrecorded database whose vectors are another size is cleared too
+    let legacy = Database::open(":memory:").unwrap();
+    run(&legacy, &small, "memory_save", serde_json::json!({ "fact": "Gym on Fridays" })).await;
+    let check = legacy.check_embedding_model("hashing-128", 128).await.unwrap();
+    let previous = "an unrecorded model (64 dims)".to_string();
+    assert_eq!(check, ModelCheck::Cleared { previous, facts: 1, chunks: 0 });
+}
@@ -0,0 +1,107 @@
+use std::sync::{Arc, Mutex};
+
+use memory_assistant::db::Database;
+use memory_assistant::db::embedding_cache::{EmbeddingCacheStats, text_hash};
+use memory_assistant::tools::embedding::{CachedEmbedder, EmbedFuture, Embedder, embedding_to_bytes};
+
+const MODEL: &str = "voyage-test";
+
+#[tokio::test]
+async fn lookups_count_hits_and_misses_per_key() {
+    let db = Database::open(":memory:").unwrap();
+    let (a, b) = (text_hash("coffee at 9"), text_hash("gym on fridays"));
+    assert_ne!(a, b);
+    assert_eq!(a, text_hash("coffee at 9"));
+
+    assert_eq!(db.cached_embeddings(MODEL, "document", &[a, b]).await.unwrap(), [None, None]);
+    db.cache_embeddings(MODEL, "document", vec![(a, embedding_to_bytes(&[1.0, 0.0]))]).await.unwrap();
+
+    let found = db.cached_embeddings(MODEL, "document", &[a, b]).await.unwrap();
+    assert_eq!(found, [Some(embedding_to_bytes(&[1.0, 0.0])), None]);
+    // The same text as a query, or under another model, is a different entry
+    assert_eq!(db.cached_embeddings(MODEL, "query", &[a]).await.unwrap(), [None]);
+    assert_eq!(db.cached_embeddings("other-model", "document", &[a]).await.unwrap(), [None]);
+
+    let stats = db.embedding_cache_stats().await.unwrap();
+    assert_eq!(stats, EmbeddingCacheStats { entries: 1, bytes: 8, hits: 1, misses: 5 });
+    assert!((stats.hit_rate() - 1.0 / 6.0).abs() < 1e-9);
+    assert_eq!(EmbeddingCacheStats::default().hit_rate(), 0.0);
+
+    // Counted in memory until flushed, then in embedding_cache_stats
+    db.flush_embedding_cache_usage().await.unwrap();
+    db.flush_embedding_cache_usage().await.unwrap();
+    assert_eq!(db.embedding_cache_stats().await.unwrap(), stats);
+}
+
+#[tokio::test]
+async fn pruning_drops_stale_and_least_recently_used_entries() {
+    let path = std::env::temp_dir().join(format!("ma-embedding-cache-{}.db", std::process::id()));
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+    }
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+    let [a, b, c] = ["eggs", "flour", "butter"].map(text_hash);
+    let entries = [a, b, c].iter().map(|hash| (*hash, embedding_to_bytes(&[1.0]))).collect();
+    db.cache_embeddings(MODEL, "document", entries).await.unwrap();
+    let raw = rusqlite::Connection::open(&path).unwrap();
+    for (hash, days) in [(a, 20), (b, 8), (c, 10)] {
+        raw.execute(
+            "UPDATE embedding_cache SET last_used_at = datetime('now', ?1) WHERE text_hash = ?2",
+            rusqlite::params![format!("-{days} days"), &hash[..]],
+        )
+        .unwrap();
+    }
+
+    // A hit makes the oldest entry the most recently used once flushed
+    db.cached_embeddings(MODEL, "document", &[a]).await.unwrap();
+    db.flush_embedding_cache_usage().await.unwrap();
+    assert_eq!(db.prune_embedding_cache(2, 0).await.unwrap(), 1);
+    assert_eq!(db.prune_embedding_cache(0, 7).await.unwrap(), 1);
+    assert_eq!(db.prune_embedding_cache(0, 0).await.unwrap(), 0);
+
+    let kept = db.cached_embeddings(MODEL, "document", &[a, b, c]).await.unwrap();
+    assert_eq!(kept.iter().map(Option::is_some).collect::<Vec<_>>(), [true, false, false]);
+    drop((raw, db));
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+    }
+}
+
+/// Records every text it is asked to embed.
+struct CountingEmbedder {
+    sent: Arc<Mutex<Vec<String>>>,
+}
+
+impl Embedder for CountingEmbedder {
+    fn model(&self) -> &str {
+        MODEL
+    }
+
+    fn embed_batch<'a>(&'a self, texts: &'a [&'a str], _input_type: &'a str) -> EmbedFuture<'a> {
+        self.sent.lock().unwrap().extend(texts.iter().map(|t| t.to_string()));
+        Box::pin(std::future::ready(Ok(texts.iter().map(|t| vec![t.len() as f32, 1.0]).collect())))
+    }
+}
+
+#[tokio::test]
+async fn cached_texts_are_not_sent_to_the_backend() {
+    let db = Database::open(":memory:").unwrap();
+    db.cache_embeddings(MODEL, "document", vec![(text_hash("eggs"), embedding_to_bytes(&[0.5, 0.5]))])
+        .await
+        .unwrap();
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let counting = Box::new(CountingEmbedder { sent: sent.clone() });
+    let embedder: Box<dyn Embedder> = Box::new(CachedEmbedder::new(counting, db.clone()));
+
+    let embeddings = embedder.embed_batch(&["flour", "eggs", "flour"], "document").await.unwrap();
+    assert_eq!(embeddings, [vec![5.0, 1.0], vec![0.5, 0.5], vec![5.0, 1.0]]);
+    assert_eq!(*sent.lock().unwrap(), ["flour"], "duplicates in a batch are sent once");
+
+    assert_eq!(embedder.embed_query("flour").await.unwrap(), [5.0, 1.0]);
+    assert_eq!(*sent.lock().unwrap(), ["flour", "flour"], "queries are not looked up or cached");
+    embedder.embed_batch(&["flour"], "document").await.unwrap();
+    assert_eq!(*sent.lock().unwrap(), ["flour", "flour"]);
+
+    let stats = db.embedding_cache_stats().await.unwrap();
+    assert_eq!((stats.entries, stats.hits, stats.misses), (2, 2, 1));
+}
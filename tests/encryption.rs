@@ -0,0 +1,128 @@
+use memory_assistant::db::Database;
+use memory_assistant::tools::file_crypto::{self, FileCipher, FileCryptoError, SALT_FILE, SALT_LEN};
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("ma-enc-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+const SALT: [u8; SALT_LEN] = [7; SALT_LEN];
+
+#[test]
+fn file_cipher_roundtrip_and_wrong_key() {
+    let cipher = FileCipher::new("correct horse", SALT);
+    let sealed = cipher.encrypt(b"secret diary");
+    assert!(file_crypto::is_encrypted(&sealed));
+    assert!(!sealed.windows(6).any(|w| w == b"secret"));
+    assert_ne!(sealed, cipher.encrypt(b"secret diary"), "fresh nonce per file");
+    assert_eq!(cipher.decrypt(&sealed).unwrap(), b"secret diary");
+
+    assert!(matches!(FileCipher::new("wrong", SALT).decrypt(&sealed), Err(FileCryptoError::Decrypt)));
+    assert!(matches!(cipher.decrypt(b"plain text"), Err(FileCryptoError::NotEncrypted)));
+}
+
+#[test]
+fn reencrypt_dir_rotates_and_decrypts() {
+    let dir = temp_dir("files");
+    std::fs::create_dir_all(dir.join("42/.checksums")).unwrap();
+    std::fs::write(dir.join("42/note.txt"), b"hello").unwrap();
+    std::fs::write(dir.join("42/.checksums/abc"), b"").unwrap();
+    let (k1, k2) = (FileCipher::for_dir("one", &dir).unwrap(), FileCipher::for_dir("two", &dir).unwrap());
+
+    // Turning encryption on seals existing plaintext
+    let stats = file_crypto::reencrypt_dir(&dir, None, Some(&k1)).unwrap();
+    assert_eq!((stats.rewritten, stats.unchanged), (1, 0));
+    let sealed = std::fs::read(dir.join("42/note.txt")).unwrap();
+    assert_eq!(k1.decrypt(&sealed).unwrap(), b"hello");
+    assert!(std::fs::read(dir.join("42/.checksums/abc")).unwrap().is_empty());
+
+    file_crypto::reencrypt_dir(&dir, Some(&k1), Some(&k2)).unwrap();
+    // An interrupted rotation can be re-run
+    let rerun = file_crypto::reencrypt_dir(&dir, Some(&k1), Some(&k2)).unwrap();
+    assert_eq!((rerun.rewritten, rerun.unchanged), (0, 1));
+
+    file_crypto::reencrypt_dir(&dir, Some(&k2), None).unwrap();
+    assert_eq!(std::fs::read(dir.join("42/note.txt")).unwrap(), b"hello");
+    assert_eq!(std::fs::read(dir.join(SALT_FILE)).unwrap().len(), SALT_LEN, "salt file left alone");
+}
+
+#[test]
+fn install_salt_is_made_once_and_kept() {
+    let dir = temp_dir("salt");
+    let salt = file_crypto::install_salt(&dir).unwrap();
+    assert_eq!(file_crypto::install_salt(&dir).unwrap(), salt);
+    assert_ne!(file_crypto::install_salt(&temp_dir("salt-other")).unwrap(), salt, "random per install");
+
+    let sealed = FileCipher::for_dir("key", &dir).unwrap().encrypt(b"notes");
+    assert_eq!(FileCipher::new("key", salt).decrypt(&sealed).unwrap(), b"notes");
+
+    std::fs::write(dir.join(SALT_FILE), b"short").unwrap();
+    assert!(matches!(FileCipher::for_dir("key", &dir), Err(FileCryptoError::BadSalt)));
+}
+
+#[test]
+fn files_under_another_salt_or_the_legacy_key_still_open() {
+    use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+    use sha2::{Digest, Sha256};
+
+    let cipher = FileCipher::new("key", SALT);
+    // The salt file was replaced: the salt in the header still derives the right key
+    let foreign = FileCipher::new("key", [9; SALT_LEN]).encrypt(b"older install");
+    assert_eq!(cipher.decrypt(&foreign).unwrap(), b"older install");
+
+    // Sealed before the salt: MAGIC v1 || nonce || ciphertext under one SHA-256 of the key
+    let digest = Sha256::new()
+        .chain_update(b"memory-assistant file encryption v1\0")
+        .chain_update(b"key")
+        .finalize();
+    let legacy = chacha20poly1305::XChaCha20Poly1305::new(&digest);
+    let nonce = chacha20poly1305::XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let mut sealed = b"MAENC\x00\x01\x00".to_vec();
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&legacy.encrypt(&nonce, b"before the salt".as_ref()).unwrap());
+    assert!(file_crypto::is_encrypted(&sealed));
+    assert_eq!(cipher.decrypt(&sealed).unwrap(), b"before the salt");
+    assert!(matches!(FileCipher::new("other", SALT).decrypt(&sealed), Err(FileCryptoError::Decrypt)));
+}
+
+#[cfg(not(feature = "sqlcipher"))]
+#[test]
+fn key_without_sqlcipher_is_refused() {
+    let err = Database::open_encrypted(":memory:", 0, Some("key")).err().unwrap();
+    assert!(err.to_string().contains("sqlcipher"), "{err}");
+}
+
+#[cfg(feature = "sqlcipher")]
+#[tokio::test]
+async fn database_is_encrypted_and_rekeyed() {
+    use memory_assistant::db::backup::{self, RetentionPolicy};
+    use memory_assistant::db::{Repository, encryption};
+
+    let dir = temp_dir("db");
+    let path = dir.join("kb.db");
+    // Start from an existing plaintext database
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+    db.save_fact(1, "the vault code is 1234", "personal").await.unwrap();
+    drop(db);
+    encryption::rekey_database(&path, None, Some("k1")).unwrap();
+
+    let db = Database::open_encrypted(path.to_str().unwrap(), 2, Some("k1")).unwrap();
+    assert_eq!(db.search_facts(1, "vault").await.unwrap().len(), 1);
+
+    // Snapshots carry the same key
+    let report = db.backup_now(&dir.join("backups"), RetentionPolicy { daily: 1, weekly: 0 }).await.unwrap();
+    backup::verify_backup(&report.backup.path, Some("k1")).unwrap();
+    drop(db);
+
+    let bytes = std::fs::read(&path).unwrap();
+    assert!(!bytes.windows(5).any(|w| w == b"vault"));
+    assert!(Database::open_encrypted(path.to_str().unwrap(), 0, Some("wrong")).is_err());
+
+    let previous = encryption::rekey_database(&path, Some("k1"), Some("k2")).unwrap();
+    assert!(previous.exists());
+    assert!(Database::open_encrypted(path.to_str().unwrap(), 0, Some("k1")).is_err());
+    let db = Database::open_encrypted(path.to_str().unwrap(), 2, Some("k2")).unwrap();
+    assert_eq!(db.search_facts(1, "vault").await.unwrap().len(), 1);
+}
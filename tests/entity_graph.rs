@@ -0,0 +1,120 @@
+use memory_assistant::db::entity_graph::{mention_context, EntityGraphReport};
+use memory_assistant::db::{Actor, Database, Repository, TrashKind};
+
+const ACTOR: Actor<'static> = Actor { user_id: 1, tool: "test" };
+
+fn temp_db(name: &str) -> (Database, std::path::PathBuf) {
+    let path = std::env::temp_dir().join(format!("ma-entity-{name}-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    (Database::open(path.to_str().unwrap()).unwrap(), path)
+}
+
+/// Make every entity old enough for garbage collection.
+fn age_entities(path: &std::path::Path) {
+    let conn = rusqlite::Connection::open(path).unwrap();
+    conn.execute("UPDATE entities SET created_at = datetime('now', '-2 hours')", []).unwrap();
+}
+
+async fn mention(db: &Database, owner: u64, name: &str, source_type: &str, source_id: i64, text: &str) -> i64 {
+    let entity = db.save_entity(owner, name, "technology").await.unwrap();
+    db.add_entity_mention(entity, source_type, source_id, mention_context(text, name).as_deref())
+        .await
+        .unwrap();
+    entity
+}
+
+#[tokio::test]
+async fn purging_a_source_removes_its_mentions_and_gc_collects_the_entity() {
+    let (db, path) = temp_db("purge");
+    let doc = db.save_document(1, "Stack", "We deploy with Kubernetes", None, None).await.unwrap();
+    mention(&db, 1, "Kubernetes", "document", doc, "Stack\n\nWe deploy with Kubernetes").await;
+    age_entities(&path);
+
+    // Trashed sources keep their mentions (restorable) but leave search
+    assert!(db.delete_document(1, doc).await.unwrap());
+    let found = db.search_entities(1, "kube").await.unwrap();
+    assert!(found[0].1.is_empty());
+    assert_eq!(db.gc_orphan_entities(None).await.unwrap(), 0);
+
+    db.purge_trash(1, Some((TrashKind::Document, doc))).await.unwrap();
+    assert_eq!(
+        db.check_entity_graph(Some(1)).await.unwrap(),
+        EntityGraphReport { orphan_entities: 1, ..Default::default() }
+    );
+    assert_eq!(db.gc_orphan_entities(None).await.unwrap(), 1);
+    assert!(db.search_entities(1, "kube").await.unwrap().is_empty());
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn patching_a_document_drops_mentions_whose_name_is_gone() {
+    let (db, path) = temp_db("patch");
+    let text = "Notes\n\nWe use Postgres and Redis";
+    let doc = db.save_document(1, "Notes", "We use Postgres and Redis", None, None).await.unwrap();
+    mention(&db, 1, "Postgres", "document", doc, text).await;
+    mention(&db, 1, "Redis", "document", doc, text).await;
+    // Normalized by the extractor, never anchored in the text: left alone
+    mention(&db, 1, "PostgreSQL", "document", doc, text).await;
+
+    db.patch_document(1, doc, "Redis", "Memcached", ACTOR).await.unwrap();
+    let names: Vec<String> = db
+        .search_entities(1, "")
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|(_, mentions)| !mentions.is_empty())
+        .map(|(e, _)| e.name)
+        .collect();
+    assert_eq!(names, ["PostgreSQL", "Postgres"]);
+    assert!(db.check_entity_graph(Some(1)).await.unwrap().is_clean());
+
+    // Facts are re-anchored on edit too
+    let fact = db.save_fact(1, "Editor is Helix", "preference").await.unwrap();
+    mention(&db, 1, "Helix", "fact", fact, "Editor is Helix").await;
+    db.update_fact(1, fact, "Editor is Zed", ACTOR).await.unwrap();
+    let helix = db.search_entities(1, "Helix").await.unwrap();
+    assert!(helix[0].1.is_empty());
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn doctor_reports_and_repairs_legacy_damage() {
+    let (db, path) = temp_db("doctor");
+    let doc = db.save_document(1, "Guide", "Install Rust first", None, None).await.unwrap();
+    let other = db.save_document(2, "Other", "Rust in another chat", None, None).await.unwrap();
+    mention(&db, 1, "Rust", "document", doc, "Guide\n\nInstall Rust first").await;
+    mention(&db, 1, "Rust", "document", other, "Other\n\nRust in another chat").await;
+    mention(&db, 1, "Go", "document", 9_999, "").await;
+    let gone = mention(&db, 1, "Cargo", "document", doc, "Guide\n\nInstall Rust first").await;
+    db.save_entity(1, "Unused", "concept").await.unwrap();
+    age_entities(&path);
+
+    // Damage the way older versions could: text edited and an entity deleted
+    // behind the graph's back
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    conn.execute_batch("PRAGMA foreign_keys = OFF").unwrap();
+    conn.execute("UPDATE knowledge_documents SET content = 'Install Cargo first' WHERE id = ?1", [doc])
+        .unwrap();
+    conn.execute("DELETE FROM entities WHERE id = ?1", [gone]).unwrap();
+    drop(conn);
+
+    // The mention of the deleted entity still counts for the document's owner
+    let expected = EntityGraphReport { dangling_mentions: 3, stale_mentions: 1, orphan_entities: 1 };
+    assert_eq!(db.check_entity_graph(Some(1)).await.unwrap(), expected);
+    assert!(db.check_entity_graph(Some(2)).await.unwrap().is_clean());
+
+    // "Rust" and "Go" lose their last mentions in the repair and go with "Unused"
+    let repaired = db.repair_entity_graph(Some(1)).await.unwrap();
+    assert_eq!(repaired, EntityGraphReport { orphan_entities: 3, ..expected });
+    assert!(db.check_entity_graph(Some(1)).await.unwrap().is_clean());
+    assert!(db.search_entities(1, "").await.unwrap().is_empty());
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn mention_context_is_case_insensitive_and_multibyte_safe() {
+    let text = "Hôm nay họp với ĐÀ NẴNG team về dự án";
+    assert_eq!(mention_context(text, "Đà Nẵng").as_deref(), Some(text));
+    assert!(mention_context(text, "Hà Nội").is_none());
+    assert!(mention_context(text, "").is_none());
+}
@@ -0,0 +1,190 @@
+use std::io::Cursor;
+
+use memory_assistant::db::export::{BundleError, ExportBundle};
+use memory_assistant::db::{Database, Repository};
+
+async fn seeded_db() -> Database {
+    let db = Database::open(":memory:").unwrap();
+    db.ensure_default_categories(1).await.unwrap();
+    db.add_category(1, "recipes").await.unwrap();
+    let pho = db.save_fact(1, "likes pho", "recipes").await.unwrap();
+    let tea = db.save_fact(1, "drinks green tea", "preference").await.unwrap();
+    db.update_fact_embedding(pho, &[1, 2, 3, 4]).await.unwrap();
+    db.link_facts(pho, tea, 0.8).await.unwrap();
+
+    let doc = db.save_document(1, "Pho recipe", "broth\nnoodles", Some("grandma"), Some("food")).await.unwrap();
+    let chunks = db.save_chunks(doc, &[(0, 1, 2, "broth\nnoodles")]).await.unwrap();
+    db.update_chunk_embeddings(&chunks, &[vec![9; 8]]).await.unwrap();
+    db.link_fact_to_doc(pho, doc).await.unwrap();
+
+    let entity = db.save_entity(1, "pho", "concept").await.unwrap();
+    db.add_entity_mention(entity, "document", doc, Some("Pho recipe")).await.unwrap();
+    db.add_entity_mention(entity, "fact", pho, None).await.unwrap();
+    db
+}
+
+fn roundtrip(bundle: &ExportBundle) -> ExportBundle {
+    let mut buf = Cursor::new(Vec::new());
+    bundle.write_zip(&mut buf).unwrap();
+    buf.set_position(0);
+    ExportBundle::read_zip(buf).unwrap()
+}
+
+#[tokio::test]
+async fn export_covers_every_table() {
+    let db = seeded_db().await;
+    let bundle = roundtrip(&db.export_owner(1, true).await.unwrap());
+
+    assert_eq!(bundle.manifest.owner_id, 1);
+    assert!(bundle.manifest.embeddings);
+    assert_eq!(bundle.categories.len(), 8);
+    assert_eq!(bundle.facts.len(), 2);
+    assert_eq!(bundle.documents.len(), 1);
+    assert_eq!(bundle.chunks.len(), 1);
+    assert_eq!((bundle.entities.len(), bundle.mentions.len()), (1, 2));
+    assert_eq!((bundle.kb_links.len(), bundle.fact_relations.len()), (1, 1));
+    assert!(bundle.facts.iter().any(|f| f.embedding.is_some()));
+
+    let without = db.export_owner(1, false).await.unwrap();
+    assert!(without.facts.iter().all(|f| f.embedding.is_none()));
+    assert!(without.chunks.iter().all(|c| c.embedding.is_none()));
+}
+
+#[tokio::test]
+async fn import_moves_owner_with_remapped_ids() {
+    let source = seeded_db().await;
+    let bundle = roundtrip(&source.export_owner(1, true).await.unwrap());
+
+    // Offset ids in the target so remapping is actually exercised
+    let target = Database::open(":memory:").unwrap();
+    target.save_fact(5, "unrelated", "general").await.unwrap();
+    target.save_document(5, "Unrelated", "text", None, None).await.unwrap();
+
+    let stats = target.import_owner(42, bundle).await.unwrap();
+    assert_eq!((stats.facts, stats.documents, stats.chunks), (2, 1, 1));
+    assert_eq!((stats.mentions, stats.kb_links, stats.fact_relations), (2, 1, 1));
+
+    let pho = target.search_facts(42, "pho").await.unwrap().remove(0);
+    let links = target.get_fact_links(pho.id).await.unwrap();
+    assert_eq!(links[0].1, "Pho recipe");
+    assert_eq!(target.get_related_facts(pho.id).await.unwrap()[0].0.text, "drinks green tea");
+    assert_eq!(target.load_all_embeddings(42).await.unwrap().len(), 1);
+
+    let (_, mentions) = &target.search_entities(42, "pho").await.unwrap()[0];
+    assert!(mentions.iter().any(|m| m.source_type == "document" && m.source_id == links[0].0));
+    assert_eq!(target.list_document_versions(42, links[0].0).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn import_merges_without_duplicates() {
+    let db = seeded_db().await;
+    let bundle = db.export_owner(1, true).await.unwrap();
+
+    let stats = db.import_owner(1, bundle).await.unwrap();
+    assert_eq!((stats.facts, stats.documents, stats.chunks, stats.mentions), (0, 0, 0, 0));
+    assert_eq!(stats.merged, 3);
+    assert_eq!(db.list_facts(1, None).await.unwrap().len(), 2);
+    assert_eq!(db.list_documents(1).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn import_without_embeddings_leaves_documents_unchunked() {
+    let source = seeded_db().await;
+    let bundle = source.export_owner(1, false).await.unwrap();
+
+    let target = Database::open(":memory:").unwrap();
+    let stats = target.import_owner(7, bundle).await.unwrap();
+    assert_eq!(stats.chunks, 0);
+    assert_eq!(target.get_unchunked_documents().await.unwrap().len(), 1);
+    assert_eq!(target.get_unembedded_facts(7).await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn newer_bundle_version_is_rejected() {
+    let db = seeded_db().await;
+    let mut bundle = db.export_owner(1, false).await.unwrap();
+    bundle.manifest.version += 1;
+
+    let mut buf = Cursor::new(Vec::new());
+    bundle.write_zip(&mut buf).unwrap();
+    buf.set_position(0);
+    assert!(matches!(ExportBundle::read_zip(buf), Err(BundleError::TooNew { .. })));
+}
+
+#[tokio::test]
+async fn obsidian_vault_links_notes() {
+    let db = seeded_db().await;
+    let trashed = db.save_document(1, "Old notes", "gone", None, None).await.unwrap();
+    db.delete_document(1, trashed).await.unwrap();
+    let notes = db.export_owner(1, false).await.unwrap().obsidian_notes();
+
+    let paths: Vec<&str> = notes.keys().map(String::as_str).collect();
+    assert_eq!(
+        paths,
+        ["Documents/Pho recipe.md", "Entities/pho.md", "Facts/preference.md", "Facts/recipes.md"]
+    );
+
+    let doc = &notes["Documents/Pho recipe.md"];
+    assert!(doc.starts_with("---\nid: "));
+    assert!(doc.contains("source: \"grandma\"\ntags:\n  - \"food\"\n"));
+    assert!(doc.contains("broth\nnoodles\n"));
+    assert!(doc.contains("[[Facts/recipes#^fact-1|likes pho]]"));
+    assert!(doc.contains("[[Entities/pho|pho]]"));
+
+    let recipes = &notes["Facts/recipes.md"];
+    assert!(recipes.contains("- likes pho ^fact-1\n"));
+    assert!(recipes.contains("Related: [[Facts/preference#^fact-2|drinks green tea]] (0.80)"));
+    assert!(recipes.contains("Documents: [[Documents/Pho recipe|Pho recipe]]"));
+    assert!(notes["Facts/preference.md"].contains("[[Facts/recipes#^fact-1|likes pho]] (0.80)"));
+
+    let entity = &notes["Entities/pho.md"];
+    assert!(entity.contains("type: \"concept\""));
+    assert!(entity.contains("- [[Documents/Pho recipe|Pho recipe]] — Pho recipe\n"));
+    assert!(entity.contains("- [[Facts/recipes#^fact-1|likes pho]]\n"));
+}
+
+#[tokio::test]
+async fn obsidian_note_names_are_sanitized_and_unique() {
+    let db = Database::open(":memory:").unwrap();
+    db.save_document(1, "a/b: c?", "one", None, None).await.unwrap();
+    db.save_document(1, "A b c", "two", None, None).await.unwrap();
+    db.save_entity(1, "Rust", "language").await.unwrap();
+    db.save_entity(1, "rust", "concept").await.unwrap();
+    let notes = db.export_owner(1, false).await.unwrap().obsidian_notes();
+
+    let paths: Vec<&str> = notes.keys().map(String::as_str).collect();
+    assert_eq!(
+        paths,
+        ["Documents/A b c (2).md", "Documents/a b c.md", "Entities/Rust.md", "Entities/rust (concept).md"]
+    );
+
+    let mut buf = Cursor::new(Vec::new());
+    db.export_owner(1, false).await.unwrap().write_obsidian_zip(&mut buf).unwrap();
+    assert_eq!(zip::ZipArchive::new(buf).unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn embeddings_from_another_model_are_left_out() {
+    let source = seeded_db().await;
+    source.check_embedding_model("model-a", 1).await.unwrap();
+    let bundle = roundtrip(&source.export_owner(1, true).await.unwrap());
+    let manifest = &bundle.manifest;
+    assert_eq!((manifest.embedding_model.as_deref(), manifest.embedding_dims), (Some("model-a"), Some(1)));
+
+    let same = Database::open(":memory:").unwrap();
+    same.check_embedding_model("model-a", 1).await.unwrap();
+    let stats = same.import_owner(42, bundle.clone()).await.unwrap();
+    assert!(!stats.embeddings_dropped);
+    assert_eq!(stats.chunks, 1);
+    assert_eq!(same.get_unembedded_facts(42).await.unwrap().len(), 1, "only the fact exported without one");
+
+    // Same vector size, different model
+    let other = Database::open(":memory:").unwrap();
+    other.check_embedding_model("model-b", 1).await.unwrap();
+    let stats = other.import_owner(42, bundle).await.unwrap();
+    assert!(stats.embeddings_dropped, "{stats}");
+    assert_eq!(stats.chunks, 0);
+    assert_eq!(other.get_unembedded_facts(42).await.unwrap().len(), 2);
+    assert_eq!(other.get_unchunked_documents().await.unwrap().len(), 1, "queued for re-chunking");
+    assert!(stats.to_string().contains("left out"));
+}
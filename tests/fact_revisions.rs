@@ -0,0 +1,66 @@
+use memory_assistant::db::{Actor, Database, Repository};
+
+const EDITOR: Actor<'static> = Actor { user_id: 7, tool: "memory_edit" };
+const REVERTER: Actor<'static> = Actor { user_id: 8, tool: "memory_revert" };
+
+#[tokio::test]
+async fn edit_is_recorded_and_reverted() {
+    let db = Database::open(":memory:").unwrap();
+    let id = db.save_fact(1, "standup is at 9am", "workflow").await.unwrap();
+
+    assert!(db.update_fact(1, id, "standup is at 10am", EDITOR).await.unwrap());
+    let history = db.get_fact_history(1, id).await.unwrap();
+    assert_eq!(history.len(), 1);
+    let rev = &history[0];
+    assert_eq!((rev.old_text.as_str(), rev.new_text.as_str()), ("standup is at 9am", "standup is at 10am"));
+    assert_eq!((rev.actor_id, rev.tool.as_str(), rev.superseded_by), (7, "memory_edit", None));
+
+    // FTS follows the edit
+    assert!(db.search_facts(1, "9am").await.unwrap().is_empty());
+    assert_eq!(db.search_facts(1, "10am").await.unwrap().len(), 1);
+
+    let (fact, replaced) = db.revert_fact(1, rev.id, REVERTER).await.unwrap();
+    assert_eq!((fact.id, fact.text.as_str(), replaced), (id, "standup is at 9am", None));
+    assert_eq!(db.search_facts(1, "9am").await.unwrap().len(), 1);
+
+    // The revert is itself a revision
+    let history = db.get_fact_history(1, id).await.unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!((history[0].tool.as_str(), history[0].actor_id), ("memory_revert", 8));
+}
+
+#[tokio::test]
+async fn supersede_is_recorded_and_reverted() {
+    let db = Database::open(":memory:").unwrap();
+    let old_id = db.save_fact(1, "lives in Hanoi", "personal").await.unwrap();
+    let new_id = db.save_fact(1, "lives in Da Nang", "personal").await.unwrap();
+
+    let actor = Actor { user_id: 7, tool: "memory_save" };
+    assert!(db.supersede_fact(1, old_id, new_id, actor).await.unwrap());
+    assert!(!db.supersede_fact(1, old_id, new_id, actor).await.unwrap(), "already superseded");
+    assert_eq!(db.list_facts(1, None).await.unwrap().len(), 1);
+
+    // Visible from both sides of the replacement
+    let history = db.get_fact_history(1, new_id).await.unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!((history[0].fact_id, history[0].superseded_by), (old_id, Some(new_id)));
+    assert_eq!(db.get_fact_history(1, old_id).await.unwrap(), history);
+
+    let (fact, replaced) = db.revert_fact(1, history[0].id, REVERTER).await.unwrap();
+    assert_eq!((fact.id, replaced), (old_id, Some(new_id)));
+    let live = db.list_facts(1, None).await.unwrap();
+    assert_eq!(live.len(), 1);
+    assert_eq!(live[0].text, "lives in Hanoi");
+}
+
+#[tokio::test]
+async fn history_is_scoped_to_owner() {
+    let db = Database::open(":memory:").unwrap();
+    let id = db.save_fact(1, "private", "general").await.unwrap();
+    db.update_fact(1, id, "still private", EDITOR).await.unwrap();
+    let rev_id = db.get_fact_history(1, id).await.unwrap()[0].id;
+
+    assert!(db.get_fact_history(2, id).await.unwrap().is_empty());
+    assert!(db.revert_fact(2, rev_id, REVERTER).await.is_err());
+    assert!(!db.update_fact(2, id, "hijacked", EDITOR).await.unwrap());
+}
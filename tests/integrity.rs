@@ -0,0 +1,100 @@
+mod common;
+
+use common::temp_db_path;
+use memory_assistant::db::{Database, Repository};
+
+async fn seed(db: &Database) -> (i64, i64) {
+    let fact = db.save_fact(1, "The server runs Debian", "infra").await.unwrap();
+    let content = "# Deploy\nBuild the image\nPush it\nRestart the service";
+    let doc = db.save_document(1, "Deploy", content, None, None).await.unwrap();
+    db.save_chunks(doc, &[(0, 1, 2, "# Deploy\nBuild the image"), (1, 3, 4, "Push it\nRestart the service")])
+        .await
+        .unwrap();
+    (fact, doc)
+}
+
+#[tokio::test]
+async fn healthy_database_is_clean() {
+    let db = Database::open(":memory:").unwrap();
+    seed(&db).await;
+    db.set_trigram_index(true).await.unwrap();
+    let report = db.check_integrity().await.unwrap();
+    assert!(report.is_clean(), "{report:?}");
+}
+
+#[tokio::test]
+async fn crlf_documents_are_not_stale() {
+    use memory_assistant::tools::knowledge::chunk_document;
+
+    let db = Database::open(":memory:").unwrap();
+    for content in ["# Notes\r\nBuy milk\r\nCall Bob\r\n", &"A long line\r\n".repeat(300)] {
+        let doc = db.save_document(1, "Notes", content, None, None).await.unwrap();
+        let chunks = chunk_document(content);
+        let data: Vec<(usize, usize, usize, &str)> =
+            chunks.iter().map(|c| (c.chunk_index, c.start_line, c.end_line, c.content.as_str())).collect();
+        db.save_chunks(doc, &data).await.unwrap();
+    }
+    let report = db.check_integrity().await.unwrap();
+    assert!(report.is_clean(), "{report:?}");
+}
+
+#[tokio::test]
+async fn drifted_fts_index_is_found_and_rebuilt() {
+    let path = temp_db_path("integrity-fts");
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+    let (fact, _) = seed(&db).await;
+    drop(db);
+    {
+        // What a manual re-index with the wrong old text leaves behind,
+        // plus an entry for a row that never existed
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute(
+            "INSERT INTO memory_facts_fts(memory_facts_fts, rowid, fact) VALUES('delete', ?1, 'some other text')",
+            [fact],
+        )
+        .unwrap();
+        conn.execute("INSERT INTO knowledge_docs_fts(rowid, title, content) VALUES (999, 'ghost', 'ghost')", [])
+            .unwrap();
+    }
+
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+    let report = db.check_integrity().await.unwrap();
+    let tables: Vec<(&str, usize, usize)> =
+        report.fts.iter().map(|i| (i.table.as_str(), i.missing_rows, i.extra_rows)).collect();
+    assert_eq!(tables, [("memory_facts_fts", 1, 0), ("knowledge_docs_fts", 0, 1)]);
+    assert!(report.fts.iter().all(|i| i.corrupt));
+    assert!(report.sqlite_errors.is_empty());
+
+    assert_eq!(db.repair_integrity().await.unwrap(), report);
+    assert!(db.check_integrity().await.unwrap().is_clean());
+    assert_eq!(db.search_facts(1, "debian").await.unwrap()[0].id, fact);
+}
+
+#[tokio::test]
+async fn chunks_out_of_line_with_their_document_are_dropped() {
+    let path = temp_db_path("integrity-chunks");
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+    let (_, doc) = seed(&db).await;
+    let other = db.save_document(1, "Empty", "", None, None).await.unwrap();
+    db.save_chunks(other, &[(0, 1, 1, "")]).await.unwrap();
+    drop(db);
+    {
+        // A patch that added a line without moving the ranges
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute(
+            "UPDATE knowledge_documents SET content = '# Deploy\nLog in\nBuild the image\nPush it\nRestart the service' WHERE id = ?1",
+            [doc],
+        )
+        .unwrap();
+    }
+
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+    let report = db.check_integrity().await.unwrap();
+    assert_eq!(report.stale_chunk_docs, [doc]);
+    assert!(report.fts.is_empty());
+
+    db.repair_integrity().await.unwrap();
+    assert!(db.check_integrity().await.unwrap().is_clean());
+    let unchunked: Vec<i64> = db.get_unchunked_documents().await.unwrap().iter().map(|d| d.id).collect();
+    assert_eq!(unchunked, [doc], "the document is queued for re-chunking");
+}
@@ -0,0 +1,162 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use memory_assistant::db::backup::{RetentionPolicy, list_backups};
+use memory_assistant::db::owners::{OwnerDatabases, OwnerFileOptions, owner_backup_dir};
+use memory_assistant::db::{Actor, ApprovalDecision, Database, Repository, SessionRetention};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ma-test-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn per_owner(dir: &std::path::Path, idle: Duration) -> OwnerDatabases {
+    let main = Database::open(dir.join("main.db").to_str().unwrap()).unwrap();
+    let options = OwnerFileOptions {
+        dir: dir.join("data"),
+        read_connections: 1,
+        key: None,
+        fts_trigram: false,
+        quantization: Default::default(),
+        idle,
+    };
+    OwnerDatabases::per_owner(main, options).unwrap()
+}
+
+#[tokio::test]
+async fn shared_mode_uses_the_main_database() {
+    let dbs = OwnerDatabases::shared(Database::open(":memory:").unwrap());
+    let id = dbs.owner(7).await.unwrap().save_fact(7, "shared", "general").await.unwrap();
+    assert_eq!(dbs.main().list_facts(7, None).await.unwrap()[0].id, id);
+    assert!(dbs.owner_ids().unwrap().is_empty());
+    assert_eq!(dbs.owner_data().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn owners_are_isolated_in_their_own_files() {
+    let dir = temp_dir("owners-isolated");
+    let dbs = per_owner(&dir, Duration::from_secs(600));
+    dbs.owner(1).await.unwrap().save_fact(1, "alice's secret", "personal").await.unwrap();
+    dbs.owner(2).await.unwrap().save_fact(2, "bob's secret", "personal").await.unwrap();
+
+    assert_eq!(dbs.owner_ids().unwrap(), [1, 2]);
+    assert!(dbs.owner_path(1).unwrap().exists());
+    // Even a query for the wrong owner id finds nothing in another owner's file
+    assert!(dbs.owner(2).await.unwrap().list_facts(1, None).await.unwrap().is_empty());
+    assert!(dbs.main().list_facts(1, None).await.unwrap().is_empty());
+    drop(dbs);
+
+    let dbs = per_owner(&dir, Duration::from_secs(600));
+    let facts = dbs.owner(1).await.unwrap().list_facts(1, None).await.unwrap();
+    assert_eq!(facts[0].text, "alice's secret");
+    assert_eq!(dbs.owner_data().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn idle_handles_are_closed_unless_held() {
+    let dir = temp_dir("owners-idle");
+    let dbs = per_owner(&dir, Duration::ZERO);
+    let held = dbs.owner(1).await.unwrap();
+    dbs.owner(2).await.unwrap();
+    assert_eq!(dbs.open_count().await, 2);
+
+    assert_eq!(dbs.close_idle().await, 1);
+    assert_eq!(dbs.open_count().await, 1);
+    drop(held);
+    assert_eq!(dbs.close_idle().await, 1);
+
+    // Reopened on next use
+    let db = dbs.owner(1).await.unwrap();
+    db.save_fact(1, "after reopen", "general").await.unwrap();
+    assert_eq!(db.list_facts(1, None).await.unwrap().len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn concurrent_opens_share_one_handle_per_owner() {
+    let dir = temp_dir("owners-concurrent");
+    let dbs = per_owner(&dir, Duration::from_secs(600));
+    let (a, b, c) = tokio::join!(dbs.owner(1), dbs.owner(2), dbs.owner(1));
+    a.unwrap().save_fact(1, "first", "general").await.unwrap();
+    b.unwrap();
+    assert_eq!(c.unwrap().list_facts(1, None).await.unwrap().len(), 1);
+    assert_eq!(dbs.open_count().await, 2);
+}
+
+#[tokio::test]
+async fn backups_cover_every_owner_file() {
+    let dir = temp_dir("owners-backup");
+    let dbs = per_owner(&dir, Duration::from_secs(600));
+    dbs.owner(1).await.unwrap().save_fact(1, "one", "general").await.unwrap();
+    dbs.owner(2).await.unwrap().save_fact(2, "two", "general").await.unwrap();
+
+    let backups = dir.join("backups");
+    let run = dbs.backup_all(&backups, RetentionPolicy { daily: 7, weekly: 4 }).await;
+    assert!(run.main.is_ok());
+    let owners = run.owners.unwrap();
+    assert_eq!(owners.iter().map(|(id, _)| *id).collect::<Vec<_>>(), [1, 2]);
+    assert!(owners.iter().all(|(_, r)| r.is_ok()));
+    assert_eq!(list_backups(&backups).unwrap().len(), 1);
+    assert_eq!(list_backups(&owner_backup_dir(&backups, 2)).unwrap().len(), 1);
+
+    // The owner files cannot be listed: reported apart from the main database's result
+    std::fs::remove_dir_all(dir.join("data")).unwrap();
+    std::fs::write(dir.join("data"), b"not a directory").unwrap();
+    let run = dbs.backup_all(&backups, RetentionPolicy { daily: 7, weekly: 4 }).await;
+    assert!(run.main.is_ok());
+    assert!(run.owners.is_err());
+}
+
+#[tokio::test]
+async fn split_copies_each_owner_out_of_the_main_database() {
+    let dir = temp_dir("owners-split");
+    let main = Database::open(dir.join("main.db").to_str().unwrap()).unwrap();
+    let fact = main.save_fact(1, "alice's fact", "personal").await.unwrap();
+    main.update_fact(1, fact, "alice's edited fact", Actor { user_id: 1, tool: "memory_update" }).await.unwrap();
+    main.save_document(2, "Bob's notes", "content", None, None).await.unwrap();
+    // Owner 3 has only chat history and settings
+    let session = main.get_or_create_session(3).await.unwrap();
+    main.append_message(&session.id, "user", "hello").await.unwrap();
+    main.set_chat_model(3, "gpt-4o").await.unwrap();
+    main.set_session_retention(3, SessionRetention { max_age_days: Some(30), max_messages: None, summarize: false })
+        .await
+        .unwrap();
+    let settled = main.save_pending(3, 9, "memory_save", "{}", "save a fact").await.unwrap();
+    main.take_pending(3, settled, 0, ApprovalDecision::Rejected, 3).await.unwrap().unwrap();
+    main.save_pending(3, 9, "memory_save", "{}", "save another").await.unwrap();
+    drop(main);
+    let conn = rusqlite::Connection::open(dir.join("main.db")).unwrap();
+    conn.execute(
+        "INSERT INTO audit_log (user_id, kb_owner_id, tool, args_json, result, before_json, after_json)
+         VALUES (1, 1, 'memory_update', '{}', 'ok', '{}', '{}')",
+        [],
+    )
+    .unwrap();
+    drop(conn);
+
+    let dbs = per_owner(&dir, Duration::from_secs(600));
+    let split = dbs.split_from_main().await.unwrap();
+    assert_eq!(split.iter().map(|(id, _)| *id).collect::<Vec<_>>(), [1, 2, 3]);
+    let (_, alice) = &split[0];
+    assert_eq!((alice.rows("memory_facts"), alice.rows("memory_fact_revisions"), alice.rows("audit_log")), (1, 1, 1));
+
+    let alice = dbs.owner(1).await.unwrap();
+    assert_eq!(alice.search_facts(1, "edited").await.unwrap()[0].id, fact, "ids kept, FTS filled");
+    assert_eq!(alice.get_fact_history(1, fact).await.unwrap().len(), 1);
+    assert_eq!(dbs.owner(2).await.unwrap().list_documents(2).await.unwrap().len(), 1);
+    assert!(alice.list_documents(2).await.unwrap().is_empty());
+
+    let carol = dbs.owner(3).await.unwrap();
+    let resumed = carol.get_or_create_session(3).await.unwrap();
+    assert_eq!(resumed.id, session.id);
+    assert_eq!(carol.load_history(&session.id, 10).await.unwrap()[0].content, "hello");
+    assert_eq!(carol.get_chat_model(3).await, "gpt-4o");
+    assert_eq!(carol.get_session_retention(3).await.unwrap().unwrap().max_age_days, Some(30));
+    assert_eq!(carol.list_pending(3).await.unwrap().len(), 1);
+    assert_eq!(carol.list_approvals(3, 10).await.unwrap().len(), 1);
+
+    // A second run would duplicate rows, so it refuses
+    let err = dbs.split_from_main().await.unwrap_err().to_string();
+    assert!(err.contains("already has"), "{err}");
+}
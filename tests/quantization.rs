@@ -0,0 +1,154 @@
+mod common;
+
+use common::{random_vectors, temp_db_path};
+use memory_assistant::db::hnsw::{Hnsw, HnswParams};
+use memory_assistant::db::quantization::{Quantization, Vector, exact_similarity};
+use memory_assistant::db::vector_index::VectorKind;
+use memory_assistant::db::{Database, Repository};
+use memory_assistant::tools::embedding::embedding_to_bytes;
+
+const OWNER: u64 = 3;
+const DIMS: usize = 64;
+
+fn quantized_column(db_path: &std::path::Path, table: &str, id: i64) -> Option<Vec<u8>> {
+    let conn = rusqlite::Connection::open(db_path).unwrap();
+    conn.query_row(&format!("SELECT embedding_q FROM {table} WHERE id = ?1"), [id], |row| row.get(0)).unwrap()
+}
+
+#[test]
+fn modes_parse_and_blobs_round_trip() {
+    assert_eq!("INT8".parse::<Quantization>().unwrap(), Quantization::Int8);
+    assert_eq!("binary".parse::<Quantization>().unwrap(), Quantization::Binary);
+    assert_eq!("".parse::<Quantization>().unwrap(), Quantization::None);
+    assert!("int4".parse::<Quantization>().is_err());
+
+    let vector: Vec<f32> = random_vectors(1, DIMS, 1).remove(0)[..37].to_vec();
+    for mode in [Quantization::Int8, Quantization::Binary] {
+        let encoded = mode.encode(&vector);
+        assert_eq!((encoded.dims(), encoded.quantization()), (37, mode));
+        let blob = encoded.to_bytes().unwrap();
+        assert_eq!(Vector::from_bytes(&blob), Some(encoded));
+    }
+    assert_eq!(Quantization::Int8.encode(&vector).to_bytes().unwrap().len(), 5 + 37);
+    assert_eq!(Quantization::Binary.encode(&vector).to_bytes().unwrap().len(), 5 + 5);
+    assert!(Quantization::None.encode(&vector).to_bytes().is_none());
+    assert!(Vector::from_bytes(&[2, 64, 0, 0, 0, 1]).is_none(), "truncated bits");
+}
+
+#[test]
+fn quantized_similarity_tracks_the_exact_one() {
+    let vectors = random_vectors(40, DIMS, 2);
+    for pair in vectors.chunks(2) {
+        let exact = exact_similarity(&pair[0], &pair[1]);
+        let int8 = Quantization::Int8.encode(&pair[0]).similarity(&Quantization::Int8.encode(&pair[1]));
+        assert!((int8 - exact).abs() < 0.02, "int8 {int8} vs {exact}");
+        let binary = Quantization::Binary.encode(&pair[0]).similarity(&Quantization::Binary.encode(&pair[1]));
+        assert!((-1.0..=1.0).contains(&binary));
+    }
+    let same = Quantization::Binary.encode(&vectors[0]);
+    assert_eq!(same.similarity(&same), 1.0);
+    assert_eq!(same.similarity(&Quantization::Int8.encode(&vectors[0])), 0.0, "different modes never match");
+}
+
+#[test]
+fn quantized_graph_finds_each_vector_first() {
+    let vectors = random_vectors(300, DIMS, 4);
+    for mode in [Quantization::Int8, Quantization::Binary] {
+        let mut index = Hnsw::new(HnswParams { quantization: mode, ..HnswParams::default() });
+        for (id, vector) in vectors.iter().enumerate() {
+            index.insert(id as i64, vector).unwrap();
+        }
+        assert_eq!(index.quantization(), mode);
+        for id in [0, 57, 299] {
+            assert_eq!(index.search(&vectors[id], 1)[0].0, id as i64, "{mode:?}");
+        }
+    }
+}
+
+#[tokio::test]
+async fn switching_modes_encodes_existing_rows_and_rebuilds_the_index() {
+    let path = temp_db_path("switch");
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+    let vectors = random_vectors(50, DIMS, 5);
+    let mut ids = Vec::new();
+    for (i, vector) in vectors.iter().enumerate() {
+        let id = db.save_fact(OWNER, &format!("fact {i}"), "general").await.unwrap();
+        db.update_fact_embedding(id, &embedding_to_bytes(vector)).await.unwrap();
+        ids.push(id);
+    }
+    let doc = db.save_document(OWNER, "Doc", "a\nb", None, None).await.unwrap();
+    let chunks = db.save_chunks(doc, &[(0, 1, 1, "a"), (1, 2, 2, "b")]).await.unwrap();
+    let blobs = [embedding_to_bytes(&vectors[0]), embedding_to_bytes(&vectors[1])];
+    db.update_chunk_embeddings(&chunks, &blobs).await.unwrap();
+    assert_eq!(db.ensure_vector_indexes().await.unwrap(), 2);
+    assert!(quantized_column(&path, "memory_facts", ids[0]).is_none());
+
+    assert_eq!(db.set_quantization(Quantization::Int8).await.unwrap(), 52);
+    assert_eq!(db.quantization().await.unwrap(), Quantization::Int8);
+    assert_eq!(db.set_quantization(Quantization::Int8).await.unwrap(), 0, "nothing left to encode");
+    let blob = quantized_column(&path, "memory_facts", ids[3]).unwrap();
+    assert_eq!(Vector::from_bytes(&blob), Some(Quantization::Int8.encode(&vectors[3])));
+    assert!(db.vector_index_stats(OWNER, VectorKind::Fact).await.unwrap().is_none(), "old index dropped");
+
+    // Saved under the new mode: encoded on write
+    let late = db.save_fact(OWNER, "late", "general").await.unwrap();
+    db.update_fact_embedding(late, &embedding_to_bytes(&vectors[49])).await.unwrap();
+    assert!(quantized_column(&path, "memory_facts", late).is_some());
+
+    assert_eq!(db.set_quantization(Quantization::Binary).await.unwrap(), 53);
+    let blob = quantized_column(&path, "knowledge_chunks", chunks[1]).unwrap();
+    assert_eq!(Vector::from_bytes(&blob).unwrap().quantization(), Quantization::Binary);
+
+    assert_eq!(db.set_quantization(Quantization::None).await.unwrap(), 0);
+    assert!(quantized_column(&path, "memory_facts", ids[3]).is_none());
+}
+
+#[tokio::test]
+async fn searches_rerank_by_exact_similarity() {
+    let vectors = random_vectors(200, DIMS, 6);
+    for mode in [Quantization::Int8, Quantization::Binary] {
+        let db = Database::open(":memory:").unwrap();
+        db.set_quantization(mode).await.unwrap();
+        let mut ids = Vec::new();
+        for (i, vector) in vectors.iter().enumerate() {
+            let id = db.save_fact(OWNER, &format!("fact {i}"), "general").await.unwrap();
+            db.update_fact_embedding(id, &embedding_to_bytes(vector)).await.unwrap();
+            ids.push(id);
+        }
+        let query = &vectors[42];
+        let found = db.nearest_facts(OWNER, query, 5).await.unwrap();
+        assert_eq!(found.len(), 5);
+        assert_eq!(found[0].0.id, ids[42], "{mode:?}");
+        for (fact, sim) in &found {
+            let index = ids.iter().position(|id| *id == fact.id).unwrap();
+            assert!((sim - exact_similarity(query, &vectors[index])).abs() < 1e-5, "exact after rerank");
+        }
+        assert!(found.windows(2).all(|w| w[0].1 >= w[1].1));
+    }
+}
+
+#[tokio::test]
+async fn a_new_embedding_replaces_the_stale_quantized_copy() {
+    let path = temp_db_path("stale");
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+    db.set_quantization(Quantization::Binary).await.unwrap();
+    let vectors = random_vectors(2, DIMS, 7);
+    let id = db.save_fact(OWNER, "moving", "general").await.unwrap();
+    db.update_fact_embedding(id, &embedding_to_bytes(&vectors[0])).await.unwrap();
+    drop(db);
+
+    // Written by something that does not know about embedding_q
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    conn.execute("UPDATE memory_facts SET embedding = ?1 WHERE id = ?2", rusqlite::params![
+        embedding_to_bytes(&vectors[1]),
+        id
+    ])
+    .unwrap();
+    drop(conn);
+    assert!(quantized_column(&path, "memory_facts", id).is_none());
+
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+    assert_eq!(db.set_quantization(Quantization::Binary).await.unwrap(), 1);
+    let blob = quantized_column(&path, "memory_facts", id).unwrap();
+    assert_eq!(Vector::from_bytes(&blob), Some(Quantization::Binary.encode(&vectors[1])));
+}
@@ -0,0 +1,57 @@
+use memory_assistant::db::{Actor, Database, DbError, Repository};
+
+/// Exercise the API through the trait only, as an alternative backend would be.
+async fn save_and_list<R: Repository>(repo: &R) -> Vec<String> {
+    repo.save_fact(1, "prefers short answers", "preference").await.unwrap();
+    repo.list_facts(1, Some("preference"))
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|f| f.text)
+        .collect()
+}
+
+#[tokio::test]
+async fn generic_caller_works_with_database() {
+    let db = Database::open(":memory:").unwrap();
+    assert_eq!(save_and_list(&db).await, vec!["prefers short answers"]);
+}
+
+#[tokio::test]
+async fn missing_rows_are_not_found() {
+    let db = Database::open(":memory:").unwrap();
+    assert!(matches!(db.get_document(1, 42).await, Err(DbError::NotFound(_))));
+    assert!(matches!(db.get_pending(42).await, Err(DbError::NotFound(_))));
+
+    let err = db.patch_document(1, 42, "a", "b", Actor { user_id: 1, tool: "test" }).await.unwrap_err();
+    assert_eq!(err.to_string(), "Document #42 not found.");
+}
+
+#[tokio::test]
+async fn category_conflicts_are_typed() {
+    let db = Database::open(":memory:").unwrap();
+    db.ensure_default_categories(1).await.unwrap();
+
+    let err = db.add_category(1, "general").await.unwrap_err();
+    assert!(matches!(err, DbError::Conflict(_)));
+    assert_eq!(err.to_string(), "Category 'general' already exists.");
+
+    db.save_fact(1, "uses it", "general").await.unwrap();
+    assert!(matches!(db.delete_category(1, "general").await, Err(DbError::Conflict(_))));
+}
+
+#[tokio::test]
+async fn documents_and_pending_items_map_to_structs() {
+    let db = Database::open(":memory:").unwrap();
+    let doc_id = db.save_document(1, "Title", "body", Some("notes.md"), Some("a,b")).await.unwrap();
+    let doc = db.get_document(1, doc_id).await.unwrap();
+    assert_eq!((doc.title.as_str(), doc.source.as_deref(), doc.tags.as_deref()), ("Title", Some("notes.md"), Some("a,b")));
+
+    let pending_id = db.save_pending(1, 99, "memory_save", "{}", "save a fact").await.unwrap();
+    let item = db.get_pending(pending_id).await.unwrap();
+    assert_eq!((item.scope_id, item.requested_by, item.tool_name.as_str()), (1, 99, "memory_save"));
+    assert_eq!(db.list_pending(1).await.unwrap(), vec![item]);
+
+    let session = db.get_or_create_session(1).await.unwrap();
+    assert_eq!(db.get_or_create_session(1).await.unwrap().id, session.id);
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use common::temp_db_path;
+use memory_assistant::db::migrations::{latest_version, MIGRATIONS};
+use memory_assistant::db::{Database, MigrationError, Repository};
+
+#[tokio::test]
+async fn fresh_database_is_at_latest_version() {
+    let db = Database::open(":memory:").expect("open in-memory db");
+    assert_eq!(db.schema_version().await.unwrap(), latest_version());
+}
+
+#[test]
+fn migration_versions_are_strictly_increasing() {
+    for pair in MIGRATIONS.windows(2) {
+        assert!(pair[0].version < pair[1].version, "v{} must precede v{}", pair[0].version, pair[1].version);
+    }
+}
+
+#[tokio::test]
+async fn reopen_is_idempotent() {
+    let path = temp_db_path("reopen");
+    let path_str = path.to_str().unwrap();
+
+    let db = Database::open(path_str).unwrap();
+    let fact_id = db.save_fact(1, "survives reopen", "general").await.unwrap();
+    drop(db);
+
+    let db = Database::open(path_str).unwrap();
+    assert_eq!(db.schema_version().await.unwrap(), latest_version());
+    let facts = db.list_facts(1, None).await.unwrap();
+    assert_eq!(facts[0].id, fact_id);
+}
+
+#[tokio::test]
+async fn legacy_database_is_adopted() {
+    // A database created before versioning: tables exist, embedding column already added.
+    let path = temp_db_path("legacy");
+    {
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE memory_facts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                fact TEXT NOT NULL,
+                category TEXT NOT NULL DEFAULT 'general',
+                created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                access_count INTEGER NOT NULL DEFAULT 0,
+                last_accessed_at TEXT,
+                embedding BLOB
+            );
+            INSERT INTO memory_facts (user_id, fact) VALUES (7, 'old fact');",
+        )
+        .unwrap();
+    }
+
+    let db = Database::open(path.to_str().unwrap()).expect("legacy db should migrate");
+    assert_eq!(db.schema_version().await.unwrap(), latest_version());
+    assert_eq!(db.list_facts(7, None).await.unwrap().len(), 1);
+}
+
+#[test]
+fn newer_schema_aborts_open() {
+    let path = temp_db_path("too-new");
+    drop(Database::open(path.to_str().unwrap()).unwrap());
+    {
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute(
+            "INSERT INTO schema_version (version, description) VALUES (?1, 'from the future')",
+            [latest_version() + 1],
+        )
+        .unwrap();
+    }
+
+    match Database::open(path.to_str().unwrap()) {
+        Err(MigrationError::TooNew { found, supported }) => {
+            assert_eq!(found, latest_version() + 1);
+            assert_eq!(supported, latest_version());
+        }
+        Err(e) => panic!("unexpected error: {e}"),
+        Ok(_) => panic!("open should refuse a newer schema"),
+    }
+}
@@ -0,0 +1,159 @@
+use memory_assistant::agent::HistoryLimits;
+use memory_assistant::agent::history::{replay, to_stored};
+use memory_assistant::db::{Database, Repository};
+use memory_assistant::provider::{Message, MessageContent, Role, ToolCall, ToolCallFunction};
+
+fn tool_turn() -> Vec<Message> {
+    vec![
+        Message {
+            role: Role::Assistant,
+            content: MessageContent::AssistantWithToolCalls {
+                text: None,
+                tool_calls: vec![ToolCall {
+                    id: "call_1".into(),
+                    function: ToolCallFunction { name: "memory_search".into(), arguments: r#"{"query":"lease"}"#.into() },
+                    thought_signature: Some("sig".into()),
+                }],
+                reasoning_content: None,
+            },
+        },
+        Message {
+            role: Role::Tool,
+            content: MessageContent::ToolResult {
+                tool_call_id: "call_1".into(),
+                name: "memory_search".into(),
+                content: "[12] Lease ends in March".into(),
+            },
+        },
+        Message { role: Role::Assistant, content: MessageContent::Text("Your lease ends in March.".into()) },
+    ]
+}
+
+#[tokio::test]
+async fn tool_calls_round_trip_through_history() {
+    let db = Database::open(":memory:").unwrap();
+    let session = db.get_or_create_session(1).await.unwrap();
+    let limits = HistoryLimits::default();
+
+    let user = Message { role: Role::User, content: MessageContent::Text("when does my lease end?".into()) };
+    let mut rows = vec![to_stored(&user, None, &limits)];
+    rows.extend(tool_turn().iter().map(|m| to_stored(m, None, &limits)));
+    db.append_messages(&session.id, rows).await.unwrap();
+
+    let stored = db.load_history(&session.id, 6).await.unwrap();
+    assert_eq!(stored.iter().map(|m| m.role.as_str()).collect::<Vec<_>>(), ["user", "assistant", "tool", "assistant"]);
+    assert_eq!(stored[1].content, "[calls memory_search]");
+    assert_eq!(stored[2].content, "[memory_search] [12] Lease ends in March");
+
+    let history = replay(stored);
+    assert_eq!(history.len(), 4);
+    match &history[1].content {
+        MessageContent::AssistantWithToolCalls { tool_calls, .. } => {
+            assert_eq!(tool_calls[0].id, "call_1");
+            assert_eq!(tool_calls[0].thought_signature.as_deref(), Some("sig"));
+        }
+        other => panic!("expected tool calls, got {other:?}"),
+    }
+    assert!(matches!(&history[2].content, MessageContent::ToolResult { tool_call_id, .. } if tool_call_id == "call_1"));
+    assert_eq!(history[2].role, Role::Tool);
+}
+
+#[test]
+fn tool_result_with_image_keeps_its_variant() {
+    let message = Message {
+        role: Role::Tool,
+        content: MessageContent::ToolResultWithImage {
+            tool_call_id: "call_2".into(),
+            name: "file_read".into(),
+            text: "photo.png".into(),
+            image_base64: "aGVsbG8=".into(),
+            media_type: "image/png".into(),
+        },
+    };
+    let stored = to_stored(&message, None, &HistoryLimits::default());
+    let row = memory_assistant::db::SessionMessage {
+        id: 1,
+        role: stored.role,
+        content: stored.content,
+        created_at: String::new(),
+        message: stored.message,
+    };
+    let user = memory_assistant::db::SessionMessage {
+        id: 0,
+        role: "user".into(),
+        content: "show me".into(),
+        created_at: String::new(),
+        message: None,
+    };
+    let history = replay(vec![user, row]);
+    assert!(matches!(&history[1].content, MessageContent::ToolResultWithImage { tool_call_id, .. } if tool_call_id == "call_2"));
+}
+
+#[test]
+fn stored_copies_are_capped() {
+    let limits = HistoryLimits { max_text_chars: 10, max_tool_chars: 5, max_image_bytes: 4 };
+    let parse = |m: &Message| -> Message {
+        serde_json::from_str(to_stored(m, None, &limits).message.as_deref().unwrap()).unwrap()
+    };
+
+    let long = Message { role: Role::User, content: MessageContent::Text("x".repeat(50)) };
+    assert!(parse(&long).content.as_text().starts_with(&"x".repeat(10)));
+    assert!(parse(&long).content.as_text().contains("40 more characters"));
+
+    let photo = Message {
+        role: Role::User,
+        content: MessageContent::ImageWithText {
+            text: "what is this".into(),
+            image_base64: "aGVsbG8gd29ybGQ=".into(),
+            media_type: "image/jpeg".into(),
+        },
+    };
+    let stored = to_stored(&photo, Some("[Photo] what is this"), &limits);
+    assert_eq!(stored.content, "[Photo] what is this");
+    assert!(matches!(parse(&photo).content, MessageContent::Text(t) if t.contains("image not kept")));
+
+    let call = Message {
+        role: Role::Assistant,
+        content: MessageContent::AssistantWithToolCalls {
+            text: None,
+            tool_calls: vec![ToolCall {
+                id: "c".into(),
+                function: ToolCallFunction { name: "file_write".into(), arguments: r#"{"content":"long body"}"#.into() },
+                thought_signature: None,
+            }],
+            reasoning_content: None,
+        },
+    };
+    match parse(&call).content {
+        MessageContent::AssistantWithToolCalls { tool_calls, .. } => {
+            let args: serde_json::Value = serde_json::from_str(&tool_calls[0].function.arguments).unwrap();
+            assert!(args["truncated_arguments"].as_str().unwrap().starts_with("{\"con"));
+        }
+        other => panic!("expected tool calls, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn history_window_counts_turns_and_skips_orphans() {
+    let db = Database::open(":memory:").unwrap();
+    let session = db.get_or_create_session(1).await.unwrap();
+    let limits = HistoryLimits::default();
+    for i in 0..3 {
+        let user = Message { role: Role::User, content: MessageContent::Text(format!("question {i}")) };
+        let mut rows = vec![to_stored(&user, None, &limits)];
+        rows.extend(tool_turn().iter().map(|m| to_stored(m, None, &limits)));
+        db.append_messages(&session.id, rows).await.unwrap();
+    }
+
+    let window = db.load_history(&session.id, 2).await.unwrap();
+    assert_eq!(window.len(), 8);
+    assert_eq!(window[0].content, "question 1");
+
+    // Retention cut the first turn mid-way: its tool result must not be replayed alone
+    let rows = db.load_history(&session.id, 6).await.unwrap();
+    let first_user = rows.iter().position(|m| m.role == "user").unwrap();
+    let cut: Vec<_> = rows[first_user + 2..].to_vec();
+    let history = replay(cut);
+    assert_eq!(history[0].role, Role::User);
+    assert_eq!(history[0].content.as_text(), "question 1");
+}
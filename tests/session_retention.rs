@@ -0,0 +1,191 @@
+use memory_assistant::db::{Database, Repository, Session, SessionMessage, SessionRetention, SpendLimits, UsageEntry};
+use memory_assistant::tools::{
+    BudgetPolicy, MAX_SUMMARY_BATCHES, OverBudgetAction, summarize_expired, transcript_batches,
+};
+
+const KEEP_4: SessionRetention = SessionRetention { max_age_days: None, max_messages: Some(4), summarize: true };
+/// With an empty `created_at`, a transcript line is `"[] user: "` + text + newline: 800 chars, 15 per batch.
+const LINE_TEXT: usize = 790;
+
+fn message(id: i64, chars: usize) -> SessionMessage {
+    SessionMessage { id, role: "user".into(), content: "x".repeat(chars), created_at: String::new(), message: None }
+}
+
+fn no_budget() -> BudgetPolicy {
+    BudgetPolicy {
+        owner: SpendLimits::default(),
+        user: SpendLimits::default(),
+        action: OverBudgetAction::Refuse,
+        fallback_model: "gpt-5-mini".into(),
+        alert_percent: 80,
+    }
+}
+
+async fn session_with_messages(db: &Database, owner: u64, n: usize) -> String {
+    let session = db.get_or_create_session(owner).await.unwrap();
+    for i in 0..n {
+        let role = if i % 2 == 0 { "user" } else { "assistant" };
+        db.append_message(&session.id, role, &format!("message {i}")).await.unwrap();
+    }
+    session.id
+}
+
+#[tokio::test]
+async fn count_limit_prunes_oldest_and_keeps_summary() {
+    let db = Database::open(":memory:").unwrap();
+    let id = session_with_messages(&db, 1, 10).await;
+
+    let expired = db.expired_session_messages(&id, KEEP_4).await.unwrap();
+    let texts: Vec<&str> = expired.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(texts, ["message 0", "message 1", "message 2", "message 3", "message 4", "message 5"]);
+
+    let last = expired.last().unwrap().id;
+    assert_eq!(db.prune_session_messages(&id, last, Some("- talked about messages")).await.unwrap(), 6);
+    assert!(db.expired_session_messages(&id, KEEP_4).await.unwrap().is_empty());
+    assert_eq!(db.load_history(&id, 10).await.unwrap().len(), 4);
+
+    // Resuming the session carries the summary
+    let session = db.get_or_create_session(1).await.unwrap();
+    assert_eq!((session.id.as_str(), session.summary.as_deref()), (id.as_str(), Some("- talked about messages")));
+}
+
+#[tokio::test]
+async fn age_limit_uses_created_at() {
+    let path = std::env::temp_dir().join(format!("ma-retention-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+    let id = session_with_messages(&db, 1, 3).await;
+
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    conn.execute("UPDATE session_messages SET created_at = datetime('now', '-40 days') WHERE content != 'message 2'", [])
+        .unwrap();
+
+    let retention = SessionRetention { max_age_days: Some(30), ..Default::default() };
+    let expired = db.expired_session_messages(&id, retention).await.unwrap();
+    assert_eq!(expired.len(), 2);
+    assert!(db.expired_session_messages(&id, SessionRetention::default()).await.unwrap().is_empty());
+
+    // Pruning without a summary leaves the previous one alone
+    db.prune_session_messages(&id, expired[1].id, None).await.unwrap();
+    assert_eq!(db.get_or_create_session(1).await.unwrap().summary, None);
+    assert!(db.list_sessions_with_messages().await.unwrap().iter().any(|s| s.id == id));
+}
+
+#[tokio::test]
+async fn per_owner_override() {
+    let db = Database::open(":memory:").unwrap();
+    assert_eq!(db.get_session_retention(1).await.unwrap(), None);
+
+    db.set_session_retention(1, KEEP_4).await.unwrap();
+    let updated = SessionRetention { max_age_days: Some(7), ..KEEP_4 };
+    db.set_session_retention(1, updated).await.unwrap();
+    assert_eq!(db.get_session_retention(1).await.unwrap(), Some(updated));
+    assert_eq!(db.get_session_retention(2).await.unwrap(), None);
+
+    assert!(db.clear_session_retention(1).await.unwrap());
+    assert!(!db.clear_session_retention(1).await.unwrap());
+}
+
+#[test]
+fn transcript_batches_split_at_the_prompt_cap() {
+    assert!(transcript_batches(&[]).is_empty());
+
+    let messages: Vec<_> = (0..40).map(|i| message(i, LINE_TEXT)).collect();
+    let sizes: Vec<usize> = transcript_batches(&messages).iter().map(|b| b.len()).collect();
+    assert_eq!(sizes, [15, 15, 10]);
+
+    // One byte more and only 14 lines fit
+    let messages: Vec<_> = (0..15).map(|i| message(i, LINE_TEXT + 1)).collect();
+    let sizes: Vec<usize> = transcript_batches(&messages).iter().map(|b| b.len()).collect();
+    assert_eq!(sizes, [14, 1]);
+}
+
+#[test]
+fn oversized_message_is_cut_to_the_per_message_cap() {
+    let messages = [message(1, 10), message(2, 50_000), message(3, 10)];
+    let batches = transcript_batches(&messages);
+    assert_eq!(batches.len(), 1, "a huge message counts as its first 800 chars");
+    assert_eq!(batches[0].iter().map(|m| m.id).collect::<Vec<_>>(), [1, 2, 3]);
+
+    let alone = transcript_batches(&messages[1..2]);
+    assert_eq!((alone.len(), alone[0].len()), (1, 1));
+}
+
+async fn long_session(db: &Database, owner: u64, n: usize) -> (Session, Vec<SessionMessage>) {
+    let session = db.get_or_create_session(owner).await.unwrap();
+    for i in 0..n {
+        // Stored rows have a timestamp: about 790 chars per line, still 15 per batch
+        db.append_message(&session.id, "user", &format!("{i:>760}")).await.unwrap();
+    }
+    let retention = SessionRetention { max_messages: Some(1), ..KEEP_4 };
+    let expired = db.expired_session_messages(&session.id, retention).await.unwrap();
+    (session, expired)
+}
+
+#[tokio::test]
+async fn failed_summary_keeps_the_unsummarized_messages() {
+    let db = Database::open(":memory:").unwrap();
+    let (session, expired) = long_session(&db, 1, 41).await;
+    assert_eq!(expired.len(), 40);
+
+    let mut calls = 0;
+    let summarize = |summary: Option<String>, batch: Vec<SessionMessage>| {
+        calls += 1;
+        let first = calls == 1;
+        async move {
+            // The second call builds on the saved summary, then fails
+            assert_eq!(summary.is_none(), first);
+            assert_eq!(batch.len(), 15);
+            first.then(|| "- first batch".to_string())
+        }
+    };
+    let outcome = summarize_expired(&db, &db, &session, &expired, &no_budget(), summarize).await.unwrap();
+    assert_eq!((outcome.pruned, outcome.summarized, calls), (15, true, 2));
+
+    let ids = |messages: &[SessionMessage]| messages.iter().map(|m| m.id).collect::<Vec<_>>();
+    let left = db.expired_session_messages(&session.id, SessionRetention { max_messages: Some(1), ..KEEP_4 }).await;
+    assert_eq!(ids(&left.unwrap()), ids(&expired[15..]));
+    assert_eq!(db.get_or_create_session(1).await.unwrap().summary.as_deref(), Some("- first batch"));
+}
+
+#[tokio::test]
+async fn summary_calls_are_capped_per_pass() {
+    let db = Database::open(":memory:").unwrap();
+    let (session, expired) = long_session(&db, 1, 101).await;
+
+    let mut calls = 0;
+    let summarize = |_: Option<String>, _: Vec<SessionMessage>| {
+        calls += 1;
+        let n = calls;
+        async move { Some(format!("- after batch {n}")) }
+    };
+    let outcome = summarize_expired(&db, &db, &session, &expired, &no_budget(), summarize).await.unwrap();
+    assert_eq!((outcome.pruned, calls), (15 * MAX_SUMMARY_BATCHES, MAX_SUMMARY_BATCHES));
+    let summary = db.get_or_create_session(1).await.unwrap().summary;
+    assert_eq!(summary, Some(format!("- after batch {MAX_SUMMARY_BATCHES}")));
+}
+
+#[tokio::test]
+async fn used_up_owner_budget_stops_summarizing() {
+    let db = Database::open(":memory:").unwrap();
+    let (session, expired) = long_session(&db, 1, 10).await;
+    // $2 of Haiku input against a $1 daily owner budget
+    let entry = UsageEntry {
+        model: "claude-haiku-4-5-20251001".into(),
+        provider: "claude".into(),
+        prompt_tokens: 2_000_000,
+        kb_owner_id: Some(1),
+        ..Default::default()
+    };
+    db.log_usage(&entry).await.unwrap();
+    let budget = BudgetPolicy { owner: SpendLimits { daily_usd: Some(1.0), monthly_usd: None }, ..no_budget() };
+
+    let mut calls = 0;
+    let summarize = |_: Option<String>, _: Vec<SessionMessage>| {
+        calls += 1;
+        async { Some("- never".to_string()) }
+    };
+    let outcome = summarize_expired(&db, &db, &session, &expired, &budget, summarize).await.unwrap();
+    assert_eq!((outcome.pruned, outcome.summarized, calls), (0, false, 0));
+    assert_eq!(db.get_or_create_session(1).await.unwrap().summary, None);
+}
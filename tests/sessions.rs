@@ -0,0 +1,81 @@
+mod common;
+
+use common::temp_db_path;
+use memory_assistant::db::{Database, DbError, Repository};
+
+#[tokio::test]
+async fn new_session_becomes_active() {
+    let db = Database::open(":memory:").unwrap();
+    let first = db.get_or_create_session(1).await.unwrap();
+    db.append_message(&first.id, "user", "hello").await.unwrap();
+
+    let second = db.create_session(1, Some("Trip planning")).await.unwrap();
+    assert_ne!(second.id, first.id, "sessions started in the same second get distinct ids");
+    assert_eq!(second.title.as_deref(), Some("Trip planning"));
+    assert_eq!(db.get_or_create_session(1).await.unwrap().id, second.id);
+    assert_eq!(db.active_session_id(1).await.unwrap(), Some(second.id.clone()));
+    assert!(db.load_history(&second.id, 6).await.unwrap().is_empty());
+
+    let third = db.create_session(1, None).await.unwrap();
+    assert_eq!(db.list_sessions(1, 10).await.unwrap().len(), 3);
+    assert_eq!(db.list_sessions(1, 2).await.unwrap()[0].id, third.id);
+}
+
+#[tokio::test]
+async fn resume_switches_the_active_session() {
+    let db = Database::open(":memory:").unwrap();
+    let first = db.get_or_create_session(1).await.unwrap();
+    db.append_message(&first.id, "user", "about the contract").await.unwrap();
+    db.create_session(1, None).await.unwrap();
+
+    let resumed = db.resume_session(1, &first.id).await.unwrap();
+    assert_eq!(resumed.id, first.id);
+    let active = db.get_or_create_session(1).await.unwrap();
+    assert_eq!(active.id, first.id);
+    assert_eq!(db.load_history(&active.id, 6).await.unwrap()[0].content, "about the contract");
+}
+
+#[tokio::test]
+async fn resume_is_scoped_to_the_owner() {
+    let db = Database::open(":memory:").unwrap();
+    let theirs = db.get_or_create_session(2).await.unwrap();
+    let mine = db.get_or_create_session(1).await.unwrap();
+
+    assert!(matches!(db.resume_session(1, &theirs.id).await, Err(DbError::NotFound(_))));
+    assert!(matches!(db.resume_session(1, "no-such-session").await, Err(DbError::NotFound(_))));
+    assert_eq!(db.get_or_create_session(1).await.unwrap().id, mine.id);
+    assert!(db.list_sessions(1, 10).await.unwrap().iter().all(|s| s.user_id == 1));
+}
+
+#[tokio::test]
+async fn title_is_stored() {
+    let db = Database::open(":memory:").unwrap();
+    let session = db.get_or_create_session(1).await.unwrap();
+    assert_eq!(session.title, None);
+    db.set_session_title(&session.id, "Hợp đồng thuê nhà").await.unwrap();
+    assert_eq!(db.get_or_create_session(1).await.unwrap().title.as_deref(), Some("Hợp đồng thuê nhà"));
+}
+
+#[tokio::test]
+async fn upgrade_keeps_the_latest_session_active() {
+    let path = temp_db_path("active-sessions");
+    let path_str = path.to_str().unwrap();
+    drop(Database::open(path_str).unwrap());
+    {
+        // Sessions as they were before v11: no active session recorded
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "DELETE FROM schema_version WHERE version >= 11;
+             DROP TABLE active_sessions;
+             INSERT INTO sessions (id, user_id, last_active_at) VALUES
+                ('1-old', 1, '2024-01-01 00:00:00'),
+                ('1-recent', 1, '2024-06-01 00:00:00'),
+                ('2-only', 2, '2024-03-01 00:00:00');",
+        )
+        .unwrap();
+    }
+
+    let db = Database::open(path_str).unwrap();
+    assert_eq!(db.active_session_id(1).await.unwrap().as_deref(), Some("1-recent"));
+    assert_eq!(db.get_or_create_session(2).await.unwrap().id, "2-only");
+}
@@ -0,0 +1,88 @@
+use memory_assistant::db::{Actor, Database, Repository, TrashKind};
+
+const ACTOR: Actor<'static> = Actor { user_id: 1, tool: "test" };
+
+#[tokio::test]
+async fn deleted_fact_is_hidden_until_restored() {
+    let db = Database::open(":memory:").unwrap();
+    let id = db.save_fact(1, "favourite editor is helix", "preference").await.unwrap();
+
+    assert!(db.delete_fact(1, id).await.unwrap());
+    assert!(!db.delete_fact(1, id).await.unwrap(), "already in trash");
+    assert!(db.list_facts(1, None).await.unwrap().is_empty());
+    assert!(db.search_facts(1, "helix").await.unwrap().is_empty());
+    assert!(db.build_memory_context(1).await.is_empty());
+    assert!(!db.update_fact(1, id, "edited", ACTOR).await.unwrap());
+
+    let trash = db.list_trash(1).await.unwrap();
+    assert_eq!(trash.len(), 1);
+    assert_eq!((trash[0].kind, trash[0].id), (TrashKind::Fact, id));
+
+    assert!(db.restore_from_trash(1, TrashKind::Fact, id).await.unwrap());
+    assert_eq!(db.search_facts(1, "helix").await.unwrap()[0].id, id);
+    assert!(db.list_trash(1).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn deleted_document_leaves_search() {
+    let db = Database::open(":memory:").unwrap();
+    let doc_id = db.save_document(1, "Deploy guide", "run the deploy script", None, None).await.unwrap();
+    let chunk_ids = db.save_chunks(doc_id, &[(0, 1, 1, "run the deploy script")]).await.unwrap();
+    db.update_chunk_embeddings(&chunk_ids, &[vec![0u8; 8]]).await.unwrap();
+
+    assert!(db.delete_document(1, doc_id).await.unwrap());
+    assert!(db.search_documents(1, "deploy").await.unwrap().is_empty());
+    assert!(db.search_chunks_fts(1, "deploy").await.unwrap().is_empty());
+    assert!(db.load_all_embeddings(1).await.unwrap().is_empty());
+    assert!(db.list_documents(1).await.unwrap().is_empty());
+    assert!(db.get_document(1, doc_id).await.is_err());
+
+    // Chunks survive the soft delete, so a restore brings search back as-is
+    assert!(db.restore_from_trash(1, TrashKind::Document, doc_id).await.unwrap());
+    assert_eq!(db.search_chunks_fts(1, "deploy").await.unwrap().len(), 1);
+    assert_eq!(db.load_all_embeddings(1).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn purge_removes_rows_and_mentions() {
+    let db = Database::open(":memory:").unwrap();
+    let fact_id = db.save_fact(1, "alice owns billing", "project").await.unwrap();
+    let doc_id = db.save_document(1, "Billing", "alice wrote this", None, None).await.unwrap();
+    let entity_id = db.save_entity(1, "alice", "person").await.unwrap();
+    db.add_entity_mention(entity_id, "fact", fact_id, None).await.unwrap();
+    db.add_entity_mention(entity_id, "document", doc_id, None).await.unwrap();
+
+    db.delete_fact(1, fact_id).await.unwrap();
+    db.delete_document(1, doc_id).await.unwrap();
+
+    // Purging one item leaves the other in the trash
+    assert_eq!(db.purge_trash(1, Some((TrashKind::Fact, fact_id))).await.unwrap(), 1);
+    assert!(!db.restore_from_trash(1, TrashKind::Fact, fact_id).await.unwrap());
+    assert_eq!(db.list_trash(1).await.unwrap().len(), 1);
+
+    assert_eq!(db.purge_trash(1, None).await.unwrap(), 1);
+    assert!(db.list_trash(1).await.unwrap().is_empty());
+
+    let (_, mentions) = &db.search_entities(1, "alice").await.unwrap()[0];
+    assert!(mentions.is_empty());
+}
+
+#[tokio::test]
+async fn expired_trash_is_purged_for_all_owners() {
+    let path = std::env::temp_dir().join(format!("ma-trash-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+
+    let old = db.save_fact(1, "old", "general").await.unwrap();
+    let recent = db.save_fact(2, "recent", "general").await.unwrap();
+    db.delete_fact(1, old).await.unwrap();
+    db.delete_fact(2, recent).await.unwrap();
+
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    conn.execute("UPDATE memory_facts SET deleted_at = datetime('now', '-40 days') WHERE id = ?1", [old])
+        .unwrap();
+
+    assert_eq!(db.purge_expired_trash(30).await.unwrap(), 1);
+    assert!(db.list_trash(1).await.unwrap().is_empty());
+    assert_eq!(db.list_trash(2).await.unwrap().len(), 1);
+}
@@ -0,0 +1,61 @@
+use base64::Engine;
+use memory_assistant::agent::approval::ApprovalPolicy;
+use memory_assistant::agent::{ToolOutput, ToolRegistry};
+use memory_assistant::db::Database;
+use memory_assistant::provider::ProviderPool;
+use memory_assistant::tools::embedding::DisabledEmbedder;
+use memory_assistant::tools::file_crypto::{self, FileCipher};
+use memory_assistant::tools::uploads::save_file_to_disk;
+
+const OWNER: u64 = 42;
+
+async fn run(db: &Database, cipher: Option<&FileCipher>, tool: &str, args: serde_json::Value) -> ToolOutput {
+    let pool = ProviderPool::new(Vec::new(), None, None, None, None);
+    let approvals = ApprovalPolicy::default();
+    ToolRegistry::execute(tool, &args.to_string(), OWNER, OWNER, db, &pool, &DisabledEmbedder, cipher, approvals).await
+}
+
+#[tokio::test]
+async fn encrypted_uploads_read_back_as_plaintext() {
+    let home = std::env::temp_dir().join(format!("ma-uploads-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&home);
+    std::fs::create_dir_all(&home).unwrap();
+    // SAFETY: the only test in this binary, set before anything reads HOME
+    unsafe { std::env::set_var("HOME", &home) };
+
+    let db = Database::open(":memory:").unwrap();
+    let cipher = FileCipher::new("upload key", [1; file_crypto::SALT_LEN]);
+    let image = b"\x89PNG\r\n\x1a\n not really a png".to_vec();
+    let path = save_file_to_disk(OWNER, "photo.png", &image, Some(&cipher)).await.unwrap();
+    assert!(path.starts_with(home.join("documents/42")));
+    assert!(file_crypto::is_encrypted(&std::fs::read(&path).unwrap()), "sealed on disk");
+
+    let args = serde_json::json!({ "path": path.display().to_string() });
+    match run(&db, Some(&cipher), "image_read", args.clone()).await {
+        ToolOutput::Image { image_base64, media_type, .. } => {
+            assert_eq!(base64::engine::general_purpose::STANDARD.decode(image_base64).unwrap(), image);
+            assert_eq!(media_type, "image/png");
+        }
+        ToolOutput::Text(text) => panic!("expected an image: {text}"),
+    }
+    match run(&db, None, "image_read", args).await {
+        ToolOutput::Text(text) => assert!(text.contains("ENCRYPTION_KEY"), "{text}"),
+        ToolOutput::Image { .. } => panic!("ciphertext returned as an image"),
+    }
+
+    let note = save_file_to_disk(OWNER, "note.txt", b"eggs\nflour\n", Some(&cipher)).await.unwrap();
+    let args = serde_json::json!({ "path": note.display().to_string() });
+    match run(&db, Some(&cipher), "file_read", args).await {
+        ToolOutput::Text(text) => assert_eq!(text, "    1\teggs\n    2\tflour"),
+        ToolOutput::Image { .. } => panic!("file_read returned an image"),
+    }
+
+    // Plaintext files (saved before encryption was turned on) still read as they are
+    let plain = save_file_to_disk(OWNER, "old.txt", b"butter", None).await.unwrap();
+    let args = serde_json::json!({ "path": plain.display().to_string() });
+    match run(&db, Some(&cipher), "file_read", args).await {
+        ToolOutput::Text(text) => assert_eq!(text, "    1\tbutter"),
+        ToolOutput::Image { .. } => panic!("file_read returned an image"),
+    }
+    let _ = std::fs::remove_dir_all(&home);
+}
@@ -0,0 +1,81 @@
+use memory_assistant::db::usage::usage_csv;
+use memory_assistant::db::{Database, Repository, UsageEntry, UsageGroup};
+
+fn entry(user: Option<u64>, owner: Option<u64>, prompt: u32) -> UsageEntry {
+    UsageEntry {
+        model: "claude-haiku-4-5-20251001".into(),
+        provider: "claude".into(),
+        prompt_tokens: prompt,
+        completion_tokens: 10,
+        user_id: user,
+        kb_owner_id: owner,
+        session_id: owner.map(|o| format!("session-{o}")),
+        turns: 2,
+        tools: vec!["memory_save".into(), "knowledge_search".into()],
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn attribution_is_stored_and_read_back() {
+    let db = Database::open(":memory:").unwrap();
+    db.log_usage(&entry(Some(7), Some(100), 1_000)).await.unwrap();
+
+    let rows = db.list_usage(None).await.unwrap();
+    assert_eq!(rows.len(), 1);
+    let row = &rows[0];
+    assert_eq!((row.user_id, row.kb_owner_id, row.session_id.as_deref()), (Some(7), Some(100), Some("session-100")));
+    assert_eq!(row.turns, 2);
+    assert_eq!(row.tools, ["memory_save", "knowledge_search"]);
+    assert_eq!((row.usage.prompt_tokens, row.usage.completion_tokens, row.usage.requests), (1_000, 10, 1));
+
+    assert!(db.list_usage(Some("2999-01-01")).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn monthly_usage_groups_by_user_chat_and_day() {
+    let db = Database::open(":memory:").unwrap();
+    db.log_usage(&entry(Some(1), Some(100), 100)).await.unwrap();
+    db.log_usage(&entry(Some(1), Some(100), 200)).await.unwrap();
+    db.log_usage(&entry(Some(2), Some(100), 400)).await.unwrap();
+    db.log_usage(&entry(None, Some(200), 800)).await.unwrap();
+
+    let by_user = db.get_monthly_usage_by(UsageGroup::User).await.unwrap();
+    let users: Vec<(Option<&str>, u64, u64)> = by_user
+        .iter()
+        .map(|g| (g.key.as_deref(), g.usage.prompt_tokens, g.usage.requests))
+        .collect();
+    assert_eq!(users, [(None, 800, 1), (Some("1"), 300, 2), (Some("2"), 400, 1)]);
+
+    let by_chat = db.get_monthly_usage_by(UsageGroup::Chat).await.unwrap();
+    let chats: Vec<(Option<&str>, u64)> = by_chat.iter().map(|g| (g.key.as_deref(), g.usage.requests)).collect();
+    assert_eq!(chats, [(Some("100"), 3), (Some("200"), 1)]);
+
+    let by_day = db.get_monthly_usage_by(UsageGroup::Day).await.unwrap();
+    assert_eq!(by_day.len(), 1);
+    assert_eq!(by_day[0].key.as_deref(), Some(chrono::Utc::now().format("%Y-%m-%d").to_string().as_str()));
+    assert_eq!(by_day[0].usage.requests, 4);
+    assert!(by_day[0].usage.cost_usd() > 0.0);
+}
+
+#[tokio::test]
+async fn csv_export_escapes_fields_and_prices_rows() {
+    let db = Database::open(":memory:").unwrap();
+    let mut odd = entry(Some(1), Some(100), 1_000_000);
+    odd.session_id = Some("a,\"b\"".into());
+    odd.tools.clear();
+    db.log_usage(&odd).await.unwrap();
+    db.log_usage(&entry(None, None, 0)).await.unwrap();
+
+    let csv = usage_csv(&db.list_usage(None).await.unwrap());
+    let lines: Vec<&str> = csv.split("\r\n").collect();
+    assert!(lines[0].starts_with("id,created_at,user_id,kb_owner_id,session_id,model"));
+    assert!(lines[0].ends_with(",turns,tools,cost_usd"));
+    assert!(lines[1].contains(",1,100,\"a,\"\"b\"\"\",claude-haiku-4-5-20251001,claude,1000000,10,0,0,2,,"));
+    let cost: f64 = lines[1].rsplit(',').next().unwrap().parse().unwrap();
+    assert!(cost > 0.0);
+    assert!(lines[2].contains(",,,,claude-haiku-4-5-20251001,"));
+    assert!(lines[2].contains(",2,memory_save;knowledge_search,"));
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[3], "");
+}
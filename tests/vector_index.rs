@@ -0,0 +1,198 @@
+mod common;
+
+use std::collections::HashMap;
+
+use common::{random_vectors, temp_db_path};
+use memory_assistant::db::hnsw::{Hnsw, HnswParams};
+use memory_assistant::db::quantization::Quantization;
+use memory_assistant::db::vector_index::VectorKind;
+use memory_assistant::db::{Database, Repository, TrashKind};
+use memory_assistant::tools::embedding::{cosine_similarity, embedding_to_bytes};
+
+const OWNER: u64 = 7;
+const DIMS: usize = 32;
+
+fn brute_force(vectors: &HashMap<i64, Vec<f32>>, query: &[f32], k: usize) -> Vec<i64> {
+    let mut scored: Vec<(i64, f32)> = vectors.iter().map(|(id, v)| (*id, cosine_similarity(query, v))).collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.into_iter().take(k).map(|(id, _)| id).collect()
+}
+
+fn recall(index: &Hnsw, vectors: &HashMap<i64, Vec<f32>>, queries: &[Vec<f32>], k: usize) -> f64 {
+    let mut found = 0;
+    for query in queries {
+        let exact = brute_force(vectors, query, k);
+        let approx: Vec<i64> = index.search(query, k).into_iter().map(|(id, _)| id).collect();
+        found += exact.iter().filter(|id| approx.contains(id)).count();
+    }
+    found as f64 / (queries.len() * k) as f64
+}
+
+/// A vector pointing mostly along axis `axis`.
+fn axis(axis: usize, noise: f32) -> Vec<f32> {
+    let mut v = vec![noise; DIMS];
+    v[axis] = 1.0;
+    v
+}
+
+async fn save_embedded_fact(db: &Database, text: &str, vector: &[f32]) -> i64 {
+    let id = db.save_fact(OWNER, text, "general").await.unwrap();
+    db.update_fact_embedding(id, &embedding_to_bytes(vector)).await.unwrap();
+    id
+}
+
+async fn nearest_fact_ids(db: &Database, query: &[f32], k: usize) -> Vec<i64> {
+    db.nearest_facts(OWNER, query, k).await.unwrap().into_iter().map(|(f, _)| f.id).collect()
+}
+
+#[test]
+fn hnsw_finds_what_brute_force_finds() {
+    let vectors: HashMap<i64, Vec<f32>> =
+        random_vectors(800, DIMS, 42).into_iter().enumerate().map(|(i, v)| (i as i64 + 1, v)).collect();
+    let mut index = Hnsw::new(HnswParams::default());
+    for (id, vector) in &vectors {
+        index.insert(*id, vector).unwrap();
+    }
+    assert_eq!(index.len(), 800);
+
+    let queries = random_vectors(50, DIMS, 7);
+    let recall = recall(&index, &vectors, &queries, 10);
+    assert!(recall >= 0.9, "recall@10 = {recall}");
+
+    // An indexed vector is its own nearest neighbour
+    let (id, sim) = index.search(&vectors[&123], 1)[0];
+    assert_eq!(id, 123);
+    assert!((sim - 1.0).abs() < 1e-4);
+}
+
+#[test]
+fn removed_vectors_are_gone_and_the_rest_still_found() {
+    let mut vectors: HashMap<i64, Vec<f32>> =
+        random_vectors(400, DIMS, 3).into_iter().enumerate().map(|(i, v)| (i as i64, v)).collect();
+    let mut index = Hnsw::new(HnswParams::default());
+    for (id, vector) in &vectors {
+        index.insert(*id, vector).unwrap();
+    }
+    // Enough removals to compact the slot table
+    for id in (0..400).filter(|id| id % 3 != 0) {
+        assert!(index.remove(id));
+        vectors.remove(&id);
+    }
+    assert!(!index.remove(1));
+    assert_eq!(index.len(), vectors.len());
+
+    let queries = random_vectors(50, DIMS, 11);
+    for query in &queries {
+        assert!(index.search(query, 10).iter().all(|(id, _)| id % 3 == 0));
+    }
+    let recall = recall(&index, &vectors, &queries, 10);
+    assert!(recall >= 0.9, "recall@10 after removals = {recall}");
+
+    assert_eq!(index.insert(5000, &[1.0; 3]).unwrap_err().got, 3);
+}
+
+#[test]
+fn restored_graph_answers_like_the_original() {
+    let vectors: HashMap<i64, Vec<f32>> =
+        random_vectors(300, DIMS, 5).into_iter().enumerate().map(|(i, v)| (i as i64, v)).collect();
+    let mut index = Hnsw::new(HnswParams::default());
+    for id in 0..300 {
+        index.insert(id, &vectors[&id]).unwrap();
+    }
+    let stored = (0..300).filter_map(|id| index.links(id)).collect();
+
+    let encoded = vectors.iter().map(|(id, v)| (*id, Quantization::None.encode(v))).collect();
+    let mut restored = Hnsw::restore(HnswParams::default(), DIMS, stored, encoded, index.entry_id());
+    assert!(restored.take_dirty().is_empty(), "nothing to repair");
+    for query in random_vectors(20, DIMS, 9) {
+        assert_eq!(index.search(&query, 10), restored.search(&query, 10));
+    }
+}
+
+#[tokio::test]
+async fn fact_index_follows_saves_edits_and_trash() {
+    let db = Database::open(":memory:").unwrap();
+    let coffee = save_embedded_fact(&db, "Coffee at 9", &axis(0, 0.01)).await;
+    let gym = save_embedded_fact(&db, "Gym on Fridays", &axis(1, 0.01)).await;
+    assert_eq!(nearest_fact_ids(&db, &axis(0, 0.0), 1).await, [coffee]);
+
+    // Saved after the index was built: picked up from the log
+    let tea = save_embedded_fact(&db, "Tea at 4", &axis(2, 0.01)).await;
+    assert_eq!(nearest_fact_ids(&db, &axis(2, 0.0), 1).await, [tea]);
+
+    // A new embedding moves the fact
+    db.update_fact_embedding(gym, &embedding_to_bytes(&axis(3, 0.01))).await.unwrap();
+    assert_eq!(nearest_fact_ids(&db, &axis(3, 0.0), 1).await, [gym]);
+
+    db.delete_fact(OWNER, tea).await.unwrap();
+    assert!(!nearest_fact_ids(&db, &axis(2, 0.0), 3).await.contains(&tea));
+    db.restore_from_trash(OWNER, TrashKind::Fact, tea).await.unwrap();
+    assert_eq!(nearest_fact_ids(&db, &axis(2, 0.0), 1).await, [tea]);
+
+    db.delete_fact(OWNER, tea).await.unwrap();
+    db.purge_trash(OWNER, None).await.unwrap();
+    let stats = db.vector_index_stats(OWNER, VectorKind::Fact).await.unwrap().unwrap();
+    assert!(stats.pending > 0, "trash and purge are logged until the next search");
+    nearest_fact_ids(&db, &axis(0, 0.0), 1).await;
+    let stats = db.vector_index_stats(OWNER, VectorKind::Fact).await.unwrap().unwrap();
+    assert_eq!((stats.vectors, stats.dims, stats.pending), (2, DIMS, 0));
+
+    // Other owners' facts never show up
+    let other = db.save_fact(OWNER + 1, "Coffee at 8", "general").await.unwrap();
+    db.update_fact_embedding(other, &embedding_to_bytes(&axis(0, 0.0))).await.unwrap();
+    assert_eq!(nearest_fact_ids(&db, &axis(0, 0.0), 5).await.len(), 2);
+}
+
+#[tokio::test]
+async fn chunk_index_drops_trashed_documents_and_replaced_chunks() {
+    let db = Database::open(":memory:").unwrap();
+    let doc = db.save_document(OWNER, "Recipe", "eggs\nflour", None, None).await.unwrap();
+    let ids = db.save_chunks(doc, &[(0, 1, 1, "eggs"), (1, 2, 2, "flour")]).await.unwrap();
+    let blobs = [embedding_to_bytes(&axis(0, 0.01)), embedding_to_bytes(&axis(1, 0.01))];
+    db.update_chunk_embeddings(&ids, &blobs).await.unwrap();
+
+    let nearest = db.nearest_chunks(OWNER, &axis(1, 0.0), 1).await.unwrap();
+    assert_eq!((nearest[0].0.id, nearest[0].0.content.as_str()), (ids[1], "flour"));
+
+    // Re-chunking replaces rows in place
+    let new_ids = db.save_chunks(doc, &[(0, 1, 1, "butter")]).await.unwrap();
+    let found = db.nearest_chunks(OWNER, &axis(0, 0.0), 5).await.unwrap();
+    assert!(found.iter().all(|(chunk, _)| chunk.id != ids[0] && chunk.id != new_ids[0]));
+
+    db.delete_document(OWNER, doc).await.unwrap();
+    assert!(db.nearest_chunks(OWNER, &axis(1, 0.0), 5).await.unwrap().is_empty());
+    let stats = db.vector_index_stats(OWNER, VectorKind::Chunk).await.unwrap().unwrap();
+    assert_eq!(stats.vectors, 0);
+
+    db.restore_from_trash(OWNER, TrashKind::Document, doc).await.unwrap();
+    assert_eq!(db.nearest_chunks(OWNER, &axis(1, 0.0), 1).await.unwrap()[0].0.id, ids[1]);
+}
+
+#[tokio::test]
+async fn indexes_are_built_once_and_survive_a_restart() {
+    let path = temp_db_path("restart");
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+    let vectors = random_vectors(300, DIMS, 13);
+    let mut ids = Vec::new();
+    for (i, vector) in vectors.iter().enumerate() {
+        ids.push(save_embedded_fact(&db, &format!("fact {i}"), vector).await);
+    }
+    assert!(db.vector_index_stats(OWNER, VectorKind::Fact).await.unwrap().is_none());
+    assert_eq!(db.ensure_vector_indexes().await.unwrap(), 1);
+    assert_eq!(db.ensure_vector_indexes().await.unwrap(), 0);
+    let before = nearest_fact_ids(&db, &vectors[17], 10).await;
+    assert_eq!(before[0], ids[17]);
+    drop(db);
+
+    // Written while the bot was down: logged by the triggers, not rebuilt
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    conn.execute("UPDATE memory_facts SET deleted_at = datetime('now') WHERE id = ?1", [ids[17]]).unwrap();
+    drop(conn);
+
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+    assert_eq!(db.ensure_vector_indexes().await.unwrap(), 0);
+    let after = nearest_fact_ids(&db, &vectors[17], 9).await;
+    assert_eq!(after, before[1..]);
+    let stats = db.vector_index_stats(OWNER, VectorKind::Fact).await.unwrap().unwrap();
+    assert_eq!((stats.vectors, stats.pending), (299, 0));
+}
@@ -0,0 +1,63 @@
+use memory_assistant::db::fts::{match_query, phrase_query, substring_query};
+use memory_assistant::db::{Database, Repository};
+
+#[test]
+fn queries_expand_d_and_dj() {
+    assert_eq!(match_query("hop dong").as_deref(), Some("\"hop\" AND (\"dong\" OR \"đong\")"));
+    assert_eq!(match_query("Hợp*").as_deref(), Some("\"hợp\"*"));
+    assert_eq!(match_query("rust OR go").as_deref(), Some("\"rust\" OR \"go\""));
+    assert_eq!(match_query(" -:- "), None);
+    assert_eq!(phrase_query("Đà Nẵng").as_deref(), Some("\"dà nẵng\" OR \"đà nẵng\""));
+    assert_eq!(substring_query("ab"), None);
+    // Many d's collapse to the two uniform spellings
+    assert_eq!(match_query("dddd").as_deref(), Some("(\"dddd\" OR \"đđđđ\")"));
+}
+
+#[tokio::test]
+async fn search_ignores_tone_marks_and_dj() {
+    let db = Database::open(":memory:").unwrap();
+    let fact = db.save_fact(1, "Ký hợp đồng thuê nhà ở Đà Nẵng", "personal").await.unwrap();
+    let plain = db.save_fact(1, "hop dong bao hiem xe", "personal").await.unwrap();
+
+    let ids = |facts: Vec<memory_assistant::db::Fact>| facts.into_iter().map(|f| f.id).collect::<Vec<_>>();
+    assert_eq!(ids(db.search_facts(1, "da nang").await.unwrap()), [fact]);
+    let mut both = ids(db.search_facts(1, "hợp đồng").await.unwrap());
+    both.sort();
+    assert_eq!(both, [fact, plain]);
+
+    let doc = db.save_document(1, "Hướng dẫn", "Cài đặt phần mềm", None, None).await.unwrap();
+    db.save_chunks(doc, &[(0, 1, 1, "Cài đặt phần mềm")]).await.unwrap();
+    assert_eq!(db.search_documents(1, "cai dat").await.unwrap()[0].id, doc);
+    assert_eq!(db.search_chunks_fts(1, "cai dat").await.unwrap()[0].0.doc_id, doc);
+    assert!(db.search_chunks_fts(1, "dat cai").await.unwrap().is_empty(), "chunks match as a phrase");
+}
+
+#[tokio::test]
+async fn titles_outrank_content() {
+    let db = Database::open(":memory:").unwrap();
+    let body = db
+        .save_document(1, "Ghi chú", "kubernetes kubernetes kubernetes cluster notes", None, None)
+        .await
+        .unwrap();
+    let titled = db.save_document(1, "Kubernetes", "cluster setup notes and more words here", None, None).await.unwrap();
+    let ranked: Vec<i64> = db.search_documents(1, "kubernetes").await.unwrap().iter().map(|d| d.id).collect();
+    assert_eq!(ranked, [titled, body]);
+}
+
+#[tokio::test]
+async fn trigram_index_finds_substrings_and_tracks_edits() {
+    let db = Database::open(":memory:").unwrap();
+    let fact = db.save_fact(1, "Dự án MemoryAssistantBot chạy trên VPS", "project").await.unwrap();
+    assert!(db.search_facts(1, "assistant").await.unwrap().is_empty());
+
+    assert!(db.set_trigram_index(true).await.unwrap());
+    assert!(!db.set_trigram_index(true).await.unwrap());
+    assert_eq!(db.search_facts(1, "assistant").await.unwrap()[0].id, fact);
+
+    // Rows written after the index was built are picked up by its triggers
+    let doc = db.save_document(1, "Notes", "Triển khai bằng dockercompose", None, None).await.unwrap();
+    assert_eq!(db.search_documents(1, "compose").await.unwrap()[0].id, doc);
+
+    assert!(db.set_trigram_index(false).await.unwrap());
+    assert!(db.search_facts(1, "assistant").await.unwrap().is_empty());
+}